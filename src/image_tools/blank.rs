@@ -0,0 +1,377 @@
+#![allow(dead_code)]
+use std::io;
+use std::fmt;
+use image_tools::image_ops::{Page, FilePath, BorderRemoval, BorderAction};
+use image_tools::image_ops::{RunOperation, OperationResults, CompoundPageOperation};
+use image_tools::cancellation::CancellationToken;
+use image_tools::raster::{self, GrayImage};
+use image_tools::borders::{self, Borders};
+
+
+/// Settings for deciding whether a page is blank. Dark scanner borders are
+/// left out, and so are specks of noise; a page is blank when what ink is
+/// left covers no more than a set share of it.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct BlankDetection {
+    max_coverage_hundredths: u32,
+    fuzz: u8,
+    speck_size: usize,
+    borders: BorderRemoval,
+}
+
+impl Default for BlankDetection {
+    fn default() -> BlankDetection {
+        BlankDetection::new()
+    }
+}
+
+impl BlankDetection {
+    /// By default a page is blank when ink covers no more than 0.1% of it,
+    /// pixels within 25% of the paper colour are paper, and marks of fewer
+    /// than 16 pixels are noise.
+    pub fn new() -> BlankDetection {
+        BlankDetection {
+            max_coverage_hundredths: 10,
+            fuzz: 25,
+            speck_size: 16,
+            borders: BorderRemoval::new(BorderAction::Trim),
+        }
+    }
+
+    /// The largest share of the page, as a percentage, that ink may cover on
+    /// a blank page. Raise it for paper with text showing through from the
+    /// other side.
+    pub fn set_max_coverage(&mut self, max_coverage: f64) {
+        self.max_coverage_hundredths = (max_coverage.clamp(0.0, 100.0) * 100.0).round() as u32;
+    }
+
+    /// How far a pixel may be from the paper colour, as a percentage of the
+    /// range from black to white, and still be paper.
+    pub fn set_fuzz(&mut self, fuzz: u8) {
+        self.fuzz = fuzz.min(100);
+    }
+
+    /// Marks of fewer pixels than this are noise and are not ink.
+    pub fn set_speck_size(&mut self, speck_size: usize) {
+        self.speck_size = speck_size;
+    }
+
+    /// How the dark borders left out of the page are found.
+    pub fn set_border_removal(&mut self, borders: BorderRemoval) {
+        self.borders = borders;
+    }
+
+    pub fn max_coverage(&self) -> f64 {
+        self.max_coverage_hundredths as f64 / 100.0
+    }
+
+    pub fn fuzz(&self) -> u8 {
+        self.fuzz
+    }
+
+    pub fn speck_size(&self) -> usize {
+        self.speck_size
+    }
+
+    pub fn border_removal(&self) -> &BorderRemoval {
+        &self.borders
+    }
+
+    /// How much of an image inside its borders is covered by ink.
+    pub fn measure(&self, image: &GrayImage) -> InkCoverage {
+        let borders = borders::detect_borders(image, &self.borders);
+        let inside = image.crop(&borders.inside());
+        let mask = inside.foreground_mask(borders.background(), self.fuzz);
+        let ink = raster::connected_components(&mask, inside.width()).iter()
+            .map(|component| component.area())
+            .filter(|&area| area >= self.speck_size)
+            .sum();
+
+        InkCoverage {
+            ink: ink,
+            area: inside.width() * inside.height(),
+            borders: borders,
+        }
+    }
+
+    pub fn is_blank(&self, coverage: &InkCoverage) -> bool {
+        coverage.ink * 10_000 <= self.max_coverage_hundredths as usize * coverage.area
+    }
+}
+
+impl fmt::Display for BlankDetection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ink over at most {}% of the page, ignoring marks under {} pixels",
+               self.max_coverage(), self.speck_size)
+    }
+}
+
+
+/// The ink found on a page inside its borders.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct InkCoverage {
+    ink: usize,
+    area: usize,
+    borders: Borders,
+}
+
+impl InkCoverage {
+    /// The number of pixels of ink.
+    pub fn ink(&self) -> usize {
+        self.ink
+    }
+
+    /// The number of pixels inside the borders.
+    pub fn area(&self) -> usize {
+        self.area
+    }
+
+    pub fn borders(&self) -> &Borders {
+        &self.borders
+    }
+
+    /// The share of the page inside the borders covered by ink, as a percentage.
+    pub fn percent(&self) -> f64 {
+        if self.area == 0 { 0.0 } else { self.ink as f64 * 100.0 / self.area as f64 }
+    }
+}
+
+impl fmt::Display for InkCoverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ink over {:.3}% of the page inside its {}", self.percent(), self.borders)
+    }
+}
+
+
+/// What is done with a page found to be blank.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum BlankPageAction {
+    /// Keep the page, only recording that it is blank.
+    Keep,
+    /// Keep the page, marking it as blank for someone to check.
+    Flag,
+    /// Leave the page out of the document.
+    Drop,
+}
+
+impl fmt::Display for BlankPageAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BlankPageAction::Keep => write!(f, "Keep"),
+            BlankPageAction::Flag => write!(f, "Flag"),
+            BlankPageAction::Drop => write!(f, "Drop"),
+        }
+    }
+}
+
+
+/// The plan option deciding which pages are blank once their operations
+/// have run, and what is done with them.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct BlankPages {
+    detection: BlankDetection,
+    action: BlankPageAction,
+}
+
+impl BlankPages {
+    pub fn new(detection: BlankDetection, action: BlankPageAction) -> BlankPages {
+        BlankPages {
+            detection: detection,
+            action: action,
+        }
+    }
+
+    pub fn detection(&self) -> &BlankDetection {
+        &self.detection
+    }
+
+    pub fn action(&self) -> BlankPageAction {
+        self.action
+    }
+
+    /// The decision for a page, from an image of it.
+    pub fn decide(&self, image: &GrayImage) -> BlankDecision {
+        let coverage = self.detection.measure(image);
+        let blank = self.detection.is_blank(&coverage);
+
+        BlankDecision {
+            coverage: coverage,
+            blank: blank,
+            action: if blank { self.action } else { BlankPageAction::Keep },
+        }
+    }
+
+    /// Decide on a page whose operations have run, reading its file the way
+    /// the backend that ran them does. Pages that failed or were aborted
+    /// are not looked at. A page that cannot be read fails, with the error
+    /// added to its results, rather than being kept or dropped unseen.
+    pub fn decide_page<Op>(&self, op: &CompoundPageOperation<Op>, file_path: &FilePath,
+                           results: &mut OperationResults, token: &CancellationToken)
+        -> Option<BlankDecision>
+            where Op: RunOperation
+    {
+        if results.is_failed() || results.is_aborted() {
+            return None;
+        }
+
+        match CompoundPageOperation::gray_image(op, file_path, token) {
+            Ok(image) => Some(self.decide(&image)),
+            Err(e) => {
+                results.push(Err(e));
+                None
+            }
+        }
+    }
+
+    /// Decide on a page from its file, which must be a format that is decoded natively.
+    pub fn decide_file(&self, page: &Page) -> io::Result<BlankDecision> {
+        Ok(self.decide(&GrayImage::open(page.file_path())?))
+    }
+}
+
+impl fmt::Display for BlankPages {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} blank pages, with {}", self.action, self.detection)
+    }
+}
+
+
+/// Whether a page was found to be blank, the ink that decided it, and what
+/// was done with the page. Pages that are not blank are always kept.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct BlankDecision {
+    coverage: InkCoverage,
+    blank: bool,
+    action: BlankPageAction,
+}
+
+impl BlankDecision {
+    pub fn coverage(&self) -> &InkCoverage {
+        &self.coverage
+    }
+
+    pub fn is_blank(&self) -> bool {
+        self.blank
+    }
+
+    pub fn action(&self) -> BlankPageAction {
+        self.action
+    }
+
+    pub fn is_flagged(&self) -> bool {
+        self.action == BlankPageAction::Flag
+    }
+
+    pub fn is_dropped(&self) -> bool {
+        self.action == BlankPageAction::Drop
+    }
+}
+
+impl fmt::Display for BlankDecision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.blank {
+            write!(f, "Blank({}), {}", self.coverage, self.action)
+        } else {
+            write!(f, "NotBlank({})", self.coverage)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use image_tools::image_ops::PageOps;
+    use pdf::operations::PdfPageOperation;
+    use util::scratch::{scratch_dir, scratch_tiff};
+
+    /// A verso: paper with a black band down the left edge and a few specks of dust.
+    fn verso() -> GrayImage {
+        let mut image = GrayImage::filled(200, 100, 240);
+        for y in 0..100 {
+            for x in 0..12 {
+                image.set(x, y, 0);
+            }
+        }
+        for &(x, y) in [(50, 20), (51, 20), (120, 70), (180, 90)].iter() {
+            image.set(x, y, 30);
+        }
+
+        image
+    }
+
+    /// A verso with a line of text on it, 4 by 100 pixels of ink.
+    fn printed() -> GrayImage {
+        let mut image = verso();
+        for y in 40..44 {
+            for x in 50..150 {
+                image.set(x, y, 0);
+            }
+        }
+
+        image
+    }
+
+    #[test]
+    fn borders_and_specks_are_not_ink() {
+        let detection = BlankDetection::new();
+        let coverage = detection.measure(&verso());
+
+        assert_eq!(coverage.ink(), 0);
+        assert_eq!(coverage.area(), 188 * 100);
+        assert_eq!(coverage.borders().left(), 12);
+        assert!(detection.is_blank(&coverage));
+    }
+
+    #[test]
+    fn the_threshold_is_a_share_of_the_page_inside_its_borders() {
+        let mut detection = BlankDetection::new();
+        let coverage = detection.measure(&printed());
+        assert_eq!(coverage.ink(), 400);
+        assert!(!detection.is_blank(&coverage));
+
+        // 400 of 18800 pixels is 2.13%.
+        detection.set_max_coverage(2.2);
+        assert!(detection.is_blank(&coverage));
+        detection.set_max_coverage(2.1);
+        assert!(!detection.is_blank(&coverage));
+    }
+
+    #[test]
+    fn only_blank_pages_are_dropped_or_flagged() {
+        let dropping = BlankPages::new(BlankDetection::new(), BlankPageAction::Drop);
+        assert!(dropping.decide(&verso()).is_dropped());
+
+        let decision = dropping.decide(&printed());
+        assert!(!decision.is_blank());
+        assert_eq!(decision.action(), BlankPageAction::Keep);
+
+        let flagging = BlankPages::new(BlankDetection::new(), BlankPageAction::Flag);
+        assert!(flagging.decide(&verso()).is_flagged());
+    }
+
+    #[test]
+    fn failed_and_unreadable_pages_are_not_decided() {
+        let dir = scratch_dir("blank-decide-page");
+        let page = scratch_tiff(&dir, "page.tif");
+        let op = CompoundPageOperation::new(page.file_name().clone(), page.file_path().clone(),
+                                            &[PageOps::NoOperation]).compile::<PdfPageOperation>();
+        let blank_pages = BlankPages::new(BlankDetection::new(), BlankPageAction::Drop);
+        let token = CancellationToken::new();
+
+        let mut completed = OperationResults::from(Ok(String::from("done")));
+        let decision = blank_pages.decide_page(&op, page.file_path(), &mut completed, &token);
+        assert!(decision.unwrap().is_dropped());
+
+        let mut failed = OperationResults::from(Err(io::Error::other("failed")));
+        assert_eq!(blank_pages.decide_page(&op, page.file_path(), &mut failed, &token), None);
+
+        let missing = dir.join("missing.tif").to_string_lossy().into_owned();
+        let mut unread = OperationResults::from(Ok(String::from("done")));
+        assert_eq!(blank_pages.decide_page(&op, &missing, &mut unread, &token), None);
+        assert!(unread.is_failed());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
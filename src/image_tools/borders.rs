@@ -0,0 +1,172 @@
+#![allow(dead_code)]
+use std::fmt;
+use image_tools::image_ops::{BorderRemoval, Pixels, ImageDimensions};
+use image_tools::raster::{GrayImage, Rectangle};
+
+
+/// The widths of the dark borders found along the edges of a page, and
+/// the colour of its paper inside them.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Borders {
+    left: Pixels,
+    right: Pixels,
+    top: Pixels,
+    bottom: Pixels,
+    dimensions: ImageDimensions,
+    background: u8,
+}
+
+impl Borders {
+    pub fn left(&self) -> Pixels {
+        self.left
+    }
+
+    pub fn right(&self) -> Pixels {
+        self.right
+    }
+
+    pub fn top(&self) -> Pixels {
+        self.top
+    }
+
+    pub fn bottom(&self) -> Pixels {
+        self.bottom
+    }
+
+    pub fn dimensions(&self) -> &ImageDimensions {
+        &self.dimensions
+    }
+
+    pub fn background(&self) -> u8 {
+        self.background
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.left == 0 && self.right == 0 && self.top == 0 && self.bottom == 0
+    }
+
+    /// The part of the page inside the borders.
+    pub fn inside(&self) -> Rectangle {
+        Rectangle::new(self.left,
+                       self.top,
+                       self.dimensions.x_pixels() - self.right,
+                       self.dimensions.y_pixels() - self.bottom)
+    }
+
+    /// The borders themselves, left, right, top and bottom, leaving out
+    /// the ones that were not found.
+    pub fn regions(&self) -> Vec<Rectangle> {
+        let (width, height) = (self.dimensions.x_pixels(), self.dimensions.y_pixels());
+
+        vec![
+            Rectangle::new(0, 0, self.left, height),
+            Rectangle::new(width - self.right, 0, width, height),
+            Rectangle::new(0, 0, width, self.top),
+            Rectangle::new(0, height - self.bottom, width, height),
+        ].into_iter().filter(|region| !region.is_empty()).collect()
+    }
+}
+
+impl fmt::Display for Borders {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "borders left {}, right {}, top {}, bottom {} pixels",
+               self.left, self.right, self.top, self.bottom)
+    }
+}
+
+
+/// Find the dark borders along each edge of an image, working inwards from
+/// the edge a row or column at a time until one is not dark enough.
+pub fn detect_borders(image: &GrayImage, removal: &BorderRemoval) -> Borders {
+    let (width, height) = (image.width(), image.height());
+    let is_border = |dark: usize, length: usize| dark * 100 >= removal.coverage() as usize * length;
+    let dark_in_column = |x: Pixels| (0..height).filter(|&y| image.get(x, y) <= removal.darkness()).count();
+    let dark_in_row = |y: Pixels| image.row(y).iter().filter(|&&value| value <= removal.darkness()).count();
+
+    let max_x = width * removal.max_width() as usize / 100;
+    let max_y = height * removal.max_width() as usize / 100;
+    let left = (0..max_x).take_while(|&x| is_border(dark_in_column(x), height)).count();
+    let right = (0..max_x).take_while(|&x| is_border(dark_in_column(width - 1 - x), height)).count();
+    let top = (0..max_y).take_while(|&y| is_border(dark_in_row(y), width)).count();
+    let bottom = (0..max_y).take_while(|&y| is_border(dark_in_row(height - 1 - y), width)).count();
+
+    // The paper colour is the commonest gray level inside the borders.
+    let mut histogram = [0usize; 256];
+    for y in top..height - bottom {
+        for &value in image.row(y)[left..width - right].iter() {
+            histogram[value as usize] += 1;
+        }
+    }
+    let background = (0..256).max_by_key(|&value| (histogram[value], value)).unwrap_or(255) as u8;
+
+    Borders {
+        left: left,
+        right: right,
+        top: top,
+        bottom: bottom,
+        dimensions: image.dimensions(),
+        background: background,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image_tools::image_ops::BorderAction;
+
+    /// A 100 by 80 page of paper with a black band 6 pixels wide down the
+    /// left edge and a dark gray one 4 pixels high along the bottom, which
+    /// is broken by a strip of paper on its first 30 pixels, so that with
+    /// the left band 76% of each of its rows is dark.
+    fn scan() -> GrayImage {
+        let mut image = GrayImage::filled(100, 80, 230);
+        for y in 0..80 {
+            for x in 0..6 {
+                image.set(x, y, 0);
+            }
+        }
+        for y in 76..80 {
+            for x in 30..100 {
+                image.set(x, y, 50);
+            }
+        }
+
+        image
+    }
+
+    #[test]
+    fn dark_bands_are_found_from_each_edge() {
+        let borders = detect_borders(&scan(), &BorderRemoval::new(BorderAction::Trim));
+
+        assert_eq!((borders.left(), borders.right(), borders.top(), borders.bottom()), (6, 0, 0, 4));
+        assert_eq!(borders.background(), 230);
+        assert_eq!(borders.inside(), Rectangle::new(6, 0, 100, 76));
+        assert_eq!(borders.regions(), vec![Rectangle::new(0, 0, 6, 80), Rectangle::new(0, 76, 100, 80)]);
+    }
+
+    #[test]
+    fn thresholds_decide_what_is_a_border() {
+        let mut removal = BorderRemoval::new(BorderAction::Paint);
+        removal.set_darkness(40);
+        assert_eq!(detect_borders(&scan(), &removal).bottom(), 0);
+
+        removal.set_darkness(64);
+        removal.set_coverage(80);
+        assert_eq!(detect_borders(&scan(), &removal).bottom(), 0);
+
+        removal.set_coverage(50);
+        removal.set_max_width(5);
+        let borders = detect_borders(&scan(), &removal);
+        assert_eq!((borders.left(), borders.bottom()), (5, 4));
+    }
+
+    #[test]
+    fn clean_pages_have_no_borders() {
+        let borders = detect_borders(&GrayImage::filled(50, 50, 255), &BorderRemoval::new(BorderAction::Trim));
+
+        assert!(borders.is_empty());
+        assert!(borders.regions().is_empty());
+        assert_eq!(borders.to_string(), "borders left 0, right 0, top 0, bottom 0 pixels");
+    }
+}
@@ -0,0 +1,54 @@
+#![allow(dead_code)]
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::io;
+use std::fmt;
+
+
+/// A CancellationToken is a shared flag used to abort a running operation plan.
+/// Clones of a token all refer to the same flag, so a token may be handed to
+/// another thread or a Ctrl-C handler and cancelled from there while the plan
+/// is executing.
+#[derive(Clone, Debug)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> CancellationToken {
+        CancellationToken::new()
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken {
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Request cancellation. Any operation observing this token stops at the
+    /// next opportunity, killing child processes that are still running.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+impl fmt::Display for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_cancelled() {
+            write!(f, "CancellationToken(Cancelled)")
+        } else {
+            write!(f, "CancellationToken(Active)")
+        }
+    }
+}
+
+/// The error reported by an operation that was interrupted by a cancellation.
+pub fn cancelled_error() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "Operation cancelled")
+}
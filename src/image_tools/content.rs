@@ -0,0 +1,197 @@
+#![allow(dead_code)]
+use std::io;
+use std::fmt;
+use image_tools::image_ops::{Page, PageOps, Pixels, ImageDimensions};
+use image_tools::raster::{self, GrayImage, Rectangle};
+
+
+/// Settings for finding the content of a page.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ContentDetection {
+    fuzz: u8,
+    speck_size: usize,
+}
+
+impl Default for ContentDetection {
+    fn default() -> ContentDetection {
+        ContentDetection::new()
+    }
+}
+
+impl ContentDetection {
+    /// By default pixels within 10% of the paper colour are background, and
+    /// every mark counts as content, however small.
+    pub fn new() -> ContentDetection {
+        ContentDetection {
+            fuzz: 10,
+            speck_size: 0,
+        }
+    }
+
+    /// How far a pixel may be from the paper colour, as a percentage of the
+    /// range from black to white, and still be background. Raise it for
+    /// off-white or yellowed paper.
+    pub fn set_fuzz(&mut self, fuzz: u8) {
+        self.fuzz = fuzz.min(100);
+    }
+
+    /// Marks of fewer pixels than this are noise, such as dust on the scanner
+    /// glass, and are not content.
+    pub fn set_speck_size(&mut self, speck_size: usize) {
+        self.speck_size = speck_size;
+    }
+
+    pub fn fuzz(&self) -> u8 {
+        self.fuzz
+    }
+
+    pub fn speck_size(&self) -> usize {
+        self.speck_size
+    }
+
+    /// The content of an image, or None if the image holds nothing but
+    /// background and specks.
+    pub fn detect(&self, image: &GrayImage) -> Option<ContentBounds> {
+        let mask = image.foreground_mask(image.background(), self.fuzz);
+
+        raster::connected_components(&mask, image.width()).iter()
+            .filter(|component| component.area() >= self.speck_size)
+            .map(|component| *component.bounds())
+            .fold(None, |bounds: Option<Rectangle>, component| match bounds {
+                Some(bounds) => Some(bounds.union(&component)),
+                None         => Some(component),
+            })
+            .map(|content| ContentBounds::new(content, image.dimensions()))
+    }
+
+    /// The content of a page's file, which must be a format that is decoded natively.
+    pub fn detect_page(&self, page: &Page) -> io::Result<Option<ContentBounds>> {
+        Ok(self.detect(&GrayImage::open(page.file_path())?))
+    }
+}
+
+
+/// The box around the content of a page, and the size of the page it is on.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ContentBounds {
+    content: Rectangle,
+    dimensions: ImageDimensions,
+}
+
+impl ContentBounds {
+    pub fn new(content: Rectangle, dimensions: ImageDimensions) -> ContentBounds {
+        ContentBounds {
+            content: content,
+            dimensions: dimensions,
+        }
+    }
+
+    pub fn content(&self) -> &Rectangle {
+        &self.content
+    }
+
+    pub fn dimensions(&self) -> &ImageDimensions {
+        &self.dimensions
+    }
+
+    pub fn left_margin(&self) -> Pixels {
+        self.content.left()
+    }
+
+    pub fn right_margin(&self) -> Pixels {
+        self.dimensions.x_pixels().saturating_sub(self.content.right())
+    }
+
+    pub fn top_margin(&self) -> Pixels {
+        self.content.top()
+    }
+
+    pub fn bottom_margin(&self) -> Pixels {
+        self.dimensions.y_pixels().saturating_sub(self.content.bottom())
+    }
+
+    /// The four operations trimming the page down to its content, leaving
+    /// up to `padding` pixels of background around it on every side.
+    pub fn trim_ops(&self, padding: Pixels) -> Vec<PageOps> {
+        vec![
+            PageOps::TrimLeftEdge(self.left_margin().saturating_sub(padding)),
+            PageOps::TrimRightEdge(self.right_margin().saturating_sub(padding)),
+            PageOps::TrimTopEdge(self.top_margin().saturating_sub(padding)),
+            PageOps::TrimBottomEdge(self.bottom_margin().saturating_sub(padding)),
+        ]
+    }
+}
+
+impl fmt::Display for ContentBounds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Content({} of {})", self.content, self.dimensions)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An off-white page with a block of text, and a speck of dust near a corner.
+    fn page() -> GrayImage {
+        let mut image = GrayImage::filled(40, 30, 235);
+        for y in 10..20 {
+            for x in 8..30 {
+                image.set(x, y, 20);
+            }
+        }
+        image.set(2, 27, 0);
+
+        image
+    }
+
+    #[test]
+    fn specks_below_the_size_are_not_content() {
+        let mut detection = ContentDetection::new();
+        assert_eq!(detection.detect(&page()).unwrap().content(), &Rectangle::new(2, 10, 30, 28));
+
+        detection.set_speck_size(2);
+        let bounds = detection.detect(&page()).unwrap();
+        assert_eq!(bounds.content(), &Rectangle::new(8, 10, 30, 20));
+        assert_eq!((bounds.left_margin(), bounds.right_margin()), (8, 10));
+        assert_eq!((bounds.top_margin(), bounds.bottom_margin()), (10, 10));
+    }
+
+    #[test]
+    fn paper_within_the_fuzz_is_background() {
+        let mut image = page();
+        // A shadow along the binding, a little darker than the paper.
+        for y in 0..30 {
+            image.set(39, y, 215);
+        }
+        let mut detection = ContentDetection::new();
+        detection.set_speck_size(2);
+        assert_eq!(detection.detect(&image).unwrap().right_margin(), 10);
+
+        detection.set_fuzz(5);
+        assert_eq!(detection.detect(&image).unwrap().right_margin(), 0);
+    }
+
+    #[test]
+    fn blank_pages_have_no_content() {
+        let mut detection = ContentDetection::new();
+        detection.set_speck_size(2);
+        let mut image = GrayImage::filled(40, 30, 250);
+        image.set(5, 5, 0);
+
+        assert_eq!(detection.detect(&image), None);
+    }
+
+    #[test]
+    fn trims_leave_the_padding_around_the_content() {
+        let bounds = ContentBounds::new(Rectangle::new(8, 3, 30, 20), ImageDimensions::new(40, 30));
+
+        assert_eq!(bounds.trim_ops(5), vec![
+            PageOps::TrimLeftEdge(3),
+            PageOps::TrimRightEdge(5),
+            PageOps::TrimTopEdge(0),
+            PageOps::TrimBottomEdge(5),
+        ]);
+    }
+}
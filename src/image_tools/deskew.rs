@@ -0,0 +1,191 @@
+#![allow(dead_code)]
+use std::fmt;
+use image_tools::image_ops::DeskewOptions;
+use image_tools::raster::{GrayImage, ANALYSIS_PIXELS, INK_FUZZ};
+
+
+/// The skew detected on a page: the angle its lines of text are rotated by,
+/// in degrees, clockwise as displayed.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Skew {
+    angle: f64,
+    background: u8,
+    found: bool,
+}
+
+impl Skew {
+    pub fn angle(&self) -> f64 {
+        self.angle
+    }
+
+    /// The gray level of the paper, which exposed corners are filled with.
+    pub fn background(&self) -> u8 {
+        self.background
+    }
+
+    /// Whether the page had enough ink to measure a skew at all.
+    pub fn is_found(&self) -> bool {
+        self.found
+    }
+
+    /// The rotation that straightens the page, clockwise, or None if the
+    /// skew is too small to be worth correcting.
+    pub fn correction(&self, options: &DeskewOptions) -> Option<f64> {
+        if self.found && self.angle.abs() >= options.min_angle() {
+            Some(-self.angle)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Skew {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.found {
+            write!(f, "skew of {:.2} degrees", self.angle)
+        } else {
+            write!(f, "no text to measure a skew from")
+        }
+    }
+}
+
+
+/// Find the angle that lines up the ink of a page into the sharpest rows,
+/// searching the range of the options in steps of a tenth of a degree and
+/// then refining to a hundredth. The score of an angle is the sum of squares
+/// of the projection profile, the count of ink pixels in each row once the
+/// page is sheared by the angle.
+pub fn detect_skew(image: &GrayImage, options: &DeskewOptions) -> Skew {
+    let small = image.downsample_to(ANALYSIS_PIXELS);
+    let background = image.background();
+    let mask = small.foreground_mask(small.background(), INK_FUZZ);
+
+    let points: Vec<(f64, f64)> = mask.iter().enumerate()
+        .filter(|&(_, &ink)| ink)
+        .map(|(index, _)| ((index % small.width()) as f64, (index / small.width()) as f64))
+        .collect();
+    if points.len() < small.width().max(small.height()) {
+        return Skew { angle: 0.0, background: background, found: false };
+    }
+
+    let max_angle = options.max_angle();
+    let margin = (small.width() as f64 * max_angle.to_radians().tan()).ceil() as usize + 1;
+    let mut profile = Profile {
+        points: points,
+        margin: margin,
+        bins: vec![0; small.height() + 2 * margin],
+    };
+
+    let coarse = profile.best_angle(-max_angle, max_angle, 0.1);
+    let fine = profile.best_angle((coarse - 0.1).max(-max_angle), (coarse + 0.1).min(max_angle), 0.01);
+
+    Skew {
+        angle: (fine * 100.0).round() / 100.0,
+        background: background,
+        found: true,
+    }
+}
+
+
+/// The ink of a page, projected onto rows.
+struct Profile {
+    points: Vec<(f64, f64)>,
+    margin: usize,
+    bins: Vec<u32>,
+}
+
+impl Profile {
+    fn score(&mut self, angle: f64) -> f64 {
+        let slope = angle.to_radians().tan();
+        for bin in self.bins.iter_mut() {
+            *bin = 0;
+        }
+        for &(x, y) in self.points.iter() {
+            let row = (y - x * slope).round() + self.margin as f64;
+            if row >= 0.0 && (row as usize) < self.bins.len() {
+                self.bins[row as usize] += 1;
+            }
+        }
+
+        self.bins.iter().map(|&count| count as f64 * count as f64).sum()
+    }
+
+    /// The best scoring angle from `from` to `to`. Ties go to the smaller rotation.
+    fn best_angle(&mut self, from: f64, to: f64, step: f64) -> f64 {
+        let steps = ((to - from) / step).round() as i64;
+        let mut best: Option<(f64, f64)> = None;
+        for i in 0..steps + 1 {
+            let angle = from + i as f64 * step;
+            let value = self.score(angle);
+            best = match best {
+                Some((best_value, best_angle)) if best_value > value ||
+                    (best_value == value && best_angle.abs() <= angle.abs()) => Some((best_value, best_angle)),
+                _ => Some((value, angle)),
+            };
+        }
+
+        best.map(|(_, angle)| angle).unwrap_or(0.0)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A page of eight lines of "text", each a row of short dashes, turned
+    /// clockwise by the angle.
+    fn skewed_page(angle: f64) -> GrayImage {
+        let mut image = GrayImage::filled(300, 240, 240);
+        let slope = angle.to_radians().tan();
+        for line in 0..8 {
+            for x in 20..280 {
+                if x % 12 >= 9 {
+                    continue;
+                }
+                let y = (30 + line * 25) as f64 + (x as f64 - 150.0) * slope;
+                for dy in 0..4 {
+                    image.set(x, y.round() as usize + dy, 10);
+                }
+            }
+        }
+
+        image
+    }
+
+    #[test]
+    fn skew_is_measured_clockwise() {
+        let options = DeskewOptions::new(5.0);
+        for &angle in [1.5, -0.8, 0.0].iter() {
+            let skew = detect_skew(&skewed_page(angle), &options);
+            assert!(skew.is_found());
+            assert!((skew.angle() - angle).abs() <= 0.05, "{} measured as {}", angle, skew.angle());
+            assert_eq!(skew.background(), 240);
+        }
+    }
+
+    #[test]
+    fn the_search_stays_within_the_range() {
+        let skew = detect_skew(&skewed_page(3.0), &DeskewOptions::new(1.0));
+
+        assert!(skew.angle().abs() <= 1.0);
+    }
+
+    #[test]
+    fn pages_without_ink_have_no_skew() {
+        let skew = detect_skew(&GrayImage::filled(300, 240, 250), &DeskewOptions::new(5.0));
+
+        assert!(!skew.is_found());
+        assert_eq!(skew.correction(&DeskewOptions::new(5.0)), None);
+    }
+
+    #[test]
+    fn small_skews_are_left_alone() {
+        let skew = Skew { angle: 0.3, background: 255, found: true };
+        let mut options = DeskewOptions::new(5.0);
+        assert_eq!(skew.correction(&options), Some(-0.3));
+
+        options.set_min_angle(0.5);
+        assert_eq!(skew.correction(&options), None);
+    }
+}
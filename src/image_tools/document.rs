@@ -0,0 +1,537 @@
+#![allow(dead_code)]
+use std::fmt;
+use std::time::SystemTime;
+
+
+/// An entry in a document's bookmark outline, pointing to a page of the
+/// assembled document. Page numbers count from 1.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct OutlineEntry {
+    title: String,
+    page_number: usize,
+    open: bool,
+    children: Vec<OutlineEntry>,
+}
+
+impl OutlineEntry {
+    pub fn new(title: &str, page_number: usize) -> OutlineEntry {
+        OutlineEntry {
+            title: String::from(title),
+            page_number: page_number,
+            open: true,
+            children: Vec::new(),
+        }
+    }
+
+    /// Whether the entry's children are shown when the document is opened.
+    pub fn set_open(&mut self, open: bool) {
+        self.open = open;
+    }
+
+    pub fn add_child(&mut self, child: OutlineEntry) {
+        self.children.push(child);
+    }
+
+    pub fn title(&self) -> &str {
+        self.title.as_ref()
+    }
+
+    pub fn page_number(&self) -> usize {
+        self.page_number
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn children(&self) -> &[OutlineEntry] {
+        self.children.as_ref()
+    }
+
+    fn insert_pages(&mut self, after: usize, count: usize) {
+        if self.page_number > after {
+            self.page_number += count;
+        }
+        for child in self.children.iter_mut() {
+            child.insert_pages(after, count);
+        }
+    }
+
+    fn remove_page(&mut self, page_number: usize) {
+        if self.page_number > page_number {
+            self.page_number -= 1;
+        }
+        for child in self.children.iter_mut() {
+            child.remove_page(page_number);
+        }
+    }
+
+    fn truncate_pages(&mut self, page_count: usize) {
+        self.page_number = self.page_number.min(page_count);
+        for child in self.children.iter_mut() {
+            child.truncate_pages(page_count);
+        }
+    }
+}
+
+impl fmt::Display for OutlineEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (page {}", self.title, self.page_number)?;
+        if !self.children.is_empty() {
+            write!(f, ", {} entries", self.children.len())?;
+        }
+        write!(f, ")")
+    }
+}
+
+
+/// The numbering style of a range of page labels.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum PageLabelStyle {
+    /// 1, 2, 3, ...
+    Decimal,
+    /// I, II, III, ...
+    UpperRoman,
+    /// i, ii, iii, ...
+    LowerRoman,
+    /// A to Z, then AA to ZZ, and so on.
+    UpperLetters,
+    /// a to z, then aa to zz, and so on.
+    LowerLetters,
+    /// Labels made of the prefix alone.
+    PrefixOnly,
+}
+
+fn roman_numeral(number: usize) -> String {
+    let numerals = [
+        (1000, "m"), (900, "cm"), (500, "d"), (400, "cd"), (100, "c"), (90, "xc"),
+        (50, "l"), (40, "xl"), (10, "x"), (9, "ix"), (5, "v"), (4, "iv"), (1, "i"),
+    ];
+
+    let mut remaining = number;
+    let mut numeral = String::new();
+    for &(value, letters) in numerals.iter() {
+        while remaining >= value {
+            numeral.push_str(letters);
+            remaining -= value;
+        }
+    }
+
+    numeral
+}
+
+fn letter_numeral(number: usize) -> String {
+    if number == 0 {
+        return String::new();
+    }
+    let letter = (b'a' + ((number - 1) % 26) as u8) as char;
+
+    ::std::iter::repeat_n(letter, (number - 1) / 26 + 1).collect()
+}
+
+impl PageLabelStyle {
+    /// The numeric part of a label in this style.
+    pub fn format(&self, number: usize) -> String {
+        match *self {
+            PageLabelStyle::Decimal      => format!("{}", number),
+            PageLabelStyle::UpperRoman   => roman_numeral(number).to_uppercase(),
+            PageLabelStyle::LowerRoman   => roman_numeral(number),
+            PageLabelStyle::UpperLetters => letter_numeral(number).to_uppercase(),
+            PageLabelStyle::LowerLetters => letter_numeral(number),
+            PageLabelStyle::PrefixOnly   => String::new(),
+        }
+    }
+}
+
+impl fmt::Display for PageLabelStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PageLabelStyle::Decimal      => write!(f, "decimal"),
+            PageLabelStyle::UpperRoman   => write!(f, "upper roman"),
+            PageLabelStyle::LowerRoman   => write!(f, "lower roman"),
+            PageLabelStyle::UpperLetters => write!(f, "upper letters"),
+            PageLabelStyle::LowerLetters => write!(f, "lower letters"),
+            PageLabelStyle::PrefixOnly   => write!(f, "prefix only"),
+        }
+    }
+}
+
+
+/// A range of page labels, running from its first page up to the first page
+/// of the next range. Page numbers count from 1; the label of the range's
+/// first page is its prefix followed by its start value in its style.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct PageLabelRange {
+    first_page: usize,
+    style: PageLabelStyle,
+    prefix: String,
+    start: usize,
+}
+
+impl PageLabelRange {
+    pub fn new(first_page: usize, style: PageLabelStyle) -> PageLabelRange {
+        PageLabelRange {
+            first_page: first_page,
+            style: style,
+            prefix: String::new(),
+            start: 1,
+        }
+    }
+
+    pub fn set_prefix(&mut self, prefix: &str) {
+        self.prefix = String::from(prefix);
+    }
+
+    pub fn set_start(&mut self, start: usize) {
+        self.start = start;
+    }
+
+    pub fn first_page(&self) -> usize {
+        self.first_page
+    }
+
+    pub fn style(&self) -> PageLabelStyle {
+        self.style
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_ref()
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The label of a page in the range.
+    pub fn label(&self, page_number: usize) -> String {
+        let number = self.start + page_number.saturating_sub(self.first_page);
+        format!("{}{}", self.prefix, self.style.format(number))
+    }
+}
+
+impl fmt::Display for PageLabelRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "pages from {}: {} from {}", self.first_page, self.style, self.start)?;
+        if !self.prefix.is_empty() {
+            write!(f, " prefixed \"{}\"", self.prefix)?;
+        }
+
+        Ok(())
+    }
+}
+
+
+/// Document level information for an assembled document: the fields of a
+/// PDF Info dictionary and its bookmark outline. Every field is optional.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct DocumentMetadata {
+    title: Option<String>,
+    author: Option<String>,
+    subject: Option<String>,
+    keywords: Vec<String>,
+    creator: Option<String>,
+    producer: Option<String>,
+    creation_date: Option<SystemTime>,
+    modification_date: Option<SystemTime>,
+    outline: Vec<OutlineEntry>,
+    page_labels: Vec<PageLabelRange>,
+}
+
+impl Default for DocumentMetadata {
+    fn default() -> DocumentMetadata {
+        DocumentMetadata::new()
+    }
+}
+
+impl DocumentMetadata {
+    pub fn new() -> DocumentMetadata {
+        DocumentMetadata {
+            title: None,
+            author: None,
+            subject: None,
+            keywords: Vec::new(),
+            creator: None,
+            producer: None,
+            creation_date: None,
+            modification_date: None,
+            outline: Vec::new(),
+            page_labels: Vec::new(),
+        }
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.title = Some(String::from(title));
+    }
+
+    pub fn set_author(&mut self, author: &str) {
+        self.author = Some(String::from(author));
+    }
+
+    pub fn set_subject(&mut self, subject: &str) {
+        self.subject = Some(String::from(subject));
+    }
+
+    pub fn add_keyword(&mut self, keyword: &str) {
+        self.keywords.push(String::from(keyword));
+    }
+
+    /// The application that created the original document, such as the scanning software.
+    pub fn set_creator(&mut self, creator: &str) {
+        self.creator = Some(String::from(creator));
+    }
+
+    /// The application that produced the PDF file.
+    pub fn set_producer(&mut self, producer: &str) {
+        self.producer = Some(String::from(producer));
+    }
+
+    pub fn set_creation_date(&mut self, date: SystemTime) {
+        self.creation_date = Some(date);
+    }
+
+    pub fn set_modification_date(&mut self, date: SystemTime) {
+        self.modification_date = Some(date);
+    }
+
+    pub fn add_outline_entry(&mut self, entry: OutlineEntry) {
+        self.outline.push(entry);
+    }
+
+    /// Add a range of page labels. A range starting on the same page as an
+    /// existing one replaces it.
+    pub fn add_page_label_range(&mut self, range: PageLabelRange) {
+        self.page_labels.retain(|existing| existing.first_page() != range.first_page());
+        self.page_labels.push(range);
+        self.page_labels.sort_by_key(|range| range.first_page());
+    }
+
+    /// Make room for pages inserted after a page, moving the outline entries
+    /// and page label ranges of the pages that follow it.
+    pub fn insert_pages(&mut self, after: usize, count: usize) {
+        for entry in self.outline.iter_mut() {
+            entry.insert_pages(after, count);
+        }
+        for range in self.page_labels.iter_mut() {
+            if range.first_page > after {
+                range.first_page += count;
+            }
+        }
+    }
+
+    /// Close up the gap left by a page removed from the document, moving the
+    /// outline entries and page label ranges of the pages that follow it
+    /// back by one. Entries that pointed at the removed page point at the
+    /// page that followed it, and a label range that held only the removed
+    /// page is dropped.
+    pub fn remove_page(&mut self, page_number: usize) {
+        for entry in self.outline.iter_mut() {
+            entry.remove_page(page_number);
+        }
+
+        let next_page_starts_range = self.page_labels.iter().any(|range| range.first_page == page_number + 1);
+        if next_page_starts_range {
+            self.page_labels.retain(|range| range.first_page != page_number);
+        }
+        for range in self.page_labels.iter_mut() {
+            if range.first_page > page_number {
+                range.first_page -= 1;
+            }
+        }
+    }
+
+    /// Keep the outline and page labels within a document of the given
+    /// number of pages, once pages at its end have been removed. Entries past
+    /// the end point at the last page, and label ranges starting past it are dropped.
+    pub fn truncate_pages(&mut self, page_count: usize) {
+        for entry in self.outline.iter_mut() {
+            entry.truncate_pages(page_count);
+        }
+        self.page_labels.retain(|range| range.first_page <= page_count);
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_ref().map(|title| title.as_ref())
+    }
+
+    pub fn author(&self) -> Option<&str> {
+        self.author.as_ref().map(|author| author.as_ref())
+    }
+
+    pub fn subject(&self) -> Option<&str> {
+        self.subject.as_ref().map(|subject| subject.as_ref())
+    }
+
+    pub fn keywords(&self) -> &[String] {
+        self.keywords.as_ref()
+    }
+
+    pub fn creator(&self) -> Option<&str> {
+        self.creator.as_ref().map(|creator| creator.as_ref())
+    }
+
+    pub fn producer(&self) -> Option<&str> {
+        self.producer.as_ref().map(|producer| producer.as_ref())
+    }
+
+    pub fn creation_date(&self) -> Option<SystemTime> {
+        self.creation_date
+    }
+
+    pub fn modification_date(&self) -> Option<SystemTime> {
+        self.modification_date
+    }
+
+    pub fn outline(&self) -> &[OutlineEntry] {
+        self.outline.as_ref()
+    }
+
+    /// The page label ranges, in page order.
+    pub fn page_labels(&self) -> &[PageLabelRange] {
+        self.page_labels.as_ref()
+    }
+
+    /// The label of a page, if page labels are defined for it.
+    pub fn page_label(&self, page_number: usize) -> Option<String> {
+        self.page_labels.iter().rfind(|range| range.first_page() <= page_number)
+            .map(|range| range.label(page_number))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == DocumentMetadata::new()
+    }
+}
+
+impl fmt::Display for DocumentMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DocumentMetadata(")?;
+        match self.title {
+            Some(ref title) => write!(f, "\"{}\"", title)?,
+            None            => write!(f, "untitled")?,
+        }
+        if let Some(ref author) = self.author {
+            write!(f, " by {}", author)?;
+        }
+        write!(f, ", {} outline entries)", self.outline.len())
+    }
+}
+
+
+/// A calendar date and time in UTC.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct UtcDateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl UtcDateTime {
+    /// The UTC calendar date of a system time. Times before 1970 are taken as
+    /// the start of 1970.
+    pub fn from_system_time(time: SystemTime) -> UtcDateTime {
+        let seconds = match time.duration_since(::std::time::UNIX_EPOCH) {
+            Ok(elapsed) => elapsed.as_secs() as i64,
+            Err(_)      => 0,
+        };
+        let days = seconds / 86400;
+        let remainder = seconds % 86400;
+
+        // Days since 1970-01-01 to a proleptic Gregorian date, counting in
+        // 400 year eras that start on the 1st of March.
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let day_of_era = z - era * 146097;
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        UtcDateTime {
+            year: year,
+            month: month as u32,
+            day: day as u32,
+            hour: (remainder / 3600) as u32,
+            minute: (remainder % 3600 / 60) as u32,
+            second: (remainder % 60) as u32,
+        }
+    }
+}
+
+impl fmt::Display for UtcDateTime {
+    /// ISO 8601, as used in XMP.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+               self.year, self.month, self.day, self.hour, self.minute, self.second)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roman_and_letter_numerals() {
+        let roman: Vec<String> = [1, 4, 9, 14, 40, 90, 400, 1994].iter().map(|n| roman_numeral(*n)).collect();
+        assert_eq!(roman, vec!["i", "iv", "ix", "xiv", "xl", "xc", "cd", "mcmxciv"]);
+        let letters: Vec<String> = [1, 26, 27, 53].iter().map(|n| letter_numeral(*n)).collect();
+        assert_eq!(letters, vec!["a", "z", "aa", "aaa"]);
+        assert_eq!(PageLabelStyle::UpperRoman.format(12), "XII");
+        assert_eq!(PageLabelStyle::UpperLetters.format(28), "BB");
+    }
+
+    #[test]
+    fn pages_are_labelled_by_the_range_they_fall_in() {
+        let mut metadata = DocumentMetadata::new();
+        let mut cover = PageLabelRange::new(1, PageLabelStyle::PrefixOnly);
+        cover.set_prefix("Cover");
+        let mut appendix = PageLabelRange::new(9, PageLabelStyle::Decimal);
+        appendix.set_prefix("A-");
+        appendix.set_start(3);
+        metadata.add_page_label_range(cover);
+        metadata.add_page_label_range(PageLabelRange::new(2, PageLabelStyle::LowerRoman));
+        metadata.add_page_label_range(PageLabelRange::new(6, PageLabelStyle::Decimal));
+        metadata.add_page_label_range(appendix);
+
+        let labels: Vec<String> = (1..11).map(|page| metadata.page_label(page).unwrap()).collect();
+        assert_eq!(labels, vec!["Cover", "i", "ii", "iii", "iv", "1", "2", "3", "A-3", "A-4"]);
+        assert_eq!(DocumentMetadata::new().page_label(1), None);
+    }
+
+    #[test]
+    fn removing_a_page_moves_later_entries_and_ranges_back() {
+        let mut metadata = DocumentMetadata::new();
+        metadata.add_outline_entry(OutlineEntry::new("Preface", 1));
+        metadata.add_outline_entry(OutlineEntry::new("Blank", 3));
+        metadata.add_outline_entry(OutlineEntry::new("Chapter 1", 4));
+        metadata.add_page_label_range(PageLabelRange::new(1, PageLabelStyle::LowerRoman));
+        metadata.add_page_label_range(PageLabelRange::new(3, PageLabelStyle::PrefixOnly));
+        metadata.add_page_label_range(PageLabelRange::new(4, PageLabelStyle::Decimal));
+
+        metadata.remove_page(3);
+
+        let pages: Vec<usize> = metadata.outline().iter().map(|entry| entry.page_number()).collect();
+        assert_eq!(pages, vec![1, 3, 3]);
+        let ranges: Vec<usize> = metadata.page_labels().iter().map(|range| range.first_page()).collect();
+        assert_eq!(ranges, vec![1, 3]);
+        assert_eq!(metadata.page_labels()[1].style(), PageLabelStyle::Decimal);
+    }
+
+    #[test]
+    fn truncating_keeps_entries_within_the_document() {
+        let mut metadata = DocumentMetadata::new();
+        metadata.add_outline_entry(OutlineEntry::new("Back cover", 5));
+        metadata.add_page_label_range(PageLabelRange::new(1, PageLabelStyle::Decimal));
+        metadata.add_page_label_range(PageLabelRange::new(5, PageLabelStyle::PrefixOnly));
+
+        metadata.remove_page(5);
+        metadata.truncate_pages(4);
+
+        assert_eq!(metadata.outline()[0].page_number(), 4);
+        assert_eq!(metadata.page_labels().len(), 1);
+    }
+}
@@ -1,17 +1,21 @@
 #![allow(dead_code)]
 use std::io;
-use std::error::Error;
 use std::result::Result;
 use std::iter::{Iterator, IntoIterator};
 use std::collections::HashMap;
-use std::collections::hash_map;
 use std::vec::Vec;
 use std::vec;
 use std::cmp::{Eq, PartialEq};
 use std::hash::{Hash, Hasher};
 use std::convert::From;
+use std::path::Path;
 use std::slice;
 use std::fmt;
+use image_tools::cancellation::{self, CancellationToken};
+use image_tools::document::DocumentMetadata;
+use image_tools::multipage::TiffCompression;
+use image_tools::raster::GrayImage;
+use image_tools::blank::{BlankPages, BlankDecision};
 
 
 pub type Pixels = usize;
@@ -55,21 +59,587 @@ pub enum ImageFileFormat {
     TIFF,
     PNG,
     JPEG,
+    JPEG2000,
+    WEBP,
+    BMP,
+    GIF,
+    PNM,
+    PDF,
     UNKNOWN,
 }
 
+impl ImageFileFormat {
+    /// The usual file name extension for the format.
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            ImageFileFormat::TIFF     => "tif",
+            ImageFileFormat::PNG      => "png",
+            ImageFileFormat::JPEG     => "jpg",
+            ImageFileFormat::JPEG2000 => "jp2",
+            ImageFileFormat::WEBP     => "webp",
+            ImageFileFormat::BMP      => "bmp",
+            ImageFileFormat::GIF      => "gif",
+            ImageFileFormat::PNM      => "pnm",
+            ImageFileFormat::PDF      => "pdf",
+            ImageFileFormat::UNKNOWN  => "",
+        }
+    }
+}
+
 impl fmt::Display for ImageFileFormat {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            ImageFileFormat::TIFF    => "TIFF".fmt(f),
-            ImageFileFormat::PNG     => "PNG".fmt(f),
-            ImageFileFormat::JPEG    => "JPEG".fmt(f),
-            ImageFileFormat::UNKNOWN => "UNKNOWN FORMAT".fmt(f),
+            ImageFileFormat::TIFF     => "TIFF".fmt(f),
+            ImageFileFormat::PNG      => "PNG".fmt(f),
+            ImageFileFormat::JPEG     => "JPEG".fmt(f),
+            ImageFileFormat::JPEG2000 => "JPEG 2000".fmt(f),
+            ImageFileFormat::WEBP     => "WebP".fmt(f),
+            ImageFileFormat::BMP      => "BMP".fmt(f),
+            ImageFileFormat::GIF      => "GIF".fmt(f),
+            ImageFileFormat::PNM      => "PNM".fmt(f),
+            ImageFileFormat::PDF      => "PDF".fmt(f),
+            ImageFileFormat::UNKNOWN  => "UNKNOWN FORMAT".fmt(f),
+        }
+    }
+}
+
+
+/// How the chroma of a JPEG file is sampled relative to its luma.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ChromaSubsampling {
+    Yuv444,
+    Yuv422,
+    Yuv420,
+}
+
+impl fmt::Display for ChromaSubsampling {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChromaSubsampling::Yuv444 => "4:4:4".fmt(f),
+            ChromaSubsampling::Yuv422 => "4:2:2".fmt(f),
+            ChromaSubsampling::Yuv420 => "4:2:0".fmt(f),
+        }
+    }
+}
+
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct JpegOptions {
+    quality: u8,
+    subsampling: ChromaSubsampling,
+}
+
+impl JpegOptions {
+    /// Options for a quality between 1 and 100, with 4:2:0 chroma subsampling.
+    pub fn new(quality: u8) -> JpegOptions {
+        JpegOptions {
+            quality: quality.clamp(1, 100),
+            subsampling: ChromaSubsampling::Yuv420,
+        }
+    }
+
+    pub fn set_subsampling(&mut self, subsampling: ChromaSubsampling) {
+        self.subsampling = subsampling;
+    }
+
+    pub fn quality(&self) -> u8 {
+        self.quality
+    }
+
+    pub fn subsampling(&self) -> ChromaSubsampling {
+        self.subsampling
+    }
+}
+
+impl fmt::Display for JpegOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "quality {}, {}", self.quality, self.subsampling)
+    }
+}
+
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct PngOptions {
+    compression_level: u8,
+    bit_depth: Option<u8>,
+}
+
+impl PngOptions {
+    /// Options for a zlib compression level between 0 and 9, keeping the
+    /// bit depth of the image.
+    pub fn new(compression_level: u8) -> PngOptions {
+        PngOptions {
+            compression_level: compression_level.min(9),
+            bit_depth: None,
+        }
+    }
+
+    /// The bit depth of the samples written: 1, 2, 4, 8 or 16.
+    pub fn set_bit_depth(&mut self, bit_depth: u8) {
+        self.bit_depth = Some(bit_depth);
+    }
+
+    pub fn compression_level(&self) -> u8 {
+        self.compression_level
+    }
+
+    pub fn bit_depth(&self) -> Option<u8> {
+        self.bit_depth
+    }
+}
+
+impl fmt::Display for PngOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.bit_depth {
+            Some(bits) => write!(f, "level {}, {} bit", self.compression_level, bits),
+            None       => write!(f, "level {}", self.compression_level),
+        }
+    }
+}
+
+
+/// The format a page is converted to, with the options for writing it.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum OutputFormat {
+    Tiff(TiffCompression),
+    Jpeg(JpegOptions),
+    Png(PngOptions),
+    Jpeg2000,
+    WebP,
+    Bmp,
+    Gif,
+    Pnm,
+}
+
+impl OutputFormat {
+    pub fn file_format(&self) -> ImageFileFormat {
+        match *self {
+            OutputFormat::Tiff(_)  => ImageFileFormat::TIFF,
+            OutputFormat::Jpeg(_)  => ImageFileFormat::JPEG,
+            OutputFormat::Png(_)   => ImageFileFormat::PNG,
+            OutputFormat::Jpeg2000 => ImageFileFormat::JPEG2000,
+            OutputFormat::WebP     => ImageFileFormat::WEBP,
+            OutputFormat::Bmp      => ImageFileFormat::BMP,
+            OutputFormat::Gif      => ImageFileFormat::GIF,
+            OutputFormat::Pnm      => ImageFileFormat::PNM,
+        }
+    }
+
+    /// The extension of the file a page converted to the format is written to.
+    pub fn extension(&self) -> &'static str {
+        self.file_format().extension()
+    }
+
+    /// Whether writing the format can change the pixels. GIF reduces the
+    /// image to a palette, Group 4 to black and white, and a PNG bit depth
+    /// may be lower than the image's.
+    pub fn is_lossy(&self) -> bool {
+        match *self {
+            OutputFormat::Tiff(TiffCompression::Jpeg)   => true,
+            OutputFormat::Tiff(TiffCompression::Group4) => true,
+            OutputFormat::Tiff(_)                       => false,
+            OutputFormat::Png(ref options)              => options.bit_depth.is_some(),
+            OutputFormat::Bmp | OutputFormat::Pnm       => false,
+            _                                           => true,
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OutputFormat::Tiff(compression) => write!(f, "TIFF ({})", compression),
+            OutputFormat::Jpeg(ref options) => write!(f, "JPEG ({})", options),
+            OutputFormat::Png(ref options)  => write!(f, "PNG ({})", options),
+            _                               => self.file_format().fmt(f),
+        }
+    }
+}
+
+
+/// The parameters of Sauvola's method: the side of the square neighbourhood
+/// each pixel's threshold is taken from, and how strongly the neighbourhood's
+/// deviation lowers the threshold.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SauvolaParameters {
+    window: Pixels,
+    k_thousandths: u32,
+}
+
+impl SauvolaParameters {
+    pub fn new(window: Pixels, k: f64) -> SauvolaParameters {
+        SauvolaParameters {
+            window: window.max(1),
+            k_thousandths: (k.max(0.0) * 1000.0).round() as u32,
+        }
+    }
+
+    pub fn window(&self) -> Pixels {
+        self.window
+    }
+
+    pub fn k(&self) -> f64 {
+        self.k_thousandths as f64 / 1000.0
+    }
+}
+
+impl fmt::Display for SauvolaParameters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} Pixel window, k = {}", self.window, self.k())
+    }
+}
+
+
+/// How a page is reduced to black and white.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum ThresholdMethod {
+    /// One threshold for the whole page, as a percentage of white.
+    Fixed(u8),
+    /// One threshold for the whole page, chosen from its histogram by Otsu's method.
+    Otsu,
+    /// A threshold for each pixel from the mean and deviation of its
+    /// neighbourhood, which copes with uneven lighting and stained paper.
+    Sauvola(SauvolaParameters),
+}
+
+impl fmt::Display for ThresholdMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ThresholdMethod::Fixed(percent)     => write!(f, "Fixed({}%)", percent),
+            ThresholdMethod::Otsu               => write!(f, "Otsu"),
+            ThresholdMethod::Sauvola(ref params) => write!(f, "Sauvola({})", params),
+        }
+    }
+}
+
+
+/// A reduction of the colours a page is stored with.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum ColorReduction {
+    Grayscale,
+    Bilevel(ThresholdMethod),
+    /// Indexed colour, with at most the given number of colours.
+    Palette(usize),
+}
+
+impl fmt::Display for ColorReduction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ColorReduction::Grayscale              => write!(f, "Grayscale"),
+            ColorReduction::Bilevel(ref threshold) => write!(f, "Bilevel({})", threshold),
+            ColorReduction::Palette(colors)        => write!(f, "Palette({} colors)", colors),
+        }
+    }
+}
+
+
+/// Settings for straightening a page.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct DeskewOptions {
+    max_angle_hundredths: u32,
+    min_angle_hundredths: u32,
+}
+
+impl DeskewOptions {
+    /// Search for a skew of up to `max_angle` degrees either way. Skews of
+    /// less than 0.05 degrees are left alone.
+    pub fn new(max_angle: f64) -> DeskewOptions {
+        DeskewOptions {
+            max_angle_hundredths: (max_angle.abs().min(45.0) * 100.0).round() as u32,
+            min_angle_hundredths: 5,
+        }
+    }
+
+    /// The smallest skew, in degrees, worth resampling the page for.
+    pub fn set_min_angle(&mut self, min_angle: f64) {
+        self.min_angle_hundredths = (min_angle.abs() * 100.0).round() as u32;
+    }
+
+    pub fn max_angle(&self) -> f64 {
+        self.max_angle_hundredths as f64 / 100.0
+    }
+
+    pub fn min_angle(&self) -> f64 {
+        self.min_angle_hundredths as f64 / 100.0
+    }
+}
+
+impl fmt::Display for DeskewOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "up to {} degrees", self.max_angle())
+    }
+}
+
+
+/// A rotation by a whole number of quarter turns, clockwise as displayed.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum QuarterTurn {
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl QuarterTurn {
+    pub fn degrees(&self) -> u32 {
+        match *self {
+            QuarterTurn::Rotate90  => 90,
+            QuarterTurn::Rotate180 => 180,
+            QuarterTurn::Rotate270 => 270,
+        }
+    }
+
+    /// The quarter turn equal to a rotation, which may be negative or more
+    /// than a whole turn, or None if it is not a quarter turn or does nothing.
+    pub fn from_degrees(degrees: i64) -> Option<QuarterTurn> {
+        match degrees.rem_euclid(360) {
+            90  => Some(QuarterTurn::Rotate90),
+            180 => Some(QuarterTurn::Rotate180),
+            270 => Some(QuarterTurn::Rotate270),
+            _   => None,
+        }
+    }
+
+    /// The quarter turn that undoes this one.
+    pub fn reverse(&self) -> QuarterTurn {
+        match *self {
+            QuarterTurn::Rotate90  => QuarterTurn::Rotate270,
+            QuarterTurn::Rotate180 => QuarterTurn::Rotate180,
+            QuarterTurn::Rotate270 => QuarterTurn::Rotate90,
+        }
+    }
+
+    pub fn swaps_dimensions(&self) -> bool {
+        *self != QuarterTurn::Rotate180
+    }
+}
+
+impl fmt::Display for QuarterTurn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} degrees", self.degrees())
+    }
+}
+
+
+/// What fills the parts of a page that an operation exposes.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum Fill {
+    /// The colour of the page's paper, detected when the operation runs.
+    Paper,
+    Gray(u8),
+}
+
+impl fmt::Display for Fill {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Fill::Paper      => write!(f, "paper"),
+            Fill::Gray(gray) => write!(f, "gray({})", gray),
+        }
+    }
+}
+
+
+/// A rotation by any angle, clockwise as displayed, to a hundredth of a
+/// degree. The page grows to hold all of the rotated image.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub struct AngleRotation {
+    hundredths: i64,
+    fill: Fill,
+}
+
+impl AngleRotation {
+    pub fn new(degrees: f64, fill: Fill) -> AngleRotation {
+        AngleRotation {
+            hundredths: (degrees * 100.0).round() as i64,
+            fill: fill,
+        }
+    }
+
+    pub fn degrees(&self) -> f64 {
+        self.hundredths as f64 / 100.0
+    }
+
+    pub fn fill(&self) -> Fill {
+        self.fill
+    }
+
+    /// The size of a page of the given size once rotated.
+    pub fn rotated_dimensions(&self, dimensions: &ImageDimensions) -> ImageDimensions {
+        let (sin, cos) = self.degrees().to_radians().sin_cos();
+        let (x, y) = (dimensions.x_pixels() as f64, dimensions.y_pixels() as f64);
+        // Rounded first, so that exact quarter turns are not grown by a pixel.
+        let size = |value: f64| ((value * 1000.0).round() / 1000.0).ceil() as Pixels;
+
+        ImageDimensions::new(size(x * cos.abs() + y * sin.abs()), size(x * sin.abs() + y * cos.abs()))
+    }
+}
+
+impl fmt::Display for AngleRotation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} degrees, {} fill", self.degrees(), self.fill)
+    }
+}
+
+
+/// What is done with the dark borders found along the edges of a page.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum BorderAction {
+    /// Cut the borders off, making the page smaller.
+    Trim,
+    /// Paint the borders the colour of the paper, keeping the page's size.
+    Paint,
+}
+
+impl fmt::Display for BorderAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BorderAction::Trim  => write!(f, "Trim"),
+            BorderAction::Paint => write!(f, "Paint"),
         }
     }
 }
 
 
+/// Settings for removing the dark bands a scanner or photocopier leaves
+/// along the edges of a page. A row or column at an edge is part of a
+/// border when enough of its pixels are dark; the border ends at the first
+/// one that is not.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct BorderRemoval {
+    action: BorderAction,
+    darkness: u8,
+    coverage: u8,
+    max_width: u8,
+}
+
+impl BorderRemoval {
+    /// By default pixels of gray level 64 or darker are dark, a line that is
+    /// at least half dark belongs to a border, and no border is wider than a
+    /// quarter of the page.
+    pub fn new(action: BorderAction) -> BorderRemoval {
+        BorderRemoval {
+            action: action,
+            darkness: 64,
+            coverage: 50,
+            max_width: 25,
+        }
+    }
+
+    /// The gray level at or below which a pixel is dark.
+    pub fn set_darkness(&mut self, darkness: u8) {
+        self.darkness = darkness;
+    }
+
+    /// The percentage of a line's pixels that must be dark for it to be part of a border.
+    pub fn set_coverage(&mut self, coverage: u8) {
+        self.coverage = coverage.clamp(1, 100);
+    }
+
+    /// The widest a border can be, as a percentage of the page's width or
+    /// height, so that a dark picture running off the page is not taken for one.
+    pub fn set_max_width(&mut self, max_width: u8) {
+        self.max_width = max_width.min(50);
+    }
+
+    pub fn action(&self) -> BorderAction {
+        self.action
+    }
+
+    pub fn darkness(&self) -> u8 {
+        self.darkness
+    }
+
+    pub fn coverage(&self) -> u8 {
+        self.coverage
+    }
+
+    pub fn max_width(&self) -> u8 {
+        self.max_width
+    }
+}
+
+impl fmt::Display for BorderRemoval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, gray {} or darker over {}% of a line, up to {}% of the page",
+               self.action, self.darkness, self.coverage, self.max_width)
+    }
+}
+
+
+/// A filter removing noise from a scanned page. Ink means pixels darker
+/// than the paper.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum NoiseFilter {
+    /// Merge marks and holes of fewer pixels than this into what surrounds them.
+    Specks(usize),
+    /// Replace each pixel with the median of the square window of this size around it.
+    Median(Pixels),
+    /// Open the ink with a disk of this radius, removing marks thinner than it.
+    Open(Pixels),
+    /// Close the ink with a disk of this radius, filling holes and gaps narrower than it.
+    Close(Pixels),
+}
+
+impl fmt::Display for NoiseFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NoiseFilter::Specks(size)  => write!(f, "Specks(under {} pixels)", size),
+            NoiseFilter::Median(size)  => write!(f, "Median({}x{})", size, size),
+            NoiseFilter::Open(radius)  => write!(f, "Open(radius {})", radius),
+            NoiseFilter::Close(radius) => write!(f, "Close(radius {})", radius),
+        }
+    }
+}
+
+
+/// The size of a page's file before and after an operation.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SizeReduction {
+    file_path: FilePath,
+    before: u64,
+    after: u64,
+}
+
+impl SizeReduction {
+    pub fn new(file_path: FilePath, before: u64, after: u64) -> SizeReduction {
+        SizeReduction {
+            file_path: file_path,
+            before: before,
+            after: after,
+        }
+    }
+
+    pub fn file_path(&self) -> &FilePath {
+        &self.file_path
+    }
+
+    pub fn before(&self) -> u64 {
+        self.before
+    }
+
+    pub fn after(&self) -> u64 {
+        self.after
+    }
+
+    /// The bytes saved, zero if the file grew.
+    pub fn saved(&self) -> u64 {
+        self.before.saturating_sub(self.after)
+    }
+}
+
+impl fmt::Display for SizeReduction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} bytes -> {} bytes", self.file_path, self.before, self.after)?;
+        if self.before > 0 && self.after <= self.before {
+            write!(f, " ({:.1}% smaller)", (self.before - self.after) as f64 / self.before as f64 * 100.0)?;
+        } else if self.before > 0 {
+            write!(f, " ({:.1}% larger)", (self.after - self.before) as f64 / self.before as f64 * 100.0)?;
+        }
+
+        Ok(())
+    }
+}
+
+
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
 pub struct ImageDimensions {
     x_pixels: Pixels,
@@ -77,12 +647,20 @@ pub struct ImageDimensions {
 }
 
 impl ImageDimensions {
-    fn new(x: Pixels, y: Pixels) -> ImageDimensions {
+    pub fn new(x: Pixels, y: Pixels) -> ImageDimensions {
         ImageDimensions {
             x_pixels: x,
             y_pixels: y,
         }
     }
+
+    pub fn x_pixels(&self) -> Pixels {
+        self.x_pixels
+    }
+
+    pub fn y_pixels(&self) -> Pixels {
+        self.y_pixels
+    }
 }
 
 impl fmt::Display for ImageDimensions {
@@ -100,12 +678,20 @@ pub struct ImageResolution {
 }
 
 impl ImageResolution {
-    fn new(amount: usize, units: ResolutionUnits) -> ImageResolution {
+    pub fn new(amount: usize, units: ResolutionUnits) -> ImageResolution {
         ImageResolution {
             amount: amount,
             units: units,
         }
     }
+
+    pub fn amount(&self) -> Pixels {
+        self.amount
+    }
+
+    pub fn units(&self) -> &ResolutionUnits {
+        &self.units
+    }
 }
 
 impl fmt::Display for ImageResolution {
@@ -131,6 +717,37 @@ pub enum PageOps {
     TrimTopEdge(Pixels),
     TrimBottomEdge(Pixels),
     SetResolution(ImageResolution),
+    /// Write the page in another format, to a file beside it with the format's
+    /// extension. The original file is left in place.
+    ConvertFormat(OutputFormat),
+    ReduceColors(ColorReduction),
+    /// Detect how far the page is rotated and rotate it back. The detected
+    /// angle is reported in the operation's results.
+    Deskew(DeskewOptions),
+    Rotate(QuarterTurn),
+    RotateByAngle(AngleRotation),
+    /// Mirror the page: left to right for Horizontal, top to bottom for Vertical.
+    Flip(Direction),
+    /// Find dark borders along the edges of the page and trim or paint them.
+    /// The width found at each edge is reported in the operation's results.
+    RemoveBorders(BorderRemoval),
+    /// Filter noise out of the page. The number of marks and holes removed
+    /// is reported in the operation's results.
+    Despeckle(NoiseFilter),
+}
+
+impl PageOps {
+    /// An operation is destructive if it changes the pixels of a page, so that
+    /// applying it a second time gives a different result than applying it once.
+    pub fn is_destructive(&self) -> bool {
+        match *self {
+            PageOps::NoOperation               => false,
+            PageOps::Identify(_, _)            => false,
+            PageOps::SetResolution(_)          => false,
+            PageOps::ConvertFormat(ref format) => format.is_lossy(),
+            _                                  => true,
+        }
+    }
 }
 
 pub trait ElementaryPageOperations {
@@ -145,6 +762,14 @@ pub trait ElementaryPageOperations {
     fn trim_top_edge(amount: Pixels)                 -> Self;
     fn trim_bottom_edge(amount: Pixels)              -> Self;
     fn set_resolution(res: ImageResolution)          -> Self;
+    fn convert_format(format: OutputFormat)          -> Self;
+    fn reduce_colors(reduction: ColorReduction)      -> Self;
+    fn deskew(options: DeskewOptions)                -> Self;
+    fn rotate(turn: QuarterTurn)                     -> Self;
+    fn rotate_by_angle(rotation: AngleRotation)      -> Self;
+    fn flip(dir: Direction)                          -> Self;
+    fn remove_borders(removal: BorderRemoval)        -> Self;
+    fn despeckle(filter: NoiseFilter)                -> Self;
     fn no_operation()                                -> Self;
 }
 
@@ -165,6 +790,14 @@ impl fmt::Display for PageOps {
             PageOps::TrimTopEdge(pixels)                    => write!(f, "TrimTopEdge({} Pixels)", pixels),
             PageOps::TrimBottomEdge(pixels)                 => write!(f, "TrimBottomEdge({} Pixels)", pixels),
             PageOps::SetResolution(ref res)                 => write!(f, "SetResolution({})", res),
+            PageOps::ConvertFormat(ref format)              => write!(f, "ConvertFormat({})", format),
+            PageOps::ReduceColors(ref reduction)            => write!(f, "ReduceColors({})", reduction),
+            PageOps::Deskew(ref options)                    => write!(f, "Deskew({})", options),
+            PageOps::Rotate(turn)                           => write!(f, "Rotate({})", turn),
+            PageOps::RotateByAngle(rotation)                => write!(f, "RotateByAngle({})", rotation),
+            PageOps::Flip(ref dir)                          => write!(f, "Flip({})", dir),
+            PageOps::RemoveBorders(ref removal)             => write!(f, "RemoveBorders({})", removal),
+            PageOps::Despeckle(filter)                      => write!(f, "Despeckle({})", filter),
         }
     }
 }
@@ -172,9 +805,43 @@ impl fmt::Display for PageOps {
 
 pub trait RunOperation {
     fn run_operation(op: Self) -> OperationResults;
+
+    /// Run an operation that can be interrupted by a cancellation token. Operations
+    /// that spawn processes should override this so running processes are killed
+    /// when the token is cancelled. The default only checks the token before starting.
+    fn run_operation_with_cancel(op: Self, token: &CancellationToken) -> OperationResults where Self: Sized {
+        if token.is_cancelled() {
+            OperationResults::aborted()
+        } else {
+            Self::run_operation(op)
+        }
+    }
+
+    /// Read a page's file as a grayscale image, for the analyses made while a
+    /// plan runs. The default decodes only the formats GrayImage reads
+    /// natively; backends that can convert other formats should override it.
+    fn gray_image(_op: &Self, file_path: &FilePath, _token: &CancellationToken) -> io::Result<GrayImage>
+        where Self: Sized
+    {
+        GrayImage::open(file_path)
+    }
 }
 
-trait CompileOperation<OpType, Op> {
+/// Operations that act on an image file can be pointed at a different file than
+/// the one they were built for, such as the page they were planned for, or a 
+/// working copy of it.
+pub trait RetargetOperation {
+    fn retarget(op: Self, file_path: &FilePath) -> Self;
+
+    /// The file the operation leaves its output in when run on a file. Most
+    /// operations rewrite the file in place; a format conversion writes a new
+    /// file with the format's extension.
+    fn output_path(_op: &Self, file_path: &FilePath) -> FilePath {
+        file_path.clone()
+    }
+}
+
+pub trait CompileOperation<OpType, Op> {
     fn compile_operation(op: OpType) -> Op;
 }
 
@@ -192,6 +859,14 @@ impl<Op> CompileOperation<PageOps, Op> for Op where Op: ElementaryPageOperations
             PageOps::TrimTopEdge(amount)      => Op::trim_top_edge(amount),
             PageOps::TrimBottomEdge(amount)   => Op::trim_bottom_edge(amount),
             PageOps::SetResolution(res)       => Op::set_resolution(res),
+            PageOps::ConvertFormat(format)    => Op::convert_format(format),
+            PageOps::ReduceColors(reduction)  => Op::reduce_colors(reduction),
+            PageOps::Deskew(options)          => Op::deskew(options),
+            PageOps::Rotate(turn)             => Op::rotate(turn),
+            PageOps::RotateByAngle(rotation)  => Op::rotate_by_angle(rotation),
+            PageOps::Flip(dir)                => Op::flip(dir),
+            PageOps::RemoveBorders(removal)   => Op::remove_borders(removal),
+            PageOps::Despeckle(filter)        => Op::despeckle(filter),
             PageOps::NoOperation              => Op::no_operation(),
         }
     }
@@ -202,7 +877,7 @@ impl<Op> CompileOperation<PageOps, Op> for Op where Op: ElementaryPageOperations
 /// page operation in sequence, so that more complicated page operations
 /// may be constructed in terms of the elementary page operations. 
 #[derive(Clone, Debug)]
-struct CompoundPageOperation<Op> {
+pub struct CompoundPageOperation<Op> {
     page_name: FileName,
     page_path: FilePath,
     ops: Vec<Op>,
@@ -210,7 +885,7 @@ struct CompoundPageOperation<Op> {
 
 impl<Op> CompoundPageOperation<Op> where Op: Clone {
 
-    fn new(page_name: FileName, page_path: FilePath, ops: &[Op]) -> CompoundPageOperation<Op> {
+    pub fn new(page_name: FileName, page_path: FilePath, ops: &[Op]) -> CompoundPageOperation<Op> {
         let mut vec = Vec::new();
         for op in ops.iter() {
             vec.push(op.clone());
@@ -223,7 +898,7 @@ impl<Op> CompoundPageOperation<Op> where Op: Clone {
         }
     }
 
-    fn make_no_op(page_name: FileName, page_path: FilePath) -> CompoundPageOperation<Op> {
+    pub fn make_no_op(page_name: FileName, page_path: FilePath) -> CompoundPageOperation<Op> {
         CompoundPageOperation {
             page_name: page_name,
             page_path: page_path,
@@ -232,27 +907,103 @@ impl<Op> CompoundPageOperation<Op> where Op: Clone {
     }
 
 
-    fn is_no_op(&self) -> bool {
-        self.ops.is_empty()
+    pub fn is_no_op(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn iter(&self) -> CPOIter<'_, Op> {
+        CPOIter {
+            inner: self.ops.iter()
+        }
+    }
+
+    pub fn page_name(&self) -> &FileName {
+        &self.page_name
+    }
+
+    pub fn page_path(&self) -> &FilePath {
+        &self.page_path
+    }
+
+}
+
+impl CompoundPageOperation<PageOps> {
+    pub fn has_no_op(&self) -> bool {
+        for op in self.ops.iter() {
+            if *op == PageOps::NoOperation {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    pub fn is_destructive(&self) -> bool {
+        self.ops.iter().any(|op| op.is_destructive())
+    }
+
+    /// The same operation with each run of consecutive rotations composed
+    /// into one. A run of quarter turns becomes one quarter turn, or nothing.
+    /// A run with any other angle becomes one rotation by the total angle,
+    /// with the fill of its last rotation, so the page is resampled once and
+    /// grows only as much as a single rotation makes it.
+    pub fn compose_rotations(&self) -> CompoundPageOperation<PageOps> {
+        let mut ops = Vec::new();
+        let mut run: Option<(i64, Option<Fill>)> = None;
+
+        for op in self.ops.iter() {
+            let (hundredths, fill) = match *op {
+                PageOps::Rotate(turn)            => (turn.degrees() as i64 * 100, None),
+                PageOps::RotateByAngle(rotation) => (rotation.hundredths, Some(rotation.fill)),
+                _ => {
+                    if let Some(rotation) = run.take().and_then(composed_rotation) {
+                        ops.push(rotation);
+                    }
+                    ops.push(op.clone());
+                    continue;
+                }
+            };
+            run = match run {
+                Some((total, run_fill)) => Some((total + hundredths, fill.or(run_fill))),
+                None                    => Some((hundredths, fill)),
+            };
+        }
+        if let Some(rotation) = run.take().and_then(composed_rotation) {
+            ops.push(rotation);
+        }
+
+        CompoundPageOperation::new(self.page_name.clone(), self.page_path.clone(), ops.as_ref())
     }
 
-    fn iter(&self) -> CPOIter<Op> {
-        CPOIter {
-            inner: self.ops.iter()
+    /// Compile each page operation into the operation type of a backend, 
+    /// targeted at the page's file. Consecutive rotations are composed into
+    /// one first, so the page is resampled only once.
+    pub fn compile<Op>(&self) -> CompoundPageOperation<Op> 
+        where Op: ElementaryPageOperations + RetargetOperation + Clone 
+    {
+        let mut new_ops = Vec::new();
+        for op in self.compose_rotations().ops.iter() {
+            new_ops.push(Op::compile_operation(op.clone()));
         }
-    }
+        let compiled = CompoundPageOperation::new(self.page_name.clone(), self.page_path.clone(), new_ops.as_ref());
 
+        CompoundPageOperation::retarget(compiled, &self.page_path)
+    }
 }
 
-impl CompoundPageOperation<PageOps> {
-    fn is_no_op(&self) -> bool {
-        for op in self.ops.iter() {
-            if *op == PageOps::NoOperation {
-                return true;
-            }
-        }
+/// The single rotation equal to a run of rotations adding up to a number of
+/// hundredths of a degree, or None if the run comes back to where it began.
+fn composed_rotation(run: (i64, Option<Fill>)) -> Option<PageOps> {
+    let (total, fill) = run;
+    let total = ((total % 36000) + 36000) % 36000;
 
-        false
+    match fill {
+        _ if total == 0 => None,
+        Some(fill) if total % 9000 != 0 => {
+            let total = if total > 18000 { total - 36000 } else { total };
+            Some(PageOps::RotateByAngle(AngleRotation { hundredths: total, fill: fill }))
+        }
+        _ => QuarterTurn::from_degrees(total / 100).map(PageOps::Rotate),
     }
 }
 
@@ -261,6 +1012,10 @@ impl<Op> fmt::Display for CompoundPageOperation<Op> where Op: Clone + fmt::Displ
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut op_string = String::new();
 
+        if self.ops.is_empty() {
+            return write!(f, "PageOps([])");
+        }
+
         for i in 0..self.ops.len()-1 {
             op_string.push_str(self.ops[i].to_string().as_str());
             op_string.push_str(", ");
@@ -303,11 +1058,69 @@ impl<Op> RunOperation for CompoundPageOperation<Op>
 
         final_results
     }
+
+    /// A compound operation cancelled before its first step is aborted. If it is
+    /// cancelled part way through, the page has been partially modified, so the
+    /// remaining steps are skipped and the operation is reported as failed.
+    fn run_operation_with_cancel(op: CompoundPageOperation<Op>, token: &CancellationToken) -> OperationResults {
+        if token.is_cancelled() {
+            return OperationResults::aborted();
+        }
+
+        let mut final_results = OperationResults::new();
+
+        for elem_op in op {
+            if token.is_cancelled() {
+                final_results.push(Err(cancellation::cancelled_error()));
+                break;
+            }
+
+            let mut results = Op::run_operation_with_cancel(elem_op, token);
+            final_results.append(&mut results);
+        }
+
+        final_results
+    }
+
+    /// The page is read the way its first step reads files, so that it is read
+    /// with the same backend and settings that processed it.
+    fn gray_image(op: &CompoundPageOperation<Op>, file_path: &FilePath, token: &CancellationToken)
+        -> io::Result<GrayImage>
+    {
+        match op.ops.first() {
+            Some(first) => Op::gray_image(first, file_path, token),
+            None        => GrayImage::open(file_path),
+        }
+    }
+}
+
+
+impl<Op> RetargetOperation for CompoundPageOperation<Op> where Op: RetargetOperation {
+    /// Each step is pointed at the file the step before it left its output in.
+    fn retarget(op: CompoundPageOperation<Op>, file_path: &FilePath) -> CompoundPageOperation<Op> {
+        let mut new_ops = Vec::new();
+        let mut current = file_path.clone();
+        for elem_op in op.ops {
+            let retargeted = Op::retarget(elem_op, &current);
+            current = Op::output_path(&retargeted, &current);
+            new_ops.push(retargeted);
+        }
+
+        CompoundPageOperation {
+            page_name: op.page_name,
+            page_path: file_path.clone(),
+            ops: new_ops,
+        }
+    }
+
+    fn output_path(op: &CompoundPageOperation<Op>, file_path: &FilePath) -> FilePath {
+        op.ops.iter().fold(file_path.clone(), |current, elem_op| Op::output_path(elem_op, &current))
+    }
 }
 
 
 /// Iterator interface for a CompoundPageOperation.
-struct CPOIter<'a, Op> where Op: 'a {
+pub struct CPOIter<'a, Op> where Op: 'a {
     inner: slice::Iter<'a, Op>,
 }
 
@@ -319,7 +1132,7 @@ impl<'a, Op> Iterator for CPOIter<'a, Op> {
     }
 }
 
-struct CPOIntoIter<Op> {
+pub struct CPOIntoIter<Op> {
     inner: vec::IntoIter<Op>,
 }
 
@@ -360,7 +1173,7 @@ impl<Op> AsRef<[Op]> for CompoundPageOperation<Op> {
 
 
 #[derive(Clone, Eq, Debug)]
-struct Page {
+pub struct Page {
     file_name:      FileName,
     file_extension: ImageFileFormat,
     file_path:      FilePath,
@@ -369,7 +1182,7 @@ struct Page {
 }
 
 impl Page {
-    fn new  ( 
+    pub fn new  ( 
             file_name: FileName, 
             file_extension: ImageFileFormat, 
             file_path: FilePath, 
@@ -385,6 +1198,95 @@ impl Page {
             resolution: resolution,
         }
     }
+
+    pub fn file_name(&self) -> &FileName {
+        &self.file_name
+    }
+
+    pub fn file_path(&self) -> &FilePath {
+        &self.file_path
+    }
+
+    pub fn file_extension(&self) -> &ImageFileFormat {
+        &self.file_extension
+    }
+
+    pub fn dimensions(&self) -> &ImageDimensions {
+        &self.dimensions
+    }
+
+    pub fn resolution(&self) -> &ImageResolution {
+        &self.resolution
+    }
+
+    /// The same page written to another file, named after the last
+    /// component of the new path.
+    pub fn with_file_path(&self, file_path: FilePath) -> Page {
+        let mut page = self.clone();
+        page.file_name = Path::new(&file_path).file_name()
+                                              .map(|name| name.to_string_lossy().into_owned())
+                                              .unwrap_or_default();
+        page.file_path = file_path;
+
+        page
+    }
+
+    /// The page as it is expected to be after applying an operation to it.
+    /// Only the dimensions, resolution and format change, and a page converted
+    /// to another format moves to a file with that format's extension.
+    pub fn after_operation(&self, op: &PageOps) -> Page {
+        let x = self.dimensions.x_pixels;
+        let y = self.dimensions.y_pixels;
+
+        let mut page = self.clone();
+        match *op {
+            PageOps::NoOperation | PageOps::Identify(_, _) | PageOps::ReduceColors(_) | PageOps::Deskew(_) => {}
+            PageOps::Despeckle(_) => {}
+            PageOps::Flip(_) => {}
+            // How much is trimmed is only known once the operation has run.
+            // It has no inverse, so nothing is planned from the page's size after it.
+            PageOps::RemoveBorders(_) => {}
+            PageOps::Rotate(turn) => {
+                if turn.swaps_dimensions() {
+                    page.dimensions = ImageDimensions::new(y, x);
+                }
+            }
+            PageOps::RotateByAngle(ref rotation) => {
+                page.dimensions = rotation.rotated_dimensions(&self.dimensions);
+            }
+            PageOps::Rescale(amount, Direction::Horizontal) => {
+                // ImageMagick preserves the aspect ratio, rounding to the nearest pixel.
+                let new_y = (y * amount + x / 2).checked_div(x).unwrap_or(y);
+                page.dimensions = ImageDimensions::new(amount, new_y);
+            }
+            PageOps::Rescale(amount, Direction::Vertical) => {
+                let new_x = (x * amount + y / 2).checked_div(y).unwrap_or(x);
+                page.dimensions = ImageDimensions::new(new_x, amount);
+            }
+            PageOps::ExpandLeftEdge(amount) | PageOps::ExpandRightEdge(amount) => {
+                page.dimensions = ImageDimensions::new(x + amount, y);
+            }
+            PageOps::ExpandTopEdge(amount) | PageOps::ExpandBottomEdge(amount) => {
+                page.dimensions = ImageDimensions::new(x, y + amount);
+            }
+            PageOps::TrimLeftEdge(amount) | PageOps::TrimRightEdge(amount) => {
+                page.dimensions = ImageDimensions::new(x.saturating_sub(amount), y);
+            }
+            PageOps::TrimTopEdge(amount) | PageOps::TrimBottomEdge(amount) => {
+                page.dimensions = ImageDimensions::new(x, y.saturating_sub(amount));
+            }
+            PageOps::SetResolution(ref res) => {
+                page.resolution = res.clone();
+            }
+            PageOps::ConvertFormat(ref format) => {
+                let file_path = Path::new(&self.file_path).with_extension(format.extension());
+                page = page.with_file_path(file_path.to_string_lossy().into_owned());
+                page.file_extension = format.file_format();
+            }
+        }
+
+        page
+    }
 }
 
 impl PartialEq for Page {
@@ -418,11 +1320,24 @@ impl fmt::Display for Page {
 pub type OperationResult = io::Result<String>;
 
 #[derive(Clone, Eq, PartialEq, Debug)]
-enum OperationStatus {
+pub enum OperationStatus {
     NotExecuted,    // Not (yet) run
     Completed,      // Ran to completion with not errors reported
     Failed,         // Completed with errors.
     Aborted,        // Operation aborted.
+    TimedOut,       // A command was killed for running past its timeout.
+}
+
+impl OperationStatus {
+    /// The status an operation has once a result is reported, if the result
+    /// is an error.
+    fn of_error(result: &OperationResult) -> Option<OperationStatus> {
+        match *result {
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => Some(OperationStatus::TimedOut),
+            Err(_)                                            => Some(OperationStatus::Failed),
+            Ok(_)                                             => None,
+        }
+    }
 }
 
 
@@ -433,6 +1348,7 @@ impl fmt::Display for OperationStatus {
             OperationStatus::Completed   => "Completed".fmt(f),
             OperationStatus::Failed      => "Failed".fmt(f),
             OperationStatus::Aborted     => "Aborted".fmt(f),
+            OperationStatus::TimedOut    => "TimedOut".fmt(f),
         }
     }
 }
@@ -443,6 +1359,12 @@ pub struct OperationResults {
     results: Vec<OperationResult>,
 }
 
+impl Default for OperationResults {
+    fn default() -> OperationResults {
+        OperationResults::new()
+    }
+}
+
 impl OperationResults {
     pub fn new() -> OperationResults {
         OperationResults {
@@ -451,9 +1373,21 @@ impl OperationResults {
         }
     }
 
+    /// The results of an operation that was aborted before it started.
+    pub fn aborted() -> OperationResults {
+        OperationResults {
+            status:  OperationStatus::Aborted,
+            results: Vec::new(),
+        }
+    }
+
+    /// Report a result. An error fails the operation, and a timeout marks it
+    /// as timed out whatever else went wrong.
     pub fn push(&mut self, result: OperationResult) {
-        if result.is_err() {
-            self.status = OperationStatus::Failed;
+        match OperationStatus::of_error(&result) {
+            Some(OperationStatus::Failed) if self.status == OperationStatus::TimedOut => {}
+            Some(status) => self.status = status,
+            None         => {}
         }
 
         if self.status == OperationStatus::NotExecuted {
@@ -465,25 +1399,46 @@ impl OperationResults {
 
     pub fn append(&mut self, other: &mut OperationResults) {
         
-        let mut status = OperationStatus::NotExecuted;
+        let mut other_status = other.status.clone();
 
         for res in other.results.iter() {
-            if res.is_err() {
-                status = OperationStatus::Failed;
-                break;
+            match OperationStatus::of_error(res) {
+                Some(OperationStatus::TimedOut) => {
+                    other_status = OperationStatus::TimedOut;
+                    break;
+                }
+                Some(status) => other_status = status,
+                None         => {}
             }
         }
 
-        if other.is_empty() {
-            status = OperationStatus::Completed;
+        if other.is_empty() && other_status == OperationStatus::NotExecuted {
+            other_status = OperationStatus::Completed;
         }
 
+        // Aborting after part of the work has run leaves things half done,
+        // which is a failure rather than an abort.
+        let status = match (self.status.clone(), other_status) {
+            (OperationStatus::TimedOut, _)                        => OperationStatus::TimedOut,
+            (_, OperationStatus::TimedOut)                        => OperationStatus::TimedOut,
+            (OperationStatus::Failed, _)                          => OperationStatus::Failed,
+            (_, OperationStatus::Failed)                          => OperationStatus::Failed,
+            (OperationStatus::NotExecuted, status)                => status,
+            (status, OperationStatus::NotExecuted)                => status,
+            (OperationStatus::Aborted, OperationStatus::Aborted)  => OperationStatus::Aborted,
+            (OperationStatus::Aborted, _)                         => OperationStatus::Failed,
+            (_, OperationStatus::Aborted)                         => OperationStatus::Failed,
+            (OperationStatus::Completed, OperationStatus::Completed) => OperationStatus::Completed,
+        };
+
         self.results.append(&mut other.results);
         self.status = status;
     }
 
+    /// Whether the operation went wrong, including by timing out. Either way
+    /// the page may be partially modified.
     pub fn is_failed(&self) -> bool {
-        self.status == OperationStatus::Failed
+        self.status == OperationStatus::Failed || self.status == OperationStatus::TimedOut
     }
 
     pub fn is_aborted(&self) -> bool {
@@ -494,12 +1449,21 @@ impl OperationResults {
         self.results.len() == 0
     }
 
+    /// Whether any command in the operation was killed for exceeding its timeout.
+    pub fn is_timed_out(&self) -> bool {
+        self.status == OperationStatus::TimedOut
+    }
+
+    pub fn status(&self) -> OperationStatus {
+        self.status.clone()
+    }
+
 }
 
 
 /// Destructive conversion from a mutable vector of OperationResult
 /// to simplify the process of returning results from running operations.
-impl<'a> From<&'a mut Vec<OperationResult>> for OperationResults {
+impl From<&mut Vec<OperationResult>> for OperationResults {
     fn from(vec: &mut Vec<OperationResult>) -> OperationResults {
         let mut results = Vec::new();
         results.append(vec);
@@ -507,11 +1471,15 @@ impl<'a> From<&'a mut Vec<OperationResult>> for OperationResults {
         let mut status = OperationStatus::Completed;
 
         for result in results.iter() {
-            if result.is_err() {
-                status = OperationStatus::Failed;
-                break;
+            match OperationStatus::of_error(result) {
+                Some(OperationStatus::TimedOut) => {
+                    status = OperationStatus::TimedOut;
+                    break;
+                }
+                Some(error_status) => status = error_status,
+                None               => {}
             }
-        } 
+        }
 
         OperationResults {
             status: status,
@@ -528,11 +1496,7 @@ impl From<OperationResult> for OperationResults {
     fn from(op_res: OperationResult) -> OperationResults {
         let mut results = Vec::new();
 
-        let status = if op_res.is_err() {
-                        OperationStatus::Failed
-                    } else {
-                        OperationStatus::Completed
-                    };
+        let status = OperationStatus::of_error(&op_res).unwrap_or(OperationStatus::Completed);
 
         results.push(op_res);
 
@@ -561,10 +1525,15 @@ impl fmt::Display for OperationResults {
         output.push_str(self.status.to_string().as_ref());
         output.push_str(", results: [");
 
+        if self.results.is_empty() {
+            output.push_str("])");
+            return write!(f, "{}", output);
+        }
+
         for i in 0..self.results.len()-1 {
             let res = match &self.results[i] {
-                    &Ok(ref s)  => s.clone(),
-                    &Err(ref e) => String::from(e.description()),
+                    Ok(s)  => s.clone(),
+                    Err(e) => e.to_string(),
                 };
 
             output.push_str(res.as_ref());
@@ -572,8 +1541,8 @@ impl fmt::Display for OperationResults {
         }
 
         let final_res = match &self.results[self.results.len()-1] {
-            &Ok(ref s) => s.clone(),
-            &Err(ref e) => String::from(e.description()),
+            Ok(s) => s.clone(),
+            Err(e) => e.to_string(),
         };
 
         output.push_str(final_res.as_ref());
@@ -584,24 +1553,104 @@ impl fmt::Display for OperationResults {
 }
 
 
+/// An OperationPlan assigns a compound operation to each page of a document.
+/// Pages keep the order they were inserted in, which is the document page order.
 #[derive(Clone, Debug)]
-struct OperationPlan<Op> {
+pub struct OperationPlan<Op> {
     plan: HashMap<Page, CompoundPageOperation<Op>>, 
+    order: Vec<Page>,
+    metadata: DocumentMetadata,
+    blank_pages: Option<BlankPages>,
 }
 
 
+impl<Op> Default for OperationPlan<Op> where Op: Clone {
+    fn default() -> OperationPlan<Op> {
+        OperationPlan::new()
+    }
+}
+
 impl<Op> OperationPlan<Op> where Op: Clone {
-    fn new() -> OperationPlan<Op> {
+    pub fn new() -> OperationPlan<Op> {
         OperationPlan {
             plan: HashMap::new(),
+            order: Vec::new(),
+            metadata: DocumentMetadata::new(),
+            blank_pages: None,
         }
     }
+
+    /// The document information and outline for the document assembled from
+    /// the plan's pages.
+    pub fn set_metadata(&mut self, metadata: DocumentMetadata) {
+        self.metadata = metadata;
+    }
+
+    pub fn metadata(&self) -> &DocumentMetadata {
+        &self.metadata
+    }
+
+    /// Look for blank pages once each page's operation has run, and keep,
+    /// flag or drop them. The decisions are recorded in the plan's result.
+    pub fn set_blank_pages(&mut self, blank_pages: Option<BlankPages>) {
+        self.blank_pages = blank_pages;
+    }
+
+    pub fn blank_pages(&self) -> Option<&BlankPages> {
+        self.blank_pages.as_ref()
+    }
     
-    fn insert(&mut self, page: Page, op: CompoundPageOperation<Op>) {
+    pub fn insert(&mut self, page: Page, op: CompoundPageOperation<Op>) {
+        if !self.plan.contains_key(&page) {
+            self.order.push(page.clone());
+        }
         self.plan.insert(page, op);
     }
 
-    fn build_schedule(pages: &[Page], ops: &[CompoundPageOperation<Op>]) -> Result<Self, String> {
+    /// Put one or more pages, each with its own operation, in the place of a
+    /// page of the plan, such as the two halves of a split spread. The pages
+    /// the document's outline and page labels point to after it move along.
+    /// Returns false, changing nothing, if the page is not in the plan or
+    /// there are no pages to put in its place.
+    pub fn replace_page(&mut self, page: &Page, replacements: Vec<(Page, CompoundPageOperation<Op>)>) -> bool {
+        let index = match self.order.iter().position(|planned| planned == page) {
+            Some(index) if !replacements.is_empty() => index,
+            _ => return false,
+        };
+
+        self.plan.remove(page);
+        self.order.remove(index);
+        let mut position = index;
+        for (new_page, op) in replacements {
+            if !self.plan.contains_key(&new_page) {
+                self.order.insert(position, new_page.clone());
+                position += 1;
+            }
+            self.plan.insert(new_page, op);
+        }
+        self.metadata.insert_pages(index + 1, (position - index).saturating_sub(1));
+
+        true
+    }
+
+    /// The pages of the plan, in document order.
+    pub fn pages(&self) -> &[Page] {
+        self.order.as_ref()
+    }
+
+    pub fn get(&self, page: &Page) -> Option<&CompoundPageOperation<Op>> {
+        self.plan.get(page)
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    pub fn build_schedule(pages: &[Page], ops: &[CompoundPageOperation<Op>]) -> Result<Self, String> {
         if pages.len() == ops.len() {
 
             let mut plan = OperationPlan::new();
@@ -619,9 +1668,10 @@ impl<Op> OperationPlan<Op> where Op: Clone {
 
     }
 
-    fn iter(&self) -> OpPlanIter<Op> {
+    pub fn iter(&self) -> OpPlanIter<'_, Op> {
         OpPlanIter {
-            inner: self.plan.iter()
+            order: self.order.iter(),
+            plan: &self.plan,
         }
     }
 
@@ -635,6 +1685,8 @@ impl<Op, OtherOp> CompileOperation<OperationPlan<Op>, OperationPlan<OtherOp>>
 {
     fn compile_operation(old_plan: OperationPlan<Op>) -> OperationPlan<OtherOp> {
         let mut new_plan = OperationPlan::new();
+        new_plan.set_metadata(old_plan.metadata().clone());
+        new_plan.set_blank_pages(old_plan.blank_pages().cloned());
 
         for (page, old_op) in old_plan.clone() {
             let new_op = CompoundPageOperation::<Op>::compile_operation(old_op);
@@ -646,16 +1698,37 @@ impl<Op, OtherOp> CompileOperation<OperationPlan<Op>, OperationPlan<OtherOp>>
 }
 
 
+impl OperationPlan<PageOps> {
+    /// Compile each page's operation into the operation type of a backend,
+    /// targeted at the page's file, with consecutive rotations composed.
+    pub fn compile<Op>(&self) -> OperationPlan<Op>
+        where Op: ElementaryPageOperations + RetargetOperation + Clone
+    {
+        let mut new_plan = OperationPlan::new();
+        new_plan.set_metadata(self.metadata().clone());
+        new_plan.set_blank_pages(self.blank_pages().cloned());
+
+        for (page, op) in self {
+            new_plan.insert(page.clone(), op.compile());
+        }
+
+        new_plan
+    }
+}
+
+
 /// Iterator implementation for OperationPlan.
-struct OpPlanIter<'a, Op: 'a> {
-    inner:  hash_map::Iter<'a, Page, CompoundPageOperation<Op>>
+pub struct OpPlanIter<'a, Op: 'a> {
+    order: slice::Iter<'a, Page>,
+    plan:  &'a HashMap<Page, CompoundPageOperation<Op>>,
 }
 
 impl<'a, Op> Iterator for OpPlanIter<'a, Op> {
     type Item = (&'a Page, &'a CompoundPageOperation<Op>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.inner.next()
+        let plan = self.plan;
+        self.order.next().map(|page| (page, &plan[page]))
     }
 
 }
@@ -670,8 +1743,9 @@ impl<'a, Op> IntoIterator for &'a OperationPlan<Op> where Op: Clone {
     }
 }
 
-struct OpPlanIntoIter<Op> {
-    inner: hash_map::IntoIter<Page, CompoundPageOperation<Op>>,
+pub struct OpPlanIntoIter<Op> {
+    order: vec::IntoIter<Page>,
+    plan:  HashMap<Page, CompoundPageOperation<Op>>,
 }
 
 impl<Op> IntoIterator for OperationPlan<Op> {
@@ -680,7 +1754,8 @@ impl<Op> IntoIterator for OperationPlan<Op> {
 
     fn into_iter(self) -> OpPlanIntoIter<Op> {
         OpPlanIntoIter {
-            inner: self.plan.into_iter()
+            order: self.order.into_iter(),
+            plan:  self.plan,
         }
     }
 }
@@ -689,13 +1764,16 @@ impl<Op> Iterator for OpPlanIntoIter<Op> {
     type Item = (Page, CompoundPageOperation<Op>);
 
     fn next(&mut self) -> Option<(Page, CompoundPageOperation<Op>)> {
-        self.inner.next()
+        let page = self.order.next()?;
+        let op = self.plan.remove(&page)?;
+
+        Some((page, op))
     }
 }
 
 
 #[derive(Clone, Eq, PartialEq, Debug)]
-enum OperationPlanStatus {
+pub enum OperationPlanStatus {
     NotCompleted,
     Completed,
     Failed,
@@ -714,21 +1792,37 @@ impl fmt::Display for OperationPlanStatus {
 }
 
 
+/// The results of executing an OperationPlan. Pages keep the order they were
+/// inserted in, so a result built from executing a plan is in document order.
 #[derive(Debug)]
-struct OperationPlanResult {
+pub struct OperationPlanResult {
     status: OperationPlanStatus,
     results: HashMap<Page, OperationResults>,
+    order: Vec<Page>,
+    blank_decisions: HashMap<Page, BlankDecision>,
+    output_paths: HashMap<Page, FilePath>,
+    backed_up_paths: HashMap<Page, Vec<FilePath>>,
+}
+
+impl Default for OperationPlanResult {
+    fn default() -> OperationPlanResult {
+        OperationPlanResult::new()
+    }
 }
 
 impl OperationPlanResult {
-    fn new() -> OperationPlanResult {
+    pub fn new() -> OperationPlanResult {
         OperationPlanResult {
             status: OperationPlanStatus::NotCompleted,
             results: HashMap::new(),
+            order: Vec::new(),
+            blank_decisions: HashMap::new(),
+            output_paths: HashMap::new(),
+            backed_up_paths: HashMap::new(),
         }
     }
 
-    fn insert(&mut self, page: Page, res: OperationResults) {
+    pub fn insert(&mut self, page: Page, res: OperationResults) {
         let status = res.status.clone();
 
         self.status = match self.status {
@@ -738,6 +1832,7 @@ impl OperationPlanResult {
                     OperationStatus::NotExecuted => OperationPlanStatus::NotCompleted,
                     OperationStatus::Completed   => OperationPlanStatus::Completed,
                     OperationStatus::Failed      => OperationPlanStatus::Failed,
+                    OperationStatus::TimedOut    => OperationPlanStatus::Failed,
                     OperationStatus::Aborted     => OperationPlanStatus::Aborted,
                 }
             }
@@ -746,6 +1841,7 @@ impl OperationPlanResult {
                     OperationStatus::NotExecuted => OperationPlanStatus::NotCompleted, // FIXME: Change this case.
                     OperationStatus::Completed   => OperationPlanStatus::Completed,
                     OperationStatus::Failed      => OperationPlanStatus::Failed,
+                    OperationStatus::TimedOut    => OperationPlanStatus::Failed,
                     OperationStatus::Aborted     => OperationPlanStatus::Failed,
                 }
             }
@@ -757,34 +1853,116 @@ impl OperationPlanResult {
             }
         };
 
+        if !self.results.contains_key(&page) {
+            self.order.push(page.clone());
+        }
         self.results.insert(page, res);
     }
 
-    fn iter(&self) -> OpPlanResultIter {
+    pub fn iter(&self) -> OpPlanResultIter<'_> {
         OpPlanResultIter {
-            inner: self.results.iter()
+            order: self.order.iter(),
+            results: &self.results,
+        }
+    }
+
+    pub fn contains(&self, page: &Page) -> bool {
+        self.results.contains_key(page)
+    }
+
+    pub fn get(&self, page: &Page) -> Option<&OperationResults> {
+        self.results.get(page)
+    }
+
+    /// The pages with results, in the order their results were inserted.
+    pub fn pages(&self) -> &[Page] {
+        self.order.as_ref()
+    }
+
+    /// Record whether a page was found to be blank and what was done with it.
+    pub fn set_blank_decision(&mut self, page: Page, decision: BlankDecision) {
+        self.blank_decisions.insert(page, decision);
+    }
+
+    pub fn blank_decision(&self, page: &Page) -> Option<&BlankDecision> {
+        self.blank_decisions.get(page)
+    }
+
+    /// Whether a page was found to be blank and is to be left out of the document.
+    pub fn is_dropped(&self, page: &Page) -> bool {
+        self.blank_decisions.get(page).is_some_and(|decision| decision.is_dropped())
+    }
+
+    /// Record the results of running a page's operation, along with where its
+    /// output was written and, when the plan looks for blank pages, whether
+    /// the page is blank.
+    pub fn record_page_result<Op>(&mut self,
+                                  page: &Page,
+                                  op: &CompoundPageOperation<Op>,
+                                  output_path: FilePath,
+                                  mut results: OperationResults,
+                                  blank_pages: Option<&BlankPages>,
+                                  token: &CancellationToken)
+        where Op: RunOperation
+    {
+        let decision = blank_pages.and_then(|blank_pages| {
+            blank_pages.decide_page(op, &output_path, &mut results, token)
+        });
+        self.insert(page.clone(), results);
+        if let Some(decision) = decision {
+            self.set_blank_decision(page.clone(), decision);
+        }
+        if output_path != *page.file_path() {
+            self.set_output_path(page.clone(), output_path);
         }
     }
 
-    fn plan_status(&self) -> OperationPlanStatus {
+    /// Record that a page's output was written somewhere other than the page's own file.
+    pub fn set_output_path(&mut self, page: Page, file_path: FilePath) {
+        self.output_paths.insert(page, file_path);
+    }
+
+    /// The page as the plan left it: at the path its output was written to,
+    /// or the page itself when it was processed in place.
+    pub fn output_page(&self, page: &Page) -> Page {
+        match self.output_paths.get(page) {
+            Some(file_path) => page.with_file_path(file_path.clone()),
+            None            => page.clone(),
+        }
+    }
+
+    /// Record that a file was kept as a backup before running a page's
+    /// operation replaced it.
+    pub fn add_backed_up_path(&mut self, page: Page, file_path: FilePath) {
+        self.backed_up_paths.entry(page).or_default().push(file_path);
+    }
+
+    /// The files backed up for a page, in the order they were backed up.
+    pub fn backed_up_paths(&self, page: &Page) -> &[FilePath] {
+        self.backed_up_paths.get(page).map(|paths| paths.as_ref()).unwrap_or(&[])
+    }
+
+    pub fn plan_status(&self) -> OperationPlanStatus {
         self.status.clone()
     }
 
-    fn is_not_completed(&self) -> bool {
+    pub fn is_not_completed(&self) -> bool {
         self.status == OperationPlanStatus::NotCompleted
     }
 }
 
 /// Iterator instances for running over operaton plan results.
-struct OpPlanResultIter<'a> {
-    inner: hash_map::Iter<'a, Page, OperationResults>,
+pub struct OpPlanResultIter<'a> {
+    order:   slice::Iter<'a, Page>,
+    results: &'a HashMap<Page, OperationResults>,
 }
 
 impl<'a> Iterator for OpPlanResultIter<'a> {
     type Item = (&'a Page, &'a OperationResults);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.inner.next()
+        let results = self.results;
+        self.order.next().map(|page| (page, &results[page]))
     }
 }
 
@@ -798,8 +1976,9 @@ impl<'a> IntoIterator for &'a OperationPlanResult {
     }
 }
 
-struct OpPlanResultIntoIter {
-    inner: hash_map::IntoIter<Page, OperationResults>,
+pub struct OpPlanResultIntoIter {
+    order:   vec::IntoIter<Page>,
+    results: HashMap<Page, OperationResults>,
 }
 
 impl IntoIterator for OperationPlanResult {
@@ -808,7 +1987,8 @@ impl IntoIterator for OperationPlanResult {
 
     fn into_iter(self) -> OpPlanResultIntoIter {
         OpPlanResultIntoIter {
-            inner: self.results.into_iter()
+            order:   self.order.into_iter(),
+            results: self.results,
         }
     }
 }
@@ -817,7 +1997,10 @@ impl Iterator for OpPlanResultIntoIter {
     type Item = (Page, OperationResults);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.inner.next()
+        let page = self.order.next()?;
+        let res = self.results.remove(&page)?;
+
+        Some((page, res))
     }
 }
 
@@ -834,6 +2017,10 @@ impl fmt::Display for OperationPlanResult {
             output.push_str(page.file_name.as_ref());
             output.push_str(": ");
             output.push_str(res.to_string().as_ref());
+            if let Some(decision) = self.blank_decision(page) {
+                output.push(' ');
+                output.push_str(decision.to_string().as_ref());
+            }
             output.push('\n');
         }
 
@@ -842,16 +2029,17 @@ impl fmt::Display for OperationPlanResult {
 }
 
 
-trait ExecutePlan<OpType> where OpType: RunOperation {
+pub trait ExecutePlan<OpType> where OpType: RunOperation {
     type ExecutionResult;
 
     fn execute_plan(&self)                                   -> Self::ExecutionResult;
+    fn execute_plan_with_cancel(&self, token: &CancellationToken) -> Self::ExecutionResult;
     fn abort_plan(&self, result: &mut Self::ExecutionResult) -> Self::ExecutionResult;
 }
 
 
 impl<Op> ExecutePlan<Op> for OperationPlan<Op> 
-    where Op: RunOperation + Clone
+    where Op: RunOperation + RetargetOperation + Clone
 {
     type ExecutionResult = OperationPlanResult;
 
@@ -862,17 +2050,254 @@ impl<Op> ExecutePlan<Op> for OperationPlan<Op>
 
         for (page, op) in self {
             let result = CompoundPageOperation::<Op>::run_operation(op.clone());
-            report.insert(page.clone(), result);
+            let output_path = CompoundPageOperation::output_path(op, page.file_path());
+            report.record_page_result(page, op, output_path, result, self.blank_pages(), &CancellationToken::new());
         }
 
         report
     }
 
+    /// Execute a plan that can be cancelled from another thread through the token.
+    /// Once the token is cancelled, the running page operation is interrupted and 
+    /// the plan is aborted.
+    fn execute_plan_with_cancel(&self, token: &CancellationToken) -> OperationPlanResult {
+        let mut report = OperationPlanResult::new();
+
+        for (page, op) in self {
+            if token.is_cancelled() {
+                break;
+            }
+
+            let result = CompoundPageOperation::<Op>::run_operation_with_cancel(op.clone(), token);
+            let output_path = CompoundPageOperation::output_path(op, page.file_path());
+            report.record_page_result(page, op, output_path, result, self.blank_pages(), token);
+        }
+
+        if token.is_cancelled() {
+            self.abort_plan(&mut report)
+        } else {
+            report
+        }
+    }
+
     /// Aborts execution of an operation plan. If the operation plan did not run yet,
     /// it terminates with an Aborted status. If it was being run, it terminates with a
-    /// Failed status.
+    /// Failed status. Every page without a result is marked as Aborted.
     fn abort_plan(&self, result: &mut OperationPlanResult) -> OperationPlanResult {
-        unimplemented!();
+        let mut report = OperationPlanResult::new();
+
+        for page in result.order.drain(..) {
+            if let Some(res) = result.results.remove(&page) {
+                report.insert(page, res);
+            }
+        }
+        for (page, decision) in result.blank_decisions.drain() {
+            report.set_blank_decision(page, decision);
+        }
+        for (page, file_path) in result.output_paths.drain() {
+            report.set_output_path(page, file_path);
+        }
+        for (page, file_paths) in result.backed_up_paths.drain() {
+            for file_path in file_paths {
+                report.add_backed_up_path(page.clone(), file_path);
+            }
+        }
+
+        for (page, _) in self {
+            if !report.contains(page) {
+                report.insert(page.clone(), OperationResults::aborted());
+            }
+        }
+
+        report.status = if report.results.values().all(|res| res.is_aborted()) {
+            OperationPlanStatus::Aborted
+        } else {
+            OperationPlanStatus::Failed
+        };
+
+        report
     }
 }
 
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use image_tools::blank::{BlankDetection, BlankPageAction};
+    use pdf::operations::PdfPageOperation;
+    use util::scratch::{scratch_dir, scratch_tiff};
+
+    fn page() -> Page {
+        Page::new(String::from("page.tif"), ImageFileFormat::TIFF, String::from("page.tif"),
+                  ImageDimensions::new(1000, 2000), ImageResolution::new(300, ResolutionUnits::PixelsPerInch))
+    }
+
+    fn two_page_plan() -> (OperationPlan<PdfPageOperation>, Page, Page) {
+        let (first, second) = (page().with_file_path(String::from("first.pdf")),
+                               page().with_file_path(String::from("second.pdf")));
+        let mut plan = OperationPlan::new();
+        for page in [&first, &second].iter() {
+            let op = CompoundPageOperation::new(page.file_name().clone(), page.file_path().clone(),
+                                                &[PageOps::NoOperation]);
+            plan.insert((*page).clone(), op.compile::<PdfPageOperation>());
+        }
+
+        (plan, first, second)
+    }
+
+    #[test]
+    fn plans_cancelled_before_they_start_are_aborted() {
+        let (plan, first, second) = two_page_plan();
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let result = plan.execute_plan_with_cancel(&token);
+        assert_eq!(result.plan_status(), OperationPlanStatus::Aborted);
+        assert!(result.get(&first).unwrap().is_aborted());
+        assert!(result.get(&second).unwrap().is_aborted());
+    }
+
+    #[test]
+    fn plans_aborted_part_way_fail_with_the_remaining_pages_aborted() {
+        let (plan, first, second) = two_page_plan();
+        let mut partial = OperationPlanResult::new();
+        partial.insert(first.clone(), OperationResults::from(Ok(String::from("done"))));
+
+        let result = plan.abort_plan(&mut partial);
+        assert_eq!(result.plan_status(), OperationPlanStatus::Failed);
+        assert_eq!(result.get(&first).unwrap().status(), OperationStatus::Completed);
+        assert!(result.get(&second).unwrap().is_aborted());
+        assert_eq!(result.pages(), &[first, second]);
+    }
+
+    #[test]
+    fn consecutive_rotations_are_composed() {
+        let ops = [
+            PageOps::Rotate(QuarterTurn::Rotate90),
+            PageOps::Rotate(QuarterTurn::Rotate90),
+            PageOps::Flip(Direction::Horizontal),
+            PageOps::RotateByAngle(AngleRotation::new(1.5, Fill::Paper)),
+            PageOps::RotateByAngle(AngleRotation::new(-1.5, Fill::Paper)),
+        ];
+        let op = CompoundPageOperation::new(String::from("page.tif"), String::from("page.tif"), &ops);
+
+        assert_eq!(op.compose_rotations().as_ref(),
+                   &[PageOps::Rotate(QuarterTurn::Rotate180), PageOps::Flip(Direction::Horizontal)]);
+    }
+
+    #[test]
+    fn quarter_turns_wrap_around_a_whole_turn() {
+        assert_eq!(QuarterTurn::from_degrees(-90), Some(QuarterTurn::Rotate270));
+        assert_eq!(QuarterTurn::from_degrees(450), Some(QuarterTurn::Rotate90));
+        assert_eq!(QuarterTurn::from_degrees(720), None);
+        assert_eq!(QuarterTurn::from_degrees(45), None);
+        assert_eq!(QuarterTurn::Rotate90.reverse(), QuarterTurn::Rotate270);
+    }
+
+    #[test]
+    fn quarter_turns_swap_the_dimensions_and_flips_keep_them() {
+        let turned = page().after_operation(&PageOps::Rotate(QuarterTurn::Rotate270));
+        assert_eq!(turned.dimensions(), &ImageDimensions::new(2000, 1000));
+
+        let upside_down = page().after_operation(&PageOps::Rotate(QuarterTurn::Rotate180));
+        assert_eq!(upside_down.dimensions(), page().dimensions());
+
+        let flipped = page().after_operation(&PageOps::Flip(Direction::Vertical));
+        assert_eq!(flipped.dimensions(), page().dimensions());
+    }
+
+    #[test]
+    fn pages_rotated_by_an_angle_grow_to_hold_the_image() {
+        let dimensions = ImageDimensions::new(1000, 2000);
+        let right_angle = AngleRotation::new(90.0, Fill::Paper).rotated_dimensions(&dimensions);
+        assert_eq!(right_angle, ImageDimensions::new(2000, 1000));
+
+        // 1000 cos 1 + 2000 sin 1 = 1034.76 and 1000 sin 1 + 2000 cos 1 = 2017.15.
+        let rotated = page().after_operation(&PageOps::RotateByAngle(AngleRotation::new(-1.0, Fill::Gray(255))));
+        assert_eq!(rotated.dimensions(), &ImageDimensions::new(1035, 2018));
+    }
+
+    #[test]
+    fn page_after_resamples_composed_rotations_once() {
+        let ops = [
+            PageOps::RotateByAngle(AngleRotation::new(2.0, Fill::Paper)),
+            PageOps::RotateByAngle(AngleRotation::new(3.0, Fill::Paper)),
+        ];
+        let op = CompoundPageOperation::new(String::from("page.tif"), String::from("page.tif"), &ops);
+        let once = AngleRotation::new(5.0, Fill::Paper).rotated_dimensions(page().dimensions());
+
+        assert_eq!(op.page_after(&page()).dimensions(), &once);
+    }
+
+    #[test]
+    fn converted_pages_move_to_the_new_extension() {
+        let page = Page::new(String::from("page.tif"), ImageFileFormat::TIFF, String::from("scans/page.tif"),
+                             ImageDimensions::new(1000, 2000), ImageResolution::new(300, ResolutionUnits::PixelsPerInch));
+        let after = page.after_operation(&PageOps::ConvertFormat(OutputFormat::Png(PngOptions::new(6))));
+
+        assert_eq!(after.file_path(), "scans/page.png");
+        assert_eq!(after.file_name(), "page.png");
+        assert_eq!(after.file_extension(), &ImageFileFormat::PNG);
+    }
+
+    #[test]
+    fn timeouts_have_their_own_status() {
+        let timed_out = || Err(io::Error::new(io::ErrorKind::TimedOut, "timed out"));
+        let failed = || Err(io::Error::other("failed"));
+
+        let mut results = OperationResults::new();
+        results.push(Ok(String::from("done")));
+        results.push(timed_out());
+        results.push(failed());
+        assert_eq!(results.status(), OperationStatus::TimedOut);
+        assert!(results.is_timed_out() && results.is_failed());
+
+        let mut results = OperationResults::from(failed());
+        results.append(&mut OperationResults::from(timed_out()));
+        assert_eq!(results.status(), OperationStatus::TimedOut);
+
+        let results = OperationResults::from(&mut vec![failed(), timed_out(), Ok(String::from("done"))]);
+        assert_eq!(results.status(), OperationStatus::TimedOut);
+
+        let mut plan_result = OperationPlanResult::new();
+        plan_result.insert(page(), results);
+        assert_eq!(plan_result.plan_status(), OperationPlanStatus::Failed);
+    }
+
+    #[test]
+    fn size_reductions_describe_the_change() {
+        let smaller = SizeReduction::new(String::from("page.tif"), 2000, 500);
+        assert_eq!(smaller.saved(), 1500);
+        assert_eq!(smaller.to_string(), "page.tif: 2000 bytes -> 500 bytes (75.0% smaller)");
+
+        let larger = SizeReduction::new(String::from("page.tif"), 400, 500);
+        assert_eq!(larger.saved(), 0);
+        assert_eq!(larger.to_string(), "page.tif: 400 bytes -> 500 bytes (25.0% larger)");
+    }
+
+    #[test]
+    fn blank_decisions_are_recorded_with_the_page_results() {
+        let dir = scratch_dir("plan-blank-decisions");
+        let page = scratch_tiff(&dir, "page.tif");
+        let op = CompoundPageOperation::new(page.file_name().clone(), page.file_path().clone(),
+                                            &[PageOps::NoOperation]).compile::<PdfPageOperation>();
+        let blank_pages = BlankPages::new(BlankDetection::new(), BlankPageAction::Drop);
+
+        let done = || OperationResults::from(Ok(String::from("done")));
+
+        let mut result = OperationPlanResult::new();
+        result.record_page_result(&page, &op, page.file_path().clone(), done(), Some(&blank_pages),
+                                  &CancellationToken::new());
+        assert!(result.is_dropped(&page));
+        assert!(result.blank_decision(&page).unwrap().is_blank());
+
+        let mut unchecked = OperationPlanResult::new();
+        unchecked.record_page_result(&page, &op, page.file_path().clone(), done(), None, &CancellationToken::new());
+        assert!(!unchecked.is_dropped(&page));
+        assert_eq!(unchecked.blank_decision(&page), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,240 @@
+#![allow(dead_code)]
+use std::fmt;
+use std::error::Error;
+use image_tools::image_ops::{PageOps, Page, Direction, ImageFileFormat, OutputFormat, PngOptions};
+use image_tools::multipage::TiffCompression;
+use image_tools::image_ops::{CompoundPageOperation, OperationPlan};
+
+
+/// The error returned when an operation cannot be undone, because it discards
+/// information about the page that the operation itself does not record.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct NonInvertibleOperation {
+    op: PageOps,
+    reason: String,
+}
+
+impl NonInvertibleOperation {
+    fn new(op: &PageOps, reason: &str) -> NonInvertibleOperation {
+        NonInvertibleOperation {
+            op: op.clone(),
+            reason: String::from(reason),
+        }
+    }
+
+    pub fn operation(&self) -> &PageOps {
+        &self.op
+    }
+
+    pub fn reason(&self) -> &str {
+        self.reason.as_ref()
+    }
+}
+
+impl fmt::Display for NonInvertibleOperation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} is not invertible: {}", self.op, self.reason)
+    }
+}
+
+impl Error for NonInvertibleOperation {}
+
+
+impl PageOps {
+    /// The operation that undoes this one, given the page as it was before this
+    /// operation was applied. Trimming is undone by expanding the same edge with
+    /// background, which is exact for the blank margins trimming is used on.
+    /// Upscaling is undone by scaling back to the original size, up to rounding.
+    /// Downscaling discards pixels, so it cannot be undone. A lossless format
+    /// conversion is undone by converting back, when the original format can
+    /// itself be written without loss.
+    pub fn inverse(&self, page: &Page) -> Result<PageOps, NonInvertibleOperation> {
+        let x = page.dimensions().x_pixels();
+        let y = page.dimensions().y_pixels();
+
+        match *self {
+            PageOps::NoOperation              => Ok(PageOps::NoOperation),
+            PageOps::Identify(_, _)           => Ok(PageOps::NoOperation),
+            PageOps::Rescale(amount, Direction::Horizontal) => {
+                if amount >= x {
+                    Ok(PageOps::Rescale(x, Direction::Horizontal))
+                } else {
+                    Err(NonInvertibleOperation::new(self, "downscaling discards pixels"))
+                }
+            }
+            PageOps::Rescale(amount, Direction::Vertical) => {
+                if amount >= y {
+                    Ok(PageOps::Rescale(y, Direction::Vertical))
+                } else {
+                    Err(NonInvertibleOperation::new(self, "downscaling discards pixels"))
+                }
+            }
+            PageOps::ExpandLeftEdge(amount)   => Ok(PageOps::TrimLeftEdge(amount)),
+            PageOps::ExpandRightEdge(amount)  => Ok(PageOps::TrimRightEdge(amount)),
+            PageOps::ExpandTopEdge(amount)    => Ok(PageOps::TrimTopEdge(amount)),
+            PageOps::ExpandBottomEdge(amount) => Ok(PageOps::TrimBottomEdge(amount)),
+            PageOps::TrimLeftEdge(amount) | PageOps::TrimRightEdge(amount) if amount >= x => {
+                Err(NonInvertibleOperation::new(self, "trimming removes the whole page"))
+            }
+            PageOps::TrimTopEdge(amount) | PageOps::TrimBottomEdge(amount) if amount >= y => {
+                Err(NonInvertibleOperation::new(self, "trimming removes the whole page"))
+            }
+            PageOps::TrimLeftEdge(amount)     => Ok(PageOps::ExpandLeftEdge(amount)),
+            PageOps::TrimRightEdge(amount)    => Ok(PageOps::ExpandRightEdge(amount)),
+            PageOps::TrimTopEdge(amount)      => Ok(PageOps::ExpandTopEdge(amount)),
+            PageOps::TrimBottomEdge(amount)   => Ok(PageOps::ExpandBottomEdge(amount)),
+            PageOps::SetResolution(_)         => Ok(PageOps::SetResolution(page.resolution().clone())),
+            PageOps::ConvertFormat(ref format) if format.is_lossy() => {
+                Err(NonInvertibleOperation::new(self, "the format does not keep every pixel"))
+            }
+            PageOps::ReduceColors(_)          => {
+                Err(NonInvertibleOperation::new(self, "reducing colours discards them"))
+            }
+            PageOps::Deskew(_)                => {
+                Err(NonInvertibleOperation::new(self, "the angle is only known once the page is deskewed"))
+            }
+            PageOps::Rotate(turn)             => Ok(PageOps::Rotate(turn.reverse())),
+            PageOps::Flip(ref dir)            => Ok(PageOps::Flip(dir.clone())),
+            PageOps::RotateByAngle(_)         => {
+                Err(NonInvertibleOperation::new(self, "rotating resamples the page and grows it"))
+            }
+            PageOps::Despeckle(_)             => {
+                Err(NonInvertibleOperation::new(self, "the noise removed is not kept"))
+            }
+            PageOps::RemoveBorders(_)         => {
+                Err(NonInvertibleOperation::new(self, "the borders are only known once they are removed"))
+            }
+            PageOps::ConvertFormat(_)         => match lossless_format(page.file_extension()) {
+                Some(format) => Ok(PageOps::ConvertFormat(format)),
+                None => Err(NonInvertibleOperation::new(self, "the original format cannot be written without loss")),
+            },
+        }
+    }
+}
+
+/// A lossless way to write a format, if it has one. The compression the
+/// original file used is not known, so TIFF is written with Deflate.
+fn lossless_format(format: &ImageFileFormat) -> Option<OutputFormat> {
+    match *format {
+        ImageFileFormat::TIFF => Some(OutputFormat::Tiff(TiffCompression::Deflate)),
+        ImageFileFormat::PNG  => Some(OutputFormat::Png(PngOptions::new(6))),
+        ImageFileFormat::BMP  => Some(OutputFormat::Bmp),
+        ImageFileFormat::PNM  => Some(OutputFormat::Pnm),
+        _                     => None,
+    }
+}
+
+
+impl CompoundPageOperation<PageOps> {
+    /// The sequence of operations that undoes this one: the inverse of each
+    /// step, in reverse order, with consecutive rotations composed as they
+    /// are when the operation is compiled. The page is the page before any
+    /// step was applied.
+    pub fn inverse(&self, page: &Page) -> Result<CompoundPageOperation<PageOps>, NonInvertibleOperation> {
+        let mut current = page.clone();
+        let mut inverses = Vec::new();
+
+        for op in self.compose_rotations().iter() {
+            inverses.push(op.inverse(&current)?);
+            current = current.after_operation(op);
+        }
+
+        inverses.reverse();
+
+        Ok(CompoundPageOperation::new(self.page_name().clone(), self.page_path().clone(), inverses.as_ref()))
+    }
+
+    /// The page as it is expected to be after every step is applied, with
+    /// consecutive rotations composed as they are when it is compiled.
+    pub fn page_after(&self, page: &Page) -> Page {
+        self.compose_rotations().iter().fold(page.clone(), |current, op| current.after_operation(op))
+    }
+}
+
+
+impl OperationPlan<PageOps> {
+    /// A plan that undoes this one, keyed by the pages as they are after this
+    /// plan has run. Fails if any page's operation cannot be undone.
+    pub fn inverse(&self) -> Result<OperationPlan<PageOps>, NonInvertibleOperation> {
+        let mut inverse_plan = OperationPlan::new();
+        inverse_plan.set_metadata(self.metadata().clone());
+
+        for (page, op) in self {
+            inverse_plan.insert(op.page_after(page), op.inverse(page)?);
+        }
+
+        Ok(inverse_plan)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image_tools::image_ops::{ImageDimensions, ImageResolution, ResolutionUnits, QuarterTurn};
+    use image_tools::image_ops::{AngleRotation, ColorReduction, DeskewOptions, Fill, JpegOptions};
+
+    fn page() -> Page {
+        Page::new(String::from("page.tif"), ImageFileFormat::TIFF, String::from("page.tif"),
+                  ImageDimensions::new(1000, 2000), ImageResolution::new(300, ResolutionUnits::PixelsPerInch))
+    }
+
+    #[test]
+    fn trims_expansions_and_turns_are_undone_exactly() {
+        let page = page();
+
+        assert_eq!(PageOps::TrimLeftEdge(10).inverse(&page), Ok(PageOps::ExpandLeftEdge(10)));
+        assert_eq!(PageOps::ExpandBottomEdge(20).inverse(&page), Ok(PageOps::TrimBottomEdge(20)));
+        assert_eq!(PageOps::Rotate(QuarterTurn::Rotate90).inverse(&page), Ok(PageOps::Rotate(QuarterTurn::Rotate270)));
+        assert_eq!(PageOps::Flip(Direction::Vertical).inverse(&page), Ok(PageOps::Flip(Direction::Vertical)));
+        assert_eq!(PageOps::Rescale(1500, Direction::Horizontal).inverse(&page),
+                   Ok(PageOps::Rescale(1000, Direction::Horizontal)));
+    }
+
+    #[test]
+    fn operations_that_discard_pixels_cannot_be_undone() {
+        let page = page();
+        let non_invertible = [
+            PageOps::Rescale(500, Direction::Horizontal),
+            PageOps::TrimTopEdge(2000),
+            PageOps::ConvertFormat(OutputFormat::Jpeg(JpegOptions::new(90))),
+            PageOps::ReduceColors(ColorReduction::Grayscale),
+            PageOps::Deskew(DeskewOptions::new(5.0)),
+            PageOps::RotateByAngle(AngleRotation::new(1.5, Fill::Paper)),
+        ];
+
+        for op in non_invertible.iter() {
+            assert_eq!(op.inverse(&page).unwrap_err().operation(), op);
+        }
+    }
+
+    #[test]
+    fn lossless_conversions_convert_back_to_the_original_format() {
+        let to_png = PageOps::ConvertFormat(OutputFormat::Png(PngOptions::new(6)));
+
+        assert_eq!(to_png.inverse(&page()), Ok(PageOps::ConvertFormat(OutputFormat::Tiff(TiffCompression::Deflate))));
+    }
+
+    #[test]
+    fn compound_operations_are_undone_in_reverse_from_the_page_each_step_saw() {
+        let page = page();
+        let ops = [
+            PageOps::TrimLeftEdge(10),
+            PageOps::Rotate(QuarterTurn::Rotate90),
+            PageOps::Rotate(QuarterTurn::Rotate90),
+            PageOps::Rescale(2000, Direction::Horizontal),
+        ];
+        let op = CompoundPageOperation::new(String::from("page.tif"), String::from("page.tif"), &ops);
+
+        assert_eq!(op.inverse(&page).unwrap().as_ref(), &[
+            PageOps::Rescale(990, Direction::Horizontal),
+            PageOps::Rotate(QuarterTurn::Rotate180),
+            PageOps::ExpandLeftEdge(10),
+        ]);
+
+        let mut plan = OperationPlan::new();
+        plan.insert(page.clone(), op.clone());
+        let inverse_plan = plan.inverse().unwrap();
+        assert!(inverse_plan.get(&op.page_after(&page)).is_some());
+    }
+}
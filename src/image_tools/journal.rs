@@ -0,0 +1,403 @@
+#![allow(dead_code)]
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::fmt;
+use image_tools::image_ops::{FilePath, PageOps, Page};
+use image_tools::image_ops::{CompoundPageOperation, OperationPlan, OperationPlanResult, OperationResults};
+use image_tools::image_ops::{ElementaryPageOperations, RunOperation, RetargetOperation};
+use image_tools::cancellation::CancellationToken;
+use util::checksum;
+
+
+/// A single entry in the execution journal. Each entry is written as one
+/// tab separated line, with the page path last so that it may contain any
+/// character other than a newline.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum JournalEvent {
+    Planned(String),
+    Started,
+    Completed(u64),
+    Failed,
+    Aborted,
+}
+
+impl JournalEvent {
+    fn to_line(&self, page_path: &FilePath) -> String {
+        match *self {
+            JournalEvent::Planned(ref ops)     => format!("PLANNED\t{}\t{}\n", ops, page_path),
+            JournalEvent::Started              => format!("STARTED\t\t{}\n", page_path),
+            JournalEvent::Completed(checksum)  => format!("COMPLETED\t{:016x}\t{}\n", checksum, page_path),
+            JournalEvent::Failed               => format!("FAILED\t\t{}\n", page_path),
+            JournalEvent::Aborted              => format!("ABORTED\t\t{}\n", page_path),
+        }
+    }
+
+    fn from_line(line: &str) -> Option<(FilePath, JournalEvent)> {
+        let fields: Vec<&str> = line.splitn(3, '\t').collect();
+        if fields.len() != 3 {
+            return None;
+        }
+
+        let event = match fields[0] {
+            "PLANNED"   => JournalEvent::Planned(String::from(fields[1])),
+            "STARTED"   => JournalEvent::Started,
+            "COMPLETED" => JournalEvent::Completed(u64::from_str_radix(fields[1], 16).ok()?),
+            "FAILED"    => JournalEvent::Failed,
+            "ABORTED"   => JournalEvent::Aborted,
+            _           => return None,
+        };
+
+        Some((String::from(fields[2]), event))
+    }
+}
+
+impl fmt::Display for JournalEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JournalEvent::Planned(ref ops)    => write!(f, "Planned({})", ops),
+            JournalEvent::Started             => write!(f, "Started"),
+            JournalEvent::Completed(checksum) => write!(f, "Completed({:016x})", checksum),
+            JournalEvent::Failed              => write!(f, "Failed"),
+            JournalEvent::Aborted             => write!(f, "Aborted"),
+        }
+    }
+}
+
+
+/// The state of a single page as reconstructed from the journal.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum PageJournalState {
+    Planned,            // Planned, but never started.
+    InProgress,         // Started, but neither completed nor failed. The run died mid-page.
+    Completed(u64),     // Completed, with the checksum of the output file.
+    Failed,             // Ran and failed; the page may be partially modified.
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct PageRecord {
+    planned_ops: String,
+    state: PageJournalState,
+}
+
+impl PageRecord {
+    fn apply(&mut self, event: &JournalEvent) {
+        self.state = match *event {
+            JournalEvent::Planned(ref ops) => {
+                self.planned_ops = ops.clone();
+                self.state.clone()
+            }
+            JournalEvent::Started             => PageJournalState::InProgress,
+            JournalEvent::Completed(checksum) => PageJournalState::Completed(checksum),
+            JournalEvent::Failed              => PageJournalState::Failed,
+            JournalEvent::Aborted             => PageJournalState::Planned,
+        };
+    }
+}
+
+
+/// An append-only journal recording the progress of an operation plan, so that
+/// a run that dies part way through can be resumed without reapplying operations
+/// to pages that were already processed.
+#[derive(Debug)]
+pub struct ExecutionJournal {
+    path: PathBuf,
+    file: File,
+    records: HashMap<FilePath, PageRecord>,
+}
+
+impl ExecutionJournal {
+    /// Open the journal at the given path, replaying any entries already in it.
+    /// The file is created if it does not exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<ExecutionJournal> {
+        let path = path.as_ref().to_path_buf();
+        let mut records = HashMap::new();
+
+        if path.exists() {
+            let reader = BufReader::new(File::open(&path)?);
+            for line in reader.lines() {
+                let line = line?;
+                // A line cut short by a crash is simply ignored.
+                if let Some((page_path, event)) = JournalEvent::from_line(&line) {
+                    let record = records.entry(page_path).or_insert(PageRecord {
+                        planned_ops: String::new(),
+                        state: PageJournalState::Planned,
+                    });
+                    record.apply(&event);
+                }
+            }
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+        Ok(ExecutionJournal {
+            path: path,
+            file: file,
+            records: records,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.path.as_ref()
+    }
+
+    /// Append an event for a page, syncing it to disk before returning.
+    pub fn record(&mut self, page_path: &FilePath, event: JournalEvent) -> io::Result<()> {
+        self.file.write_all(event.to_line(page_path).as_bytes())?;
+        self.file.sync_data()?;
+
+        let record = self.records.entry(page_path.clone()).or_insert(PageRecord {
+            planned_ops: String::new(),
+            state: PageJournalState::Planned,
+        });
+        record.apply(&event);
+
+        Ok(())
+    }
+
+    pub fn page_state(&self, page_path: &FilePath) -> Option<PageJournalState> {
+        self.records.get(page_path).map(|record| record.state.clone())
+    }
+
+    pub fn planned_ops(&self, page_path: &FilePath) -> Option<&str> {
+        self.records.get(page_path).map(|record| record.planned_ops.as_ref())
+    }
+}
+
+
+/// Checksum of a file's contents, using the 64 bit FNV-1a hash.
+pub fn file_checksum<P: AsRef<Path>>(path: P) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut buffer = [0; 8192];
+    let mut hash = checksum::FNV_OFFSET_BASIS;
+
+    loop {
+        let count = file.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+
+        hash = checksum::fnv1a_update(hash, &buffer[..count]);
+    }
+
+    Ok(hash)
+}
+
+fn refused(message: String) -> OperationResults {
+    OperationResults::from(Err(io::Error::other(message)))
+}
+
+/// What to do with a page given what the journal says about it.
+enum Resume {
+    Run,
+    Skip,
+    Refuse(String),
+}
+
+fn resume_decision(journal: &ExecutionJournal, page: &Page, op: &CompoundPageOperation<PageOps>) -> Resume {
+    let page_path = page.file_path();
+    let output_path = op.page_after(page).file_path().clone();
+    let planned_ops = op.to_string();
+
+    let state = match journal.page_state(page_path) {
+        Some(state) => state,
+        None        => return Resume::Run,
+    };
+
+    if state != PageJournalState::Planned && journal.planned_ops(page_path) != Some(planned_ops.as_ref()) {
+        return Resume::Refuse(format!(
+            "Journal records different operations for {}; refusing to apply {}", page_path, planned_ops
+        ));
+    }
+
+    match state {
+        PageJournalState::Planned => Resume::Run,
+        // An output that was moved or deleted since counts as changed.
+        PageJournalState::Completed(checksum) => {
+            if file_checksum(&output_path).ok() == Some(checksum) {
+                Resume::Skip
+            } else if op.is_destructive() {
+                Resume::Refuse(format!(
+                    "{} changed or went missing since it was completed; refusing to reapply destructive operations",
+                    output_path
+                ))
+            } else {
+                Resume::Run
+            }
+        }
+        PageJournalState::InProgress | PageJournalState::Failed => {
+            if op.is_destructive() {
+                Resume::Refuse(format!(
+                    "{} may be partially modified by an earlier run; refusing to reapply destructive operations", page_path
+                ))
+            } else {
+                Resume::Run
+            }
+        }
+    }
+}
+
+/// Execute a plan while recording its progress in a journal. Pages the journal shows
+/// as completed, whose files are unchanged since, are skipped. Destructive operations
+/// are never applied to a page the journal shows may already have been modified;
+/// such pages fail and are left for the user to inspect.
+pub fn execute_plan_with_journal<Op>(plan: &OperationPlan<PageOps>,
+                                     journal: &mut ExecutionJournal,
+                                     token: &CancellationToken)
+    -> io::Result<OperationPlanResult>
+        where Op: ElementaryPageOperations + RunOperation + RetargetOperation + Clone
+{
+    for (page, op) in plan {
+        if journal.page_state(page.file_path()).is_none() {
+            journal.record(page.file_path(), JournalEvent::Planned(op.to_string()))?;
+        }
+    }
+
+    let mut report = OperationPlanResult::new();
+
+    for (page, op) in plan {
+        if token.is_cancelled() {
+            report.insert(page.clone(), OperationResults::aborted());
+            continue;
+        }
+
+        let compiled = op.compile::<Op>();
+        let result = match resume_decision(journal, page, op) {
+            Resume::Skip => {
+                OperationResults::from(Ok(format!("{} already completed according to the journal", page.file_path())))
+            }
+            Resume::Refuse(message) => refused(message),
+            Resume::Run => {
+                let planned_ops = op.to_string();
+                if journal.planned_ops(page.file_path()) != Some(planned_ops.as_ref()) {
+                    journal.record(page.file_path(), JournalEvent::Planned(planned_ops))?;
+                }
+                journal.record(page.file_path(), JournalEvent::Started)?;
+
+                let result = CompoundPageOperation::<Op>::run_operation_with_cancel(compiled.clone(), token);
+
+                if result.is_aborted() {
+                    journal.record(page.file_path(), JournalEvent::Aborted)?;
+                } else if result.is_failed() {
+                    journal.record(page.file_path(), JournalEvent::Failed)?;
+                } else {
+                    let checksum = file_checksum(op.page_after(page).file_path())?;
+                    journal.record(page.file_path(), JournalEvent::Completed(checksum))?;
+                }
+
+                result
+            }
+        };
+
+        let output_path = op.page_after(page).file_path().clone();
+        report.record_page_result(page, &compiled, output_path, result, plan.blank_pages(), token);
+    }
+
+    Ok(report)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use image_tools::image_ops::{QuarterTurn, OperationStatus};
+    use pdf::operations::PdfPageOperation;
+    use util::scratch::{scratch_dir, scratch_pdf};
+
+    fn op_on(page: &Page, ops: &[PageOps]) -> CompoundPageOperation<PageOps> {
+        CompoundPageOperation::new(page.file_name().clone(), page.file_path().clone(), ops)
+    }
+
+    #[test]
+    fn journal_events_are_replayed_on_reopening() {
+        let dir = scratch_dir("journal-replay");
+        let journal_path = dir.join("journal");
+        let (first, second) = (String::from("first.tif"), String::from("second.tif"));
+        {
+            let mut journal = ExecutionJournal::open(&journal_path).unwrap();
+            journal.record(&first, JournalEvent::Planned(String::from("Rotate(90)"))).unwrap();
+            journal.record(&first, JournalEvent::Started).unwrap();
+            journal.record(&first, JournalEvent::Completed(0xfeed)).unwrap();
+            journal.record(&second, JournalEvent::Planned(String::from("Rotate(90)"))).unwrap();
+            journal.record(&second, JournalEvent::Started).unwrap();
+        }
+        // A line cut short by a crash.
+        OpenOptions::new().append(true).open(&journal_path).unwrap().write_all(b"COMPLETED\t00").unwrap();
+
+        let journal = ExecutionJournal::open(&journal_path).unwrap();
+        assert_eq!(journal.page_state(&first), Some(PageJournalState::Completed(0xfeed)));
+        assert_eq!(journal.page_state(&second), Some(PageJournalState::InProgress));
+        assert_eq!(journal.planned_ops(&first), Some("Rotate(90)"));
+        assert_eq!(journal.page_state(&String::from("third.tif")), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn completed_pages_are_not_rotated_twice_on_resume() {
+        let dir = scratch_dir("journal-resume");
+        let page = scratch_pdf(&dir, "page.pdf");
+        let mut plan = OperationPlan::new();
+        plan.insert(page.clone(), op_on(&page, &[PageOps::Rotate(QuarterTurn::Rotate90)]));
+        let token = CancellationToken::new();
+
+        let mut journal = ExecutionJournal::open(dir.join("journal")).unwrap();
+        let first = execute_plan_with_journal::<PdfPageOperation>(&plan, &mut journal, &token).unwrap();
+        assert_eq!(first.get(&page).unwrap().status(), OperationStatus::Completed);
+        let rotated = fs::read(page.file_path()).unwrap();
+
+        let mut journal = ExecutionJournal::open(dir.join("journal")).unwrap();
+        let second = execute_plan_with_journal::<PdfPageOperation>(&plan, &mut journal, &token).unwrap();
+        assert_eq!(second.get(&page).unwrap().status(), OperationStatus::Completed);
+        assert_eq!(fs::read(page.file_path()).unwrap(), rotated);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn changed_or_missing_outputs_refuse_destructive_operations_only() {
+        let dir = scratch_dir("journal-refuse");
+        let page = scratch_pdf(&dir, "page.pdf");
+        let destructive = op_on(&page, &[PageOps::Rotate(QuarterTurn::Rotate90)]);
+        let mut journal = ExecutionJournal::open(dir.join("journal")).unwrap();
+        journal.record(page.file_path(), JournalEvent::Planned(destructive.to_string())).unwrap();
+        journal.record(page.file_path(), JournalEvent::Completed(file_checksum(page.file_path()).unwrap())).unwrap();
+
+        assert!(matches!(resume_decision(&journal, &page, &destructive), Resume::Skip));
+
+        fs::write(page.file_path(), b"edited").unwrap();
+        assert!(matches!(resume_decision(&journal, &page, &destructive), Resume::Refuse(_)));
+
+        fs::remove_file(page.file_path()).unwrap();
+        assert!(matches!(resume_decision(&journal, &page, &destructive), Resume::Refuse(_)));
+
+        let harmless = op_on(&page, &[PageOps::NoOperation]);
+        journal.record(page.file_path(), JournalEvent::Planned(harmless.to_string())).unwrap();
+        assert!(matches!(resume_decision(&journal, &page, &harmless), Resume::Run));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pages_interrupted_mid_operation_are_refused_destructive_operations() {
+        let dir = scratch_dir("journal-interrupted");
+        let page = scratch_pdf(&dir, "page.pdf");
+        let op = op_on(&page, &[PageOps::Rotate(QuarterTurn::Rotate90)]);
+        let mut journal = ExecutionJournal::open(dir.join("journal")).unwrap();
+        journal.record(page.file_path(), JournalEvent::Planned(op.to_string())).unwrap();
+        journal.record(page.file_path(), JournalEvent::Started).unwrap();
+
+        let mut plan = OperationPlan::new();
+        plan.insert(page.clone(), op);
+        let before = fs::read(page.file_path()).unwrap();
+        let result = execute_plan_with_journal::<PdfPageOperation>(&plan, &mut journal, &CancellationToken::new());
+
+        assert!(result.unwrap().get(&page).unwrap().is_failed());
+        assert_eq!(fs::read(page.file_path()).unwrap(), before);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
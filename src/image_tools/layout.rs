@@ -0,0 +1,272 @@
+#![allow(dead_code)]
+use std::fmt;
+use image_tools::image_ops::{Page, PageOps, Pixels, ImageDimensions, OperationPlan, CompoundPageOperation};
+use image_tools::content::ContentBounds;
+use image_tools::raster::Rectangle;
+
+
+/// Where the content block of each page is put on the canvas.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum Placement {
+    /// In the middle of the canvas.
+    Centered,
+    /// With its top left corner where the median content block of the pages
+    /// has it, so that blocks of text line up from page to page and a short
+    /// last page of a chapter stays at the top.
+    MedianBlock,
+}
+
+impl fmt::Display for Placement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Placement::Centered    => write!(f, "Centered"),
+            Placement::MedianBlock => write!(f, "MedianBlock"),
+        }
+    }
+}
+
+
+/// Settings for laying out the pages of a document on a uniform canvas,
+/// with each page's content block at the same position.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ContentLayout {
+    placement: Placement,
+    canvas: Option<ImageDimensions>,
+}
+
+impl ContentLayout {
+    /// By default the canvas is the median size of the pages, grown where
+    /// needed so that every content block fits on it where it is placed.
+    pub fn new(placement: Placement) -> ContentLayout {
+        ContentLayout {
+            placement: placement,
+            canvas: None,
+        }
+    }
+
+    /// Lay the pages out on a canvas of this size. Content that does not fit
+    /// where it is placed is cut off.
+    pub fn set_canvas(&mut self, canvas: ImageDimensions) {
+        self.canvas = Some(canvas);
+    }
+
+    pub fn placement(&self) -> Placement {
+        self.placement
+    }
+
+    pub fn canvas(&self) -> Option<&ImageDimensions> {
+        self.canvas.as_ref()
+    }
+
+    /// The layout of pages, each given with its content, or None for a page
+    /// with no content.
+    pub fn layout(&self, pages: &[(Page, Option<ContentBounds>)]) -> PageLayout {
+        let blocks: Vec<&ContentBounds> = pages.iter().filter_map(|(_, bounds)| bounds.as_ref()).collect();
+        let block = match self.placement {
+            Placement::Centered    => None,
+            Placement::MedianBlock if blocks.is_empty() => None,
+            Placement::MedianBlock => {
+                Some((median(blocks.iter().map(|bounds| bounds.left_margin()).collect()),
+                      median(blocks.iter().map(|bounds| bounds.top_margin()).collect())))
+            }
+        };
+
+        let canvas = match self.canvas {
+            Some(ref canvas) => canvas.clone(),
+            None => {
+                let (left, top) = block.unwrap_or((0, 0));
+                let widest = blocks.iter().map(|bounds| bounds.content().width()).max().unwrap_or(0);
+                let tallest = blocks.iter().map(|bounds| bounds.content().height()).max().unwrap_or(0);
+                let width = median(pages.iter().map(|(page, _)| page.dimensions().x_pixels()).collect());
+                let height = median(pages.iter().map(|(page, _)| page.dimensions().y_pixels()).collect());
+                ImageDimensions::new(width.max(left + widest), height.max(top + tallest))
+            }
+        };
+
+        PageLayout {
+            canvas: canvas,
+            block: block,
+        }
+    }
+
+    /// A plan laying out pages, given in document order with their content.
+    pub fn plan(&self, pages: &[(Page, Option<ContentBounds>)]) -> OperationPlan<PageOps> {
+        let layout = self.layout(pages);
+        let mut plan = OperationPlan::new();
+        for (page, bounds) in pages.iter() {
+            let op = CompoundPageOperation::new(page.file_name().clone(), page.file_path().clone(),
+                                                &layout.ops(page, bounds.as_ref()));
+            plan.insert(page.clone(), op);
+        }
+
+        plan
+    }
+}
+
+impl fmt::Display for ContentLayout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.canvas {
+            Some(ref canvas) => write!(f, "{} on {}", self.placement, canvas),
+            None             => write!(f, "{} on the median page", self.placement),
+        }
+    }
+}
+
+
+/// The canvas a document's pages are laid out on, and where the top left
+/// corner of each content block goes on it, if the blocks are not centred.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct PageLayout {
+    canvas: ImageDimensions,
+    block: Option<(Pixels, Pixels)>,
+}
+
+impl PageLayout {
+    pub fn canvas(&self) -> &ImageDimensions {
+        &self.canvas
+    }
+
+    /// The position of the top left corner of each content block, or None
+    /// if each block is centred.
+    pub fn block(&self) -> Option<(Pixels, Pixels)> {
+        self.block
+    }
+
+    /// The operations moving a page's content block to its place and making
+    /// the page the size of the canvas, by adding or trimming paper at each
+    /// edge. A page without content is centred on the canvas as a whole.
+    pub fn ops(&self, page: &Page, bounds: Option<&ContentBounds>) -> Vec<PageOps> {
+        let (content, dimensions) = match bounds {
+            Some(bounds) => (*bounds.content(), bounds.dimensions().clone()),
+            None => {
+                let dimensions = page.dimensions().clone();
+                (Rectangle::new(0, 0, dimensions.x_pixels(), dimensions.y_pixels()), dimensions)
+            }
+        };
+        let (left, top) = match (self.block, bounds) {
+            (Some((left, top)), Some(_)) => (left as isize, top as isize),
+            _ => (centred(content.width(), self.canvas.x_pixels()), centred(content.height(), self.canvas.y_pixels())),
+        };
+
+        // Moving the content adds or cuts paper at the left and top; the
+        // right and bottom then make up the size of the canvas.
+        let shift_x = left - content.left() as isize;
+        let shift_y = top - content.top() as isize;
+        let right = self.canvas.x_pixels() as isize - (dimensions.x_pixels() as isize + shift_x);
+        let bottom = self.canvas.y_pixels() as isize - (dimensions.y_pixels() as isize + shift_y);
+
+        let mut ops = Vec::new();
+        push_edge(&mut ops, shift_x, PageOps::ExpandLeftEdge, PageOps::TrimLeftEdge);
+        push_edge(&mut ops, right, PageOps::ExpandRightEdge, PageOps::TrimRightEdge);
+        push_edge(&mut ops, shift_y, PageOps::ExpandTopEdge, PageOps::TrimTopEdge);
+        push_edge(&mut ops, bottom, PageOps::ExpandBottomEdge, PageOps::TrimBottomEdge);
+
+        ops
+    }
+}
+
+impl fmt::Display for PageLayout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.block {
+            Some((left, top)) => write!(f, "content at {}, {} on {}", left, top, self.canvas),
+            None              => write!(f, "content centred on {}", self.canvas),
+        }
+    }
+}
+
+/// Where a span of the given length starts when centred in another; before
+/// the start when it is longer.
+fn centred(length: Pixels, within: Pixels) -> isize {
+    (within as isize - length as isize) / 2
+}
+
+fn push_edge(ops: &mut Vec<PageOps>, amount: isize, expand: fn(Pixels) -> PageOps, trim: fn(Pixels) -> PageOps) {
+    if amount > 0 {
+        ops.push(expand(amount as Pixels));
+    } else if amount < 0 {
+        ops.push(trim((-amount) as Pixels));
+    }
+}
+
+/// The middle value, or the lower of the two middle values, or 0 for no values.
+fn median(mut values: Vec<Pixels>) -> Pixels {
+    values.sort();
+    if values.is_empty() { 0 } else { values[(values.len() - 1) / 2] }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image_tools::image_ops::{ImageFileFormat, ImageResolution, ResolutionUnits};
+
+    fn page(name: &str, width: Pixels, height: Pixels) -> Page {
+        Page::new(String::from(name), ImageFileFormat::TIFF, String::from(name),
+                  ImageDimensions::new(width, height), ImageResolution::new(300, ResolutionUnits::PixelsPerInch))
+    }
+
+    fn content(page: &Page, left: Pixels, top: Pixels, right: Pixels, bottom: Pixels) -> Option<ContentBounds> {
+        Some(ContentBounds::new(Rectangle::new(left, top, right, bottom), page.dimensions().clone()))
+    }
+
+    fn laid_out(page: &Page, ops: &[PageOps]) -> Page {
+        ops.iter().fold(page.clone(), |page, op| page.after_operation(op))
+    }
+
+    #[test]
+    fn centred_content_is_moved_by_adding_and_trimming_paper() {
+        let page = page("p1.tif", 100, 150);
+        let mut layout = ContentLayout::new(Placement::Centered);
+        layout.set_canvas(ImageDimensions::new(120, 160));
+        let pages = vec![(page.clone(), content(&page, 10, 20, 60, 100))];
+        let ops = layout.layout(&pages).ops(&page, pages[0].1.as_ref());
+
+        // The 50 by 80 block goes to 35, 40 on the canvas.
+        assert_eq!(ops, vec![PageOps::ExpandLeftEdge(25), PageOps::TrimRightEdge(5),
+                             PageOps::ExpandTopEdge(20), PageOps::TrimBottomEdge(10)]);
+        assert_eq!(laid_out(&page, &ops).dimensions(), &ImageDimensions::new(120, 160));
+    }
+
+    #[test]
+    fn blocks_line_up_with_the_median_block() {
+        let pages: Vec<Page> = (1..4).map(|number| page(&format!("p{}.tif", number), 100, 150)).collect();
+        let laid = vec![
+            (pages[0].clone(), content(&pages[0], 10, 20, 80, 130)),
+            (pages[1].clone(), content(&pages[1], 12, 22, 82, 132)),
+            (pages[2].clone(), content(&pages[2], 30, 50, 70, 70)),
+        ];
+        let layout = ContentLayout::new(Placement::MedianBlock).layout(&laid);
+
+        assert_eq!(layout.block(), Some((12, 22)));
+        assert_eq!(layout.canvas(), &ImageDimensions::new(100, 150));
+        // The short last page stays at the top, with its block's corner at the median.
+        assert_eq!(layout.ops(&pages[2], laid[2].1.as_ref()),
+                   vec![PageOps::TrimLeftEdge(18), PageOps::ExpandRightEdge(18),
+                        PageOps::TrimTopEdge(28), PageOps::ExpandBottomEdge(28)]);
+    }
+
+    #[test]
+    fn planned_pages_all_end_up_the_size_of_the_canvas() {
+        let (wide, narrow) = (page("wide.tif", 140, 150), page("narrow.tif", 90, 140));
+        let blank = page("blank.tif", 100, 100);
+        let pages = vec![
+            (wide.clone(), content(&wide, 20, 10, 130, 140)),
+            (narrow.clone(), content(&narrow, 5, 5, 85, 135)),
+            (blank.clone(), None),
+        ];
+        let plan = ContentLayout::new(Placement::Centered).plan(&pages);
+
+        // The median page is 100 by 140, widened to hold the 110 pixel block.
+        for (page, _) in pages.iter() {
+            let after = plan.get(page).unwrap().page_after(page);
+            assert_eq!(after.dimensions(), &ImageDimensions::new(110, 140), "{}", page);
+        }
+    }
+
+    #[test]
+    fn medians_take_the_lower_middle_value() {
+        assert_eq!(median(vec![5, 1, 3]), 3);
+        assert_eq!(median(vec![4, 1, 3, 2]), 2);
+        assert_eq!(median(Vec::new()), 0);
+    }
+}
@@ -0,0 +1,254 @@
+#![allow(dead_code)]
+use std::fmt;
+use std::collections::BTreeMap;
+use image_tools::image_ops::{Page, PageOps, Pixels, OperationPlan, CompoundPageOperation};
+
+
+/// Which side of an open book a page falls on. A recto is a right-hand page,
+/// bound along its left edge; a verso is a left-hand page, bound along its
+/// right edge.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum PageSide {
+    Recto,
+    Verso,
+}
+
+impl PageSide {
+    pub fn opposite(&self) -> PageSide {
+        match *self {
+            PageSide::Recto => PageSide::Verso,
+            PageSide::Verso => PageSide::Recto,
+        }
+    }
+}
+
+impl fmt::Display for PageSide {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PageSide::Recto => write!(f, "Recto"),
+            PageSide::Verso => write!(f, "Verso"),
+        }
+    }
+}
+
+
+/// Margins for the pages of a bound book, given by where they fall relative
+/// to the binding rather than by left and right. The inner margin lies along
+/// the binding: on the left of a recto and on the right of a verso.
+///
+/// Each page's side follows from its page number, counting from 1, with the
+/// pages alternating from the side of the first page, unless a side is set
+/// for the page itself, such as for a foldout or an inserted plate.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct BookMargins {
+    inner: Pixels,
+    outer: Pixels,
+    top: Pixels,
+    bottom: Pixels,
+    first_page: PageSide,
+    page_sides: BTreeMap<usize, PageSide>,
+}
+
+impl BookMargins {
+    /// Margins for a book whose first page is a recto, as in a book read
+    /// from left to right.
+    pub fn new(inner: Pixels, outer: Pixels, top: Pixels, bottom: Pixels) -> BookMargins {
+        BookMargins {
+            inner: inner,
+            outer: outer,
+            top: top,
+            bottom: bottom,
+            first_page: PageSide::Recto,
+            page_sides: BTreeMap::new(),
+        }
+    }
+
+    /// The side the first page falls on. Set it to Verso for a book read
+    /// from right to left, or for scans starting at the inside of a cover.
+    pub fn set_first_page_side(&mut self, side: PageSide) {
+        self.first_page = side;
+    }
+
+    /// Put a page on a side whatever its page number.
+    pub fn set_page_side(&mut self, page_number: usize, side: PageSide) {
+        self.page_sides.insert(page_number, side);
+    }
+
+    pub fn inner(&self) -> Pixels {
+        self.inner
+    }
+
+    pub fn outer(&self) -> Pixels {
+        self.outer
+    }
+
+    pub fn top(&self) -> Pixels {
+        self.top
+    }
+
+    pub fn bottom(&self) -> Pixels {
+        self.bottom
+    }
+
+    pub fn first_page_side(&self) -> PageSide {
+        self.first_page
+    }
+
+    /// The side a page falls on, by its page number.
+    pub fn side(&self, page_number: usize) -> PageSide {
+        match self.page_sides.get(&page_number) {
+            Some(&side) => side,
+            None if page_number % 2 == 1 => self.first_page,
+            None => self.first_page.opposite(),
+        }
+    }
+
+    /// The margins of a page on a side, as left, right, top and bottom.
+    pub fn edges(&self, side: PageSide) -> (Pixels, Pixels, Pixels, Pixels) {
+        match side {
+            PageSide::Recto => (self.inner, self.outer, self.top, self.bottom),
+            PageSide::Verso => (self.outer, self.inner, self.top, self.bottom),
+        }
+    }
+
+    /// The operations adding the margins to a page on a side. Edges without
+    /// a margin get no operation.
+    pub fn expand_ops(&self, side: PageSide) -> Vec<PageOps> {
+        let (left, right, top, bottom) = self.edges(side);
+
+        vec![
+            (left, PageOps::ExpandLeftEdge(left)),
+            (right, PageOps::ExpandRightEdge(right)),
+            (top, PageOps::ExpandTopEdge(top)),
+            (bottom, PageOps::ExpandBottomEdge(bottom)),
+        ].into_iter().filter(|&(amount, _)| amount > 0).map(|(_, op)| op).collect()
+    }
+
+    /// A plan adding the margins to each of a document's pages, given in
+    /// document order, each on the side its page number puts it.
+    pub fn plan(&self, pages: &[Page]) -> OperationPlan<PageOps> {
+        let sides: Vec<(Page, PageSide)> = pages.iter().enumerate()
+            .map(|(i, page)| (page.clone(), self.side(i + 1)))
+            .collect();
+
+        self.plan_with_sides(&sides)
+    }
+
+    /// A plan adding the margins to each page on the side given with it.
+    pub fn plan_with_sides(&self, pages: &[(Page, PageSide)]) -> OperationPlan<PageOps> {
+        let mut plan = OperationPlan::new();
+        for &(ref page, side) in pages.iter() {
+            let op = CompoundPageOperation::new(page.file_name().clone(), page.file_path().clone(),
+                                                &self.expand_ops(side));
+            plan.insert(page.clone(), op);
+        }
+
+        plan
+    }
+
+    /// Add the margins after the operations already planned for each page of
+    /// a plan, each page on the side its place in the document puts it.
+    pub fn extend_plan(&self, plan: &mut OperationPlan<PageOps>) {
+        let pages = plan.pages().to_vec();
+        for (i, page) in pages.iter().enumerate() {
+            let extended = match plan.get(page) {
+                Some(op) => {
+                    let mut ops = op.as_ref().to_vec();
+                    ops.extend(self.expand_ops(self.side(i + 1)));
+                    CompoundPageOperation::new(op.page_name().clone(), op.page_path().clone(), &ops)
+                }
+                None => continue,
+            };
+            plan.insert(page.clone(), extended);
+        }
+    }
+}
+
+impl fmt::Display for BookMargins {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "inner {}, outer {}, top {}, bottom {} pixels, first page {}",
+               self.inner, self.outer, self.top, self.bottom, self.first_page)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image_tools::image_ops::{ImageFileFormat, ImageDimensions, ImageResolution, ResolutionUnits};
+
+    fn pages(count: usize) -> Vec<Page> {
+        (1..count + 1).map(|number| {
+            Page::new(format!("p{}.tif", number), ImageFileFormat::TIFF, format!("scans/p{}.tif", number),
+                      ImageDimensions::new(1000, 1500), ImageResolution::new(300, ResolutionUnits::PixelsPerInch))
+        }).collect()
+    }
+
+    fn planned_ops(plan: &OperationPlan<PageOps>, page: &Page) -> Vec<PageOps> {
+        plan.get(page).unwrap().as_ref().to_vec()
+    }
+
+    #[test]
+    fn sides_alternate_from_the_first_page() {
+        let mut margins = BookMargins::new(60, 20, 10, 30);
+        assert_eq!((margins.side(1), margins.side(2), margins.side(3)),
+                   (PageSide::Recto, PageSide::Verso, PageSide::Recto));
+
+        margins.set_first_page_side(PageSide::Verso);
+        assert_eq!((margins.side(1), margins.side(2)), (PageSide::Verso, PageSide::Recto));
+    }
+
+    #[test]
+    fn pages_can_be_put_on_a_side_of_their_own() {
+        let mut margins = BookMargins::new(60, 20, 10, 30);
+        margins.set_page_side(4, PageSide::Recto);
+
+        assert_eq!(margins.side(4), PageSide::Recto);
+        assert_eq!(margins.side(5), PageSide::Recto);
+        assert_eq!(margins.side(6), PageSide::Verso);
+    }
+
+    #[test]
+    fn the_inner_margin_lies_along_the_binding() {
+        let margins = BookMargins::new(60, 20, 10, 0);
+
+        assert_eq!(margins.expand_ops(PageSide::Recto), vec![
+            PageOps::ExpandLeftEdge(60), PageOps::ExpandRightEdge(20), PageOps::ExpandTopEdge(10),
+        ]);
+        assert_eq!(margins.expand_ops(PageSide::Verso), vec![
+            PageOps::ExpandLeftEdge(20), PageOps::ExpandRightEdge(60), PageOps::ExpandTopEdge(10),
+        ]);
+    }
+
+    #[test]
+    fn plans_resolve_each_page_by_parity_and_overrides() {
+        let pages = pages(3);
+        let mut margins = BookMargins::new(60, 0, 0, 0);
+        margins.set_page_side(3, PageSide::Verso);
+        let plan = margins.plan(&pages);
+
+        assert_eq!(plan.pages(), pages.as_slice());
+        assert_eq!(planned_ops(&plan, &pages[0]), vec![PageOps::ExpandLeftEdge(60)]);
+        assert_eq!(planned_ops(&plan, &pages[1]), vec![PageOps::ExpandRightEdge(60)]);
+        assert_eq!(planned_ops(&plan, &pages[2]), vec![PageOps::ExpandRightEdge(60)]);
+
+        let sides = vec![(pages[0].clone(), PageSide::Verso)];
+        assert_eq!(planned_ops(&margins.plan_with_sides(&sides), &pages[0]), vec![PageOps::ExpandRightEdge(60)]);
+    }
+
+    #[test]
+    fn margins_are_added_after_the_planned_operations() {
+        let pages = pages(2);
+        let mut plan = OperationPlan::new();
+        for page in pages.iter() {
+            let op = CompoundPageOperation::new(page.file_name().clone(), page.file_path().clone(),
+                                                &[PageOps::TrimTopEdge(5)]);
+            plan.insert(page.clone(), op);
+        }
+
+        BookMargins::new(60, 0, 0, 0).extend_plan(&mut plan);
+        assert_eq!(plan.pages(), pages.as_slice());
+        assert_eq!(planned_ops(&plan, &pages[0]), vec![PageOps::TrimTopEdge(5), PageOps::ExpandLeftEdge(60)]);
+        assert_eq!(planned_ops(&plan, &pages[1]), vec![PageOps::TrimTopEdge(5), PageOps::ExpandRightEdge(60)]);
+    }
+}
@@ -1 +1,17 @@
-pub mod image_ops;
\ No newline at end of file
+pub mod image_ops;
+pub mod cancellation;
+pub mod journal;
+pub mod transaction;
+pub mod inverse;
+pub mod tiff;
+pub mod document;
+pub mod multipage;
+pub mod raster;
+pub mod content;
+pub mod deskew;
+pub mod orientation;
+pub mod borders;
+pub mod blank;
+pub mod spread;
+pub mod margins;
+pub mod layout;
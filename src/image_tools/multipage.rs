@@ -0,0 +1,336 @@
+#![allow(dead_code)]
+use std::io;
+use std::io::Write;
+use std::fs;
+use std::fs::File;
+use std::fmt;
+use std::path::Path;
+use image_tools::image_ops::{Page, ImageFileFormat, ImageResolution, ResolutionUnits};
+use image_tools::tiff;
+use image_tools::tiff::{TiffFile, TiffDirectory, TiffImageData, TiffValue, ByteOrder};
+use image_tools::transaction;
+use pdf::flate;
+
+
+/// The compression used for the frames of a merged TIFF file.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum TiffCompression {
+    /// Copy each frame's compressed data unchanged, whatever it is.
+    Keep,
+    None,
+    PackBits,
+    Lzw,
+    Deflate,
+    /// CCITT Group 4, for bilevel images. Only ImageMagick writes it.
+    Group4,
+    /// Lossy JPEG compression. Only ImageMagick writes it.
+    Jpeg,
+}
+
+impl TiffCompression {
+    /// The TIFF Compression value, for the compressions written natively.
+    pub fn tiff_value(&self) -> Option<u32> {
+        match *self {
+            TiffCompression::None     => Some(tiff::COMPRESSION_NONE),
+            TiffCompression::PackBits => Some(tiff::COMPRESSION_PACKBITS),
+            TiffCompression::Lzw      => Some(tiff::COMPRESSION_LZW),
+            TiffCompression::Deflate  => Some(tiff::COMPRESSION_DEFLATE),
+            _                         => None,
+        }
+    }
+
+    /// The compression written natively for a TIFF Compression value,
+    /// falling back to Deflate for the ones that cannot be.
+    fn from_tiff_value(value: u32) -> TiffCompression {
+        match value {
+            tiff::COMPRESSION_NONE     => TiffCompression::None,
+            tiff::COMPRESSION_PACKBITS => TiffCompression::PackBits,
+            tiff::COMPRESSION_LZW      => TiffCompression::Lzw,
+            _                          => TiffCompression::Deflate,
+        }
+    }
+
+    /// The name ImageMagick's `-compress` option takes, or None to keep
+    /// the compression of the input.
+    pub fn imagemagick_name(&self) -> Option<&'static str> {
+        match *self {
+            TiffCompression::Keep     => None,
+            TiffCompression::None     => Some("None"),
+            TiffCompression::PackBits => Some("RLE"),
+            TiffCompression::Lzw      => Some("LZW"),
+            TiffCompression::Deflate  => Some("Zip"),
+            TiffCompression::Group4   => Some("Group4"),
+            TiffCompression::Jpeg     => Some("JPEG"),
+        }
+    }
+}
+
+impl fmt::Display for TiffCompression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TiffCompression::Keep     => write!(f, "Keep"),
+            TiffCompression::None     => write!(f, "None"),
+            TiffCompression::PackBits => write!(f, "PackBits"),
+            TiffCompression::Lzw      => write!(f, "LZW"),
+            TiffCompression::Deflate  => write!(f, "Deflate"),
+            TiffCompression::Group4   => write!(f, "Group4"),
+            TiffCompression::Jpeg     => write!(f, "JPEG"),
+        }
+    }
+}
+
+
+/// Fields describing the layout of a directory's data, which are written
+/// afresh, and fields holding offsets into the original file, which
+/// cannot be carried over to another file.
+const LAYOUT_TAGS: [u16; 12] = [
+    tiff::TAG_NEW_SUBFILE_TYPE, tiff::TAG_STRIP_OFFSETS, tiff::TAG_ROWS_PER_STRIP, tiff::TAG_STRIP_BYTE_COUNTS,
+    tiff::TAG_PAGE_NUMBER, 322, 323, 324, 325, 330, 513, 514,
+];
+const OFFSET_TAGS: [u16; 3] = [34665, 34853, 40965];
+
+/// Fields that only describe the compressed data, dropped when a frame is
+/// recompressed.
+const ENCODING_TAGS: [u16; 6] = [
+    tiff::TAG_COMPRESSION, tiff::TAG_PREDICTOR, tiff::TAG_FILL_ORDER, tiff::TAG_T4_OPTIONS, tiff::TAG_T6_OPTIONS, 347,
+];
+
+/// The fields of a directory that can be copied to a frame of a new file.
+fn copied_fields(directory: &TiffDirectory, recompress: bool) -> Vec<(u16, TiffValue)> {
+    directory.entries().iter()
+        .filter(|entry| !LAYOUT_TAGS.contains(&entry.tag()) && !OFFSET_TAGS.contains(&entry.tag()))
+        .filter(|entry| !recompress || !ENCODING_TAGS.contains(&entry.tag()))
+        .filter_map(|entry| entry.to_value(directory.byte_order()).map(|value| (entry.tag(), value)))
+        .collect()
+}
+
+/// A directory's image as a frame for a new file. The strips are copied
+/// unchanged when the compression is kept and their bytes mean the same in a
+/// little endian file; otherwise the samples are decoded and compressed again.
+pub fn directory_frame(file: &TiffFile, directory: &TiffDirectory, compression: TiffCompression) -> io::Result<TiffImageData> {
+    let width = directory.width()?;
+    let height = directory.height()?;
+    let bits = directory.bits_per_sample()?;
+
+    // 16 bit samples from a big endian file have to be swapped, so they
+    // are compressed again the nearest way that can be written.
+    let keep_bytes = directory.byte_order() == ByteOrder::LittleEndian || bits <= 8;
+    let codec = match compression {
+        TiffCompression::Keep if keep_bytes => None,
+        TiffCompression::Keep                => Some(TiffCompression::from_tiff_value(directory.compression())),
+        other                                => Some(other),
+    };
+    let codec = match codec {
+        None => None,
+        Some(codec) => match codec.tiff_value() {
+            Some(value) => Some((codec, value)),
+            None => {
+                return Err(tiff::unsupported(&format!("{} compression cannot be written natively; use ImageMagick",
+                                                      codec)));
+            }
+        },
+    };
+
+    let mut frame = match codec {
+        None => {
+            let strips = directory.strips(file.data())?.iter().map(|strip| strip.to_vec()).collect();
+            let rows_per_strip = directory.get_u32(tiff::TAG_ROWS_PER_STRIP).unwrap_or(height).min(height);
+            let mut frame = TiffImageData::with_strips(width, height, rows_per_strip, strips);
+            for (tag, value) in copied_fields(directory, false) {
+                frame.set(tag, value);
+            }
+            frame
+        }
+        Some((codec, value)) => {
+            let mut samples = directory.decode_samples(file.data())?;
+            // Decoded 16 bit samples are big endian; the new file is not.
+            if bits == 16 {
+                for pair in samples.chunks_mut(2) {
+                    pair.swap(0, 1);
+                }
+            }
+            let strip = match codec {
+                TiffCompression::PackBits => {
+                    let row_bytes = directory.row_bytes()?.max(1);
+                    samples.chunks(row_bytes).flat_map(tiff::packbits_encode).collect()
+                }
+                TiffCompression::Lzw     => tiff::lzw_encode(&samples),
+                TiffCompression::Deflate => flate::compress(&samples),
+                _                        => samples,
+            };
+            let mut frame = TiffImageData::new(width, height, strip);
+            for (tag, value) in copied_fields(directory, true) {
+                frame.set(tag, value);
+            }
+            frame.set(tiff::TAG_COMPRESSION, TiffValue::Short(vec![value as u16]));
+            frame
+        }
+    };
+
+    if directory.get_u32(tiff::TAG_SAMPLES_PER_PIXEL).is_none() {
+        frame.set(tiff::TAG_SAMPLES_PER_PIXEL, TiffValue::Short(vec![directory.samples_per_pixel() as u16]));
+    }
+
+    Ok(frame)
+}
+
+/// The number of frames in a TIFF file.
+pub fn frame_count<P: AsRef<Path>>(path: P) -> io::Result<usize> {
+    Ok(TiffFile::open(path)?.page_count())
+}
+
+fn frame_page(file_name: String, file_path: String, directory: &TiffDirectory) -> io::Result<Page> {
+    let res = directory.resolution().unwrap_or(ImageResolution::new(72, ResolutionUnits::PixelsPerInch));
+
+    Ok(Page::new(file_name, ImageFileFormat::TIFF, file_path, directory.dimensions()?, res))
+}
+
+/// Split a multi-page TIFF file into one file per frame, named after the
+/// original with the frame number, and return the pages in order. The
+/// frames' data is copied, not recompressed, so nothing is lost.
+pub fn split_tiff<P: AsRef<Path>, Q: AsRef<Path>>(tiff_path: P, output_dir: Q) -> io::Result<Vec<Page>> {
+    let file = TiffFile::open(tiff_path.as_ref())?;
+    let stem = tiff_path.as_ref().file_stem()
+                        .map(|stem| stem.to_string_lossy().into_owned())
+                        .unwrap_or(String::from("page"));
+
+    let mut pages = Vec::new();
+    for (i, directory) in file.directories().iter().enumerate() {
+        let frame = directory_frame(&file, directory, TiffCompression::Keep)?;
+        let file_name = format!("{}-f{:04}.tif", stem, i + 1);
+        let file_path = output_dir.as_ref().join(&file_name);
+        File::create(&file_path)?.write_all(&tiff::write_tiff(&[frame]))?;
+
+        pages.push(frame_page(file_name, file_path.to_string_lossy().into_owned(), directory)?);
+    }
+
+    Ok(pages)
+}
+
+/// Merge pages into one multi-page TIFF file, in the order given, returning
+/// the number of frames written. Every page must be a TIFF file; a page
+/// file with several frames contributes all of them. The file is written
+/// beside its destination first, so a page can be merged into itself.
+pub fn merge_tiff<P: AsRef<Path>>(pages: &[Page], output_path: P, compression: TiffCompression) -> io::Result<usize> {
+    let mut frames = Vec::new();
+    for page in pages.iter() {
+        let file = TiffFile::open(page.file_path()).map_err(|e| {
+            io::Error::new(e.kind(), format!("{}: {}", page.file_name(), e))
+        })?;
+        for directory in file.directories().iter() {
+            frames.push(directory_frame(&file, directory, compression)?);
+        }
+    }
+
+    let count = frames.len() as u16;
+    for (i, frame) in frames.iter_mut().enumerate() {
+        // A frame of a multi-page document, and which one it is.
+        frame.set(tiff::TAG_NEW_SUBFILE_TYPE, TiffValue::Long(vec![2]));
+        frame.set(tiff::TAG_PAGE_NUMBER, TiffValue::Short(vec![i as u16, count]));
+    }
+
+    let output_path = output_path.as_ref();
+    let temporary = transaction::working_copy_path(output_path);
+    File::create(&temporary)?.write_all(&tiff::write_tiff(&frames))?;
+    fs::rename(&temporary, output_path).inspect_err(|_| {
+        let _ = fs::remove_file(&temporary);
+    })?;
+
+    Ok(frames.len())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::scratch::{scratch_dir, scratch_tiff};
+
+    /// A gray page of the given height whose rows get darker downwards.
+    fn gradient_tiff(dir: &Path, name: &str, height: u32) -> Page {
+        let samples = (0..height * 8).map(|i| (i / 8 * 16) as u8).collect();
+        let mut image = TiffImageData::new(8, height, samples);
+        image.set(tiff::TAG_BITS_PER_SAMPLE, TiffValue::Short(vec![8]));
+        image.set(tiff::TAG_COMPRESSION, TiffValue::Short(vec![tiff::COMPRESSION_NONE as u16]));
+        image.set(tiff::TAG_PHOTOMETRIC, TiffValue::Short(vec![tiff::PHOTOMETRIC_BLACK_IS_ZERO as u16]));
+        image.set_resolution(&ImageResolution::new(300, ResolutionUnits::PixelsPerInch));
+        let path = dir.join(name);
+        File::create(&path).unwrap().write_all(&tiff::write_tiff(&[image])).unwrap();
+
+        frame_page(String::from(name), path.to_string_lossy().into_owned(),
+                   &TiffFile::open(&path).unwrap().directories()[0]).unwrap()
+    }
+
+    fn frame_samples<P: AsRef<Path>>(path: P) -> Vec<Vec<u8>> {
+        let file = TiffFile::open(path).unwrap();
+        file.directories().iter().map(|directory| directory.decode_samples(file.data()).unwrap()).collect()
+    }
+
+    #[test]
+    fn merged_pages_split_back_into_the_same_frames() {
+        let dir = scratch_dir("multipage-round-trip");
+        let pages = vec![scratch_tiff(&dir, "first.tif"), gradient_tiff(&dir, "second.tif", 12)];
+        let originals: Vec<Vec<u8>> = pages.iter().flat_map(|page| frame_samples(page.file_path())).collect();
+
+        let merged = dir.join("book.tif");
+        assert_eq!(merge_tiff(&pages, &merged, TiffCompression::Keep).unwrap(), 2);
+        assert_eq!(frame_count(&merged).unwrap(), 2);
+        let file = TiffFile::open(&merged).unwrap();
+        assert_eq!(file.directories()[1].get_u32s(tiff::TAG_PAGE_NUMBER), Some(vec![1, 2]));
+
+        let split_dir = dir.join("split");
+        fs::create_dir(&split_dir).unwrap();
+        let split = split_tiff(&merged, &split_dir).unwrap();
+        assert_eq!(split.iter().map(|page| page.file_name().as_str()).collect::<Vec<_>>(),
+                   vec!["book-f0001.tif", "book-f0002.tif"]);
+        assert_eq!(split[1].dimensions().y_pixels(), 12);
+        assert_eq!(split[1].resolution().amount(), 300);
+        let frames: Vec<Vec<u8>> = split.iter().flat_map(|page| frame_samples(page.file_path())).collect();
+        assert_eq!(frames, originals);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn recompressed_frames_keep_their_samples() {
+        let dir = scratch_dir("multipage-recompress");
+        let page = gradient_tiff(&dir, "page.tif", 16);
+        let original = frame_samples(page.file_path());
+
+        for compression in [TiffCompression::None, TiffCompression::PackBits,
+                            TiffCompression::Lzw, TiffCompression::Deflate].iter() {
+            let merged = dir.join(format!("{}.tif", compression));
+            merge_tiff(::std::slice::from_ref(&page), &merged, *compression).unwrap();
+            let file = TiffFile::open(&merged).unwrap();
+            assert_eq!(Some(file.directories()[0].compression()), compression.tiff_value());
+            assert_eq!(frame_samples(&merged), original, "{}", compression);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_page_can_be_merged_into_itself() {
+        let dir = scratch_dir("multipage-into-itself");
+        let first = gradient_tiff(&dir, "first.tif", 8);
+        let second = scratch_tiff(&dir, "second.tif");
+
+        assert_eq!(merge_tiff(&[first.clone(), second], first.file_path(), TiffCompression::Keep).unwrap(), 2);
+        assert_eq!(frame_count(first.file_path()).unwrap(), 2);
+        assert!(!transaction::working_copy_path(Path::new(first.file_path())).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compressions_only_imagemagick_writes_are_refused() {
+        let dir = scratch_dir("multipage-group4");
+        let page = scratch_tiff(&dir, "page.tif");
+        let merged = dir.join("book.tif");
+
+        let error = merge_tiff(&[page], &merged, TiffCompression::Group4).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(!merged.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,282 @@
+#![allow(dead_code)]
+use std::fmt;
+use image_tools::image_ops::{PageOps, QuarterTurn, Pixels};
+use image_tools::raster::{GrayImage, ANALYSIS_PIXELS, INK_FUZZ};
+
+
+/// Rows with less ink than this share of the fullest row are gaps between lines.
+const LINE_GAP_PERCENT: usize = 2;
+
+/// Lines shorter than this many rows are too small to have a core band.
+const MIN_LINE_HEIGHT: Pixels = 4;
+
+
+/// How the orientation of a page was found.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum OrientationMethod {
+    /// From the direction of the lines of text and the balance of ink above
+    /// and below their core, assuming a script with more ascenders than
+    /// descenders, such as Latin.
+    TextLines,
+    /// Reported by Tesseract's orientation and script detection.
+    Tesseract,
+}
+
+impl fmt::Display for OrientationMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OrientationMethod::TextLines => write!(f, "text lines"),
+            OrientationMethod::Tesseract => write!(f, "Tesseract"),
+        }
+    }
+}
+
+
+/// The orientation found for a page: the quarter turn, clockwise, that
+/// puts its text upright, and how sure the method that found it is. The
+/// text line method gives confidences from 0 to 1; Tesseract's have no
+/// fixed scale, but values above about 2 are usually right.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Orientation {
+    correction: Option<QuarterTurn>,
+    confidence: f64,
+    method: OrientationMethod,
+}
+
+impl Orientation {
+    pub fn new(correction: Option<QuarterTurn>, confidence: f64, method: OrientationMethod) -> Orientation {
+        Orientation {
+            correction: correction,
+            confidence: confidence,
+            method: method,
+        }
+    }
+
+    /// The turn that puts the page upright, or None if it is upright already.
+    pub fn correction(&self) -> Option<QuarterTurn> {
+        self.correction
+    }
+
+    pub fn confidence(&self) -> f64 {
+        self.confidence
+    }
+
+    pub fn method(&self) -> OrientationMethod {
+        self.method
+    }
+
+    /// The angle the text of the page is turned by, clockwise as displayed.
+    pub fn text_angle(&self) -> u32 {
+        self.correction.map(|turn| turn.reverse().degrees()).unwrap_or(0)
+    }
+
+    /// The rotation putting the page upright, if there is one and the
+    /// orientation is at least as sure as `min_confidence`.
+    pub fn rotation_ops(&self, min_confidence: f64) -> Vec<PageOps> {
+        match self.correction {
+            Some(turn) if self.confidence >= min_confidence => vec![PageOps::Rotate(turn)],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Orientation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.correction {
+            Some(turn) => write!(f, "text turned by {} degrees, rotate by {}", self.text_angle(), turn)?,
+            None       => write!(f, "upright")?,
+        }
+        write!(f, " (confidence {:.2} from {})", self.confidence, self.method)
+    }
+}
+
+
+/// Estimate the orientation of a page of text. The lines run along the
+/// axis whose ink profile is the more uneven; the page is then turned so
+/// they run across, and it is upright when there is more ink above the
+/// dense core of its lines, from ascenders and capitals, than below it,
+/// from descenders. The confidence is that of the weaker of the two
+/// decisions, so a page without text has a confidence near 0.
+pub fn detect_orientation(image: &GrayImage) -> Orientation {
+    let small = image.downsample_to(ANALYSIS_PIXELS);
+    let (width, height) = (small.width(), small.height());
+    let mask = small.foreground_mask(small.background(), INK_FUZZ);
+
+    let mut rows = vec![0; height];
+    let mut columns = vec![0; width];
+    for (index, _) in mask.iter().enumerate().filter(|&(_, &ink)| ink) {
+        rows[index / width] += 1;
+        columns[index % width] += 1;
+    }
+    let row_spread = spread(&rows);
+    let column_spread = spread(&columns);
+    if row_spread == 0.0 && column_spread == 0.0 {
+        return Orientation::new(None, 0.0, OrientationMethod::TextLines);
+    }
+
+    let across = row_spread >= column_spread;
+    let line_confidence = 1.0 - row_spread.min(column_spread) / row_spread.max(column_spread);
+    let (above, below) = if across {
+        ink_around_lines(&mask, width)
+    } else {
+        ink_around_lines(&turn_clockwise(&mask, width, height), height)
+    };
+    if above + below == 0 {
+        return Orientation::new(None, 0.0, OrientationMethod::TextLines);
+    }
+
+    let upright = above >= below;
+    let balance_confidence = (above as f64 - below as f64).abs() / (above + below) as f64;
+    let correction = match (across, upright) {
+        (true, true)   => None,
+        (true, false)  => Some(QuarterTurn::Rotate180),
+        (false, true)  => Some(QuarterTurn::Rotate90),
+        (false, false) => Some(QuarterTurn::Rotate270),
+    };
+
+    Orientation::new(correction, line_confidence.min(balance_confidence), OrientationMethod::TextLines)
+}
+
+
+/// How uneven a profile is over the stretch holding ink: the squared
+/// coefficient of variation of its bins, 0 when they are all the same.
+fn spread(profile: &[usize]) -> f64 {
+    let first = profile.iter().position(|&count| count > 0);
+    let last = profile.iter().rposition(|&count| count > 0);
+    let bins = match (first, last) {
+        (Some(first), Some(last)) => &profile[first..last + 1],
+        _ => return 0.0,
+    };
+
+    let sum: f64 = bins.iter().map(|&count| count as f64).sum();
+    let squares: f64 = bins.iter().map(|&count| count as f64 * count as f64).sum();
+
+    bins.len() as f64 * squares / (sum * sum) - 1.0
+}
+
+/// A mask turned a quarter turn clockwise; it is `height` pixels wide.
+fn turn_clockwise(mask: &[bool], width: Pixels, height: Pixels) -> Vec<bool> {
+    let mut turned = vec![false; mask.len()];
+    for y in 0..height {
+        for x in 0..width {
+            turned[x * height + (height - 1 - y)] = mask[y * width + x];
+        }
+    }
+
+    turned
+}
+
+/// The ink above and below the core band of each line of text running
+/// across a mask. The core of a line is the rows holding at least half as
+/// much ink as its fullest row.
+fn ink_around_lines(mask: &[bool], width: Pixels) -> (usize, usize) {
+    let rows: Vec<usize> = mask.chunks(width).map(|row| row.iter().filter(|&&ink| ink).count()).collect();
+    let gap = rows.iter().cloned().max().unwrap_or(0) * LINE_GAP_PERCENT / 100;
+
+    let mut above = 0;
+    let mut below = 0;
+    let mut y = 0;
+    while y < rows.len() {
+        if rows[y] <= gap {
+            y += 1;
+            continue;
+        }
+        let start = y;
+        while y < rows.len() && rows[y] > gap {
+            y += 1;
+        }
+        let line = &rows[start..y];
+        if line.len() < MIN_LINE_HEIGHT {
+            continue;
+        }
+
+        let peak = line.iter().cloned().max().unwrap_or(0);
+        let core_top = line.iter().position(|&count| 2 * count >= peak).unwrap_or(0);
+        let core_bottom = line.iter().rposition(|&count| 2 * count >= peak).unwrap_or(0);
+        above += line[..core_top].iter().sum::<usize>();
+        below += line[core_bottom + 1..].iter().sum::<usize>();
+    }
+
+    (above, below)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An upright page of six lines of text, with an ascender every third
+    /// letter and a descender every sixth.
+    fn text_page() -> GrayImage {
+        let mut image = GrayImage::filled(200, 200, 245);
+        for line in 0..6 {
+            let top = 15 + line * 30;
+            for x in 10..190 {
+                let letter = x / 8;
+                if x % 8 >= 6 {
+                    continue;
+                }
+                let (first, last) = match letter % 6 {
+                    0     => (top, top + 16),
+                    3     => (top, top + 12),
+                    _     => (top + 6, top + 12),
+                };
+                for y in first..last {
+                    image.set(x, y, 0);
+                }
+            }
+        }
+
+        image
+    }
+
+    /// An image turned a quarter turn clockwise.
+    fn turned(image: &GrayImage) -> GrayImage {
+        let (width, height) = (image.width(), image.height());
+        let mut turned = GrayImage::filled(height, width, 255);
+        for y in 0..height {
+            for x in 0..width {
+                turned.set(height - 1 - y, x, image.get(x, y));
+            }
+        }
+
+        turned
+    }
+
+    #[test]
+    fn upright_text_needs_no_turn() {
+        let orientation = detect_orientation(&text_page());
+
+        assert_eq!(orientation.correction(), None);
+        assert!(orientation.confidence() > 0.3, "{}", orientation);
+        assert!(orientation.rotation_ops(0.0).is_empty());
+    }
+
+    #[test]
+    fn turned_text_is_turned_back() {
+        let mut page = text_page();
+        for &correction in [QuarterTurn::Rotate270, QuarterTurn::Rotate180, QuarterTurn::Rotate90].iter() {
+            page = turned(&page);
+            let orientation = detect_orientation(&page);
+            assert_eq!(orientation.correction(), Some(correction), "{}", orientation);
+            assert_eq!(orientation.text_angle(), correction.reverse().degrees());
+            assert_eq!(orientation.rotation_ops(0.3), vec![PageOps::Rotate(correction)]);
+        }
+    }
+
+    #[test]
+    fn pages_without_text_have_no_confidence() {
+        let orientation = detect_orientation(&GrayImage::filled(100, 100, 255));
+
+        assert_eq!(orientation.correction(), None);
+        assert_eq!(orientation.confidence(), 0.0);
+    }
+
+    #[test]
+    fn unsure_orientations_produce_no_rotation() {
+        let orientation = Orientation::new(Some(QuarterTurn::Rotate90), 0.4, OrientationMethod::TextLines);
+
+        assert_eq!(orientation.rotation_ops(0.4), vec![PageOps::Rotate(QuarterTurn::Rotate90)]);
+        assert!(orientation.rotation_ops(0.5).is_empty());
+    }
+}
@@ -0,0 +1,658 @@
+#![allow(dead_code)]
+use std::io;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::fmt;
+use image_tools::image_ops::{ImageDimensions, Pixels};
+use image_tools::tiff::{self, TiffFile, TiffDirectory};
+use pdf::images;
+use pdf::flate;
+
+
+/// How far a pixel must be from the paper colour to count as ink.
+pub const INK_FUZZ: u8 = 25;
+
+/// Pages are analysed at no more than about this many pixels, which keeps
+/// text lines and the ascenders and descenders of body text several pixels tall.
+pub const ANALYSIS_PIXELS: usize = 4_000_000;
+
+
+pub fn unsupported(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Cannot decode image: {}", message))
+}
+
+
+/// A rectangle of pixels, from its top left corner up to but not including
+/// its right and bottom edges.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub struct Rectangle {
+    left: Pixels,
+    top: Pixels,
+    right: Pixels,
+    bottom: Pixels,
+}
+
+impl Rectangle {
+    pub fn new(left: Pixels, top: Pixels, right: Pixels, bottom: Pixels) -> Rectangle {
+        Rectangle {
+            left: left,
+            top: top,
+            right: right.max(left),
+            bottom: bottom.max(top),
+        }
+    }
+
+    pub fn left(&self) -> Pixels {
+        self.left
+    }
+
+    pub fn top(&self) -> Pixels {
+        self.top
+    }
+
+    pub fn right(&self) -> Pixels {
+        self.right
+    }
+
+    pub fn bottom(&self) -> Pixels {
+        self.bottom
+    }
+
+    pub fn width(&self) -> Pixels {
+        self.right - self.left
+    }
+
+    pub fn height(&self) -> Pixels {
+        self.bottom - self.top
+    }
+
+    pub fn area(&self) -> usize {
+        self.width() * self.height()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.area() == 0
+    }
+
+    /// The smallest rectangle holding both rectangles.
+    pub fn union(&self, other: &Rectangle) -> Rectangle {
+        Rectangle::new(self.left.min(other.left), self.top.min(other.top),
+                       self.right.max(other.right), self.bottom.max(other.bottom))
+    }
+}
+
+/// Displayed as an ImageMagick geometry, `WxH+X+Y`.
+impl fmt::Display for Rectangle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}+{}+{}", self.width(), self.height(), self.left, self.top)
+    }
+}
+
+
+/// A group of pixels that touch, horizontally, vertically or diagonally.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct Component {
+    area: usize,
+    bounds: Rectangle,
+}
+
+impl Component {
+    /// The number of pixels in the component.
+    pub fn area(&self) -> usize {
+        self.area
+    }
+
+    pub fn bounds(&self) -> &Rectangle {
+        &self.bounds
+    }
+}
+
+
+/// An 8 bit grayscale image, 0 for black and 255 for white, which is what the
+/// page analyses work on whatever the format of the page.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct GrayImage {
+    width: Pixels,
+    height: Pixels,
+    pixels: Vec<u8>,
+}
+
+impl GrayImage {
+    pub fn new(width: Pixels, height: Pixels, pixels: Vec<u8>) -> GrayImage {
+        let mut pixels = pixels;
+        pixels.resize(width * height, 255);
+
+        GrayImage {
+            width: width,
+            height: height,
+            pixels: pixels,
+        }
+    }
+
+    pub fn filled(width: Pixels, height: Pixels, value: u8) -> GrayImage {
+        GrayImage::new(width, height, vec![value; width * height])
+    }
+
+    /// Decode an image file natively: TIFF files that are not CCITT or JPEG
+    /// compressed, non-interlaced PNG files, and PNM files. Other files give
+    /// an InvalidData error.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<GrayImage> {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+
+        GrayImage::from_bytes(data)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> io::Result<GrayImage> {
+        if TiffFile::is_tiff(&data) {
+            let file = TiffFile::parse(data)?;
+            let directory = file.directories().first().ok_or_else(|| unsupported("TIFF file without images"))?;
+            GrayImage::from_tiff(&file, directory)
+        } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            GrayImage::from_png(&data)
+        } else if data.len() > 2 && data[0] == b'P' && data[1] >= b'1' && data[1] <= b'6' {
+            GrayImage::from_pnm(&data)
+        } else {
+            Err(unsupported("not a TIFF, PNG or PNM file"))
+        }
+    }
+
+    pub fn from_tiff(file: &TiffFile, directory: &TiffDirectory) -> io::Result<GrayImage> {
+        match directory.compression() {
+            tiff::COMPRESSION_CCITT_RLE | tiff::COMPRESSION_CCITT_G3 |
+            tiff::COMPRESSION_CCITT_G4  | tiff::COMPRESSION_JPEG => {
+                return Err(unsupported(&format!("TIFF compression {}", directory.compression())));
+            }
+            _ => {}
+        }
+
+        let width = directory.width()? as usize;
+        let height = directory.height()? as usize;
+        let bits = directory.bits_per_sample()?;
+        let color = match directory.photometric()? {
+            tiff::PHOTOMETRIC_WHITE_IS_ZERO => SampleColor::InvertedGray,
+            tiff::PHOTOMETRIC_BLACK_IS_ZERO => SampleColor::Gray,
+            tiff::PHOTOMETRIC_RGB           => SampleColor::Rgb,
+            tiff::PHOTOMETRIC_SEPARATED     => SampleColor::Cmyk,
+            tiff::PHOTOMETRIC_PALETTE       => {
+                let map = directory.color_map().ok_or_else(|| unsupported("palette TIFF without a colour map"))?;
+                let entries = map.len() / 3;
+                let mut palette = Vec::with_capacity(map.len());
+                for i in 0..entries {
+                    palette.push((map[i] >> 8) as u8);
+                    palette.push((map[entries + i] >> 8) as u8);
+                    palette.push((map[2 * entries + i] >> 8) as u8);
+                }
+                SampleColor::Palette(palette)
+            }
+            other => return Err(unsupported(&format!("TIFF photometric interpretation {}", other))),
+        };
+
+        let samples = directory.decode_samples(file.data())?;
+        let layout = SampleLayout {
+            width: width,
+            height: height,
+            row_bytes: directory.row_bytes()?,
+            bits: bits,
+            samples_per_pixel: directory.samples_per_pixel() as usize,
+        };
+
+        Ok(GrayImage::new(width, height, layout.to_gray(&samples, &color)))
+    }
+
+    pub fn from_png(data: &[u8]) -> io::Result<GrayImage> {
+        let mut pos = 8;
+        let mut header = None;
+        let mut palette = Vec::new();
+        let mut image_data = Vec::new();
+
+        while pos + 8 <= data.len() {
+            let length = read_u32_be(&data[pos..]);
+            if pos + 12 + length > data.len() {
+                return Err(unsupported("truncated PNG chunk"));
+            }
+            let chunk = &data[pos + 8..pos + 8 + length];
+
+            match &data[pos + 4..pos + 8] {
+                b"IHDR" if chunk.len() >= 13 => header = Some((read_u32_be(chunk), read_u32_be(&chunk[4..]),
+                                                               chunk[8] as u32, chunk[9], chunk[12])),
+                b"PLTE" => palette = chunk.to_vec(),
+                b"IDAT" => image_data.extend_from_slice(chunk),
+                b"IEND" => break,
+                _ => {}
+            }
+
+            pos += 12 + length;
+        }
+
+        let (width, height, bits, color_type, interlace) = header.ok_or_else(|| unsupported("PNG without a header"))?;
+        if interlace != 0 {
+            return Err(unsupported("interlaced PNG"));
+        }
+
+        let (color, samples_per_pixel) = match color_type {
+            0 => (SampleColor::Gray, 1),
+            2 => (SampleColor::Rgb, 3),
+            3 => (SampleColor::Palette(palette), 1),
+            4 => (SampleColor::Gray, 2),
+            6 => (SampleColor::Rgb, 4),
+            _ => return Err(unsupported("bad PNG colour type")),
+        };
+
+        let layout = SampleLayout {
+            width: width,
+            height: height,
+            row_bytes: (width * samples_per_pixel * bits as usize).div_ceil(8),
+            bits: bits,
+            samples_per_pixel: samples_per_pixel,
+        };
+        let pixel_bytes = (samples_per_pixel * bits as usize).div_ceil(8).max(1);
+        let samples = images::png_unfilter(&flate::decompress(&image_data)?, layout.row_bytes, pixel_bytes, height)?;
+
+        Ok(GrayImage::new(width, height, layout.to_gray(&samples, &color)))
+    }
+
+    /// Decode any of the plain and raw PBM, PGM and PPM formats.
+    pub fn from_pnm(data: &[u8]) -> io::Result<GrayImage> {
+        let kind = data[1];
+        let mut pos = 2;
+        let header_fields = if kind == b'1' || kind == b'4' { 2 } else { 3 };
+        let mut header = Vec::new();
+        while header.len() < header_fields {
+            header.push(pnm_number(data, &mut pos)?);
+        }
+        let width = header[0];
+        let height = header[1];
+        let max_value = if header_fields == 3 { header[2].max(1) } else { 1 };
+
+        let channels = if kind == b'3' || kind == b'6' { 3 } else { 1 };
+        let mut values = Vec::with_capacity(width * height * channels);
+        match kind {
+            b'1' | b'2' | b'3' => {
+                while values.len() < width * height * channels {
+                    if kind == b'1' {
+                        // Plain PBM digits need not be separated.
+                        skip_pnm_space(data, &mut pos);
+                        let digit = *data.get(pos).ok_or_else(|| unsupported("truncated PBM file"))?;
+                        pos += 1;
+                        values.push(if digit == b'1' { 0 } else { 1 });
+                    } else {
+                        values.push(pnm_number(data, &mut pos)?);
+                    }
+                }
+            }
+            b'4' => {
+                // One bit per pixel, 1 for black, rows padded to a byte.
+                pos += 1;
+                let row_bytes = width.div_ceil(8);
+                for y in 0..height {
+                    let row = data.get(pos + y * row_bytes..pos + (y + 1) * row_bytes)
+                                  .ok_or_else(|| unsupported("truncated PBM file"))?;
+                    for x in 0..width {
+                        values.push(if row[x / 8] & (0x80 >> (x % 8)) != 0 { 0 } else { 1 });
+                    }
+                }
+            }
+            _ => {
+                pos += 1;
+                let sample_bytes = if max_value > 255 { 2 } else { 1 };
+                let raster = data.get(pos..pos + width * height * channels * sample_bytes)
+                                 .ok_or_else(|| unsupported("truncated PNM file"))?;
+                for sample in raster.chunks(sample_bytes) {
+                    values.push(sample.iter().fold(0, |value, &byte| (value << 8) | byte as usize));
+                }
+            }
+        }
+
+        let pixels = values.chunks(channels).map(|pixel| {
+            let scaled: Vec<u8> = pixel.iter().map(|&value| (value.min(max_value) * 255 / max_value) as u8).collect();
+            if channels == 3 { luma(scaled[0], scaled[1], scaled[2]) } else { scaled[0] }
+        }).collect();
+
+        Ok(GrayImage::new(width, height, pixels))
+    }
+
+    /// The image shrunk by a whole factor, each pixel the average of a
+    /// square of the original's.
+    pub fn downsample(&self, factor: usize) -> GrayImage {
+        let factor = factor.max(1);
+        let width = (self.width / factor).max(1);
+        let height = (self.height / factor).max(1);
+        let mut pixels = Vec::with_capacity(width * height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = 0;
+                let mut count = 0;
+                for sy in y * factor..((y + 1) * factor).min(self.height) {
+                    for sx in x * factor..((x + 1) * factor).min(self.width) {
+                        sum += self.get(sx, sy) as usize;
+                        count += 1;
+                    }
+                }
+                pixels.push(sum.checked_div(count).map_or(255, |mean| mean as u8));
+            }
+        }
+
+        GrayImage::new(width, height, pixels)
+    }
+
+    /// The image shrunk by the smallest whole factor that leaves it no more
+    /// than about `max_pixels` pixels.
+    pub fn downsample_to(&self, max_pixels: usize) -> GrayImage {
+        let ratio = (self.width * self.height) as f64 / max_pixels.max(1) as f64;
+        self.downsample(ratio.sqrt().ceil().max(1.0) as usize)
+    }
+
+    /// The part of the image inside a rectangle, which is clipped to the image.
+    pub fn crop(&self, region: &Rectangle) -> GrayImage {
+        let right = region.right().min(self.width);
+        let bottom = region.bottom().min(self.height);
+        let left = region.left().min(right);
+        let top = region.top().min(bottom);
+        let mut pixels = Vec::with_capacity((right - left) * (bottom - top));
+        for y in top..bottom {
+            pixels.extend_from_slice(&self.row(y)[left..right]);
+        }
+
+        GrayImage::new(right - left, bottom - top, pixels)
+    }
+
+    /// The image as a raw PGM file.
+    pub fn to_pgm(&self) -> Vec<u8> {
+        let mut data = format!("P5\n{} {}\n255\n", self.width, self.height).into_bytes();
+        data.extend_from_slice(&self.pixels);
+
+        data
+    }
+
+    pub fn width(&self) -> Pixels {
+        self.width
+    }
+
+    pub fn height(&self) -> Pixels {
+        self.height
+    }
+
+    pub fn dimensions(&self) -> ImageDimensions {
+        ImageDimensions::new(self.width, self.height)
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn get(&self, x: Pixels, y: Pixels) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: Pixels, y: Pixels, value: u8) {
+        self.pixels[y * self.width + x] = value;
+    }
+
+    pub fn row(&self, y: Pixels) -> &[u8] {
+        &self.pixels[y * self.width..(y + 1) * self.width]
+    }
+
+    pub fn histogram(&self) -> [usize; 256] {
+        let mut histogram = [0; 256];
+        for &pixel in self.pixels.iter() {
+            histogram[pixel as usize] += 1;
+        }
+
+        histogram
+    }
+
+    /// The paper colour, taken to be the most common gray level.
+    pub fn background(&self) -> u8 {
+        let histogram = self.histogram();
+        (0..256).max_by_key(|&level| (histogram[level], level)).unwrap_or(255) as u8
+    }
+
+    /// Which pixels differ from the background by more than the fuzz, a
+    /// percentage of the range from black to white.
+    pub fn foreground_mask(&self, background: u8, fuzz: u8) -> Vec<bool> {
+        let tolerance = fuzz.min(100) as i32 * 255 / 100;
+        self.pixels.iter().map(|&pixel| (pixel as i32 - background as i32).abs() > tolerance).collect()
+    }
+}
+
+
+/// The groups of touching pixels set in a mask of the given width.
+pub fn connected_components(mask: &[bool], width: Pixels) -> Vec<Component> {
+    label_components(mask, width).1
+}
+
+/// The groups of touching pixels set in a mask, and which group each pixel
+/// belongs to: the component at index `n` has label `n + 1`, and pixels
+/// that are not set have label 0.
+pub fn label_components(mask: &[bool], width: Pixels) -> (Vec<u32>, Vec<Component>) {
+    let mut labels = vec![0; mask.len()];
+    if width == 0 {
+        return (labels, Vec::new());
+    }
+    let height = mask.len() / width;
+    let mut stack = Vec::new();
+    let mut components = Vec::new();
+
+    for start in 0..mask.len() {
+        if !mask[start] || labels[start] != 0 {
+            continue;
+        }
+
+        let label = components.len() as u32 + 1;
+        labels[start] = label;
+        stack.push(start);
+        let mut area = 0;
+        let (mut left, mut top, mut right, mut bottom) = (width, height, 0, 0);
+        while let Some(index) = stack.pop() {
+            let (x, y) = (index % width, index / width);
+            area += 1;
+            left = left.min(x);
+            right = right.max(x + 1);
+            top = top.min(y);
+            bottom = bottom.max(y + 1);
+
+            for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                    let neighbour = ny * width + nx;
+                    if mask[neighbour] && labels[neighbour] == 0 {
+                        labels[neighbour] = label;
+                        stack.push(neighbour);
+                    }
+                }
+            }
+        }
+
+        components.push(Component {
+            area: area,
+            bounds: Rectangle::new(left, top, right, bottom),
+        });
+    }
+
+    (labels, components)
+}
+
+/// How many of the components of a mask have no pixel left set in another
+/// mask of the same size.
+pub fn vanished_components(before: &[bool], after: &[bool], width: Pixels) -> usize {
+    let (labels, components) = label_components(before, width);
+    let mut remaining = vec![false; components.len()];
+    for (&label, &set) in labels.iter().zip(after.iter()) {
+        if label != 0 && set {
+            remaining[label as usize - 1] = true;
+        }
+    }
+
+    remaining.iter().filter(|&&remains| !remains).count()
+}
+
+
+/// How the samples of a decoded image are laid out.
+struct SampleLayout {
+    width: usize,
+    height: usize,
+    row_bytes: usize,
+    bits: u32,
+    samples_per_pixel: usize,
+}
+
+/// What the samples of a decoded image mean. Samples past the colour
+/// channels, such as alpha, are ignored.
+enum SampleColor {
+    Gray,
+    InvertedGray,
+    Rgb,
+    Cmyk,
+    /// Indices into a table of RGB triples.
+    Palette(Vec<u8>),
+}
+
+impl SampleLayout {
+    /// A sample's value, unscaled.
+    fn raw_sample(&self, row: &[u8], index: usize) -> usize {
+        match self.bits {
+            8  => row[index] as usize,
+            16 => ((row[2 * index] as usize) << 8) | row[2 * index + 1] as usize,
+            bits => {
+                let bit = index * bits as usize;
+                let shift = 8 - bits as usize - bit % 8;
+                (row[bit / 8] as usize >> shift) & ((1 << bits) - 1)
+            }
+        }
+    }
+
+    fn sample(&self, row: &[u8], index: usize) -> u8 {
+        match self.bits {
+            8  => row[index],
+            16 => row[2 * index],
+            bits => (self.raw_sample(row, index) * 255 / ((1 << bits) - 1)) as u8,
+        }
+    }
+
+    fn to_gray(&self, samples: &[u8], color: &SampleColor) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity(self.width * self.height);
+
+        for y in 0..self.height {
+            let start = y * self.row_bytes;
+            let row = match samples.get(start..start + self.row_bytes) {
+                Some(row) => row,
+                None      => break,
+            };
+
+            for x in 0..self.width {
+                let first = x * self.samples_per_pixel;
+                let pixel = match *color {
+                    SampleColor::Gray         => self.sample(row, first),
+                    SampleColor::InvertedGray => 255 - self.sample(row, first),
+                    SampleColor::Rgb => {
+                        luma(self.sample(row, first), self.sample(row, first + 1), self.sample(row, first + 2))
+                    }
+                    SampleColor::Cmyk => {
+                        let k = 255 - self.sample(row, first + 3) as u32;
+                        let channel = |i| ((255 - self.sample(row, first + i) as u32) * k / 255) as u8;
+                        luma(channel(0), channel(1), channel(2))
+                    }
+                    SampleColor::Palette(ref palette) => {
+                        let entry = 3 * self.raw_sample(row, first);
+                        match palette.get(entry..entry + 3) {
+                            Some(rgb) => luma(rgb[0], rgb[1], rgb[2]),
+                            None      => 0,
+                        }
+                    }
+                };
+                pixels.push(pixel);
+            }
+        }
+
+        pixels
+    }
+}
+
+fn luma(red: u8, green: u8, blue: u8) -> u8 {
+    ((299 * red as u32 + 587 * green as u32 + 114 * blue as u32 + 500) / 1000) as u8
+}
+
+fn read_u32_be(bytes: &[u8]) -> usize {
+    ((bytes[0] as usize) << 24) | ((bytes[1] as usize) << 16) | ((bytes[2] as usize) << 8) | bytes[3] as usize
+}
+
+fn skip_pnm_space(data: &[u8], pos: &mut usize) {
+    while *pos < data.len() {
+        match data[*pos] {
+            b'#' => {
+                while *pos < data.len() && data[*pos] != b'\n' {
+                    *pos += 1;
+                }
+            }
+            byte if byte.is_ascii_whitespace() => *pos += 1,
+            _ => break,
+        }
+    }
+}
+
+fn pnm_number(data: &[u8], pos: &mut usize) -> io::Result<usize> {
+    skip_pnm_space(data, pos);
+    let start = *pos;
+    while *pos < data.len() && data[*pos].is_ascii_digit() {
+        *pos += 1;
+    }
+    if start == *pos {
+        return Err(unsupported("bad PNM header"));
+    }
+
+    Ok(data[start..*pos].iter().fold(0, |value, &digit| value * 10 + (digit - b'0') as usize))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagonal_neighbours_are_one_component() {
+        let mask = [
+            true,  false, false, false,
+            false, true,  false, true,
+            false, false, false, true,
+        ];
+        let (labels, components) = label_components(&mask, 4);
+
+        assert_eq!(components.len(), 2);
+        assert_eq!(components[0].area(), 2);
+        assert_eq!(components[0].bounds(), &Rectangle::new(0, 0, 2, 2));
+        assert_eq!(components[1].bounds(), &Rectangle::new(3, 1, 4, 3));
+        assert_eq!(labels, vec![1, 0, 0, 0, 0, 1, 0, 2, 0, 0, 0, 2]);
+    }
+
+    #[test]
+    fn plain_and_raw_pnm_files_decode_alike() {
+        let plain = GrayImage::from_pnm(b"P1\n# a comment\n3 2\n010\n1 0 0\n").unwrap();
+        let raw = GrayImage::from_pnm(b"P4\n3 2\n\x40\x80").unwrap();
+        assert_eq!(plain.pixels(), &[255, 0, 255, 0, 255, 255]);
+        assert_eq!(raw, plain);
+
+        let color = GrayImage::from_pnm(b"P3 2 1 255 255 255 255 0 0 0").unwrap();
+        assert_eq!(color.pixels(), &[255, 0]);
+    }
+
+    #[test]
+    fn downsampled_pixels_are_averages() {
+        let image = GrayImage::new(4, 2, vec![0, 100, 200, 200, 100, 200, 200, 200]);
+        let small = image.downsample(2);
+
+        assert_eq!(small.dimensions(), ImageDimensions::new(2, 1));
+        assert_eq!(small.pixels(), &[100, 200]);
+        assert_eq!(image.downsample_to(8), image);
+    }
+
+    #[test]
+    fn crops_are_clipped_to_the_image() {
+        let image = GrayImage::new(3, 3, (0..9).collect());
+        let cropped = image.crop(&Rectangle::new(1, 2, 10, 10));
+
+        assert_eq!(cropped.dimensions(), ImageDimensions::new(2, 1));
+        assert_eq!(cropped.pixels(), &[7, 8]);
+    }
+}
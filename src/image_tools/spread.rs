@@ -0,0 +1,256 @@
+#![allow(dead_code)]
+use std::fmt;
+use std::path::Path;
+use image_tools::image_ops::{Page, Pixels, ImageDimensions, FilePath};
+use image_tools::image_ops::{OperationPlan, CompoundPageOperation};
+use image_tools::raster::{GrayImage, Rectangle, INK_FUZZ};
+
+/// The ink in each column is averaged over about this share of the page's
+/// width, in thousandths, so that a gap between two letters is not taken
+/// for the gutter.
+const SMOOTHING_PER_MILLE: usize = 10;
+
+
+/// Where a spread of two facing pages is split.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum SpreadSplit {
+    /// At the gutter, the column with the least ink within this percentage
+    /// of the spread's width either side of its centre.
+    Gutter(u8),
+    /// At this many pixels from the left edge.
+    At(Pixels),
+}
+
+impl fmt::Display for SpreadSplit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SpreadSplit::Gutter(search) => write!(f, "Gutter(within {}% of the centre)", search),
+            SpreadSplit::At(x)          => write!(f, "At({} pixels)", x),
+        }
+    }
+}
+
+
+/// The column with the least ink within `search` percent of the width either
+/// side of the centre of a spread. Of columns with as little ink, the one
+/// nearest the centre is taken.
+pub fn detect_gutter(image: &GrayImage, search: u8) -> Pixels {
+    let (width, height) = (image.width(), image.height());
+    if width < 2 {
+        return width;
+    }
+
+    let mask = image.foreground_mask(image.background(), INK_FUZZ);
+    let mut columns = vec![0usize; width];
+    for y in 0..height {
+        for (x, &ink) in mask[y * width..(y + 1) * width].iter().enumerate() {
+            if ink {
+                columns[x] += 1;
+            }
+        }
+    }
+
+    let radius = width * SMOOTHING_PER_MILLE / 2000;
+    let smoothed = |x: Pixels| -> usize {
+        let (first, last) = (x.saturating_sub(radius), (x + radius).min(width - 1));
+        columns[first..last + 1].iter().sum::<usize>() / (last + 1 - first)
+    };
+
+    let centre = width / 2;
+    let reach = width * search.min(50) as usize / 100;
+    let first = centre.saturating_sub(reach).max(1);
+    let last = (centre + reach).min(width - 1);
+
+    (first..last + 1)
+        .min_by_key(|&x| (smoothed(x), x.abs_diff(centre)))
+        .unwrap_or(centre)
+}
+
+/// The column a spread is split at, kept at least one pixel from either edge.
+pub fn split_position(image: &GrayImage, split: SpreadSplit) -> Pixels {
+    match split {
+        SpreadSplit::Gutter(search) => clamp_split(detect_gutter(image, search), image.width()),
+        SpreadSplit::At(x)          => clamp_split(x, image.width()),
+    }
+}
+
+/// A column kept at least one pixel from either edge of a spread of the given width.
+pub fn clamp_split(x: Pixels, width: Pixels) -> Pixels {
+    x.max(1).min(width.saturating_sub(1))
+}
+
+
+/// A spread split into its left and right pages, in reading order, and the
+/// column it was split at.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SplitSpread {
+    gutter: Pixels,
+    left: Page,
+    right: Page,
+}
+
+impl SplitSpread {
+    /// The pages a spread splits into at a column. Each is written next to
+    /// the spread, named after it with `-1` for the left page and `-2` for
+    /// the right one.
+    pub fn new(spread: &Page, gutter: Pixels) -> SplitSpread {
+        let (width, height) = (spread.dimensions().x_pixels(), spread.dimensions().y_pixels());
+        let gutter = gutter.min(width);
+
+        SplitSpread {
+            gutter: gutter,
+            left: half_page(spread, 1, ImageDimensions::new(gutter, height)),
+            right: half_page(spread, 2, ImageDimensions::new(width - gutter, height)),
+        }
+    }
+
+    pub fn gutter(&self) -> Pixels {
+        self.gutter
+    }
+
+    pub fn left(&self) -> &Page {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Page {
+        &self.right
+    }
+
+    /// The part of the spread that becomes the left page.
+    pub fn left_region(&self) -> Rectangle {
+        Rectangle::new(0, 0, self.gutter, self.left.dimensions().y_pixels())
+    }
+
+    /// The part of the spread that becomes the right page.
+    pub fn right_region(&self) -> Rectangle {
+        Rectangle::new(self.gutter, 0,
+                       self.gutter + self.right.dimensions().x_pixels(), self.right.dimensions().y_pixels())
+    }
+
+    /// The pages, in reading order.
+    pub fn pages(&self) -> Vec<Page> {
+        vec![self.left.clone(), self.right.clone()]
+    }
+}
+
+impl fmt::Display for SplitSpread {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "split at {} pixels into {} and {}", self.gutter, self.left, self.right)
+    }
+}
+
+fn half_page(spread: &Page, number: usize, dimensions: ImageDimensions) -> Page {
+    let path = Path::new(spread.file_path());
+    let stem = path.file_stem()
+                   .map(|stem| stem.to_string_lossy().into_owned())
+                   .unwrap_or(String::from("page"));
+    let file_name = match path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, number, extension.to_string_lossy()),
+        None            => format!("{}-{}", stem, number),
+    };
+    let file_path: FilePath = path.with_file_name(&file_name).to_string_lossy().into_owned();
+
+    Page::new(file_name, spread.file_extension().clone(), file_path, dimensions, spread.resolution().clone())
+}
+
+
+/// Put the pages of a split spread in its place in a plan, in reading order,
+/// with the operations planned for each of them.
+pub fn plan_split<Op, F>(plan: &mut OperationPlan<Op>, spread: &Page, split: &SplitSpread, mut plan_page: F) -> bool
+    where Op: Clone,
+          F: FnMut(&Page) -> CompoundPageOperation<Op>
+{
+    let replacements = split.pages().into_iter().map(|page| {
+        let op = plan_page(&page);
+        (page, op)
+    }).collect();
+
+    plan.replace_page(spread, replacements)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image_tools::image_ops::{ImageFileFormat, ImageResolution, ResolutionUnits, PageOps};
+    use image_tools::document::{DocumentMetadata, OutlineEntry};
+
+    /// A spread 200 pixels wide with a block of text on each page, the
+    /// gutter between them from column 100 up to column 120.
+    fn spread_image() -> GrayImage {
+        let mut image = GrayImage::filled(200, 100, 250);
+        for y in 10..90 {
+            for x in (15..100).chain(120..190) {
+                if y % 6 < 3 && x % 5 != 0 {
+                    image.set(x, y, 0);
+                }
+            }
+        }
+
+        image
+    }
+
+    fn page(name: &str, width: Pixels) -> Page {
+        Page::new(String::from(name), ImageFileFormat::TIFF, format!("scans/{}", name),
+                  ImageDimensions::new(width, 100), ImageResolution::new(300, ResolutionUnits::PixelsPerInch))
+    }
+
+    #[test]
+    fn gutters_are_the_emptiest_columns_near_the_centre() {
+        let gutter = detect_gutter(&spread_image(), 20);
+        assert!((100..120).contains(&gutter), "gutter at {}", gutter);
+
+        // Without a range to search, the centre is taken.
+        assert_eq!(detect_gutter(&spread_image(), 0), 100);
+    }
+
+    #[test]
+    fn splits_stay_inside_the_spread() {
+        assert_eq!(split_position(&spread_image(), SpreadSplit::At(0)), 1);
+        assert_eq!(split_position(&spread_image(), SpreadSplit::At(500)), 199);
+        assert_eq!(clamp_split(80, 200), 80);
+    }
+
+    #[test]
+    fn halves_are_named_after_the_spread() {
+        let split = SplitSpread::new(&page("spread.tif", 200), 110);
+
+        assert_eq!(split.left().file_path(), "scans/spread-1.tif");
+        assert_eq!(split.right().file_name(), "spread-2.tif");
+        assert_eq!(split.left().dimensions(), &ImageDimensions::new(110, 100));
+        assert_eq!(split.right().dimensions(), &ImageDimensions::new(90, 100));
+        assert_eq!(split.left_region(), Rectangle::new(0, 0, 110, 100));
+        assert_eq!(split.right_region(), Rectangle::new(110, 0, 200, 100));
+    }
+
+    #[test]
+    fn split_halves_take_the_spreads_place_in_the_plan() {
+        let (title, spread, last) = (page("title.tif", 100), page("spread.tif", 200), page("last.tif", 100));
+        let mut plan = OperationPlan::new();
+        for page in [&title, &spread, &last].iter() {
+            plan.insert((*page).clone(), CompoundPageOperation::make_no_op(page.file_name().clone(),
+                                                                           page.file_path().clone()));
+        }
+        let mut metadata = DocumentMetadata::new();
+        metadata.add_outline_entry(OutlineEntry::new("Plates", 2));
+        metadata.add_outline_entry(OutlineEntry::new("Index", 3));
+        plan.set_metadata(metadata);
+
+        let split = SplitSpread::new(&spread, 110);
+        assert!(plan_split(&mut plan, &spread, &split, |half| {
+            CompoundPageOperation::new(half.file_name().clone(), half.file_path().clone(),
+                                       &[PageOps::TrimLeftEdge(5)])
+        }));
+
+        assert_eq!(plan.pages(), &[title.clone(), split.left().clone(), split.right().clone(), last]);
+        assert!(plan.get(&spread).is_none());
+        assert!(!plan.get(split.right()).unwrap().is_no_op());
+        let pages: Vec<usize> = plan.metadata().outline().iter().map(|entry| entry.page_number()).collect();
+        assert_eq!(pages, vec![2, 4]);
+
+        // A page that is not in the plan is left alone.
+        assert!(!plan_split(&mut plan, &spread, &split, |half| {
+            CompoundPageOperation::make_no_op(half.file_name().clone(), half.file_path().clone())
+        }));
+    }
+}
@@ -0,0 +1,932 @@
+#![allow(dead_code)]
+use std::io;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::fmt;
+use std::collections::HashMap;
+use image_tools::image_ops::{ImageDimensions, ImageResolution, ResolutionUnits};
+use pdf::flate;
+
+
+pub const TAG_NEW_SUBFILE_TYPE: u16       = 254;
+pub const TAG_IMAGE_WIDTH: u16            = 256;
+pub const TAG_IMAGE_LENGTH: u16           = 257;
+pub const TAG_BITS_PER_SAMPLE: u16        = 258;
+pub const TAG_COMPRESSION: u16            = 259;
+pub const TAG_PHOTOMETRIC: u16            = 262;
+pub const TAG_FILL_ORDER: u16             = 266;
+pub const TAG_STRIP_OFFSETS: u16          = 273;
+pub const TAG_SAMPLES_PER_PIXEL: u16      = 277;
+pub const TAG_ROWS_PER_STRIP: u16         = 278;
+pub const TAG_STRIP_BYTE_COUNTS: u16      = 279;
+pub const TAG_X_RESOLUTION: u16           = 282;
+pub const TAG_Y_RESOLUTION: u16           = 283;
+pub const TAG_PLANAR_CONFIGURATION: u16   = 284;
+pub const TAG_PAGE_NUMBER: u16            = 297;
+pub const TAG_T4_OPTIONS: u16             = 292;
+pub const TAG_T6_OPTIONS: u16             = 293;
+pub const TAG_RESOLUTION_UNIT: u16        = 296;
+pub const TAG_PREDICTOR: u16              = 317;
+pub const TAG_COLOR_MAP: u16              = 320;
+pub const TAG_TILE_WIDTH: u16             = 322;
+pub const TAG_EXTRA_SAMPLES: u16          = 338;
+
+pub const COMPRESSION_NONE: u32           = 1;
+pub const COMPRESSION_CCITT_RLE: u32      = 2;
+pub const COMPRESSION_CCITT_G3: u32       = 3;
+pub const COMPRESSION_CCITT_G4: u32       = 4;
+pub const COMPRESSION_LZW: u32            = 5;
+pub const COMPRESSION_JPEG: u32           = 7;
+pub const COMPRESSION_DEFLATE: u32        = 8;
+pub const COMPRESSION_PACKBITS: u32       = 32773;
+pub const COMPRESSION_DEFLATE_OLD: u32    = 32946;
+
+pub const PHOTOMETRIC_WHITE_IS_ZERO: u32  = 0;
+pub const PHOTOMETRIC_BLACK_IS_ZERO: u32  = 1;
+pub const PHOTOMETRIC_RGB: u32            = 2;
+pub const PHOTOMETRIC_PALETTE: u32        = 3;
+pub const PHOTOMETRIC_SEPARATED: u32      = 5;
+
+const TYPE_BYTE: u16      = 1;
+const TYPE_ASCII: u16     = 2;
+const TYPE_SHORT: u16     = 3;
+const TYPE_LONG: u16      = 4;
+const TYPE_RATIONAL: u16  = 5;
+const TYPE_UNDEFINED: u16 = 7;
+
+
+pub fn unsupported(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Unsupported TIFF: {}", message))
+}
+
+fn malformed(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Malformed TIFF: {}", message))
+}
+
+
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum ByteOrder {
+    LittleEndian,
+    BigEndian,
+}
+
+impl ByteOrder {
+    pub fn read_u16(&self, bytes: &[u8]) -> u16 {
+        match *self {
+            ByteOrder::LittleEndian => (bytes[0] as u16) | (bytes[1] as u16) << 8,
+            ByteOrder::BigEndian    => (bytes[1] as u16) | (bytes[0] as u16) << 8,
+        }
+    }
+
+    pub fn read_u32(&self, bytes: &[u8]) -> u32 {
+        match *self {
+            ByteOrder::LittleEndian => {
+                (bytes[0] as u32) | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+            }
+            ByteOrder::BigEndian    => {
+                (bytes[3] as u32) | (bytes[2] as u32) << 8 | (bytes[1] as u32) << 16 | (bytes[0] as u32) << 24
+            }
+        }
+    }
+}
+
+
+/// A single field of an image file directory. The value bytes are kept in the
+/// byte order of the file they were read from.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TiffEntry {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    value: Vec<u8>,
+}
+
+fn type_size(field_type: u16) -> usize {
+    match field_type {
+        TYPE_SHORT         => 2,
+        TYPE_LONG | 9 | 11 => 4,
+        TYPE_RATIONAL | 10 | 12 => 8,
+        _                  => 1,
+    }
+}
+
+impl TiffEntry {
+    pub fn tag(&self) -> u16 {
+        self.tag
+    }
+
+    /// The entry as a value that can be written to another file, for the
+    /// field types TiffValue can hold.
+    pub fn to_value(&self, byte_order: ByteOrder) -> Option<TiffValue> {
+        let size = type_size(self.field_type);
+        match self.field_type {
+            TYPE_BYTE      => Some(TiffValue::Byte(self.value.clone())),
+            TYPE_UNDEFINED => Some(TiffValue::Undefined(self.value.clone())),
+            TYPE_ASCII     => {
+                let text: Vec<u8> = self.value.iter().cloned().take_while(|&byte| byte != 0).collect();
+                Some(TiffValue::Ascii(String::from_utf8_lossy(&text).into_owned()))
+            }
+            TYPE_SHORT     => Some(TiffValue::Short(self.value.chunks(size).map(|v| byte_order.read_u16(v)).collect())),
+            TYPE_LONG      => Some(TiffValue::Long(self.value.chunks(size).map(|v| byte_order.read_u32(v)).collect())),
+            TYPE_RATIONAL  => Some(TiffValue::Rational(self.value.chunks(size).map(|v| {
+                (byte_order.read_u32(&v[0..4]), byte_order.read_u32(&v[4..8]))
+            }).collect())),
+            _              => None,
+        }
+    }
+}
+
+
+/// An image file directory: the description of a single image in a TIFF file.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TiffDirectory {
+    byte_order: ByteOrder,
+    entries: Vec<TiffEntry>,
+}
+
+impl TiffDirectory {
+    fn entry(&self, tag: u16) -> Option<&TiffEntry> {
+        self.entries.iter().find(|entry| entry.tag == tag)
+    }
+
+    pub fn entries(&self) -> &[TiffEntry] {
+        self.entries.as_ref()
+    }
+
+    pub fn byte_order(&self) -> ByteOrder {
+        self.byte_order
+    }
+
+    /// The integer values of a field of type BYTE, SHORT or LONG.
+    pub fn get_u32s(&self, tag: u16) -> Option<Vec<u32>> {
+        let entry = self.entry(tag)?;
+        let size = type_size(entry.field_type);
+        let mut values = Vec::new();
+
+        for chunk in entry.value.chunks(size) {
+            let value = match entry.field_type {
+                TYPE_BYTE  => chunk[0] as u32,
+                TYPE_SHORT => self.byte_order.read_u16(chunk) as u32,
+                TYPE_LONG  => self.byte_order.read_u32(chunk),
+                _          => return None,
+            };
+            values.push(value);
+        }
+
+        Some(values)
+    }
+
+    pub fn get_u32(&self, tag: u16) -> Option<u32> {
+        self.get_u32s(tag).and_then(|values| values.first().cloned())
+    }
+
+    pub fn get_rational(&self, tag: u16) -> Option<f64> {
+        let entry = self.entry(tag)?;
+        if entry.field_type != TYPE_RATIONAL || entry.value.len() < 8 {
+            return None;
+        }
+
+        let numerator = self.byte_order.read_u32(&entry.value[0..4]);
+        let denominator = self.byte_order.read_u32(&entry.value[4..8]);
+        if denominator == 0 {
+            None
+        } else {
+            Some(numerator as f64 / denominator as f64)
+        }
+    }
+
+    pub fn width(&self) -> io::Result<u32> {
+        self.get_u32(TAG_IMAGE_WIDTH).ok_or_else(|| malformed("missing ImageWidth"))
+    }
+
+    pub fn height(&self) -> io::Result<u32> {
+        self.get_u32(TAG_IMAGE_LENGTH).ok_or_else(|| malformed("missing ImageLength"))
+    }
+
+    pub fn dimensions(&self) -> io::Result<ImageDimensions> {
+        Ok(ImageDimensions::new(self.width()? as usize, self.height()? as usize))
+    }
+
+    pub fn compression(&self) -> u32 {
+        self.get_u32(TAG_COMPRESSION).unwrap_or(COMPRESSION_NONE)
+    }
+
+    pub fn photometric(&self) -> io::Result<u32> {
+        self.get_u32(TAG_PHOTOMETRIC).ok_or_else(|| malformed("missing PhotometricInterpretation"))
+    }
+
+    pub fn samples_per_pixel(&self) -> u32 {
+        self.get_u32(TAG_SAMPLES_PER_PIXEL).unwrap_or(1)
+    }
+
+    /// The bits per sample, which TIFF allows to differ between samples
+    /// but which must be the same for every sample here. An empty tag is
+    /// taken as the default of 1.
+    pub fn bits_per_sample(&self) -> io::Result<u32> {
+        let bits = self.get_u32s(TAG_BITS_PER_SAMPLE).unwrap_or_default();
+        let first = bits.first().cloned().unwrap_or(1);
+        if bits.iter().any(|b| *b != first) {
+            return Err(unsupported("differing bits per sample"));
+        }
+
+        Ok(first)
+    }
+
+    pub fn fill_order(&self) -> u32 {
+        self.get_u32(TAG_FILL_ORDER).unwrap_or(1)
+    }
+
+    pub fn predictor(&self) -> u32 {
+        self.get_u32(TAG_PREDICTOR).unwrap_or(1)
+    }
+
+    pub fn extra_samples(&self) -> Vec<u32> {
+        self.get_u32s(TAG_EXTRA_SAMPLES).unwrap_or_default()
+    }
+
+    pub fn color_map(&self) -> Option<Vec<u32>> {
+        self.get_u32s(TAG_COLOR_MAP)
+    }
+
+    /// The horizontal resolution, if the file records one in absolute units.
+    pub fn resolution(&self) -> Option<ImageResolution> {
+        let amount = self.get_rational(TAG_X_RESOLUTION)?;
+        match self.get_u32(TAG_RESOLUTION_UNIT).unwrap_or(2) {
+            2 => Some(ImageResolution::new(amount.round() as usize, ResolutionUnits::PixelsPerInch)),
+            3 => Some(ImageResolution::new(amount.round() as usize, ResolutionUnits::PixelsPerCentimeter)),
+            _ => None,
+        }
+    }
+
+    /// The rows in each strip; the last strip may hold fewer.
+    pub fn rows_per_strip(&self) -> io::Result<usize> {
+        let height = self.height()? as usize;
+        Ok((self.get_u32(TAG_ROWS_PER_STRIP).unwrap_or(u32::MAX) as usize).min(height).max(1))
+    }
+
+    /// The bytes in one row of decoded samples.
+    pub fn row_bytes(&self) -> io::Result<usize> {
+        let bits = self.width()? as usize * self.samples_per_pixel() as usize * self.bits_per_sample()? as usize;
+        Ok(bits.div_ceil(8))
+    }
+
+    fn check_layout(&self) -> io::Result<()> {
+        if self.entry(TAG_TILE_WIDTH).is_some() {
+            return Err(unsupported("tiled images"));
+        }
+
+        if self.get_u32(TAG_PLANAR_CONFIGURATION).unwrap_or(1) != 1 {
+            return Err(unsupported("planar sample layout"));
+        }
+
+        Ok(())
+    }
+
+    /// The raw, still compressed, strips of the image.
+    pub fn strips<'a>(&self, data: &'a [u8]) -> io::Result<Vec<&'a [u8]>> {
+        self.check_layout()?;
+
+        let offsets = self.get_u32s(TAG_STRIP_OFFSETS).ok_or_else(|| malformed("missing StripOffsets"))?;
+        let counts = self.get_u32s(TAG_STRIP_BYTE_COUNTS).ok_or_else(|| malformed("missing StripByteCounts"))?;
+        if offsets.len() != counts.len() {
+            return Err(malformed("StripOffsets and StripByteCounts differ in length"));
+        }
+
+        let mut strips = Vec::new();
+        for (offset, count) in offsets.iter().zip(counts.iter()) {
+            let start = *offset as usize;
+            let end = start + *count as usize;
+            if end > data.len() {
+                return Err(malformed("strip extends past the end of the file"));
+            }
+            strips.push(&data[start..end]);
+        }
+
+        Ok(strips)
+    }
+
+    /// Decompress the image into rows of packed samples, most significant bit first,
+    /// with the horizontal predictor undone and 16 bit samples in big endian order.
+    pub fn decode_samples(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let row_bytes = self.row_bytes()?;
+        let height = self.height()? as usize;
+        let rows_per_strip = self.rows_per_strip()?;
+
+        let mut samples = Vec::with_capacity(row_bytes * height);
+        for strip in self.strips(data)? {
+            let mut decoded = match self.compression() {
+                COMPRESSION_NONE                             => strip.to_vec(),
+                COMPRESSION_LZW                              => lzw_decode(strip)?,
+                COMPRESSION_PACKBITS                         => packbits_decode(strip),
+                COMPRESSION_DEFLATE | COMPRESSION_DEFLATE_OLD => flate::decompress(strip)?,
+                other => return Err(unsupported(&format!("compression {} cannot be decoded", other))),
+            };
+
+            // A strip may be padded or, at the end of the image, short.
+            decoded.resize(row_bytes * rows_per_strip, 0);
+            samples.append(&mut decoded);
+        }
+        samples.resize(row_bytes * height, 0);
+
+        if self.fill_order() == 2 {
+            reverse_bits(&mut samples);
+        }
+
+        let bits = self.bits_per_sample()?;
+        let samples_per_pixel = self.samples_per_pixel() as usize;
+        if bits == 16 && self.byte_order == ByteOrder::LittleEndian {
+            for pair in samples.chunks_mut(2) {
+                pair.swap(0, 1);
+            }
+        }
+
+        match self.predictor() {
+            1 => {}
+            2 => undo_horizontal_predictor(&mut samples, row_bytes, samples_per_pixel, bits)?,
+            _ => return Err(unsupported("floating point predictor")),
+        }
+
+        Ok(samples)
+    }
+}
+
+
+/// A parsed TIFF file, holding the file contents and every image file directory.
+#[derive(Clone, Debug)]
+pub struct TiffFile {
+    data: Vec<u8>,
+    byte_order: ByteOrder,
+    directories: Vec<TiffDirectory>,
+}
+
+impl TiffFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<TiffFile> {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+
+        TiffFile::parse(data)
+    }
+
+    pub fn is_tiff(data: &[u8]) -> bool {
+        data.starts_with(b"II*\0") || data.starts_with(b"MM\0*")
+    }
+
+    pub fn parse(data: Vec<u8>) -> io::Result<TiffFile> {
+        if data.len() < 8 {
+            return Err(malformed("truncated header"));
+        }
+
+        let byte_order = if data.starts_with(b"II*\0") {
+            ByteOrder::LittleEndian
+        } else if data.starts_with(b"MM\0*") {
+            ByteOrder::BigEndian
+        } else {
+            return Err(malformed("bad header"));
+        };
+
+        let mut directories = Vec::new();
+        let mut offset = byte_order.read_u32(&data[4..8]) as usize;
+        let mut visited = Vec::new();
+
+        while offset != 0 {
+            // Guard against directory chains that loop back on themselves.
+            if visited.contains(&offset) || offset + 2 > data.len() {
+                return Err(malformed("bad directory offset"));
+            }
+            visited.push(offset);
+
+            let count = byte_order.read_u16(&data[offset..offset + 2]) as usize;
+            let end = offset + 2 + 12 * count;
+            if end + 4 > data.len() {
+                return Err(malformed("directory extends past the end of the file"));
+            }
+
+            let mut entries = Vec::new();
+            for i in 0..count {
+                let raw = &data[offset + 2 + 12 * i..offset + 14 + 12 * i];
+                let tag = byte_order.read_u16(&raw[0..2]);
+                let field_type = byte_order.read_u16(&raw[2..4]);
+                let value_count = byte_order.read_u32(&raw[4..8]);
+                let size = type_size(field_type) * value_count as usize;
+
+                let value = if size <= 4 {
+                    raw[8..8 + size].to_vec()
+                } else {
+                    let value_offset = byte_order.read_u32(&raw[8..12]) as usize;
+                    if value_offset + size > data.len() {
+                        return Err(malformed("field value extends past the end of the file"));
+                    }
+                    data[value_offset..value_offset + size].to_vec()
+                };
+
+                entries.push(TiffEntry {
+                    tag: tag,
+                    field_type: field_type,
+                    count: value_count,
+                    value: value,
+                });
+            }
+
+            directories.push(TiffDirectory {
+                byte_order: byte_order,
+                entries: entries,
+            });
+
+            offset = byte_order.read_u32(&data[end..end + 4]) as usize;
+        }
+
+        if directories.is_empty() {
+            return Err(malformed("no image file directories"));
+        }
+
+        Ok(TiffFile {
+            data: data,
+            byte_order: byte_order,
+            directories: directories,
+        })
+    }
+
+    pub fn data(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    pub fn byte_order(&self) -> ByteOrder {
+        self.byte_order
+    }
+
+    pub fn directories(&self) -> &[TiffDirectory] {
+        self.directories.as_ref()
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.directories.len()
+    }
+}
+
+impl fmt::Display for TiffFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TiffFile({} Bytes, {} Pages)", self.data.len(), self.directories.len())
+    }
+}
+
+
+pub fn reverse_bits(data: &mut [u8]) {
+    for byte in data.iter_mut() {
+        *byte = byte.reverse_bits();
+    }
+}
+
+fn undo_horizontal_predictor(samples: &mut [u8], row_bytes: usize, samples_per_pixel: usize, bits: u32) -> io::Result<()> {
+    match bits {
+        8 => {
+            for row in samples.chunks_mut(row_bytes) {
+                for i in samples_per_pixel..row.len() {
+                    row[i] = row[i].wrapping_add(row[i - samples_per_pixel]);
+                }
+            }
+        }
+        16 => {
+            let stride = 2 * samples_per_pixel;
+            for row in samples.chunks_mut(row_bytes) {
+                let mut i = stride;
+                while i + 1 < row.len() {
+                    let previous = (row[i - stride] as u16) << 8 | row[i - stride + 1] as u16;
+                    let current = (row[i] as u16) << 8 | row[i + 1] as u16;
+                    let value = current.wrapping_add(previous);
+                    row[i] = (value >> 8) as u8;
+                    row[i + 1] = value as u8;
+                    i += 2;
+                }
+            }
+        }
+        _ => return Err(unsupported("horizontal predictor with fewer than 8 bits per sample")),
+    }
+
+    Ok(())
+}
+
+pub fn packbits_decode(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let header = data[pos] as i8;
+        pos += 1;
+
+        if header >= 0 {
+            let end = (pos + header as usize + 1).min(data.len());
+            output.extend_from_slice(&data[pos..end]);
+            pos = end;
+        } else if header != -128 && pos < data.len() {
+            for _ in 0..(1 - header as isize) {
+                output.push(data[pos]);
+            }
+            pos += 1;
+        }
+    }
+
+    output
+}
+
+pub fn packbits_encode(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let mut run = 1;
+        while pos + run < data.len() && run < 128 && data[pos + run] == data[pos] {
+            run += 1;
+        }
+
+        if run >= 2 {
+            output.push((1 - run as isize) as i8 as u8);
+            output.push(data[pos]);
+            pos += run;
+        } else {
+            // A literal run lasts until the next repeated pair of bytes.
+            let start = pos;
+            while pos < data.len() && pos - start < 128
+                && !(pos + 1 < data.len() && data[pos] == data[pos + 1]) {
+                pos += 1;
+            }
+            if pos == start {
+                pos += 1;
+            }
+            output.push((pos - start - 1) as u8);
+            output.extend_from_slice(&data[start..pos]);
+        }
+    }
+
+    output
+}
+
+/// Encode data as TIFF LZW, growing the code width at the point lzw_decode expects.
+pub fn lzw_encode(data: &[u8]) -> Vec<u8> {
+    const CLEAR: u32 = 256;
+    const END_OF_INFORMATION: u32 = 257;
+
+    let mut output = Vec::new();
+    let mut buffer: u64 = 0;
+    let mut count = 0;
+    let mut put = |code: u32, width: u32, output: &mut Vec<u8>| {
+        buffer = (buffer << width) | code as u64;
+        count += width;
+        while count >= 8 {
+            output.push((buffer >> (count - 8)) as u8);
+            count -= 8;
+        }
+    };
+
+    let mut table: HashMap<(u32, u8), u32> = HashMap::new();
+    let mut next = 258;
+    let mut width = 9;
+    put(CLEAR, width, &mut output);
+
+    let mut current: Option<u32> = None;
+    for &byte in data {
+        let code = match current {
+            None       => { current = Some(byte as u32); continue; }
+            Some(code) => code,
+        };
+        if let Some(&extended) = table.get(&(code, byte)) {
+            current = Some(extended);
+            continue;
+        }
+
+        put(code, width, &mut output);
+        table.insert((code, byte), next);
+        next += 1;
+        if next == 1 << width && width < 12 {
+            width += 1;
+        }
+        if next == 4094 {
+            put(CLEAR, width, &mut output);
+            table.clear();
+            next = 258;
+            width = 9;
+        }
+        current = Some(byte as u32);
+    }
+
+    if let Some(code) = current {
+        put(code, width, &mut output);
+        // The decoder adds an entry for the last code too, before reading
+        // the end of information code.
+        next += 1;
+        if next == 1 << width && width < 12 {
+            width += 1;
+        }
+    }
+    put(END_OF_INFORMATION, width, &mut output);
+    put(0, 7, &mut output);
+
+    output
+}
+
+/// Decode TIFF LZW data: codes of 9 to 12 bits, most significant bit first, where
+/// the code width grows one code early. Table entries are kept as positions in
+/// the output, since every entry is a string that has already been written.
+pub fn lzw_decode(data: &[u8]) -> io::Result<Vec<u8>> {
+    const CLEAR: usize = 256;
+    const END_OF_INFORMATION: usize = 257;
+
+    let mut output: Vec<u8> = Vec::new();
+    let mut table: Vec<(usize, usize)> = Vec::new();
+    let mut width = 9;
+    let mut previous: Option<(usize, usize)> = None;
+
+    let mut buffer: u32 = 0;
+    let mut count = 0;
+    let mut pos = 0;
+
+    loop {
+        while count < width && pos < data.len() {
+            buffer = (buffer << 8) | data[pos] as u32;
+            pos += 1;
+            count += 8;
+        }
+        if count < width {
+            break;
+        }
+
+        let code = ((buffer >> (count - width)) & ((1 << width) - 1)) as usize;
+        count -= width;
+
+        if code == CLEAR {
+            table.clear();
+            width = 9;
+            previous = None;
+            continue;
+        }
+        if code == END_OF_INFORMATION {
+            break;
+        }
+
+        let start = output.len();
+        if code < 256 {
+            output.push(code as u8);
+        } else if code - 258 < table.len() {
+            let (offset, length) = table[code - 258];
+            for i in 0..length {
+                let byte = output[offset + i];
+                output.push(byte);
+            }
+        } else if let Some((offset, length)) = previous.filter(|_| code - 258 == table.len()) {
+            for i in 0..length {
+                let byte = output[offset + i];
+                output.push(byte);
+            }
+            let first = output[offset];
+            output.push(first);
+        } else {
+            return Err(malformed("bad LZW code"));
+        }
+        let length = output.len() - start;
+
+        if let Some((offset, previous_length)) = previous {
+            table.push((offset, previous_length + 1));
+        }
+        previous = Some((start, length));
+
+        if table.len() + 258 + 1 >= (1 << width) && width < 12 {
+            width += 1;
+        }
+    }
+
+    Ok(output)
+}
+
+
+/// The value of a field in a TIFF file being written.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum TiffValue {
+    Byte(Vec<u8>),
+    Undefined(Vec<u8>),
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Rational(Vec<(u32, u32)>),
+    Ascii(String),
+}
+
+impl TiffValue {
+    fn field_type(&self) -> u16 {
+        match *self {
+            TiffValue::Byte(_)      => TYPE_BYTE,
+            TiffValue::Undefined(_) => TYPE_UNDEFINED,
+            TiffValue::Short(_)    => TYPE_SHORT,
+            TiffValue::Long(_)     => TYPE_LONG,
+            TiffValue::Rational(_) => TYPE_RATIONAL,
+            TiffValue::Ascii(_)    => TYPE_ASCII,
+        }
+    }
+
+    fn count(&self) -> u32 {
+        match *self {
+            TiffValue::Byte(ref values)      => values.len() as u32,
+            TiffValue::Undefined(ref values) => values.len() as u32,
+            TiffValue::Short(ref values)    => values.len() as u32,
+            TiffValue::Long(ref values)     => values.len() as u32,
+            TiffValue::Rational(ref values) => values.len() as u32,
+            TiffValue::Ascii(ref text)      => text.len() as u32 + 1,
+        }
+    }
+
+    /// The value bytes, little endian.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match *self {
+            TiffValue::Byte(ref values) | TiffValue::Undefined(ref values) => {
+                bytes.extend_from_slice(values);
+            }
+            TiffValue::Short(ref values) => {
+                for value in values {
+                    bytes.extend_from_slice(&[*value as u8, (*value >> 8) as u8]);
+                }
+            }
+            TiffValue::Long(ref values) => {
+                for value in values {
+                    push_u32_le(&mut bytes, *value);
+                }
+            }
+            TiffValue::Rational(ref values) => {
+                for &(numerator, denominator) in values {
+                    push_u32_le(&mut bytes, numerator);
+                    push_u32_le(&mut bytes, denominator);
+                }
+            }
+            TiffValue::Ascii(ref text) => {
+                bytes.extend_from_slice(text.as_bytes());
+                bytes.push(0);
+            }
+        }
+
+        bytes
+    }
+}
+
+fn push_u32_le(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&[value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]);
+}
+
+
+/// A single image to be written to a TIFF file, stored as strips of already
+/// compressed data. The strip layout fields are filled in when the file is
+/// written.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TiffImageData {
+    fields: Vec<(u16, TiffValue)>,
+    strips: Vec<Vec<u8>>,
+}
+
+impl TiffImageData {
+    pub fn new(width: u32, height: u32, strip: Vec<u8>) -> TiffImageData {
+        TiffImageData::with_strips(width, height, height, vec![strip])
+    }
+
+    /// An image stored as several strips of `rows_per_strip` rows each.
+    pub fn with_strips(width: u32, height: u32, rows_per_strip: u32, strips: Vec<Vec<u8>>) -> TiffImageData {
+        let mut image = TiffImageData {
+            fields: Vec::new(),
+            strips: strips,
+        };
+        image.set(TAG_IMAGE_WIDTH, TiffValue::Long(vec![width]));
+        image.set(TAG_IMAGE_LENGTH, TiffValue::Long(vec![height]));
+        image.set(TAG_ROWS_PER_STRIP, TiffValue::Long(vec![rows_per_strip]));
+
+        image
+    }
+
+    pub fn set(&mut self, tag: u16, value: TiffValue) {
+        self.fields.retain(|field| field.0 != tag);
+        self.fields.push((tag, value));
+    }
+
+    pub fn set_resolution(&mut self, res: &ImageResolution) {
+        let unit = match *res.units() {
+            ResolutionUnits::PixelsPerInch       => 2,
+            ResolutionUnits::PixelsPerCentimeter => 3,
+        };
+        self.set(TAG_X_RESOLUTION, TiffValue::Rational(vec![(res.amount() as u32, 1)]));
+        self.set(TAG_Y_RESOLUTION, TiffValue::Rational(vec![(res.amount() as u32, 1)]));
+        self.set(TAG_RESOLUTION_UNIT, TiffValue::Short(vec![unit]));
+    }
+}
+
+/// Write images to a little endian TIFF file, one directory per image.
+pub fn write_tiff(images: &[TiffImageData]) -> Vec<u8> {
+    let mut output = b"II*\0".to_vec();
+    // The offset of the first directory, filled in below.
+    push_u32_le(&mut output, 0);
+    let mut link_position = 4;
+
+    for image in images {
+        let mut strip_offsets = Vec::new();
+        for strip in image.strips.iter() {
+            strip_offsets.push(output.len() as u32);
+            output.extend_from_slice(strip);
+            if output.len() % 2 == 1 {
+                output.push(0);
+            }
+        }
+
+        let mut fields = image.fields.clone();
+        fields.retain(|field| field.0 != TAG_STRIP_OFFSETS && field.0 != TAG_STRIP_BYTE_COUNTS);
+        fields.push((TAG_STRIP_OFFSETS, TiffValue::Long(strip_offsets)));
+        fields.push((TAG_STRIP_BYTE_COUNTS, TiffValue::Long(image.strips.iter().map(|strip| strip.len() as u32).collect())));
+        // Directory entries must be sorted by tag.
+        fields.sort_by_key(|field| field.0);
+
+        // Values too large to fit in an entry go before the directory.
+        let mut entries = Vec::new();
+        for &(tag, ref value) in fields.iter() {
+            let bytes = value.to_bytes();
+            let inline = if bytes.len() <= 4 {
+                let mut inline = bytes.clone();
+                inline.resize(4, 0);
+                inline
+            } else {
+                let offset = output.len() as u32;
+                output.extend_from_slice(&bytes);
+                if output.len() % 2 == 1 {
+                    output.push(0);
+                }
+                let mut inline = Vec::new();
+                push_u32_le(&mut inline, offset);
+                inline
+            };
+            entries.push((tag, value.field_type(), value.count(), inline));
+        }
+
+        let directory_offset = output.len() as u32;
+        let link = [directory_offset as u8, (directory_offset >> 8) as u8,
+                    (directory_offset >> 16) as u8, (directory_offset >> 24) as u8];
+        output[link_position..link_position + 4].copy_from_slice(&link);
+
+        output.extend_from_slice(&[entries.len() as u8, (entries.len() >> 8) as u8]);
+        for (tag, field_type, count, inline) in entries {
+            output.extend_from_slice(&[tag as u8, (tag >> 8) as u8, field_type as u8, (field_type >> 8) as u8]);
+            push_u32_le(&mut output, count);
+            output.extend_from_slice(&inline);
+        }
+        link_position = output.len();
+        push_u32_le(&mut output, 0);
+    }
+
+    output
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray_image(width: u32, height: u32) -> Vec<u8> {
+        let mut image = TiffImageData::new(width, height, vec![0x80; (width * height) as usize]);
+        image.set(TAG_BITS_PER_SAMPLE, TiffValue::Short(vec![8]));
+        image.set(TAG_COMPRESSION, TiffValue::Short(vec![COMPRESSION_NONE as u16]));
+        image.set(TAG_PHOTOMETRIC, TiffValue::Short(vec![PHOTOMETRIC_BLACK_IS_ZERO as u16]));
+
+        write_tiff(&[image])
+    }
+
+    #[test]
+    fn written_files_parse() {
+        let file = TiffFile::parse(gray_image(3, 2)).unwrap();
+
+        assert_eq!(file.page_count(), 1);
+        assert_eq!(file.directories()[0].width().unwrap(), 3);
+        assert_eq!(file.directories()[0].height().unwrap(), 2);
+    }
+
+    #[test]
+    fn an_empty_bits_per_sample_tag_means_one_bit() {
+        let mut image = TiffImageData::new(8, 1, vec![0]);
+        image.set(TAG_BITS_PER_SAMPLE, TiffValue::Short(Vec::new()));
+        let file = TiffFile::parse(write_tiff(&[image])).unwrap();
+
+        assert_eq!(file.directories()[0].bits_per_sample().unwrap(), 1);
+    }
+
+    #[test]
+    fn truncated_headers_are_rejected() {
+        for length in 0..8 {
+            let data = gray_image(3, 2)[..length].to_vec();
+            assert_eq!(TiffFile::parse(data).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        // Not a byte order mark.
+        assert!(TiffFile::parse(b"XX*\0\x08\0\0\0".to_vec()).is_err());
+        // No image file directory.
+        assert!(TiffFile::parse(b"II*\0\0\0\0\0".to_vec()).is_err());
+        // A directory offset past the end of the file.
+        assert!(TiffFile::parse(b"II*\0\xff\0\0\0".to_vec()).is_err());
+        // A directory that points back at itself.
+        assert!(TiffFile::parse(b"II*\0\x08\0\0\0\0\0\x08\0\0\0".to_vec()).is_err());
+        // A directory with more entries than the file holds.
+        assert!(TiffFile::parse(b"II*\0\x08\0\0\0\x05\0\0\0\0\0".to_vec()).is_err());
+    }
+}
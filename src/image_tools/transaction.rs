@@ -0,0 +1,346 @@
+#![allow(dead_code)]
+use std::io;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ffi::OsString;
+use image_tools::image_ops::{FilePath, Page};
+use image_tools::image_ops::{CompoundPageOperation, OperationPlan, OperationPlanResult, OperationResults, OperationStatus};
+use image_tools::image_ops::{RunOperation, RetargetOperation};
+use image_tools::cancellation::CancellationToken;
+
+
+/// Options controlling how page operations are committed.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TransactionOptions {
+    keep_backup: bool,
+    output_dir:  Option<PathBuf>,
+}
+
+impl Default for TransactionOptions {
+    fn default() -> TransactionOptions {
+        TransactionOptions::new()
+    }
+}
+
+impl TransactionOptions {
+    /// By default pages are replaced in place and no backup is kept.
+    pub fn new() -> TransactionOptions {
+        TransactionOptions {
+            keep_backup: false,
+            output_dir:  None,
+        }
+    }
+
+    /// Keep the original of each replaced page next to it as `<file>.bak`,
+    /// so that the plan can be rolled back later.
+    pub fn keep_backup(&mut self, keep_backup: bool) {
+        self.keep_backup = keep_backup;
+    }
+
+    /// Write processed pages into a directory instead of replacing the originals.
+    pub fn output_dir<P: AsRef<Path>>(&mut self, output_dir: P) {
+        self.output_dir = Some(output_dir.as_ref().to_path_buf());
+    }
+}
+
+
+/// The backup file kept for a page: the page's path with `.bak` appended.
+pub fn backup_path<P: AsRef<Path>>(file_path: P) -> PathBuf {
+    let mut path = OsString::from(file_path.as_ref().as_os_str());
+    path.push(".bak");
+
+    PathBuf::from(path)
+}
+
+/// The working copy lives next to its destination, so that the final rename
+/// does not cross file systems and is therefore atomic. The file extension is
+/// kept, since ImageMagick uses it to decide the image format.
+pub fn working_copy_path(destination: &Path) -> PathBuf {
+    let stem = destination.file_stem().map(|s| s.to_os_string()).unwrap_or_default();
+    let mut name = OsString::from(".");
+    name.push(stem);
+    name.push(".pdftools-tmp");
+    if let Some(extension) = destination.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+
+    destination.with_file_name(name)
+}
+
+fn destination_path(page: &Page, options: &TransactionOptions) -> PathBuf {
+    let source = Path::new(page.file_path());
+    match options.output_dir {
+        Some(ref output_dir) => output_dir.join(source.file_name().unwrap_or_default()),
+        None                 => source.to_path_buf(),
+    }
+}
+
+/// Where the output of an operation run on a file ends up: the file itself,
+/// or a file beside it with another extension when the operation converts
+/// the file to another format.
+fn output_path<Op>(file_path: &Path, op: &CompoundPageOperation<Op>) -> PathBuf
+    where Op: RetargetOperation
+{
+    let file_path: FilePath = file_path.to_string_lossy().into_owned();
+
+    PathBuf::from(CompoundPageOperation::output_path(op, &file_path))
+}
+
+/// Set aside a page replaced by a file in another format: kept as its backup
+/// when backups are kept, and removed otherwise. Returns the file backed up.
+fn retire(original: &Path, options: &TransactionOptions) -> io::Result<Option<PathBuf>> {
+    if options.keep_backup {
+        fs::rename(original, backup_path(original))?;
+        Ok(Some(original.to_path_buf()))
+    } else {
+        fs::remove_file(original)?;
+        Ok(None)
+    }
+}
+
+/// Move the working copy over its destination, backing up the destination
+/// first if backups are kept and it exists. Returns the file backed up.
+fn commit(working_copy: &Path, destination: &Path, options: &TransactionOptions) -> io::Result<Option<PathBuf>> {
+    let mut backed_up = None;
+    if options.keep_backup && destination.exists() {
+        let backup = backup_path(destination);
+        if backup.exists() {
+            fs::remove_file(&backup)?;
+        }
+
+        // Prefer a hard link, which is cheap; fall back to copying when the
+        // file system does not support them.
+        if fs::hard_link(destination, &backup).is_err() {
+            fs::copy(destination, &backup)?;
+        }
+        backed_up = Some(destination.to_path_buf());
+    }
+
+    fs::rename(working_copy, destination)?;
+
+    Ok(backed_up)
+}
+
+/// Run a page's compound operation against a working copy of the page. The
+/// destination is replaced by the working copy only if every step succeeds;
+/// otherwise the working copy is discarded and the page is left untouched.
+/// A page converted to another format is written beside its destination with
+/// the new extension, and a page replaced in place is then set aside; the
+/// conversion's leftover working copy is removed.
+pub fn run_page_transaction<Op>(page: &Page,
+                                op: CompoundPageOperation<Op>,
+                                options: &TransactionOptions,
+                                token: &CancellationToken)
+    -> OperationResults
+        where Op: RunOperation + RetargetOperation + Clone
+{
+    run_transaction(page, op, options, token).0
+}
+
+/// Run a page transaction, returning along with its results the files that
+/// were backed up before being replaced.
+fn run_transaction<Op>(page: &Page,
+                       op: CompoundPageOperation<Op>,
+                       options: &TransactionOptions,
+                       token: &CancellationToken)
+    -> (OperationResults, Vec<PathBuf>)
+        where Op: RunOperation + RetargetOperation + Clone
+{
+    if token.is_cancelled() {
+        return (OperationResults::aborted(), Vec::new());
+    }
+
+    let source = Path::new(page.file_path());
+    let destination = destination_path(page, options);
+    let working_copy = working_copy_path(&destination);
+
+    if let Err(e) = fs::copy(source, &working_copy) {
+        return (OperationResults::from(Err(e)), Vec::new());
+    }
+
+    let working_path: FilePath = working_copy.to_string_lossy().into_owned();
+    let retargeted = CompoundPageOperation::<Op>::retarget(op, &working_path);
+    let working_output = output_path(&working_copy, &retargeted);
+    let output = output_path(&destination, &retargeted);
+    let mut results = CompoundPageOperation::<Op>::run_operation_with_cancel(retargeted, token);
+
+    if results.is_failed() || results.is_aborted() {
+        let _ = fs::remove_file(&working_copy);
+        let _ = fs::remove_file(&working_output);
+        return (results, Vec::new());
+    }
+
+    let mut backed_up = Vec::new();
+    let committed = commit(&working_output, &output, options).and_then(|backup| {
+        backed_up.extend(backup);
+        if output != destination && destination == source {
+            retire(&destination, options).map(|backup| backed_up.extend(backup))
+        } else {
+            Ok(())
+        }
+    });
+
+    if let Err(e) = committed {
+        let _ = fs::remove_file(&working_output);
+        results.push(Err(e));
+    }
+    // A conversion leaves the working copy it was made from behind.
+    if working_output != working_copy {
+        let _ = fs::remove_file(&working_copy);
+    }
+
+    (results, backed_up)
+}
+
+/// Execute a plan with each page's operation run as a transaction. The files
+/// backed up for each page are recorded in the result, for `rollback_plan`.
+pub fn execute_plan_transactionally<Op>(plan: &OperationPlan<Op>,
+                                        options: &TransactionOptions,
+                                        token: &CancellationToken)
+    -> OperationPlanResult
+        where Op: RunOperation + RetargetOperation + Clone
+{
+    let mut report = OperationPlanResult::new();
+
+    for (page, op) in plan {
+        let (result, backed_up) = run_transaction(page, op.clone(), options, token);
+        let destination: FilePath = output_path(&destination_path(page, options), op).to_string_lossy().into_owned();
+        report.record_page_result(page, op, destination, result, plan.blank_pages(), token);
+        for file_path in backed_up {
+            report.add_backed_up_path(page.clone(), file_path.to_string_lossy().into_owned());
+        }
+    }
+
+    report
+}
+
+fn rollback_page(result: &OperationPlanResult, page: &Page) -> io::Result<String> {
+    let source = page.file_path();
+    let output = result.output_page(page).file_path().clone();
+    let backed_up = result.backed_up_paths(page);
+    let completed = result.get(page).is_some_and(|results| results.status() == OperationStatus::Completed);
+
+    if !completed && backed_up.is_empty() {
+        return Ok(format!("{} was not changed", source));
+    }
+
+    // A page replaced in place, or set aside for a converted file, can only
+    // be restored from its backup.
+    let replaced = output == *source || !Path::new(source).exists();
+    if replaced && !backed_up.contains(source) {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} was replaced without a backup", source)));
+    }
+
+    for file_path in backed_up {
+        fs::rename(backup_path(file_path), file_path)?;
+    }
+    if output != *source && !backed_up.contains(&output) {
+        fs::remove_file(&output)?;
+    }
+
+    Ok(format!("Rolled back {}", source))
+}
+
+/// Roll back a plan executed by `execute_plan_transactionally`, given its
+/// result. Only the files the run backed up are restored from their `.bak`
+/// files, and outputs the run created without a backup are removed. Pages
+/// replaced without a backup are reported as failures.
+pub fn rollback_plan(result: &OperationPlanResult) -> OperationPlanResult {
+    let mut report = OperationPlanResult::new();
+
+    for page in result.pages() {
+        report.insert(page.clone(), OperationResults::from(rollback_page(result, page)));
+    }
+
+    report
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image_tools::image_ops::{PageOps, QuarterTurn, Direction};
+    use pdf::operations::PdfPageOperation;
+    use util::scratch::{scratch_dir, scratch_pdf};
+
+    fn plan_of(pages: &[(&Page, PageOps)]) -> OperationPlan<PdfPageOperation> {
+        let mut plan = OperationPlan::new();
+        for &(page, ref op) in pages {
+            let op = CompoundPageOperation::new(page.file_name().clone(), page.file_path().clone(), ::std::slice::from_ref(op));
+            plan.insert(page.clone(), op.compile::<PdfPageOperation>());
+        }
+
+        plan
+    }
+
+    #[test]
+    fn rollback_restores_only_the_pages_the_run_backed_up() {
+        let dir = scratch_dir("rollback-in-place");
+        let rotated = scratch_pdf(&dir, "rotated.pdf");
+        // PDF pages cannot be flipped without rasterizing, so this page fails.
+        let failed = scratch_pdf(&dir, "failed.pdf");
+        fs::write(backup_path(failed.file_path()), b"stale backup").unwrap();
+        let (rotated_before, failed_before) = (fs::read(rotated.file_path()).unwrap(), fs::read(failed.file_path()).unwrap());
+
+        let mut options = TransactionOptions::new();
+        options.keep_backup(true);
+        let plan = plan_of(&[(&rotated, PageOps::Rotate(QuarterTurn::Rotate90)),
+                             (&failed, PageOps::Flip(Direction::Horizontal))]);
+        let result = execute_plan_transactionally(&plan, &options, &CancellationToken::new());
+        assert!(!result.get(&rotated).unwrap().is_failed());
+        assert!(result.get(&failed).unwrap().is_failed());
+        assert_eq!(result.backed_up_paths(&rotated), &[rotated.file_path().clone()]);
+        assert!(result.backed_up_paths(&failed).is_empty());
+        assert_ne!(fs::read(rotated.file_path()).unwrap(), rotated_before);
+
+        let rollback = rollback_plan(&result);
+        assert!(!rollback.get(&rotated).unwrap().is_failed());
+        assert!(!rollback.get(&failed).unwrap().is_failed());
+        assert_eq!(fs::read(rotated.file_path()).unwrap(), rotated_before);
+        assert!(!backup_path(rotated.file_path()).exists());
+        assert_eq!(fs::read(failed.file_path()).unwrap(), failed_before);
+        assert_eq!(fs::read(backup_path(failed.file_path())).unwrap(), b"stale backup");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rollback_removes_outputs_written_without_a_backup() {
+        let dir = scratch_dir("rollback-output-dir");
+        let page = scratch_pdf(&dir, "page.pdf");
+        let before = fs::read(page.file_path()).unwrap();
+
+        let mut options = TransactionOptions::new();
+        options.keep_backup(true);
+        options.output_dir(dir.join("out"));
+        fs::create_dir_all(dir.join("out")).unwrap();
+        let plan = plan_of(&[(&page, PageOps::Rotate(QuarterTurn::Rotate90))]);
+        let result = execute_plan_transactionally(&plan, &options, &CancellationToken::new());
+        let output = result.output_page(&page).file_path().clone();
+        assert!(Path::new(&output).exists());
+        assert!(result.backed_up_paths(&page).is_empty());
+
+        let rollback = rollback_plan(&result);
+        assert!(!rollback.get(&page).unwrap().is_failed());
+        assert!(!Path::new(&output).exists());
+        assert_eq!(fs::read(page.file_path()).unwrap(), before);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pages_replaced_without_a_backup_cannot_be_rolled_back() {
+        let dir = scratch_dir("rollback-no-backup");
+        let page = scratch_pdf(&dir, "page.pdf");
+
+        let plan = plan_of(&[(&page, PageOps::Rotate(QuarterTurn::Rotate90))]);
+        let result = execute_plan_transactionally(&plan, &TransactionOptions::new(), &CancellationToken::new());
+        let rotated = fs::read(page.file_path()).unwrap();
+
+        assert!(rollback_plan(&result).get(&page).unwrap().is_failed());
+        assert_eq!(fs::read(page.file_path()).unwrap(), rotated);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,247 @@
+#![allow(dead_code)]
+use std::io;
+use std::fs;
+use std::path::Path;
+use super::imagemagick_commands;
+use super::settings::ImageMagickSettings;
+use image_tools::image_ops::{Page, FilePath, DeskewOptions, AngleRotation, Fill, BorderRemoval, BorderAction, NoiseFilter};
+use image_tools::cancellation::CancellationToken;
+use image_tools::content::{ContentDetection, ContentBounds};
+use image_tools::raster::{self, GrayImage, INK_FUZZ};
+use image_tools::deskew;
+use image_tools::borders;
+use image_tools::orientation::{self, Orientation};
+use image_tools::spread::{self, SpreadSplit, SplitSpread};
+use image_tools::transaction;
+
+
+/// A file's first frame as a grayscale image. Files that cannot be decoded
+/// natively are converted to a temporary PGM file beside them first.
+pub fn gray_image(file_path: &FilePath,
+                  settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<GrayImage> {
+    match GrayImage::open(file_path) {
+        Err(ref e) if e.kind() == io::ErrorKind::InvalidData => {}
+        other => return other,
+    }
+
+    let pgm = transaction::working_copy_path(&Path::new(file_path).with_extension("pgm"));
+    let args = [
+        format!("{}[0]", file_path),
+        String::from("-colorspace"), String::from("Gray"),
+        String::from("-depth"), String::from("8"),
+    ];
+    let image = imagemagick_commands::imagemagick_convert(&format!("PGM:{}", pgm.display()), &args, settings, token)
+        .and_then(|_| GrayImage::open(&pgm));
+    let _ = fs::remove_file(&pgm);
+
+    image
+}
+
+/// The content of a page in any format ImageMagick reads.
+pub fn detect_content(page: &Page, detection: &ContentDetection,
+                      settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<Option<ContentBounds>> {
+    Ok(detection.detect(&gray_image(page.file_path(), settings, token)?))
+}
+
+/// The content of each of a document's pages, in any format ImageMagick
+/// reads, for laying the pages out.
+pub fn detect_contents(pages: &[Page], detection: &ContentDetection,
+                       settings: &ImageMagickSettings, token: &CancellationToken)
+    -> io::Result<Vec<(Page, Option<ContentBounds>)>>
+{
+    pages.iter().map(|page| Ok((page.clone(), detect_content(page, detection, settings, token)?))).collect()
+}
+
+/// The orientation of a page in any format ImageMagick reads, from its lines of text.
+pub fn detect_orientation(page: &Page,
+                          settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<Orientation> {
+    Ok(orientation::detect_orientation(&gray_image(page.file_path(), settings, token)?))
+}
+
+/// Measure the skew of a file and rotate it straight, keeping its size and
+/// filling the corners that rotation exposes with the paper colour.
+pub fn deskew(file_path: &FilePath, options: &DeskewOptions,
+              settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<String> {
+    let skew = deskew::detect_skew(&gray_image(file_path, settings, token)?, options);
+
+    match skew.correction(options) {
+        Some(angle) => {
+            imagemagick_commands::imagemagick_mogrify(file_path, &[
+                String::from("-background"), format!("gray({})", skew.background()),
+                String::from("-virtual-pixel"), String::from("Background"),
+                String::from("-distort"), String::from("SRT"), format!("{:.2}", angle),
+            ], settings, token)?;
+            Ok(format!("{}: {}, rotated by {:.2} degrees", file_path, skew, angle))
+        }
+        None => Ok(format!("{}: {}, left as it is", file_path, skew)),
+    }
+}
+
+/// The gray level a fill stands for in a file.
+pub fn fill_level(file_path: &FilePath, fill: Fill,
+                  settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<u8> {
+    match fill {
+        Fill::Gray(level) => Ok(level),
+        Fill::Paper       => Ok(gray_image(file_path, settings, token)?.background()),
+    }
+}
+
+/// Rotate a file by any angle, growing it to hold the whole rotated image.
+pub fn rotate_by_angle(file_path: &FilePath, rotation: &AngleRotation,
+                       settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<String> {
+    let level = fill_level(file_path, rotation.fill(), settings, token)?;
+    imagemagick_commands::imagemagick_mogrify(file_path, &[
+        String::from("-background"), format!("gray({})", level),
+        String::from("-virtual-pixel"), String::from("Background"),
+        String::from("+distort"), String::from("SRT"), rotation.degrees().to_string(),
+        String::from("+repage"),
+    ], settings, token)?;
+
+    Ok(format!("{}: rotated by {} degrees", file_path, rotation.degrees()))
+}
+
+/// Find the dark borders along the edges of a file and trim them off or
+/// paint them the colour of the paper, reporting their widths.
+pub fn remove_borders(file_path: &FilePath, removal: &BorderRemoval,
+                      settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<String> {
+    let borders = borders::detect_borders(&gray_image(file_path, settings, token)?, removal);
+    if borders.is_empty() {
+        return Ok(format!("{}: {}, left as it is", file_path, borders));
+    }
+    // An empty crop geometry means the whole image to ImageMagick, and
+    // painting would leave nothing but paper, so a page that is border from
+    // edge to edge is not touched.
+    if borders.inside().is_empty() {
+        return Ok(format!("{}: {} cover the whole page, left as it is", file_path, borders));
+    }
+
+    match removal.action() {
+        BorderAction::Trim => {
+            let inside = borders.inside();
+            imagemagick_commands::imagemagick_mogrify(file_path, &[
+                String::from("-crop"), inside.to_string(),
+                String::from("+repage"),
+            ], settings, token)?;
+            Ok(format!("{}: {}, trimmed to {}x{}", file_path, borders, inside.width(), inside.height()))
+        }
+        BorderAction::Paint => {
+            let mut args = vec![String::from("-fill"), format!("gray({})", borders.background())];
+            for region in borders.regions() {
+                args.push(String::from("-draw"));
+                args.push(format!("rectangle {},{} {},{}",
+                                  region.left(), region.top(), region.right() - 1, region.bottom() - 1));
+            }
+            imagemagick_commands::imagemagick_mogrify(file_path, &args, settings, token)?;
+            Ok(format!("{}: {}, painted gray({})", file_path, borders, borders.background()))
+        }
+    }
+}
+
+/// Filter noise out of a file, reporting how many marks of ink and holes in
+/// it the filter removed.
+pub fn despeckle(file_path: &FilePath, filter: NoiseFilter,
+                 settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<String> {
+    let before = gray_image(file_path, settings, token)?;
+    imagemagick_commands::imagemagick_mogrify(file_path, &noise_filter_args(filter), settings, token)?;
+    let after = gray_image(file_path, settings, token)?;
+
+    match removed_noise(&before, &after) {
+        Some((marks, holes)) => Ok(format!("{}: removed {} marks and filled {} holes", file_path, marks, holes)),
+        None => Ok(format!("{}: filtered, but its size changed so nothing was counted", file_path)),
+    }
+}
+
+/// The mogrify arguments applying a noise filter. ImageMagick's morphology
+/// treats white as the foreground, so opening the ink is closing the image,
+/// and the other way round.
+fn noise_filter_args(filter: NoiseFilter) -> Vec<String> {
+    match filter {
+        NoiseFilter::Specks(size) => vec![
+            String::from("-define"), format!("connected-components:area-threshold={}", size),
+            String::from("-define"), String::from("connected-components:mean-color=true"),
+            String::from("-connected-components"), String::from("8"),
+        ],
+        NoiseFilter::Median(size) => vec![
+            String::from("-statistic"), String::from("Median"), format!("{}x{}", size, size),
+        ],
+        NoiseFilter::Open(radius) => vec![
+            String::from("-morphology"), String::from("Close"), format!("Disk:{}", radius),
+        ],
+        NoiseFilter::Close(radius) => vec![
+            String::from("-morphology"), String::from("Open"), format!("Disk:{}", radius),
+        ],
+    }
+}
+
+/// The marks of ink and the holes in them that are gone from an image
+/// after filtering, or None if the filter changed its size.
+fn removed_noise(before: &GrayImage, after: &GrayImage) -> Option<(usize, usize)> {
+    if after.dimensions() != before.dimensions() {
+        return None;
+    }
+    let background = before.background();
+    let ink_before = before.foreground_mask(background, INK_FUZZ);
+    let ink_after = after.foreground_mask(background, INK_FUZZ);
+    let paper_before: Vec<bool> = ink_before.iter().map(|&ink| !ink).collect();
+    let paper_after: Vec<bool> = ink_after.iter().map(|&ink| !ink).collect();
+
+    Some((raster::vanished_components(&ink_before, &ink_after, before.width()),
+          raster::vanished_components(&paper_before, &paper_after, before.width())))
+}
+
+/// Split a spread of two facing pages in any format ImageMagick reads into
+/// a file for each page, written next to it. The spread itself is left as
+/// it is.
+pub fn split_spread(page: &Page, split: SpreadSplit,
+                    settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<SplitSpread> {
+    let gutter = match split {
+        SpreadSplit::At(x) => spread::clamp_split(x, page.dimensions().x_pixels()),
+        SpreadSplit::Gutter(_) => spread::split_position(&gray_image(page.file_path(), settings, token)?, split),
+    };
+    let spread = SplitSpread::new(page, gutter);
+
+    for (half, region) in [(spread.left(), spread.left_region()), (spread.right(), spread.right_region())] {
+        imagemagick_commands::imagemagick_convert(half.file_path(), &[
+            page.file_path().clone(),
+            String::from("-crop"), region.to_string(),
+            String::from("+repage"),
+        ], settings, token)?;
+    }
+
+    Ok(spread)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_count_the_marks_and_holes_they_remove() {
+        // A ring of ink with a hole in it, and a speck beside it.
+        let mut before = GrayImage::filled(10, 10, 255);
+        for y in 2..5 {
+            for x in 2..5 {
+                before.set(x, y, 0);
+            }
+        }
+        before.set(3, 3, 255);
+        before.set(8, 8, 0);
+
+        let mut after = before.clone();
+        after.set(3, 3, 0);
+        after.set(8, 8, 255);
+        assert_eq!(removed_noise(&before, &after), Some((1, 1)));
+        assert_eq!(removed_noise(&before, &before), Some((0, 0)));
+        assert_eq!(removed_noise(&before, &GrayImage::filled(9, 10, 255)), None);
+    }
+
+    #[test]
+    fn morphology_is_inverted_for_ink_on_paper() {
+        assert_eq!(noise_filter_args(NoiseFilter::Open(2)), vec!["-morphology", "Close", "Disk:2"]);
+        assert_eq!(noise_filter_args(NoiseFilter::Close(1)), vec!["-morphology", "Open", "Disk:1"]);
+        assert_eq!(noise_filter_args(NoiseFilter::Median(3)), vec!["-statistic", "Median", "3x3"]);
+        assert!(noise_filter_args(NoiseFilter::Specks(12))
+                    .contains(&String::from("connected-components:area-threshold=12")));
+    }
+}
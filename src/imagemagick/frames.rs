@@ -0,0 +1,58 @@
+#![allow(dead_code)]
+use std::io;
+use std::path::Path;
+use super::imagemagick_commands;
+use super::settings::ImageMagickSettings;
+use image_tools::image_ops::{Page, FilePath, ImageFileFormat, ImageResolution, ResolutionUnits};
+use image_tools::cancellation::CancellationToken;
+use image_tools::multipage::TiffCompression;
+use image_tools::tiff::TiffFile;
+
+
+/// The number of frames ImageMagick finds in a file, one line of identify
+/// output per frame. This works for any format ImageMagick reads, not only TIFF.
+pub fn frame_count(file_path: &FilePath,
+                   settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<usize> {
+    let output = imagemagick_commands::imagemagick_identify_default(file_path, settings, token)?;
+
+    Ok(output.lines().filter(|line| !line.trim().is_empty()).count())
+}
+
+/// Split a multi-frame file into one TIFF file per frame, addressing each
+/// frame as `file[n]`, and return the pages in order.
+pub fn split_frames<P: AsRef<Path>>(file_path: &FilePath, output_dir: P,
+                                    settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<Vec<Page>> {
+    let stem = Path::new(file_path).file_stem()
+                                   .map(|stem| stem.to_string_lossy().into_owned())
+                                   .unwrap_or(String::from("page"));
+
+    let mut pages = Vec::new();
+    for frame in 0..frame_count(file_path, settings, token)? {
+        let file_name = format!("{}-f{:04}.tif", stem, frame + 1);
+        let output_path = output_dir.as_ref().join(&file_name).to_string_lossy().into_owned();
+        imagemagick_commands::imagemagick_convert(&output_path, &[format!("{}[{}]", file_path, frame)], settings, token)?;
+
+        let file = TiffFile::open(&output_path)?;
+        let directory = &file.directories()[0];
+        let res = directory.resolution().unwrap_or(ImageResolution::new(72, ResolutionUnits::PixelsPerInch));
+        pages.push(Page::new(file_name, ImageFileFormat::TIFF, output_path, directory.dimensions()?, res));
+    }
+
+    Ok(pages)
+}
+
+/// Merge pages of any format ImageMagick reads into one multi-page TIFF file,
+/// in the order given.
+pub fn merge_frames(pages: &[Page], output_path: &FilePath, compression: TiffCompression,
+                    settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<String>
+{
+    let mut args: Vec<String> = pages.iter().map(|page| page.file_path().clone()).collect();
+    if let Some(name) = compression.imagemagick_name() {
+        args.push(String::from("-compress"));
+        args.push(String::from(name));
+    }
+    // Every frame goes into the one output file.
+    args.push(String::from("-adjoin"));
+
+    imagemagick_commands::imagemagick_convert(&format!("TIFF:{}", output_path), &args, settings, token)
+}
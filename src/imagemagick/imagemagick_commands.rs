@@ -1,61 +1,108 @@
 #![allow(dead_code)]
-use std::process::{Command, Output};
+use std::process::{Command, Child, Stdio};
 use std::string::{String, ToString};
 use std::ffi::OsString;
 use std::io;
-use image_tools::image_ops::{FileName, FilePath};
+use std::path::Path;
+use image_tools::image_ops::FilePath;
+use image_tools::cancellation::{self, CancellationToken};
 use super::op_types::{ImageMagickOpType};
+use super::settings::ImageMagickSettings;
 use util::shell::AsShellCommand;
+use util::process;
 
 
 fn imagemagick_command(command_name: ImageMagickOpType, 
                        file_path: &FilePath, 
-                       args: &[String]) 
+                       args: &[String],
+                       settings: &ImageMagickSettings,
+                       token: &CancellationToken) 
     -> io::Result<String> {
 
+    if token.is_cancelled() {
+        return Err(cancellation::cancelled_error());
+    }
+
     let mut command_args: Vec<OsString> = Vec::new();
+    for arg in settings.limit_args() {
+        command_args.push(OsString::from(arg));
+    }
+
     for arg in args {
         command_args.push(OsString::from(arg));
     }
 
-    let output: io::Result<Output> = Command::new(command_name.as_shell_command())
-                                            .args(command_args.as_ref())
-                                            .arg(file_path)
-                                            .output();
-    
-    let result: io::Result<String> = match output {
-            Ok(output) => Ok(String::from_utf8(output.stdout).unwrap()),
-            Err(e)     => Err(e),
-        };
+    let child: Child = Command::new(command_name.as_shell_command())
+                                .args(&command_args)
+                                .arg(file_path)
+                                .stdout(Stdio::piped())
+                                .stderr(Stdio::piped())
+                                .spawn()?;
+
+    let stdout = process::wait_for_output(&command_name.as_shell_command(), child, token, settings.timeout())?;
 
-    result
+    Ok(String::from_utf8_lossy(&stdout).into_owned())
 }
 
 #[inline]
-fn imagemagick_identify(file_path: &FilePath, args: &[String]) -> io::Result<String> {
-    imagemagick_command(ImageMagickOpType::Identify, file_path, args)
+fn imagemagick_identify(file_path: &FilePath, args: &[String],
+                        settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<String> {
+    imagemagick_command(ImageMagickOpType::Identify, file_path, args, settings, token)
 }
 
 
-pub fn imagemagick_identify_default(file_path: &FilePath) -> io::Result<String> {
-    imagemagick_identify(file_path, &[])
+pub fn imagemagick_identify_default(file_path: &FilePath,
+                                    settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<String> {
+    imagemagick_identify(file_path, &[], settings, token)
 }
 
 
-pub fn imagemagick_identify_verbose(file_path: &FilePath) -> io::Result<String> {
+pub fn imagemagick_identify_verbose(file_path: &FilePath,
+                                    settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<String> {
     let args = ["-verbose".to_string()];
 
-    imagemagick_identify(file_path, &args)
+    imagemagick_identify(file_path, &args, settings, token)
 }
 
 
-pub fn imagemagick_mogrify(file_path: &FilePath, args: &[String]) -> io::Result<String> {
-    imagemagick_command(ImageMagickOpType::Mogrify, file_path, args)
+pub fn imagemagick_mogrify(file_path: &FilePath, args: &[String],
+                           settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<String> {
+    imagemagick_command(ImageMagickOpType::Mogrify, file_path, args, settings, token)
 }
 
 
-pub fn imagemagick_convert(file_path: &FilePath, args: &[String]) -> io::Result<String> {
-    imagemagick_command(ImageMagickOpType::Convert, file_path, args)
+pub fn imagemagick_convert(file_path: &FilePath, args: &[String],
+                           settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<String> {
+    imagemagick_command(ImageMagickOpType::Convert, file_path, args, settings, token)
+}
+
+/// Convert a file in place, for operations that need more than mogrify
+/// offers, such as combining images derived from the file.
+pub fn imagemagick_convert_in_place(file_path: &FilePath, args: &[String],
+                                    settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<String> {
+    let mut command_args = vec![file_path.clone()];
+    command_args.extend_from_slice(args);
+
+    imagemagick_command(ImageMagickOpType::ConvertInPlace, file_path, &command_args, settings, token)
+}
+
+/// The file a conversion into a format with the given extension writes.
+pub fn reformat_path(file_path: &FilePath, extension: &str) -> FilePath {
+    Path::new(file_path).with_extension(extension).to_string_lossy().into_owned()
+}
+
+/// Convert a file through the named coder into a file beside it with the
+/// format's extension. The original is left in place for the caller to
+/// keep or remove. A file that already has the extension is converted in
+/// place.
+pub fn imagemagick_reformat(file_path: &FilePath, coder: &str, extension: &str, args: &[String],
+                            settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<String> {
+    let output_path = reformat_path(file_path, extension);
+    let mut command_args = vec![file_path.clone()];
+    command_args.extend_from_slice(args);
+
+    imagemagick_command(ImageMagickOpType::Reformat(String::from(coder), String::from(extension)),
+                        &format!("{}:{}", coder, output_path), &command_args, settings, token)
 }
 
 pub fn imagemagick_no_operation() -> io::Result<String> {
@@ -1,4 +1,7 @@
 pub mod operations;
 pub mod op_types;
+pub mod settings;
+pub mod frames;
+pub mod analysis;
 
 mod imagemagick_commands;
\ No newline at end of file
@@ -1,5 +1,6 @@
 use std::fmt;
 use util::shell;
+use image_tools::image_ops::{DeskewOptions, AngleRotation, BorderRemoval, NoiseFilter};
 
 #[derive(Clone, Debug)]
 pub enum ImageMagickOpType {
@@ -7,6 +8,19 @@ pub enum ImageMagickOpType {
     IdentifyVerbose,
     Mogrify,
     Convert,
+    /// Convert a file, writing it back to the same path.
+    ConvertInPlace,
+    /// Convert a file into the named format, writing it next to the original
+    /// with the given extension in its place.
+    Reformat(String, String),
+    /// Measure a file's skew, then rotate it back with mogrify.
+    Deskew(DeskewOptions),
+    /// Rotate a file by any angle with mogrify, once its fill is known.
+    RotateByAngle(AngleRotation),
+    /// Trim or paint the borders found in a file with mogrify.
+    RemoveBorders(BorderRemoval),
+    /// Filter noise out of a file with mogrify, counting what is removed.
+    Despeckle(NoiseFilter),
     NoOperation,
 }
 
@@ -18,6 +32,12 @@ impl shell::AsShellCommand for ImageMagickOpType {
             ImageMagickOpType::IdentifyVerbose => String::from("identify"),
             ImageMagickOpType::Mogrify         => String::from("mogrify"),
             ImageMagickOpType::Convert         => String::from("convert"),
+            ImageMagickOpType::ConvertInPlace  => String::from("convert"),
+            ImageMagickOpType::Reformat(_, _)  => String::from("convert"),
+            ImageMagickOpType::Deskew(_)       => String::from("mogrify"),
+            ImageMagickOpType::RotateByAngle(_) => String::from("mogrify"),
+            ImageMagickOpType::RemoveBorders(_) => String::from("mogrify"),
+            ImageMagickOpType::Despeckle(_)     => String::from("mogrify"),
             ImageMagickOpType::NoOperation     => String::from("echo"),
         }
     }
@@ -30,6 +50,12 @@ impl fmt::Display for ImageMagickOpType {
             ImageMagickOpType::IdentifyVerbose => write!(f, "IdentifyVerbose"),
             ImageMagickOpType::Mogrify         => write!(f, "Mogrify"),
             ImageMagickOpType::Convert         => write!(f, "Convert"),
+            ImageMagickOpType::ConvertInPlace  => write!(f, "ConvertInPlace"),
+            ImageMagickOpType::Reformat(ref coder, ref extension) => write!(f, "Reformat({}, .{})", coder, extension),
+            ImageMagickOpType::Deskew(ref options) => write!(f, "Deskew({})", options),
+            ImageMagickOpType::RotateByAngle(ref rotation) => write!(f, "RotateByAngle({})", rotation),
+            ImageMagickOpType::RemoveBorders(ref removal) => write!(f, "RemoveBorders({})", removal),
+            ImageMagickOpType::Despeckle(filter) => write!(f, "Despeckle({})", filter),
             ImageMagickOpType::NoOperation     => write!(f, "NoOperation"),
         }
     }
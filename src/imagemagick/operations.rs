@@ -1,13 +1,20 @@
 #![allow(dead_code)]
 use super::imagemagick_commands;
+use super::analysis;
 use super::op_types::{ImageMagickOpType};
+use super::settings::{self, ImageMagickSettings};
 use image_tools::image_ops::{ElementaryPageOperations, Pixels, Direction};
-use image_tools::image_ops::ImageResolution;
-use image_tools::image_ops::RunOperation;
-use image_tools::image_ops::OperationResults;
+use image_tools::image_ops::{ImageResolution, ResolutionUnits};
+use image_tools::image_ops::{OutputFormat, ChromaSubsampling, ColorReduction, ThresholdMethod, SizeReduction};
+use image_tools::image_ops::{DeskewOptions, QuarterTurn, AngleRotation, BorderRemoval, NoiseFilter};
+use image_tools::image_ops::{RunOperation, RetargetOperation};
+use image_tools::image_ops::{OperationResults, OperationPlan, CompoundPageOperation};
 use image_tools::image_ops::{FileName, FilePath};
+use image_tools::cancellation::{self, CancellationToken};
+use image_tools::raster::GrayImage;
 use std::string::{String};
 use std::io;
+use std::fs;
 use std::convert::AsRef;
 
 
@@ -22,11 +29,11 @@ struct ImageMagickArgs {
 }
 
 impl ImageMagickArgs {
-    fn new(file_path: FilePath, file_name: FileName, img_args: &Vec<ImageMagickArg>) -> ImageMagickArgs {
+    fn new(file_path: FilePath, file_name: FileName, img_args: &[ImageMagickArg]) -> ImageMagickArgs {
         ImageMagickArgs {
             file_path: file_path,
             file_name: file_name,
-            img_args:  img_args.clone(),
+            img_args:  img_args.to_vec(),
         }
     }
 }
@@ -57,19 +64,47 @@ impl ElementaryImageMagickOperation {
         }
     }
 
-    fn run_operation(&self) -> io::Result<String> {
+    /// The file the command leaves its output in when run on a file.
+    fn output_path(&self, file_path: &FilePath) -> FilePath {
+        match self.op {
+            ImageMagickOpType::Reformat(_, ref extension) => imagemagick_commands::reformat_path(file_path, extension),
+            _ => file_path.clone(),
+        }
+    }
+
+    fn run_operation(&self, settings: &ImageMagickSettings, token: &CancellationToken) -> io::Result<String> {
         match self.op {
             ImageMagickOpType::Identify        => {
-                imagemagick_commands::imagemagick_identify_default(&self.args.file_path)
+                imagemagick_commands::imagemagick_identify_default(&self.args.file_path, settings, token)
             }
             ImageMagickOpType::IdentifyVerbose => {
-                imagemagick_commands::imagemagick_identify_verbose(&self.args.file_path)
+                imagemagick_commands::imagemagick_identify_verbose(&self.args.file_path, settings, token)
             }
             ImageMagickOpType::Convert         => {
-                imagemagick_commands::imagemagick_convert(&self.args.file_path, &self.args.img_args)
+                imagemagick_commands::imagemagick_convert(&self.args.file_path, &self.args.img_args, settings, token)
             }
             ImageMagickOpType::Mogrify         => {
-                imagemagick_commands::imagemagick_mogrify(&self.args.file_path, &self.args.img_args)
+                imagemagick_commands::imagemagick_mogrify(&self.args.file_path, &self.args.img_args, settings, token)
+            }
+            ImageMagickOpType::ConvertInPlace  => {
+                imagemagick_commands::imagemagick_convert_in_place(&self.args.file_path, &self.args.img_args,
+                                                                   settings, token)
+            }
+            ImageMagickOpType::Reformat(ref coder, ref extension) => {
+                imagemagick_commands::imagemagick_reformat(&self.args.file_path, coder, extension,
+                                                           &self.args.img_args, settings, token)
+            }
+            ImageMagickOpType::Deskew(ref options) => {
+                analysis::deskew(&self.args.file_path, options, settings, token)
+            }
+            ImageMagickOpType::RotateByAngle(ref rotation) => {
+                analysis::rotate_by_angle(&self.args.file_path, rotation, settings, token)
+            }
+            ImageMagickOpType::RemoveBorders(ref removal) => {
+                analysis::remove_borders(&self.args.file_path, removal, settings, token)
+            }
+            ImageMagickOpType::Despeckle(filter) => {
+                analysis::despeckle(&self.args.file_path, filter, settings, token)
             }
             ImageMagickOpType::NoOperation     => {
                 imagemagick_commands::imagemagick_no_operation()
@@ -81,15 +116,43 @@ impl ElementaryImageMagickOperation {
 #[derive(Clone, Debug)]
 pub struct ImageMagickOperation {
     ops: Vec<ElementaryImageMagickOperation>,
+    /// Whether the results report how the size of the file changed.
+    report_size: bool,
+    /// Settings of the operation's own, in place of the backend's.
+    settings: Option<ImageMagickSettings>,
 }
 
 impl ImageMagickOperation {
     fn new() -> ImageMagickOperation {
         ImageMagickOperation {
             ops: Vec::new(),
+            report_size: false,
+            settings: None,
+        }
+    }
+
+    /// The operation run with the given timeout and resource limits instead
+    /// of ImageMagick's own defaults.
+    pub fn with_settings(op: ImageMagickOperation, settings: &ImageMagickSettings) -> ImageMagickOperation {
+        let mut new_op = op;
+        new_op.settings = Some(settings.clone());
+
+        new_op
+    }
+
+    /// The settings the operation runs with: its own, or the backend's
+    /// when it was given none.
+    pub fn settings(&self) -> ImageMagickSettings {
+        match self.settings {
+            Some(ref settings) => settings.clone(),
+            None               => settings::current_settings(),
         }
     }
 
+    fn file_path(&self) -> Option<&FilePath> {
+        self.ops.first().map(|op| &op.args.file_path)
+    }
+
     fn add_op(&mut self, op: ElementaryImageMagickOperation)  {
         self.ops.push(op);
     }
@@ -100,6 +163,68 @@ impl ImageMagickOperation {
         }
     }
 
+    /// A single mogrify command. The file it acts on is filled in when the
+    /// operation is targeted at a page.
+    fn mogrify(img_args: &[ImageMagickArg]) -> ImageMagickOperation {
+        let args = ImageMagickArgs::new(String::from(""), String::from(""), img_args);
+        let elem_op = ElementaryImageMagickOperation::new(ImageMagickOpType::Mogrify, args);
+        let mut op = ImageMagickOperation::new();
+        op.add_op(elem_op);
+
+        op
+    }
+
+    /// A single convert command writing the file through the coder to a file
+    /// with the extension. The file it acts on is filled in when the operation
+    /// is targeted at a page.
+    fn reformat(coder: &str, extension: &str, img_args: &[ImageMagickArg]) -> ImageMagickOperation {
+        let args = ImageMagickArgs::new(String::from(""), String::from(""), img_args);
+        let op_type = ImageMagickOpType::Reformat(String::from(coder), String::from(extension));
+        let elem_op = ElementaryImageMagickOperation::new(op_type, args);
+        let mut op = ImageMagickOperation::new();
+        op.add_op(elem_op);
+
+        op
+    }
+
+    /// A single convert command that writes the file back in place.
+    fn convert_in_place(img_args: &[ImageMagickArg]) -> ImageMagickOperation {
+        let args = ImageMagickArgs::new(String::from(""), String::from(""), img_args);
+        let elem_op = ElementaryImageMagickOperation::new(ImageMagickOpType::ConvertInPlace, args);
+        let mut op = ImageMagickOperation::new();
+        op.add_op(elem_op);
+
+        op
+    }
+
+    /// An operation whose commands depend on what is found in the file when
+    /// it runs. The file it acts on is filled in when the operation is
+    /// targeted at a page.
+    fn analysed(op_type: ImageMagickOpType) -> ImageMagickOperation {
+        let args = ImageMagickArgs::new(String::from(""), String::from(""), &Vec::new());
+        let mut op = ImageMagickOperation::new();
+        op.add_op(ElementaryImageMagickOperation::new(op_type, args));
+
+        op
+    }
+
+    /// Add rows or columns of background colour at the edge given by the gravity.
+    fn splice(gravity: &str, geometry: String) -> ImageMagickOperation {
+        ImageMagickOperation::mogrify(&[
+            String::from("-background"), String::from("white"),
+            String::from("-gravity"), String::from(gravity),
+            String::from("-splice"), geometry,
+        ])
+    }
+
+    /// Remove rows or columns from the edge given by the gravity.
+    fn chop(gravity: &str, geometry: String) -> ImageMagickOperation {
+        ImageMagickOperation::mogrify(&[
+            String::from("-gravity"), String::from(gravity),
+            String::from("-chop"), geometry,
+        ])
+    }
+
 }
 
 // This implementation will be the generator for the sequence of 
@@ -109,7 +234,7 @@ impl ElementaryPageOperations for ImageMagickOperation {
     fn identify(file_name: FileName, file_path: FilePath) -> ImageMagickOperation {
         // Identify presesntly generates an identify command without the -verbose flag.
         // This may change in the future where we use verbose as the default.
-        let args = ImageMagickArgs::new(file_name, file_path, &Vec::new());
+        let args = ImageMagickArgs::new(file_path, file_name, &Vec::new());
         let elem_op = ElementaryImageMagickOperation::new(ImageMagickOpType::Identify, args);
         let mut op = ImageMagickOperation::new();
         op.add_op(elem_op);
@@ -119,43 +244,174 @@ impl ElementaryPageOperations for ImageMagickOperation {
     }
 
     fn rescale(amount: Pixels, dir: Direction) -> ImageMagickOperation {
-        unimplemented!();
+        let geometry = match dir {
+            Direction::Horizontal => format!("{}x", amount),
+            Direction::Vertical   => format!("x{}", amount),
+        };
+
+        ImageMagickOperation::mogrify(&[String::from("-resize"), geometry])
     }
 
     fn expand_left_edge(amount: Pixels)        -> ImageMagickOperation {
-        unimplemented!();
+        ImageMagickOperation::splice("West", format!("{}x0", amount))
     }
 
     fn expand_right_edge(amount: Pixels)       -> ImageMagickOperation {
-        unimplemented!();
+        ImageMagickOperation::splice("East", format!("{}x0", amount))
     }
 
     fn expand_top_edge(amount: Pixels)         -> ImageMagickOperation {
-        unimplemented!();
+        ImageMagickOperation::splice("North", format!("0x{}", amount))
     }
 
     fn expand_bottom_edge(amount: Pixels)      -> ImageMagickOperation {
-        unimplemented!();
+        ImageMagickOperation::splice("South", format!("0x{}", amount))
     }
 
     fn trim_left_edge(amount: Pixels)          -> ImageMagickOperation {
-        unimplemented!();
+        ImageMagickOperation::chop("West", format!("{}x0", amount))
     }
 
     fn trim_right_edge(amount: Pixels)         -> ImageMagickOperation {
-        unimplemented!();
+        ImageMagickOperation::chop("East", format!("{}x0", amount))
     }
 
     fn trim_top_edge(amount: Pixels)           -> ImageMagickOperation {
-        unimplemented!();
+        ImageMagickOperation::chop("North", format!("0x{}", amount))
     }
 
     fn trim_bottom_edge(amount: Pixels)        -> ImageMagickOperation {
-        unimplemented!();
+        ImageMagickOperation::chop("South", format!("0x{}", amount))
     }
 
     fn set_resolution(res: ImageResolution)    -> ImageMagickOperation {
-        unimplemented!();
+        // Only the metadata changes; the pixels are not resampled.
+        let units = match *res.units() {
+            ResolutionUnits::PixelsPerInch       => "PixelsPerInch",
+            ResolutionUnits::PixelsPerCentimeter => "PixelsPerCentimeter",
+        };
+
+        ImageMagickOperation::mogrify(&[
+            String::from("-units"), String::from(units),
+            String::from("-density"), res.amount().to_string(),
+        ])
+    }
+
+    fn convert_format(format: OutputFormat)    -> ImageMagickOperation {
+        let extension = format.extension();
+        let mut args = Vec::new();
+        let coder = match format {
+            OutputFormat::Tiff(compression) => {
+                if let Some(name) = compression.imagemagick_name() {
+                    args.push(String::from("-compress"));
+                    args.push(String::from(name));
+                }
+                "TIFF"
+            }
+            OutputFormat::Jpeg(ref options) => {
+                let sampling = match options.subsampling() {
+                    ChromaSubsampling::Yuv444 => "1x1",
+                    ChromaSubsampling::Yuv422 => "2x1",
+                    ChromaSubsampling::Yuv420 => "2x2",
+                };
+                args.push(String::from("-quality"));
+                args.push(options.quality().to_string());
+                args.push(String::from("-sampling-factor"));
+                args.push(String::from(sampling));
+                "JPEG"
+            }
+            OutputFormat::Png(ref options) => {
+                args.push(String::from("-define"));
+                args.push(format!("png:compression-level={}", options.compression_level()));
+                if let Some(bits) = options.bit_depth() {
+                    args.push(String::from("-depth"));
+                    args.push(bits.to_string());
+                    args.push(String::from("-define"));
+                    args.push(format!("png:bit-depth={}", bits));
+                }
+                "PNG"
+            }
+            OutputFormat::Jpeg2000 => "JP2",
+            OutputFormat::WebP     => "WEBP",
+            OutputFormat::Bmp      => "BMP",
+            OutputFormat::Gif      => "GIF",
+            OutputFormat::Pnm      => "PNM",
+        };
+
+        ImageMagickOperation::reformat(coder, extension, &args)
+    }
+
+    fn reduce_colors(reduction: ColorReduction) -> ImageMagickOperation {
+        let args: Vec<String> = match reduction {
+            ColorReduction::Grayscale => vec![
+                String::from("-colorspace"), String::from("Gray"),
+                String::from("-type"), String::from("Grayscale"),
+            ],
+            ColorReduction::Bilevel(ThresholdMethod::Fixed(percent)) => vec![
+                String::from("-colorspace"), String::from("Gray"),
+                String::from("-threshold"), format!("{}%", percent.min(100)),
+                String::from("-type"), String::from("Bilevel"),
+            ],
+            ColorReduction::Bilevel(ThresholdMethod::Otsu) => vec![
+                String::from("-colorspace"), String::from("Gray"),
+                String::from("-auto-threshold"), String::from("OTSU"),
+                String::from("-type"), String::from("Bilevel"),
+            ],
+            ColorReduction::Bilevel(ThresholdMethod::Sauvola(ref params)) => {
+                // T = m (1 + k (s / R - 1)), with the local mean m and standard
+                // deviation s of the window, and R half the range of s.
+                let window = format!("{}x{}", params.window(), params.window());
+                let mut op = ImageMagickOperation::convert_in_place(&[
+                    String::from("-colorspace"), String::from("Gray"),
+                    String::from("("), String::from("-clone"), String::from("0"),
+                    String::from("-statistic"), String::from("Mean"), window.clone(), String::from(")"),
+                    String::from("("), String::from("-clone"), String::from("0"),
+                    String::from("-statistic"), String::from("StandardDeviation"), window, String::from(")"),
+                    String::from("-fx"), format!("u > u[1] * (1 + {} * (u[2] / 0.5 - 1))", params.k()),
+                    String::from("-type"), String::from("Bilevel"),
+                ]);
+                op.report_size = true;
+                return op;
+            }
+            // Without dithering, so flat areas of a scan stay flat.
+            ColorReduction::Palette(colors) => vec![
+                String::from("+dither"),
+                String::from("-colors"), colors.max(2).to_string(),
+                String::from("-type"), String::from("Palette"),
+            ],
+        };
+
+        let mut op = ImageMagickOperation::mogrify(&args);
+        op.report_size = true;
+
+        op
+    }
+
+    fn deskew(options: DeskewOptions)          -> ImageMagickOperation {
+        ImageMagickOperation::analysed(ImageMagickOpType::Deskew(options))
+    }
+
+    fn rotate(turn: QuarterTurn)               -> ImageMagickOperation {
+        ImageMagickOperation::mogrify(&[String::from("-rotate"), turn.degrees().to_string()])
+    }
+
+    fn rotate_by_angle(rotation: AngleRotation) -> ImageMagickOperation {
+        ImageMagickOperation::analysed(ImageMagickOpType::RotateByAngle(rotation))
+    }
+
+    fn flip(dir: Direction)                    -> ImageMagickOperation {
+        match dir {
+            Direction::Horizontal => ImageMagickOperation::mogrify(&[String::from("-flop")]),
+            Direction::Vertical   => ImageMagickOperation::mogrify(&[String::from("-flip")]),
+        }
+    }
+
+    fn remove_borders(removal: BorderRemoval)  -> ImageMagickOperation {
+        ImageMagickOperation::analysed(ImageMagickOpType::RemoveBorders(removal))
+    }
+
+    fn despeckle(filter: NoiseFilter)          -> ImageMagickOperation {
+        ImageMagickOperation::analysed(ImageMagickOpType::Despeckle(filter))
     }
 
     fn no_operation()                          -> ImageMagickOperation {
@@ -176,17 +432,210 @@ impl AsRef<[ElementaryImageMagickOperation]> for ImageMagickOperation {
 }
  
 
+impl RetargetOperation for ImageMagickOperation {
+    fn retarget(op: ImageMagickOperation, file_path: &FilePath) -> ImageMagickOperation {
+        let mut new_op = op;
+        let mut current = file_path.clone();
+        for elem_op in new_op.ops.iter_mut() {
+            elem_op.args.file_path = current.clone();
+            current = elem_op.output_path(&current);
+        }
+
+        new_op
+    }
+
+    fn output_path(op: &ImageMagickOperation, file_path: &FilePath) -> FilePath {
+        op.ops.iter().fold(file_path.clone(), |current, elem_op| elem_op.output_path(&current))
+    }
+}
+
+
 impl RunOperation for ImageMagickOperation {
     fn run_operation(op: ImageMagickOperation) -> OperationResults {
+        Self::run_operation_with_cancel(op, &CancellationToken::new())
+    }
+
+    /// Runs each ImageMagick command in turn. A cancellation that arrives before
+    /// the first command starts aborts the operation; one that arrives later kills
+    /// the running command and the operation is reported as failed.
+    fn run_operation_with_cancel(op: ImageMagickOperation, token: &CancellationToken) -> OperationResults {
+        if token.is_cancelled() {
+            return OperationResults::aborted();
+        }
+
         let mut results = OperationResults::new();
+        let size_before = match op.file_path() {
+            Some(file_path) if op.report_size => fs::metadata(file_path).ok().map(|metadata| metadata.len()),
+            _ => None,
+        };
+        let file_path = op.file_path().cloned();
+        let settings = op.settings();
 
         for action in op.ops {
+            if token.is_cancelled() {
+                results.push(Err(cancellation::cancelled_error()));
+                break;
+            }
+
             let mut result = Vec::new();
-            result.push(action.run_operation());
+            result.push(action.run_operation(&settings, token));
             results.append(&mut OperationResults::from(&mut result));
         }
 
+        if let (Some(before), Some(file_path)) = (size_before, file_path) {
+            if !results.is_failed() {
+                let reduction = fs::metadata(&file_path).map(|metadata| {
+                    SizeReduction::new(file_path.clone(), before, metadata.len()).to_string()
+                });
+                results.push(reduction);
+            }
+        }
+
         results
     }
+
+    /// Files ImageMagick reads but GrayImage does not decode are converted
+    /// first, with the operation's settings.
+    fn gray_image(op: &ImageMagickOperation, file_path: &FilePath, token: &CancellationToken) -> io::Result<GrayImage> {
+        analysis::gray_image(file_path, &op.settings(), token)
+    }
 }
 
+
+
+/// A compiled plan with every page's operations run with the given settings.
+pub fn configure_plan(plan: &OperationPlan<ImageMagickOperation>, settings: &ImageMagickSettings)
+    -> OperationPlan<ImageMagickOperation>
+{
+    let mut configured = OperationPlan::new();
+    configured.set_metadata(plan.metadata().clone());
+    configured.set_blank_pages(plan.blank_pages().cloned());
+
+    for (page, op) in plan {
+        let ops: Vec<ImageMagickOperation> = op.as_ref().iter()
+            .map(|elem_op| ImageMagickOperation::with_settings(elem_op.clone(), settings))
+            .collect();
+        configured.insert(page.clone(),
+                          CompoundPageOperation::new(op.page_name().clone(), op.page_path().clone(), &ops));
+    }
+
+    configured
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use imagemagick::settings::ResourceLimit;
+    use image_tools::image_ops::{JpegOptions, PngOptions, SauvolaParameters};
+    use util::scratch::{scratch_dir, scratch_tiff};
+    use image_tools::multipage::TiffCompression;
+
+    /// The coder, extension and arguments of a single conversion.
+    fn reformat(op: &ImageMagickOperation) -> (String, String, Vec<String>) {
+        assert_eq!(op.ops.len(), 1);
+        match op.ops[0].op {
+            ImageMagickOpType::Reformat(ref coder, ref extension) => {
+                (coder.clone(), extension.clone(), op.ops[0].args.img_args.clone())
+            }
+            ref other => panic!("not a conversion: {}", other),
+        }
+    }
+
+    #[test]
+    fn operations_without_settings_use_the_backends() {
+        let mut backend = ImageMagickSettings::new();
+        backend.set_timeout(Duration::from_secs(60));
+        settings::configure(backend.clone());
+
+        let mut own = ImageMagickSettings::new();
+        own.set_limit(ResourceLimit::Memory(1 << 30));
+        let op = ImageMagickOperation::no_operation();
+        let configured = ImageMagickOperation::with_settings(op.clone(), &own);
+
+        assert_eq!(op.settings(), backend);
+        assert_eq!(configured.settings(), own);
+
+        settings::configure(ImageMagickSettings::new());
+    }
+
+    #[test]
+    fn conversions_pass_the_format_options_to_the_coder() {
+        let mut jpeg = JpegOptions::new(85);
+        jpeg.set_subsampling(ChromaSubsampling::Yuv444);
+        let (coder, extension, args) = reformat(&ImageMagickOperation::convert_format(OutputFormat::Jpeg(jpeg)));
+        assert_eq!((coder.as_str(), extension.as_str()), ("JPEG", "jpg"));
+        assert_eq!(args, vec!["-quality", "85", "-sampling-factor", "1x1"]);
+
+        let mut png = PngOptions::new(9);
+        png.set_bit_depth(1);
+        let (coder, _, args) = reformat(&ImageMagickOperation::convert_format(OutputFormat::Png(png)));
+        assert_eq!(coder, "PNG");
+        assert_eq!(args, vec!["-define", "png:compression-level=9", "-depth", "1", "-define", "png:bit-depth=1"]);
+
+        let g4 = OutputFormat::Tiff(TiffCompression::Group4);
+        assert_eq!(reformat(&ImageMagickOperation::convert_format(g4)).2, vec!["-compress", "Group4"]);
+        let kept = OutputFormat::Tiff(TiffCompression::Keep);
+        assert!(reformat(&ImageMagickOperation::convert_format(kept)).2.is_empty());
+
+        let (coder, extension, _) = reformat(&ImageMagickOperation::convert_format(OutputFormat::Jpeg2000));
+        assert_eq!((coder.as_str(), extension.as_str()), ("JP2", "jp2"));
+    }
+
+    #[test]
+    fn conversions_write_beside_the_original() {
+        let op = ImageMagickOperation::convert_format(OutputFormat::WebP);
+        let file_path = String::from("/scans/page-0001.tif");
+
+        assert_eq!(op.ops[0].output_path(&file_path), "/scans/page-0001.webp");
+        assert_eq!(ImageMagickOperation::no_operation().ops[0].output_path(&file_path), file_path);
+    }
+
+    fn img_args(op: &ImageMagickOperation) -> Vec<String> {
+        op.ops[0].args.img_args.clone()
+    }
+
+    #[test]
+    fn color_reductions_report_the_change_in_size() {
+        let fixed = ImageMagickOperation::reduce_colors(ColorReduction::Bilevel(ThresholdMethod::Fixed(150)));
+        assert!(fixed.report_size);
+        assert_eq!(img_args(&fixed), vec!["-colorspace", "Gray", "-threshold", "100%", "-type", "Bilevel"]);
+
+        let palette = ImageMagickOperation::reduce_colors(ColorReduction::Palette(1));
+        assert_eq!(img_args(&palette), vec!["+dither", "-colors", "2", "-type", "Palette"]);
+
+        let params = SauvolaParameters::new(25, 0.34);
+        let sauvola = ImageMagickOperation::reduce_colors(ColorReduction::Bilevel(ThresholdMethod::Sauvola(params)));
+        assert!(sauvola.report_size);
+        assert!(matches!(sauvola.ops[0].op, ImageMagickOpType::ConvertInPlace));
+        assert!(img_args(&sauvola).contains(&String::from("25x25")));
+        assert!(img_args(&sauvola).contains(&String::from("u > u[1] * (1 + 0.34 * (u[2] / 0.5 - 1))")));
+    }
+
+    #[test]
+    fn size_reports_follow_the_commands() {
+        let dir = scratch_dir("imagemagick-size-report");
+        let page = scratch_tiff(&dir, "page.tif");
+        let size = fs::metadata(page.file_path()).unwrap().len();
+
+        let mut op = ImageMagickOperation::no_operation();
+        op.report_size = true;
+        let results = <ImageMagickOperation as RunOperation>::run_operation(
+            ImageMagickOperation::retarget(op, page.file_path()));
+        let report = SizeReduction::new(page.file_path().clone(), size, size).to_string();
+        assert_eq!(results.as_ref().last().unwrap().as_ref().unwrap(), &report);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn flips_and_quarter_turns_are_single_mogrify_commands() {
+        assert_eq!(img_args(&ImageMagickOperation::flip(Direction::Horizontal)), vec!["-flop"]);
+        assert_eq!(img_args(&ImageMagickOperation::flip(Direction::Vertical)), vec!["-flip"]);
+
+        let turn = ImageMagickOperation::rotate(QuarterTurn::Rotate270);
+        assert!(matches!(turn.ops[0].op, ImageMagickOpType::Mogrify));
+        assert_eq!(img_args(&turn), vec!["-rotate", "270"]);
+    }
+}
@@ -0,0 +1,157 @@
+#![allow(dead_code)]
+use std::time::Duration;
+use std::sync::RwLock;
+use std::fmt;
+use util::shell::AsShellArg;
+
+
+/// An ImageMagick resource limit, passed to every command as `-limit <resource> <value>`.
+/// Memory, map and disk limits are given in bytes.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum ResourceLimit {
+    Memory(u64),
+    Map(u64),
+    Disk(u64),
+    Thread(usize),
+}
+
+impl ResourceLimit {
+    fn value(&self) -> String {
+        match *self {
+            ResourceLimit::Memory(bytes) => bytes.to_string(),
+            ResourceLimit::Map(bytes)    => bytes.to_string(),
+            ResourceLimit::Disk(bytes)   => bytes.to_string(),
+            ResourceLimit::Thread(count) => count.to_string(),
+        }
+    }
+
+    fn same_resource(&self, other: &ResourceLimit) -> bool {
+        self.as_shell_arg() == other.as_shell_arg()
+    }
+}
+
+impl AsShellArg for ResourceLimit {
+    fn as_shell_arg(&self) -> String {
+        match *self {
+            ResourceLimit::Memory(_) => String::from("memory"),
+            ResourceLimit::Map(_)    => String::from("map"),
+            ResourceLimit::Disk(_)   => String::from("disk"),
+            ResourceLimit::Thread(_) => String::from("thread"),
+        }
+    }
+}
+
+impl fmt::Display for ResourceLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ResourceLimit::Memory(bytes) => write!(f, "Memory({} Bytes)", bytes),
+            ResourceLimit::Map(bytes)    => write!(f, "Map({} Bytes)", bytes),
+            ResourceLimit::Disk(bytes)   => write!(f, "Disk({} Bytes)", bytes),
+            ResourceLimit::Thread(count) => write!(f, "Thread({})", count),
+        }
+    }
+}
+
+
+/// Settings applied to every ImageMagick command an operation runs, given to
+/// the operation with `ImageMagickOperation::with_settings`, or to a whole
+/// plan with `operations::configure_plan`. Operations given no settings of
+/// their own use the backend's, set with `configure`. The timeout is a wall clock limit
+/// per command; a command that exceeds it is killed and the operation is
+/// reported as timed out.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ImageMagickSettings {
+    timeout: Option<Duration>,
+    limits:  Vec<ResourceLimit>,
+}
+
+impl Default for ImageMagickSettings {
+    fn default() -> ImageMagickSettings {
+        ImageMagickSettings::new()
+    }
+}
+
+impl ImageMagickSettings {
+    pub fn new() -> ImageMagickSettings {
+        ImageMagickSettings {
+            timeout: None,
+            limits:  Vec::new(),
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    /// Set a resource limit, replacing any earlier limit on the same resource.
+    pub fn set_limit(&mut self, limit: ResourceLimit) {
+        self.limits.retain(|other| !other.same_resource(&limit));
+        self.limits.push(limit);
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn limits(&self) -> &[ResourceLimit] {
+        self.limits.as_ref()
+    }
+
+    /// The command line arguments that apply the resource limits.
+    pub fn limit_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for limit in self.limits.iter() {
+            args.push(String::from("-limit"));
+            args.push(limit.as_shell_arg());
+            args.push(limit.value());
+        }
+
+        args
+    }
+}
+
+impl fmt::Display for ImageMagickSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let limits: Vec<String> = self.limits.iter().map(|limit| limit.to_string()).collect();
+        match self.timeout {
+            Some(timeout) => write!(f, "ImageMagickSettings(timeout: {:?}, limits: [{}])", timeout, limits.join(", ")),
+            None          => write!(f, "ImageMagickSettings(timeout: None, limits: [{}])", limits.join(", ")),
+        }
+    }
+}
+
+
+static SETTINGS: RwLock<Option<ImageMagickSettings>> = RwLock::new(None);
+
+/// Configure the ImageMagick backend. The settings apply to every command
+/// run after this call by an operation without settings of its own.
+pub fn configure(settings: ImageMagickSettings) {
+    let mut current = SETTINGS.write().unwrap_or_else(|e| e.into_inner());
+    *current = Some(settings);
+}
+
+/// The settings the ImageMagick backend is currently configured with.
+pub fn current_settings() -> ImageMagickSettings {
+    let current = SETTINGS.read().unwrap_or_else(|e| e.into_inner());
+    match *current {
+        Some(ref settings) => settings.clone(),
+        None               => ImageMagickSettings::new(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_on_the_same_resource_replace_each_other() {
+        let mut settings = ImageMagickSettings::new();
+        settings.set_limit(ResourceLimit::Memory(1 << 20));
+        settings.set_limit(ResourceLimit::Thread(2));
+        settings.set_limit(ResourceLimit::Memory(1 << 30));
+
+        assert_eq!(settings.limits(), &[ResourceLimit::Thread(2), ResourceLimit::Memory(1 << 30)]);
+        assert_eq!(settings.limit_args(), vec!["-limit", "thread", "2", "-limit", "memory", "1073741824"]);
+    }
+}
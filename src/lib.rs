@@ -1,4 +1,10 @@
+// Struct literals spell out `field: field` throughout the crate.
+#![allow(clippy::redundant_field_names)]
+
 pub mod imagemagick;
 pub mod image_tools;
+pub mod pdf;
+pub mod rasterize;
+pub mod tesseract;
 
 mod util;
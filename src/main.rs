@@ -2,7 +2,6 @@ extern crate rust_pdftools;
 
 //use rust_pdftools::imagemagick::imagemagick_commands as commands;
 use rust_pdftools::imagemagick::operations::ImageMagickOperation as IMO;
-use rust_pdftools::imagemagick::operations as ops;
 use rust_pdftools::image_tools::image_ops::RunOperation as RO;
 use rust_pdftools::image_tools::image_ops::ElementaryPageOperations as EPO;
 use std::env;
@@ -16,7 +15,7 @@ fn main() {
 
     if args.len() > 1 {
         let op: IMO = EPO::identify(String::from(""), args[1].clone());
-        let res = RO::run_operation(op);
+        let _res = RO::run_operation(op);
         println!("Operation completed.");
     } else {
         println!("{}", usage());
@@ -0,0 +1,316 @@
+#![allow(dead_code)]
+use std::io;
+use std::io::{Write, BufWriter};
+use std::fs::File;
+use std::path::Path;
+use image_tools::image_ops::{Page, ImageDimensions, ImageResolution, ResolutionUnits};
+use image_tools::image_ops::{OperationPlan, OperationPlanResult};
+use image_tools::document::DocumentMetadata;
+use pdf::objects::{ObjectId, PdfObject, PdfDictionary, PdfStream};
+use pdf::writer::PdfWriter;
+use pdf::images::PdfImage;
+use pdf::metadata;
+use pdf::pdfa;
+use pdf::pdfa::{OutputIntentProfile, PdfAViolation};
+
+
+/// PDF user space units per inch.
+pub const POINTS_PER_INCH: f64 = 72.0;
+
+/// The resolution assumed for images that do not record a usable one.
+const DEFAULT_PIXELS_PER_INCH: f64 = 72.0;
+
+pub fn pixels_per_inch(res: &ImageResolution) -> f64 {
+    let amount = res.amount() as f64;
+    let per_inch = match *res.units() {
+        ResolutionUnits::PixelsPerInch       => amount,
+        ResolutionUnits::PixelsPerCentimeter => amount * 2.54,
+    };
+
+    if per_inch > 0.0 { per_inch } else { DEFAULT_PIXELS_PER_INCH }
+}
+
+/// The size of a page in points, for an image of the given dimensions
+/// printed at the given resolution.
+pub fn page_size(dims: &ImageDimensions, res: &ImageResolution) -> (f64, f64) {
+    let per_inch = pixels_per_inch(res);
+    let width = dims.x_pixels() as f64 * POINTS_PER_INCH / per_inch;
+    let height = dims.y_pixels() as f64 * POINTS_PER_INCH / per_inch;
+
+    (width, height)
+}
+
+
+/// Assembles page images into a PDF document, one page per image. The page
+/// size is worked out from the image's dimensions and resolution: the resolution
+/// the image file records if it has one, otherwise the resolution of the Page.
+#[derive(Clone, Debug)]
+pub struct PdfAssembly {
+    pages: Vec<Page>,
+    metadata: DocumentMetadata,
+    pdfa: Option<OutputIntentProfile>,
+}
+
+impl Default for PdfAssembly {
+    fn default() -> PdfAssembly {
+        PdfAssembly::new()
+    }
+}
+
+impl PdfAssembly {
+    pub fn new() -> PdfAssembly {
+        PdfAssembly {
+            pages: Vec::new(),
+            metadata: DocumentMetadata::new(),
+            pdfa: None,
+        }
+    }
+
+    /// An assembly of the output pages of an executed plan, carrying the
+    /// document metadata the plan defines. The outline and page labels are
+    /// adjusted for the blank pages the plan dropped.
+    pub fn from_plan<Op: Clone>(plan: &OperationPlan<Op>, result: &OperationPlanResult) -> io::Result<PdfAssembly> {
+        let mut assembly = PdfAssembly::from_plan_result(result)?;
+        let mut metadata = plan.metadata().clone();
+
+        let dropped: Vec<usize> = result.pages().iter().enumerate()
+            .filter(|&(_, page)| result.is_dropped(page))
+            .map(|(i, _)| i + 1)
+            .collect();
+        for &page_number in dropped.iter().rev() {
+            metadata.remove_page(page_number);
+        }
+        if !dropped.is_empty() {
+            metadata.truncate_pages(assembly.pages().len());
+        }
+        assembly.set_metadata(metadata);
+
+        Ok(assembly)
+    }
+
+    /// An assembly of the output pages of an executed plan, in document order,
+    /// leaving out the blank pages the plan dropped. Pages written to an output
+    /// directory are taken from there rather than from their original files. Every page must have
+    /// completed; a document silently missing pages is worse than no document.
+    pub fn from_plan_result(result: &OperationPlanResult) -> io::Result<PdfAssembly> {
+        let mut assembly = PdfAssembly::new();
+        let mut failed = Vec::new();
+
+        for (page, res) in result {
+            if res.is_failed() || res.is_aborted() {
+                failed.push(page.file_name().clone());
+            } else if !result.is_dropped(page) {
+                assembly.add_page(result.output_page(page));
+            }
+        }
+
+        if !failed.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Pages did not complete: {}", failed.join(", "))));
+        }
+
+        Ok(assembly)
+    }
+
+    pub fn add_page(&mut self, page: Page) {
+        self.pages.push(page);
+    }
+
+    pub fn pages(&self) -> &[Page] {
+        self.pages.as_ref()
+    }
+
+    pub fn set_metadata(&mut self, metadata: DocumentMetadata) {
+        self.metadata = metadata;
+    }
+
+    pub fn metadata(&self) -> &DocumentMetadata {
+        &self.metadata
+    }
+
+    /// Produce PDF/A-2b output, with the given output intent profile, or
+    /// ordinary PDF output.
+    pub fn set_pdfa(&mut self, profile: Option<OutputIntentProfile>) {
+        self.pdfa = profile;
+    }
+
+    pub fn pdfa(&self) -> Option<OutputIntentProfile> {
+        self.pdfa
+    }
+
+    /// Add a page showing an image scaled to fill it.
+    fn add_image_page(&self, writer: &mut PdfWriter, parent: ObjectId, page: &Page) -> io::Result<ObjectId> {
+        let image = PdfImage::open(page.file_path())?;
+        let res = image.resolution().cloned().unwrap_or(page.resolution().clone());
+        let (width, height) = page_size(&image.dimensions(), &res);
+
+        let image_id = image.add_to(writer);
+
+        let content = format!("q\n{} 0 0 {} 0 0 cm\n/Im0 Do\nQ\n", PdfObject::real(width), PdfObject::real(height));
+        let content_id = writer.add(PdfObject::Stream(PdfStream::new(PdfDictionary::new(), content.into_bytes())));
+
+        let mut xobjects = PdfDictionary::new();
+        xobjects.set("Im0", PdfObject::Reference(image_id));
+        let mut resources = PdfDictionary::new();
+        resources.set("XObject", PdfObject::Dictionary(xobjects));
+
+        let mut page_dict = PdfDictionary::new();
+        page_dict.set("Type", PdfObject::name("Page"));
+        page_dict.set("Parent", PdfObject::Reference(parent));
+        page_dict.set("MediaBox", PdfObject::rectangle(0.0, 0.0, width, height));
+        page_dict.set("Resources", PdfObject::Dictionary(resources));
+        page_dict.set("Contents", PdfObject::Reference(content_id));
+
+        Ok(writer.add(PdfObject::Dictionary(page_dict)))
+    }
+
+    /// Build the objects of the document, returning the writer holding them
+    /// and the trailer pointing at the catalog and Info dictionary.
+    pub fn build(&self) -> io::Result<(PdfWriter, PdfDictionary)> {
+        if self.pages.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "A PDF document needs at least one page"));
+        }
+
+        let mut writer = PdfWriter::new();
+        let catalog_id = writer.reserve();
+        let pages_id = writer.reserve();
+
+        let mut page_ids = Vec::new();
+        for page in self.pages.iter() {
+            page_ids.push(self.add_image_page(&mut writer, pages_id, page)?);
+        }
+        let kids: Vec<PdfObject> = page_ids.iter().map(|&id| PdfObject::Reference(id)).collect();
+
+        let mut pages = PdfDictionary::new();
+        pages.set("Type", PdfObject::name("Pages"));
+        pages.set("Count", PdfObject::Integer(kids.len() as i64));
+        pages.set("Kids", PdfObject::Array(kids));
+        writer.set(pages_id, PdfObject::Dictionary(pages))?;
+
+        let mut catalog = PdfDictionary::new();
+        catalog.set("Type", PdfObject::name("Catalog"));
+        catalog.set("Pages", PdfObject::Reference(pages_id));
+
+        let mut trailer = PdfDictionary::new();
+        trailer.set("Root", PdfObject::Reference(catalog_id));
+        let xmp_properties = match self.pdfa {
+            Some(profile) => {
+                writer.set_version("1.7");
+                let intent_id = pdfa::add_output_intent(&mut writer, profile);
+                catalog.set("OutputIntents", PdfObject::Array(vec![PdfObject::Reference(intent_id)]));
+                pdfa::pdfa_identification()
+            }
+            None => Vec::new(),
+        };
+        metadata::add_document_metadata(&mut writer, &mut catalog, &mut trailer, &self.metadata, &page_ids,
+                                        &xmp_properties)?;
+        writer.set(catalog_id, PdfObject::Dictionary(catalog))?;
+
+        Ok((writer, trailer))
+    }
+
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let (writer, trailer) = self.build()?;
+
+        writer.write(out, &trailer)
+    }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write(&mut out)
+    }
+
+    /// Write a PDF/A file and check the result, returning the ways in which
+    /// it fails to conform. An image the output intent cannot describe, such
+    /// as a CMYK JPEG in a document with an sRGB intent, shows up here.
+    pub fn write_pdfa_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<Vec<PdfAViolation>> {
+        if self.pdfa.is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "No PDF/A output intent profile was chosen"));
+        }
+
+        self.write_to_file(path.as_ref())?;
+        pdfa::check_file(path)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use image_tools::image_ops::{ImageFileFormat, OperationResults};
+    use image_tools::raster::GrayImage;
+    use image_tools::tiff::{self, TiffImageData, TiffValue};
+    use pdf::reader::PdfDocument;
+    use pdf::extract;
+    use util::scratch::scratch_dir;
+
+    /// A grayscale TIFF page with a different shade in every pixel.
+    fn write_page(dir: &Path, name: &str, width: usize, height: usize) -> (Page, Vec<u8>) {
+        let pixels: Vec<u8> = (0..width * height).map(|i| (i * 7 % 256) as u8).collect();
+        let res = ImageResolution::new(150, ResolutionUnits::PixelsPerInch);
+        let mut image = TiffImageData::new(width as u32, height as u32, pixels.clone());
+        image.set(tiff::TAG_BITS_PER_SAMPLE, TiffValue::Short(vec![8]));
+        image.set(tiff::TAG_COMPRESSION, TiffValue::Short(vec![tiff::COMPRESSION_NONE as u16]));
+        image.set(tiff::TAG_PHOTOMETRIC, TiffValue::Short(vec![tiff::PHOTOMETRIC_BLACK_IS_ZERO as u16]));
+        image.set_resolution(&res);
+
+        let path = dir.join(name);
+        fs::write(&path, tiff::write_tiff(&[image])).unwrap();
+        let page = Page::new(String::from(name), ImageFileFormat::TIFF, path.to_string_lossy().into_owned(),
+                             ImageDimensions::new(width, height), res);
+
+        (page, pixels)
+    }
+
+    #[test]
+    fn assembled_pages_read_back_and_extract_unchanged() {
+        let dir = scratch_dir("assemble");
+        let (first, first_pixels) = write_page(&dir, "first.tif", 30, 20);
+        let (second, second_pixels) = write_page(&dir, "second.tif", 15, 45);
+
+        let mut assembly = PdfAssembly::new();
+        assembly.add_page(first);
+        assembly.add_page(second);
+        let pdf_path = dir.join("book.pdf");
+        assembly.write_to_file(&pdf_path).unwrap();
+
+        let document = PdfDocument::open(&pdf_path).unwrap();
+        let sizes: Vec<(f64, f64)> = document.pages().unwrap().iter().map(|page| page.size()).collect();
+        assert_eq!(sizes, vec![(14.4, 9.6), (7.2, 21.6)]);
+
+        let extracted_dir = dir.join("extracted");
+        fs::create_dir_all(&extracted_dir).unwrap();
+        let pages = extract::extract_page_images(&pdf_path, &extracted_dir).unwrap();
+        assert_eq!(pages.len(), 2);
+        for (page, pixels) in pages.iter().zip(vec![first_pixels, second_pixels]) {
+            assert_eq!(GrayImage::open(page.file_path()).unwrap().pixels(), &pixels[..]);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pages_written_to_an_output_directory_are_assembled_from_there() {
+        let dir = scratch_dir("output-dir");
+        let (page, _) = write_page(&dir, "page.tif", 4, 4);
+        let output_path = dir.join("out").join("page.tif").to_string_lossy().into_owned();
+
+        let mut result = OperationPlanResult::new();
+        result.insert(page.clone(), OperationResults::from(Ok(String::from("done"))));
+        result.set_output_path(page.clone(), output_path.clone());
+        let assembly = PdfAssembly::from_plan_result(&result).unwrap();
+
+        assert_eq!(assembly.pages()[0].file_path(), &output_path);
+        assert_eq!(assembly.pages()[0].file_name(), "page.tif");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn assemblies_without_pages_are_rejected() {
+        let mut out = Vec::new();
+
+        assert_eq!(PdfAssembly::new().write(&mut out).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,534 @@
+//! CCITT Group 3 and Group 4 fax coding (ITU-T T.4 and T.6), as used by
+//! bilevel TIFF images and the PDF CCITTFaxDecode filter. A line is held as
+//! the positions where its colour changes, starting from white, which is all
+//! that either coding describes.
+#![allow(dead_code)]
+use std::io;
+use std::collections::HashMap;
+
+
+/// White run lengths: the terminating codes for 0 to 63, then the make-up codes.
+const WHITE_CODES: [(u16, &str); 91] = [
+    (0, "00110101"), (1, "000111"), (2, "0111"), (3, "1000"),
+    (4, "1011"), (5, "1100"), (6, "1110"), (7, "1111"),
+    (8, "10011"), (9, "10100"), (10, "00111"), (11, "01000"),
+    (12, "001000"), (13, "000011"), (14, "110100"), (15, "110101"),
+    (16, "101010"), (17, "101011"), (18, "0100111"), (19, "0001100"),
+    (20, "0001000"), (21, "0010111"), (22, "0000011"), (23, "0000100"),
+    (24, "0101000"), (25, "0101011"), (26, "0010011"), (27, "0100100"),
+    (28, "0011000"), (29, "00000010"), (30, "00000011"), (31, "00011010"),
+    (32, "00011011"), (33, "00010010"), (34, "00010011"), (35, "00010100"),
+    (36, "00010101"), (37, "00010110"), (38, "00010111"), (39, "00101000"),
+    (40, "00101001"), (41, "00101010"), (42, "00101011"), (43, "00101100"),
+    (44, "00101101"), (45, "00000100"), (46, "00000101"), (47, "00001010"),
+    (48, "00001011"), (49, "01010010"), (50, "01010011"), (51, "01010100"),
+    (52, "01010101"), (53, "00100100"), (54, "00100101"), (55, "01011000"),
+    (56, "01011001"), (57, "01011010"), (58, "01011011"), (59, "01001010"),
+    (60, "01001011"), (61, "00110010"), (62, "00110011"), (63, "00110100"),
+    (64, "11011"), (128, "10010"), (192, "010111"), (256, "0110111"),
+    (320, "00110110"), (384, "00110111"), (448, "01100100"), (512, "01100101"),
+    (576, "01101000"), (640, "01100111"), (704, "011001100"), (768, "011001101"),
+    (832, "011010010"), (896, "011010011"), (960, "011010100"), (1024, "011010101"),
+    (1088, "011010110"), (1152, "011010111"), (1216, "011011000"), (1280, "011011001"),
+    (1344, "011011010"), (1408, "011011011"), (1472, "010011000"), (1536, "010011001"),
+    (1600, "010011010"), (1664, "011000"), (1728, "010011011"),
+];
+
+/// Black run lengths: the terminating codes for 0 to 63, then the make-up codes.
+const BLACK_CODES: [(u16, &str); 91] = [
+    (0, "0000110111"), (1, "010"), (2, "11"), (3, "10"),
+    (4, "011"), (5, "0011"), (6, "0010"), (7, "00011"),
+    (8, "000101"), (9, "000100"), (10, "0000100"), (11, "0000101"),
+    (12, "0000111"), (13, "00000100"), (14, "00000111"), (15, "000011000"),
+    (16, "0000010111"), (17, "0000011000"), (18, "0000001000"), (19, "00001100111"),
+    (20, "00001101000"), (21, "00001101100"), (22, "00000110111"), (23, "00000101000"),
+    (24, "00000010111"), (25, "00000011000"), (26, "000011001010"), (27, "000011001011"),
+    (28, "000011001100"), (29, "000011001101"), (30, "000001101000"), (31, "000001101001"),
+    (32, "000001101010"), (33, "000001101011"), (34, "000011010010"), (35, "000011010011"),
+    (36, "000011010100"), (37, "000011010101"), (38, "000011010110"), (39, "000011010111"),
+    (40, "000001101100"), (41, "000001101101"), (42, "000011011010"), (43, "000011011011"),
+    (44, "000001010100"), (45, "000001010101"), (46, "000001010110"), (47, "000001010111"),
+    (48, "000001100100"), (49, "000001100101"), (50, "000001010010"), (51, "000001010011"),
+    (52, "000000100100"), (53, "000000110111"), (54, "000000111000"), (55, "000000100111"),
+    (56, "000000101000"), (57, "000001011000"), (58, "000001011001"), (59, "000000101011"),
+    (60, "000000101100"), (61, "000001011010"), (62, "000001100110"), (63, "000001100111"),
+    (64, "0000001111"), (128, "000011001000"), (192, "000011001001"), (256, "000001011011"),
+    (320, "000000110011"), (384, "000000110100"), (448, "000000110101"), (512, "0000001101100"),
+    (576, "0000001101101"), (640, "0000001001010"), (704, "0000001001011"), (768, "0000001001100"),
+    (832, "0000001001101"), (896, "0000001110010"), (960, "0000001110011"), (1024, "0000001110100"),
+    (1088, "0000001110101"), (1152, "0000001110110"), (1216, "0000001110111"), (1280, "0000001010010"),
+    (1344, "0000001010011"), (1408, "0000001010100"), (1472, "0000001010101"), (1536, "0000001011010"),
+    (1600, "0000001011011"), (1664, "0000001100100"), (1728, "0000001100101"),
+];
+
+/// Make-up codes for long runs of either colour.
+const EXTENDED_CODES: [(u16, &str); 13] = [
+    (1792, "00000001000"), (1856, "00000001100"), (1920, "00000001101"), (1984, "000000010010"),
+    (2048, "000000010011"), (2112, "000000010100"), (2176, "000000010101"), (2240, "000000010110"),
+    (2304, "000000010111"), (2368, "000000011100"), (2432, "000000011101"), (2496, "000000011110"),
+    (2560, "000000011111"),
+];
+
+/// The end of line code, which may also be preceded by fill bits.
+const EOL: &str = "000000000001";
+
+/// The longest run or end of line code, in bits.
+const MAX_CODE_BITS: usize = 13;
+
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid CCITT data: {}", message))
+}
+
+/// How the lines of a CCITT stream are coded, as given by the PDF K parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CcittCoding {
+    /// Group 3 one-dimensional (Modified Huffman) coding, K = 0.
+    OneDimensional,
+    /// Group 3 coding with each line tagged as one or two-dimensional, K > 0.
+    Mixed,
+    /// Group 4 two-dimensional coding, K < 0.
+    TwoDimensional,
+}
+
+/// A two-dimensional coding mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Pass,
+    Horizontal,
+    Vertical(i64),
+}
+
+/// Reads bits most significant bit first, as fax coding requires.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data: data,
+            pos: 0,
+        }
+    }
+
+    fn peek_bit(&self, offset: usize) -> Option<u32> {
+        let pos = self.pos + offset;
+        self.data.get(pos / 8).map(|byte| ((byte >> (7 - pos % 8)) & 1) as u32)
+    }
+
+    fn read_bit(&mut self) -> io::Result<u32> {
+        let bit = self.peek_bit(0).ok_or_else(|| invalid_data("unexpected end of stream"))?;
+        self.pos += 1;
+
+        Ok(bit)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.pos = self.pos.div_ceil(8) * 8;
+    }
+
+    /// Skip any end of line codes, with the fill bits before them.
+    fn skip_end_of_line(&mut self) {
+        loop {
+            let mut zeros = 0;
+            while self.peek_bit(zeros) == Some(0) {
+                zeros += 1;
+            }
+            if zeros < EOL.len() - 1 || self.peek_bit(zeros).is_none() {
+                return;
+            }
+            self.pos += zeros + 1;
+        }
+    }
+}
+
+/// Writes bits most significant bit first.
+struct BitWriter {
+    output: Vec<u8>,
+    count: usize,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            output: Vec::new(),
+            count: 0,
+        }
+    }
+
+    fn write_code(&mut self, code: &str) {
+        for bit in code.bytes() {
+            if self.count.is_multiple_of(8) {
+                self.output.push(0);
+            }
+            if bit == b'1' {
+                let last = self.output.len() - 1;
+                self.output[last] |= 0x80 >> (self.count % 8);
+            }
+            self.count += 1;
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.output
+    }
+}
+
+/// The run lengths of one colour, looked up by code length and code.
+struct RunCodes {
+    runs: HashMap<(usize, u16), u16>,
+}
+
+impl RunCodes {
+    fn new(codes: &[(u16, &str)]) -> RunCodes {
+        let mut runs = HashMap::new();
+        for &(run, code) in codes.iter().chain(EXTENDED_CODES.iter()) {
+            runs.insert((code.len(), u16::from_str_radix(code, 2).unwrap_or(0)), run);
+        }
+
+        RunCodes {
+            runs: runs,
+        }
+    }
+
+    fn read_code(&self, reader: &mut BitReader) -> io::Result<u16> {
+        let mut code = 0u16;
+        for bits in 1..MAX_CODE_BITS + 1 {
+            code = code << 1 | reader.read_bit()? as u16;
+            if let Some(&run) = self.runs.get(&(bits, code)) {
+                return Ok(run);
+            }
+        }
+
+        Err(invalid_data("unknown run length code"))
+    }
+
+    /// A run: any make-up codes, then a terminating code below 64.
+    fn read_run(&self, reader: &mut BitReader) -> io::Result<usize> {
+        let mut run = 0;
+        loop {
+            let part = self.read_code(reader)? as usize;
+            run += part;
+            if part < 64 {
+                return Ok(run);
+            }
+        }
+    }
+}
+
+fn read_mode(reader: &mut BitReader) -> io::Result<Mode> {
+    let mut zeros = 0;
+    while reader.read_bit()? == 0 {
+        zeros += 1;
+        if zeros > 6 {
+            return Err(invalid_data("unexpected end of line in a two-dimensional line"));
+        }
+    }
+
+    match zeros {
+        0 => Ok(Mode::Vertical(0)),
+        1 => Ok(if reader.read_bit()? == 1 { Mode::Vertical(1) } else { Mode::Vertical(-1) }),
+        2 => Ok(Mode::Horizontal),
+        3 => Ok(Mode::Pass),
+        4 => Ok(if reader.read_bit()? == 1 { Mode::Vertical(2) } else { Mode::Vertical(-2) }),
+        5 => Ok(if reader.read_bit()? == 1 { Mode::Vertical(3) } else { Mode::Vertical(-3) }),
+        _ => Err(invalid_data("uncompressed mode and extensions are not supported")),
+    }
+}
+
+/// Finds the changes b1 and b2 on the reference line for a changing element
+/// a0 of the given colour. Since a0 only moves right along a line, the search
+/// starts where the last one left off.
+struct ReferenceLine<'a> {
+    changes: &'a [usize],
+    width: usize,
+    next: usize,
+}
+
+impl<'a> ReferenceLine<'a> {
+    fn new(changes: &'a [usize], width: usize) -> ReferenceLine<'a> {
+        ReferenceLine {
+            changes: changes,
+            width: width,
+            next: 0,
+        }
+    }
+
+    fn change(&self, index: usize) -> i64 {
+        self.changes.get(index).map_or(self.width, |&change| change) as i64
+    }
+
+    /// b1 is the first change right of a0 to the colour opposite a0's, and
+    /// b2 the change after it. Changes at even indices are to black.
+    fn b1_b2(&mut self, a0: i64, white: bool) -> (i64, i64) {
+        while self.next < self.changes.len() && self.changes[self.next] as i64 <= a0 {
+            self.next += 1;
+        }
+        let b1 = if self.next.is_multiple_of(2) == white { self.next } else { self.next + 1 };
+
+        (self.change(b1), self.change(b1 + 1))
+    }
+}
+
+/// Record a change of colour, dropping changes at the end of the line. Two
+/// changes at the same place cancel out.
+fn push_change(line: &mut Vec<usize>, change: i64, width: usize) {
+    let change = change as usize;
+    if change >= width {
+        return;
+    }
+    if line.last() == Some(&change) {
+        line.pop();
+    } else {
+        line.push(change);
+    }
+}
+
+fn decode_one_dimensional(reader: &mut BitReader, white_codes: &RunCodes, black_codes: &RunCodes,
+                          width: usize) -> io::Result<Vec<usize>> {
+    let mut line = Vec::new();
+    let mut pos = 0;
+    let mut white = true;
+    while pos < width {
+        pos += if white { white_codes.read_run(reader)? } else { black_codes.read_run(reader)? };
+        if pos > width {
+            return Err(invalid_data("run past the end of the line"));
+        }
+        push_change(&mut line, pos as i64, width);
+        white = !white;
+    }
+
+    Ok(line)
+}
+
+fn decode_two_dimensional(reader: &mut BitReader, white_codes: &RunCodes, black_codes: &RunCodes,
+                          reference: &[usize], width: usize) -> io::Result<Vec<usize>> {
+    let mut reference = ReferenceLine::new(reference, width);
+    let mut line = Vec::new();
+    let mut a0: i64 = -1;
+    let mut white = true;
+    while a0 < width as i64 {
+        let (b1, b2) = reference.b1_b2(a0, white);
+        match read_mode(reader)? {
+            Mode::Pass => a0 = b2,
+            Mode::Horizontal => {
+                let (first, second) = if white { (white_codes, black_codes) } else { (black_codes, white_codes) };
+                let a1 = a0.max(0) + first.read_run(reader)? as i64;
+                let a2 = a1 + second.read_run(reader)? as i64;
+                if a2 > width as i64 {
+                    return Err(invalid_data("run past the end of the line"));
+                }
+                push_change(&mut line, a1, width);
+                push_change(&mut line, a2, width);
+                a0 = a2;
+            }
+            Mode::Vertical(offset) => {
+                let a1 = b1 + offset;
+                if a1 < a0.max(0) || a1 > width as i64 {
+                    return Err(invalid_data("vertical mode change outside the line"));
+                }
+                push_change(&mut line, a1, width);
+                a0 = a1;
+                white = !white;
+            }
+        }
+    }
+
+    Ok(line)
+}
+
+/// Decode `rows` lines of `width` pixels, each given as the positions where
+/// its colour changes. With `byte_align` each line starts on a byte boundary,
+/// as with TIFF's Modified Huffman compression and EncodedByteAlign.
+pub fn decode(data: &[u8], width: usize, rows: usize, coding: CcittCoding, byte_align: bool)
+    -> io::Result<Vec<Vec<usize>>>
+{
+    let white_codes = RunCodes::new(&WHITE_CODES);
+    let black_codes = RunCodes::new(&BLACK_CODES);
+    let mut reader = BitReader::new(data);
+    let mut lines: Vec<Vec<usize>> = Vec::new();
+
+    for _ in 0..rows {
+        if byte_align {
+            reader.align_to_byte();
+        }
+        let reference: &[usize] = lines.last().map_or(&[], |line| line.as_slice());
+        let line = match coding {
+            CcittCoding::OneDimensional => {
+                reader.skip_end_of_line();
+                decode_one_dimensional(&mut reader, &white_codes, &black_codes, width)?
+            }
+            CcittCoding::Mixed => {
+                reader.skip_end_of_line();
+                if reader.read_bit()? == 1 {
+                    decode_one_dimensional(&mut reader, &white_codes, &black_codes, width)?
+                } else {
+                    decode_two_dimensional(&mut reader, &white_codes, &black_codes, reference, width)?
+                }
+            }
+            CcittCoding::TwoDimensional => {
+                decode_two_dimensional(&mut reader, &white_codes, &black_codes, reference, width)?
+            }
+        };
+        lines.push(line);
+    }
+
+    Ok(lines)
+}
+
+fn run_code(codes: &'static [(u16, &'static str)], run: usize) -> &'static str {
+    codes.iter().chain(EXTENDED_CODES.iter())
+         .find(|&&(length, _)| length as usize == run)
+         .map_or(EOL, |&(_, code)| code)
+}
+
+fn write_run(writer: &mut BitWriter, run: usize, white: bool) {
+    let codes: &'static [(u16, &'static str)] = if white { &WHITE_CODES } else { &BLACK_CODES };
+    let mut run = run;
+    while run > 2560 {
+        writer.write_code(run_code(codes, 2560));
+        run -= 2560;
+    }
+    if run >= 64 {
+        writer.write_code(run_code(codes, run / 64 * 64));
+        run %= 64;
+    }
+    writer.write_code(run_code(codes, run));
+}
+
+/// Code lines of `width` pixels, each given as the positions where its
+/// colour changes, as a Group 4 stream ended by an EOFB.
+pub fn encode_g4(lines: &[Vec<usize>], width: usize) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    let mut reference: &[usize] = &[];
+
+    for line in lines {
+        let mut references = ReferenceLine::new(reference, width);
+        let change = |index: usize| line.get(index).map_or(width, |&change| change.min(width)) as i64;
+        let mut next = 0;
+        let mut a0: i64 = -1;
+        let mut white = true;
+        while a0 < width as i64 {
+            while next < line.len() && line[next] as i64 <= a0 {
+                next += 1;
+            }
+            let (a1, a2) = (change(next), change(next + 1));
+            let (b1, b2) = references.b1_b2(a0, white);
+
+            if b2 < a1 {
+                writer.write_code("0001");
+                a0 = b2;
+            } else if (a1 - b1).abs() <= 3 {
+                writer.write_code(match a1 - b1 {
+                    -3 => "0000010",
+                    -2 => "000010",
+                    -1 => "010",
+                    0  => "1",
+                    1  => "011",
+                    2  => "000011",
+                    _  => "0000011",
+                });
+                a0 = a1;
+                white = !white;
+            } else {
+                writer.write_code("001");
+                write_run(&mut writer, (a1 - a0.max(0)) as usize, white);
+                write_run(&mut writer, (a2 - a1) as usize, !white);
+                a0 = a2;
+            }
+        }
+        reference = line;
+    }
+
+    writer.write_code(EOL);
+    writer.write_code(EOL);
+    writer.finish()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The changes of colour along a row of pixels, true for black.
+    fn changes(row: &[bool]) -> Vec<usize> {
+        (0..row.len()).filter(|&x| row[x] != if x == 0 { false } else { row[x - 1] }).collect()
+    }
+
+    /// A page with text-like marks, a solid bar and a black line wider than
+    /// the longest make-up code.
+    fn sample_lines(width: usize, height: usize) -> Vec<Vec<usize>> {
+        let mut state = 0x2545_f491u32;
+        (0..height).map(|y| {
+            let row: Vec<bool> = (0..width).map(|x| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                match y % 16 {
+                    0       => true,
+                    1 | 2   => x > width / 3 && x < width / 3 + 40,
+                    5..=11  => (x / 7 + y) % 3 == 0 && !(state >> 16).is_multiple_of(5),
+                    _       => false,
+                }
+            }).collect();
+            changes(&row)
+        }).collect()
+    }
+
+    #[test]
+    fn codes_are_prefix_free() {
+        for codes in [&WHITE_CODES[..], &BLACK_CODES[..]].iter() {
+            let all: Vec<&str> = codes.iter().chain(EXTENDED_CODES.iter()).map(|&(_, code)| code)
+                                      .chain(Some(EOL)).collect();
+            for a in all.iter() {
+                for b in all.iter() {
+                    assert!(a == b || !b.starts_with(a), "{} is a prefix of {}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn white_lines_are_coded_as_vertical_modes() {
+        // Each line is V0, then the two end of line codes of the EOFB.
+        assert_eq!(encode_g4(&[vec![], vec![]], 8), vec![0xc0, 0x04, 0x00, 0x40]);
+    }
+
+    #[test]
+    fn group_4_round_trips() {
+        for &(width, height) in [(1, 1), (17, 5), (300, 40), (3000, 20)].iter() {
+            let lines = sample_lines(width, height);
+            let data = encode_g4(&lines, width);
+            assert_eq!(decode(&data, width, height, CcittCoding::TwoDimensional, false).unwrap(), lines);
+        }
+    }
+
+    #[test]
+    fn one_dimensional_lines_decode_after_end_of_line_codes() {
+        let lines = vec![vec![], vec![0, 3], vec![2, 5, 6], vec![0]];
+        let mut writer = BitWriter::new();
+        for line in lines.iter() {
+            // Fill bits, then an end of line code before each line.
+            writer.write_code("000");
+            writer.write_code(EOL);
+            let mut start = 0;
+            let mut white = true;
+            for &change in line.iter().chain(Some(&8)) {
+                write_run(&mut writer, change - start, white);
+                start = change;
+                white = !white;
+            }
+        }
+
+        let data = writer.finish();
+        assert_eq!(decode(&data, 8, lines.len(), CcittCoding::OneDimensional, false).unwrap(), lines);
+    }
+
+    #[test]
+    fn truncated_data_is_an_error() {
+        let lines = sample_lines(100, 10);
+        let data = encode_g4(&lines, 100);
+        let error = decode(&data[..data.len() / 2], 100, 10, CcittCoding::TwoDimensional, false).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,645 @@
+#![allow(dead_code)]
+use std::io;
+use std::io::Write;
+use std::fs::File;
+use std::fmt;
+use std::path::Path;
+use std::collections::HashSet;
+use image_tools::image_ops::{Page, PageOps, ImageFileFormat, ImageDimensions, ImageResolution, ResolutionUnits};
+use image_tools::image_ops::{OperationPlan, CompoundPageOperation};
+use image_tools::tiff::{self, TiffImageData, TiffValue};
+use pdf::objects::{ObjectId, PdfObject, PdfDictionary, PdfStream};
+use pdf::reader::{PdfDocument, PdfPage};
+use pdf::assemble::POINTS_PER_INCH;
+use pdf::filters;
+use pdf::flate;
+use util::checksum;
+
+
+fn unsupported(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Unsupported image XObject: {}", message))
+}
+
+
+/// The colour space of an image XObject, reduced to what the extracted
+/// file needs to know.
+#[derive(Clone, PartialEq, Debug)]
+enum ImageColor {
+    Gray,
+    Rgb,
+    Cmyk,
+    /// A palette of base colour values, with the highest index.
+    Indexed(Box<ImageColor>, usize, Vec<u8>),
+}
+
+impl ImageColor {
+    fn channels(&self) -> usize {
+        match *self {
+            ImageColor::Gray | ImageColor::Indexed(..) => 1,
+            ImageColor::Rgb  => 3,
+            ImageColor::Cmyk => 4,
+        }
+    }
+}
+
+
+/// An image XObject written out of a PDF file.
+#[derive(Clone, Debug)]
+pub struct ExtractedImage {
+    page_number: usize,
+    resource_name: String,
+    filter: String,
+    page: Page,
+}
+
+impl ExtractedImage {
+    /// The number of the PDF page the image was found on, counting from 1.
+    pub fn page_number(&self) -> usize {
+        self.page_number
+    }
+
+    /// The name of the image in the page's resources.
+    pub fn resource_name(&self) -> &str {
+        self.resource_name.as_ref()
+    }
+
+    /// The filter the image was stored with in the PDF file.
+    pub fn filter(&self) -> &str {
+        self.filter.as_ref()
+    }
+
+    pub fn page(&self) -> &Page {
+        &self.page
+    }
+}
+
+impl fmt::Display for ExtractedImage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "page {} /{} ({}) -> {}", self.page_number, self.resource_name, self.filter, self.page.file_path())
+    }
+}
+
+
+/// Extract the images of a PDF file into a directory, one file per image
+/// XObject, returning them as pages in document order. Images compressed
+/// with DCTDecode are written as JPEG files, CCITT fax images as TIFF files
+/// with a Group 3 or Group 4 header, and Flate images as PNG or Deflate
+/// compressed TIFF files, all without recompressing the image data where
+/// the file format allows it. Inline images are not extracted.
+pub fn extract_page_images<P: AsRef<Path>, Q: AsRef<Path>>(pdf_path: P, output_dir: Q) -> io::Result<Vec<Page>> {
+    let document = PdfDocument::open(pdf_path.as_ref())?;
+    let stem = pdf_path.as_ref().file_stem()
+                       .map(|stem| stem.to_string_lossy().into_owned())
+                       .unwrap_or(String::from("page"));
+
+    let images = extract_images(&document, output_dir.as_ref(), &stem)?;
+
+    Ok(images.into_iter().map(|image| image.page).collect())
+}
+
+/// A plan with an empty operation for every page, ready to have operations
+/// added to it.
+pub fn extraction_plan(pages: &[Page]) -> OperationPlan<PageOps> {
+    let mut plan = OperationPlan::new();
+    for page in pages.iter() {
+        plan.insert(page.clone(), CompoundPageOperation::make_no_op(page.file_name().clone(), page.file_path().clone()));
+    }
+
+    plan
+}
+
+pub fn extract_images(document: &PdfDocument, output_dir: &Path, stem: &str) -> io::Result<Vec<ExtractedImage>> {
+    let mut extracted = Vec::new();
+
+    for (i, pdf_page) in document.pages()?.iter().enumerate() {
+        let mut found = Vec::new();
+        let mut visited = HashSet::new();
+        if let Some(resources) = document.resolve_key(pdf_page.dict(), "Resources")? {
+            if let Some(resources) = resources.as_dictionary() {
+                find_images(document, resources, &mut visited, &mut found)?;
+            }
+        }
+
+        for (index, (name, id)) in found.into_iter().enumerate() {
+            let stream = match document.get(id)? {
+                PdfObject::Stream(stream) => stream,
+                _ => continue,
+            };
+            let file_stem = format!("{}-p{:04}-{:02}", stem, i + 1, index);
+            let image = extract_image(document, pdf_page, &stream, output_dir, &file_stem)
+                .map_err(|e| io::Error::new(e.kind(), format!("Page {} image /{}: {}", i + 1, name, e)))?;
+
+            extracted.push(ExtractedImage {
+                page_number: i + 1,
+                resource_name: name,
+                filter: image.1,
+                page: image.0,
+            });
+        }
+    }
+
+    Ok(extracted)
+}
+
+/// Collect the image XObjects named in a resource dictionary, looking
+/// inside form XObjects too.
+fn find_images(document: &PdfDocument, resources: &PdfDictionary,
+               visited: &mut HashSet<ObjectId>, found: &mut Vec<(String, ObjectId)>) -> io::Result<()>
+{
+    let xobjects = match document.resolve_key(resources, "XObject")? {
+        Some(PdfObject::Dictionary(xobjects)) => xobjects,
+        _ => return Ok(()),
+    };
+
+    for (name, value) in xobjects.iter() {
+        let id = match *value {
+            PdfObject::Reference(id) => id,
+            _ => continue,
+        };
+        if !visited.insert(id) {
+            continue;
+        }
+
+        let object = document.get(id)?;
+        let dict = match object.as_dictionary() {
+            Some(dict) => dict,
+            None       => continue,
+        };
+        match dict.get("Subtype").and_then(|s| s.as_name()) {
+            Some("Image") => found.push((name.clone(), id)),
+            Some("Form")  => {
+                if let Some(PdfObject::Dictionary(form_resources)) = document.resolve_key(dict, "Resources")? {
+                    find_images(document, &form_resources, visited, found)?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn resolve_color(document: &PdfDocument, color_space: &PdfObject) -> io::Result<ImageColor> {
+    match document.resolve(color_space)? {
+        PdfObject::Name(ref name) => match name.as_ref() {
+            "DeviceGray" | "G" | "CalGray"  => Ok(ImageColor::Gray),
+            "DeviceRGB" | "RGB" | "CalRGB"  => Ok(ImageColor::Rgb),
+            "DeviceCMYK" | "CMYK"           => Ok(ImageColor::Cmyk),
+            other => Err(unsupported(&format!("colour space {}", other))),
+        },
+        PdfObject::Array(ref items) if !items.is_empty() => {
+            let family = document.resolve(&items[0])?;
+            match family.as_name() {
+                Some("CalGray") => Ok(ImageColor::Gray),
+                Some("CalRGB")  => Ok(ImageColor::Rgb),
+                Some("ICCBased") if items.len() > 1 => {
+                    let profile = document.resolve(&items[1])?;
+                    let components = profile.as_dictionary()
+                                            .and_then(|dict| dict.get("N"))
+                                            .and_then(|n| n.as_integer());
+                    match components {
+                        Some(1) => Ok(ImageColor::Gray),
+                        Some(3) => Ok(ImageColor::Rgb),
+                        Some(4) => Ok(ImageColor::Cmyk),
+                        _       => Err(unsupported("ICC profile with an unknown number of components")),
+                    }
+                }
+                Some("Indexed") | Some("I") if items.len() > 3 => {
+                    let base = resolve_color(document, &items[1])?;
+                    let highest = document.resolve(&items[2])?.as_integer().unwrap_or(0).clamp(0, 255) as usize;
+                    let lookup = match document.resolve(&items[3])? {
+                        PdfObject::String(bytes)  => bytes,
+                        PdfObject::Stream(stream) => document.decode_stream(&stream)?,
+                        _ => return Err(unsupported("indexed colour space without a lookup table")),
+                    };
+                    Ok(ImageColor::Indexed(Box::new(base), highest, lookup))
+                }
+                Some(other) => Err(unsupported(&format!("colour space {}", other))),
+                None        => Err(unsupported("malformed colour space")),
+            }
+        }
+        _ => Err(unsupported("missing colour space")),
+    }
+}
+
+fn integer_entry(document: &PdfDocument, dict: &PdfDictionary, key: &str) -> io::Result<Option<i64>> {
+    Ok(document.resolve_key(dict, key)?.and_then(|value| value.as_integer()))
+}
+
+/// Whether the image's Decode array swaps the meaning of 0 and 1, which is
+/// the only Decode array that is kept when extracting.
+fn is_inverted(document: &PdfDocument, dict: &PdfDictionary) -> io::Result<bool> {
+    match document.resolve_key(dict, "Decode")? {
+        Some(PdfObject::Array(values)) => {
+            let values: Vec<f64> = values.iter().filter_map(|v| v.as_number()).collect();
+            if values.len() >= 2 && values[0] == 1.0 && values[1] == 0.0 {
+                Ok(true)
+            } else {
+                Ok(false)
+            }
+        }
+        _ => Ok(false),
+    }
+}
+
+/// The resolution of an image filling the page's MediaBox. Scans are
+/// sometimes stored sideways and turned with the page's Rotate entry, so
+/// the image is matched against whichever orientation of the page fits
+/// its shape best.
+fn image_resolution(page: &PdfPage, width: usize, height: usize) -> ImageResolution {
+    let (mut page_width, mut page_height) = page.size();
+    let image_aspect = width as f64 / height.max(1) as f64;
+    let page_aspect = page_width / page_height.max(1.0);
+    if (image_aspect - 1.0 / page_aspect).abs() < (image_aspect - page_aspect).abs() {
+        ::std::mem::swap(&mut page_width, &mut page_height);
+    }
+
+    let x_ppi = width as f64 * POINTS_PER_INCH / page_width.max(1.0);
+    let y_ppi = height as f64 * POINTS_PER_INCH / page_height.max(1.0);
+
+    ImageResolution::new(((x_ppi + y_ppi) / 2.0).round() as usize, ResolutionUnits::PixelsPerInch)
+}
+
+/// Write one image XObject to a file, returning the page for it and the
+/// name of the filter it was stored with.
+fn extract_image(document: &PdfDocument, pdf_page: &PdfPage, stream: &PdfStream,
+                 output_dir: &Path, file_stem: &str) -> io::Result<(Page, String)>
+{
+    let dict = document.resolve_filter_entries(stream.dict())?;
+    let width = integer_entry(document, &dict, "Width")?.unwrap_or(0).max(0) as usize;
+    let height = integer_entry(document, &dict, "Height")?.unwrap_or(0).max(0) as usize;
+    if width == 0 || height == 0 {
+        return Err(unsupported("missing image dimensions"));
+    }
+    let res = image_resolution(pdf_page, width, height);
+
+    let mut filters = filters::stream_filters(&dict);
+    let (filter, parms) = filters.pop().unwrap_or((String::from("None"), None));
+
+    // Filters applied on top of the image filter, such as ASCII85, are
+    // undone before the image data is written out.
+    let mut data = stream.data().to_vec();
+    for (name, parms) in filters.iter() {
+        data = filters::decode_filter(name, &data, parms.as_ref())?;
+    }
+
+    let image_mask = document.resolve_key(&dict, "ImageMask")? == Some(PdfObject::Boolean(true));
+    let inverted = is_inverted(document, &dict)?;
+
+    let (format, extension, bytes) = match filter.as_ref() {
+        "DCTDecode" | "DCT" => {
+            let mut data = data;
+            set_jfif_density(&mut data, &res);
+            (ImageFileFormat::JPEG, "jpg", data)
+        }
+        "CCITTFaxDecode" | "CCF" => {
+            (ImageFileFormat::TIFF, "tiff", ccitt_tiff(width, height, parms.as_ref(), inverted, data, &res)?)
+        }
+        _ => {
+            let (bits, color) = if image_mask {
+                (1, ImageColor::Gray)
+            } else {
+                let bits = integer_entry(document, &dict, "BitsPerComponent")?.unwrap_or(8) as u32;
+                let color = match dict.get("ColorSpace") {
+                    Some(color_space) => resolve_color(document, color_space)?,
+                    None => return Err(unsupported("missing colour space")),
+                };
+                (bits, color)
+            };
+            // A stencil mask paints where its samples are 0, so it reads as
+            // a gray image with black where painted.
+            let raster = Raster {
+                width: width,
+                height: height,
+                bits: bits,
+                color: color,
+                inverted: inverted,
+            };
+
+            if filter == "FlateDecode" || filter == "Fl" {
+                flate_image(&raster, parms.as_ref(), data, &res)?
+            } else {
+                let samples = filters::decode_filter(&filter, &data, parms.as_ref())
+                    .or_else(|e| if filter == "None" { Ok(data.clone()) } else { Err(e) })?;
+                (ImageFileFormat::TIFF, "tiff", raster.to_tiff(samples, &res)?)
+            }
+        }
+    };
+
+    let file_name = format!("{}.{}", file_stem, extension);
+    let file_path = output_dir.join(&file_name);
+    File::create(&file_path)?.write_all(&bytes)?;
+
+    let page = Page::new(file_name, format, file_path.to_string_lossy().into_owned(),
+                         ImageDimensions::new(width, height), res);
+
+    Ok((page, filter))
+}
+
+/// Record the resolution in a JPEG file's JFIF header, if it has one.
+fn set_jfif_density(data: &mut [u8], res: &ImageResolution) {
+    if data.len() < 20 || &data[0..4] != b"\xff\xd8\xff\xe0" || &data[6..11] != b"JFIF\0" {
+        return;
+    }
+
+    let density = res.amount().min(0xffff) as u16;
+    data[13] = 1;
+    data[14] = (density >> 8) as u8;
+    data[15] = density as u8;
+    data[16] = (density >> 8) as u8;
+    data[17] = density as u8;
+}
+
+/// Wrap CCITT fax data in a TIFF header. The fax codes describe white and
+/// black runs; whether the white runs show as white depends on BlackIs1 and
+/// the Decode array, and decides the photometric interpretation.
+fn ccitt_tiff(width: usize, height: usize, parms: Option<&PdfDictionary>, inverted: bool,
+              data: Vec<u8>, res: &ImageResolution) -> io::Result<Vec<u8>>
+{
+    let parm = |key: &str| parms.and_then(|parms| parms.get(key)).cloned();
+    let k = parm("K").and_then(|k| k.as_integer()).unwrap_or(0);
+    let columns = parm("Columns").and_then(|c| c.as_integer()).unwrap_or(1728) as usize;
+    let rows = parm("Rows").and_then(|r| r.as_integer()).filter(|r| *r > 0).map(|r| r as usize).unwrap_or(height);
+    let byte_align = parm("EncodedByteAlign") == Some(PdfObject::Boolean(true));
+    let black_is_1 = parm("BlackIs1") == Some(PdfObject::Boolean(true));
+
+    if columns != width {
+        return Err(unsupported("CCITT Columns differ from the image width"));
+    }
+
+    let mut image = TiffImageData::new(width as u32, rows as u32, data);
+    if k < 0 {
+        if byte_align {
+            return Err(unsupported("byte aligned Group 4 data"));
+        }
+        image.set(tiff::TAG_COMPRESSION, TiffValue::Short(vec![tiff::COMPRESSION_CCITT_G4 as u16]));
+        image.set(tiff::TAG_T6_OPTIONS, TiffValue::Long(vec![0]));
+    } else {
+        let two_dimensional = if k > 0 { 1 } else { 0 };
+        let align = if byte_align { 4 } else { 0 };
+        image.set(tiff::TAG_COMPRESSION, TiffValue::Short(vec![tiff::COMPRESSION_CCITT_G3 as u16]));
+        image.set(tiff::TAG_T4_OPTIONS, TiffValue::Long(vec![two_dimensional | align]));
+    }
+
+    let photometric = if black_is_1 != inverted {
+        tiff::PHOTOMETRIC_BLACK_IS_ZERO
+    } else {
+        tiff::PHOTOMETRIC_WHITE_IS_ZERO
+    };
+    image.set(tiff::TAG_PHOTOMETRIC, TiffValue::Short(vec![photometric as u16]));
+    image.set(tiff::TAG_BITS_PER_SAMPLE, TiffValue::Short(vec![1]));
+    image.set(tiff::TAG_SAMPLES_PER_PIXEL, TiffValue::Short(vec![1]));
+    image.set(tiff::TAG_FILL_ORDER, TiffValue::Short(vec![1]));
+    image.set_resolution(res);
+
+    Ok(tiff::write_tiff(&[image]))
+}
+
+
+/// The layout of an image's samples.
+struct Raster {
+    width: usize,
+    height: usize,
+    bits: u32,
+    color: ImageColor,
+    inverted: bool,
+}
+
+impl Raster {
+    fn row_bytes(&self) -> usize {
+        (self.width * self.color.channels() * self.bits as usize).div_ceil(8)
+    }
+
+    /// Whether the samples can be stored in a PNG file as they are.
+    fn fits_png(&self) -> bool {
+        if self.inverted {
+            return false;
+        }
+        match self.color {
+            ImageColor::Gray => [1, 2, 4, 8, 16].contains(&self.bits),
+            ImageColor::Rgb  => [8, 16].contains(&self.bits),
+            ImageColor::Indexed(ref base, _, _) => {
+                [1, 2, 4, 8].contains(&self.bits) && (**base == ImageColor::Rgb || **base == ImageColor::Gray)
+            }
+            ImageColor::Cmyk => false,
+        }
+    }
+
+    /// The TIFF fields describing the samples, other than compression.
+    fn tiff_fields(&self, image: &mut TiffImageData) -> io::Result<()> {
+        let channels = self.color.channels();
+        let photometric = match self.color {
+            ImageColor::Gray if self.inverted => tiff::PHOTOMETRIC_WHITE_IS_ZERO,
+            ImageColor::Gray => tiff::PHOTOMETRIC_BLACK_IS_ZERO,
+            ImageColor::Rgb  => tiff::PHOTOMETRIC_RGB,
+            ImageColor::Cmyk => tiff::PHOTOMETRIC_SEPARATED,
+            ImageColor::Indexed(ref base, highest, ref lookup) => {
+                if self.bits > 8 {
+                    return Err(unsupported("indexed image with more than 8 bits per sample"));
+                }
+                let palette = rgb_palette(base, highest, lookup)?;
+                let entries = 1usize << self.bits;
+                let mut color_map = vec![0u16; 3 * entries];
+                for (index, rgb) in palette.chunks(3).enumerate().take(entries) {
+                    for channel in 0..3 {
+                        color_map[channel * entries + index] = rgb[channel] as u16 * 257;
+                    }
+                }
+                image.set(tiff::TAG_COLOR_MAP, TiffValue::Short(color_map));
+                tiff::PHOTOMETRIC_PALETTE
+            }
+        };
+        if self.inverted && self.color != ImageColor::Gray {
+            return Err(unsupported("Decode array on a colour image"));
+        }
+
+        image.set(tiff::TAG_PHOTOMETRIC, TiffValue::Short(vec![photometric as u16]));
+        image.set(tiff::TAG_BITS_PER_SAMPLE, TiffValue::Short(vec![self.bits as u16; channels]));
+        image.set(tiff::TAG_SAMPLES_PER_PIXEL, TiffValue::Short(vec![channels as u16]));
+        image.set(tiff::TAG_PLANAR_CONFIGURATION, TiffValue::Short(vec![1]));
+
+        Ok(())
+    }
+
+    /// Write decoded samples to a Deflate compressed TIFF file.
+    fn to_tiff(&self, samples: Vec<u8>, res: &ImageResolution) -> io::Result<Vec<u8>> {
+        let mut samples = samples;
+        samples.resize(self.row_bytes() * self.height, 0);
+        // PDF samples are big endian; the TIFF files written here are not.
+        if self.bits == 16 {
+            for pair in samples.chunks_mut(2) {
+                pair.swap(0, 1);
+            }
+        }
+
+        let mut image = TiffImageData::new(self.width as u32, self.height as u32, flate::compress(&samples));
+        image.set(tiff::TAG_COMPRESSION, TiffValue::Short(vec![tiff::COMPRESSION_DEFLATE as u16]));
+        self.tiff_fields(&mut image)?;
+        image.set_resolution(res);
+
+        Ok(tiff::write_tiff(&[image]))
+    }
+}
+
+/// The palette of an indexed image as RGB triples.
+fn rgb_palette(base: &ImageColor, highest: usize, lookup: &[u8]) -> io::Result<Vec<u8>> {
+    let mut palette = Vec::new();
+    for index in 0..highest + 1 {
+        match *base {
+            ImageColor::Rgb => {
+                for channel in 0..3 {
+                    palette.push(lookup.get(index * 3 + channel).cloned().unwrap_or(0));
+                }
+            }
+            ImageColor::Gray => {
+                let gray = lookup.get(index).cloned().unwrap_or(0);
+                palette.extend_from_slice(&[gray, gray, gray]);
+            }
+            _ => return Err(unsupported("indexed colour space on other than RGB or gray")),
+        }
+    }
+
+    Ok(palette)
+}
+
+/// Flate compressed images keep their compressed data: with a PNG predictor
+/// the data is exactly a PNG file's image data, and without one it is a
+/// Deflate compressed TIFF strip. Anything else is decoded and recompressed.
+fn flate_image(raster: &Raster, parms: Option<&PdfDictionary>, data: Vec<u8>,
+               res: &ImageResolution) -> io::Result<(ImageFileFormat, &'static str, Vec<u8>)>
+{
+    let parm = |key: &str, default: i64| {
+        parms.and_then(|parms| parms.get(key)).and_then(|value| value.as_integer()).unwrap_or(default)
+    };
+    let predictor = parm("Predictor", 1);
+    let parms_match = parm("Colors", 1) as usize == raster.color.channels()
+        && parm("BitsPerComponent", 8) as u32 == raster.bits
+        && parm("Columns", 1) as usize == raster.width;
+
+    if predictor >= 10 && parms_match && raster.fits_png() {
+        return Ok((ImageFileFormat::PNG, "png", png_file(raster, data, res)?));
+    }
+
+    if (predictor == 1 || (predictor == 2 && parms_match && raster.bits == 8)) && raster.bits != 16 {
+        let mut image = TiffImageData::new(raster.width as u32, raster.height as u32, data);
+        image.set(tiff::TAG_COMPRESSION, TiffValue::Short(vec![tiff::COMPRESSION_DEFLATE as u16]));
+        if predictor == 2 {
+            image.set(tiff::TAG_PREDICTOR, TiffValue::Short(vec![2]));
+        }
+        raster.tiff_fields(&mut image)?;
+        image.set_resolution(res);
+        return Ok((ImageFileFormat::TIFF, "tiff", tiff::write_tiff(&[image])));
+    }
+
+    let samples = filters::decode_filter("FlateDecode", &data, parms)?;
+    Ok((ImageFileFormat::TIFF, "tiff", raster.to_tiff(samples, res)?))
+}
+
+fn png_chunk(output: &mut Vec<u8>, kind: &[u8], body: &[u8]) {
+    let length = body.len() as u32;
+    output.extend_from_slice(&[(length >> 24) as u8, (length >> 16) as u8, (length >> 8) as u8, length as u8]);
+
+    let mut checked = kind.to_vec();
+    checked.extend_from_slice(body);
+    let crc = checksum::crc32(&checked);
+    output.extend_from_slice(&checked);
+    output.extend_from_slice(&[(crc >> 24) as u8, (crc >> 16) as u8, (crc >> 8) as u8, crc as u8]);
+}
+
+/// A PNG file around zlib data that already has PNG row filters applied.
+fn png_file(raster: &Raster, zlib_data: Vec<u8>, res: &ImageResolution) -> io::Result<Vec<u8>> {
+    let color_type = match raster.color {
+        ImageColor::Gray        => 0,
+        ImageColor::Rgb         => 2,
+        ImageColor::Indexed(..) => 3,
+        ImageColor::Cmyk        => return Err(unsupported("CMYK image in a PNG file")),
+    };
+
+    let mut output = b"\x89PNG\r\n\x1a\n".to_vec();
+
+    let (width, height) = (raster.width as u32, raster.height as u32);
+    let header = [
+        (width >> 24) as u8, (width >> 16) as u8, (width >> 8) as u8, width as u8,
+        (height >> 24) as u8, (height >> 16) as u8, (height >> 8) as u8, height as u8,
+        raster.bits as u8, color_type, 0, 0, 0,
+    ];
+    png_chunk(&mut output, b"IHDR", &header);
+
+    if let ImageColor::Indexed(ref base, highest, ref lookup) = raster.color {
+        png_chunk(&mut output, b"PLTE", &rgb_palette(base, highest, lookup)?);
+    }
+
+    let per_meter = match *res.units() {
+        ResolutionUnits::PixelsPerInch       => (res.amount() as f64 / 0.0254).round() as u32,
+        ResolutionUnits::PixelsPerCentimeter => res.amount() as u32 * 100,
+    };
+    let mut phys = Vec::new();
+    for _ in 0..2 {
+        phys.extend_from_slice(&[(per_meter >> 24) as u8, (per_meter >> 16) as u8, (per_meter >> 8) as u8, per_meter as u8]);
+    }
+    phys.push(1);
+    png_chunk(&mut output, b"pHYs", &phys);
+
+    png_chunk(&mut output, b"IDAT", &zlib_data);
+    png_chunk(&mut output, b"IEND", &[]);
+
+    Ok(output)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use image_tools::tiff::TiffFile;
+    use pdf::assemble::PdfAssembly;
+    use pdf::ccitt;
+    use util::scratch::scratch_dir;
+
+    #[test]
+    fn fax_images_are_extracted_without_recompressing_them() {
+        let dir = scratch_dir("extract-fax");
+        let (width, height) = (64, 16);
+        let lines: Vec<Vec<usize>> = (0..height).map(|y| if y % 2 == 0 { vec![4, 60] } else { vec![] }).collect();
+        let g4 = ccitt::encode_g4(&lines, width);
+        let res = ImageResolution::new(200, ResolutionUnits::PixelsPerInch);
+        let mut image = TiffImageData::new(width as u32, height as u32, g4.clone());
+        image.set(tiff::TAG_BITS_PER_SAMPLE, TiffValue::Short(vec![1]));
+        image.set(tiff::TAG_COMPRESSION, TiffValue::Short(vec![tiff::COMPRESSION_CCITT_G4 as u16]));
+        image.set(tiff::TAG_PHOTOMETRIC, TiffValue::Short(vec![tiff::PHOTOMETRIC_WHITE_IS_ZERO as u16]));
+        image.set_resolution(&res);
+        let tiff_path = dir.join("fax.tif");
+        fs::write(&tiff_path, tiff::write_tiff(&[image])).unwrap();
+
+        let mut assembly = PdfAssembly::new();
+        assembly.add_page(Page::new(String::from("fax.tif"), ImageFileFormat::TIFF,
+                                    tiff_path.to_string_lossy().into_owned(), ImageDimensions::new(width, height),
+                                    res.clone()));
+        let pdf_path = dir.join("fax.pdf");
+        assembly.write_to_file(&pdf_path).unwrap();
+
+        let output_dir = dir.join("extracted");
+        fs::create_dir_all(&output_dir).unwrap();
+        let images = extract_images(&PdfDocument::open(&pdf_path).unwrap(), &output_dir, "fax").unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!((images[0].page_number(), images[0].filter()), (1, "CCITTFaxDecode"));
+        assert_eq!(images[0].page().resolution(), &res);
+
+        let extracted = TiffFile::open(images[0].page().file_path()).unwrap();
+        let directory = &extracted.directories()[0];
+        assert_eq!(directory.compression(), tiff::COMPRESSION_CCITT_G4);
+        assert_eq!(directory.dimensions().unwrap(), ImageDimensions::new(width, height));
+        assert_eq!(directory.strips(extracted.data()).unwrap().concat(), g4);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn extraction_plans_start_with_nothing_to_do() {
+        let page = Page::new(String::from("page.tif"), ImageFileFormat::TIFF, String::from("page.tif"),
+                             ImageDimensions::new(10, 10), ImageResolution::new(72, ResolutionUnits::PixelsPerInch));
+        let plan = extraction_plan(::std::slice::from_ref(&page));
+
+        assert!(plan.get(&page).unwrap().is_no_op());
+    }
+}
@@ -0,0 +1,200 @@
+#![allow(dead_code)]
+use std::io;
+use pdf::objects::{PdfObject, PdfDictionary};
+use pdf::flate;
+use pdf::images;
+use pdf::parser::is_whitespace;
+use image_tools::tiff;
+
+
+pub fn unsupported_filter(name: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Unsupported PDF filter: {}", name))
+}
+
+/// The filters applied to a stream, outermost first, with their decode
+/// parameters. The parameters must already be resolved to direct objects.
+pub fn stream_filters(dict: &PdfDictionary) -> Vec<(String, Option<PdfDictionary>)> {
+    let names = match dict.get("Filter") {
+        Some(PdfObject::Name(name))   => vec![name.clone()],
+        Some(PdfObject::Array(names)) => {
+            names.iter().filter_map(|name| name.as_name()).map(String::from).collect()
+        }
+        _ => Vec::new(),
+    };
+    let parms = match dict.get("DecodeParms") {
+        Some(PdfObject::Array(parms)) => parms.iter().map(|p| p.as_dictionary().cloned()).collect(),
+        Some(parms)                   => vec![parms.as_dictionary().cloned()],
+        None                          => Vec::new(),
+    };
+
+    names.into_iter().enumerate().map(|(i, name)| {
+        (name, parms.get(i).cloned().unwrap_or(None))
+    }).collect()
+}
+
+/// Undo every filter applied to a stream's data. Image filters such as
+/// DCTDecode cannot be undone here; streams using them are passed through
+/// whole by the image extraction code instead.
+pub fn decode_stream_data(dict: &PdfDictionary, data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut data = data.to_vec();
+    for (name, parms) in stream_filters(dict) {
+        data = decode_filter(&name, &data, parms.as_ref())?;
+    }
+
+    Ok(data)
+}
+
+pub fn decode_filter(name: &str, data: &[u8], parms: Option<&PdfDictionary>) -> io::Result<Vec<u8>> {
+    match name {
+        "FlateDecode" | "Fl"     => apply_predictor(&flate::decompress(data)?, parms),
+        "LZWDecode" | "LZW"      => apply_predictor(&lzw_decode(data, parms)?, parms),
+        "ASCIIHexDecode" | "AHx" => ascii_hex_decode(data),
+        "ASCII85Decode" | "A85"  => ascii85_decode(data),
+        "RunLengthDecode" | "RL" => Ok(run_length_decode(data)),
+        _                        => Err(unsupported_filter(name)),
+    }
+}
+
+fn integer_parm(parms: Option<&PdfDictionary>, key: &str, default: i64) -> i64 {
+    parms.and_then(|parms| parms.get(key)).and_then(|value| value.as_integer()).unwrap_or(default)
+}
+
+fn lzw_decode(data: &[u8], parms: Option<&PdfDictionary>) -> io::Result<Vec<u8>> {
+    // The TIFF decoder switches code widths one code early, which is what
+    // PDF's default EarlyChange of 1 means.
+    if integer_parm(parms, "EarlyChange", 1) != 1 {
+        return Err(unsupported_filter("LZWDecode with EarlyChange 0"));
+    }
+
+    tiff::lzw_decode(data)
+}
+
+/// Undo the TIFF or PNG predictor named in the decode parameters of a
+/// Flate or LZW stream.
+pub fn apply_predictor(data: &[u8], parms: Option<&PdfDictionary>) -> io::Result<Vec<u8>> {
+    let predictor = integer_parm(parms, "Predictor", 1);
+    if predictor <= 1 {
+        return Ok(data.to_vec());
+    }
+
+    let colors = integer_parm(parms, "Colors", 1).max(1) as usize;
+    let bits = integer_parm(parms, "BitsPerComponent", 8).max(1) as usize;
+    let columns = integer_parm(parms, "Columns", 1).max(1) as usize;
+    let pixel_bytes = (colors * bits).div_ceil(8).max(1);
+    let row_bytes = (colors * bits * columns).div_ceil(8);
+
+    if predictor == 2 {
+        if bits != 8 {
+            return Err(unsupported_filter("TIFF predictor with other than 8 bits per component"));
+        }
+        let mut output = data.to_vec();
+        for row in output.chunks_mut(row_bytes) {
+            for i in pixel_bytes..row.len() {
+                row[i] = row[i].wrapping_add(row[i - pixel_bytes]);
+            }
+        }
+        return Ok(output);
+    }
+
+    let rows = data.len() / (row_bytes + 1);
+    images::png_unfilter(data, row_bytes, pixel_bytes, rows)
+}
+
+fn ascii_hex_decode(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut digits = Vec::new();
+    for byte in data {
+        match *byte {
+            b'>' => break,
+            b'0'..=b'9' => digits.push(byte - b'0'),
+            b'a'..=b'f' => digits.push(byte - b'a' + 10),
+            b'A'..=b'F' => digits.push(byte - b'A' + 10),
+            byte if is_whitespace(byte) => {}
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Bad digit in ASCIIHexDecode stream")),
+        }
+    }
+    if digits.len() % 2 == 1 {
+        digits.push(0);
+    }
+
+    Ok(digits.chunks(2).map(|pair| pair[0] << 4 | pair[1]).collect())
+}
+
+fn ascii85_decode(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    let mut group = Vec::with_capacity(5);
+
+    let data = if data.starts_with(b"<~") { &data[2..] } else { data };
+    for byte in data {
+        match *byte {
+            b'~' => break,
+            b'z' if group.is_empty() => output.extend_from_slice(&[0, 0, 0, 0]),
+            b'!'..=b'u' => {
+                group.push(byte - b'!');
+                if group.len() == 5 {
+                    let value = group.iter().fold(0u32, |acc, digit| acc.wrapping_mul(85).wrapping_add(*digit as u32));
+                    output.extend_from_slice(&[(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]);
+                    group.clear();
+                }
+            }
+            byte if is_whitespace(byte) => {}
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Bad character in ASCII85Decode stream")),
+        }
+    }
+
+    // A final partial group is padded with the highest digit.
+    if group.len() > 1 {
+        let count = group.len();
+        group.resize(5, 84);
+        let value = group.iter().fold(0u32, |acc, digit| acc.wrapping_mul(85).wrapping_add(*digit as u32));
+        let bytes = [(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8];
+        output.extend_from_slice(&bytes[0..count - 1]);
+    }
+
+    Ok(output)
+}
+
+fn run_length_decode(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut i = 0;
+
+    while i < data.len() {
+        let length = data[i] as usize;
+        i += 1;
+        if length < 128 {
+            let end = (i + length + 1).min(data.len());
+            output.extend_from_slice(&data[i..end]);
+            i = end;
+        } else if length > 128 {
+            if i < data.len() {
+                for _ in 0..(257 - length) {
+                    output.push(data[i]);
+                }
+            }
+            i += 1;
+        } else {
+            // 128 marks the end of the data.
+            break;
+        }
+    }
+
+    output
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_filters_decode_their_examples() {
+        assert_eq!(ascii_hex_decode(b"48 65 6c6C 6f7>").unwrap(), b"Hellop");
+        assert_eq!(ascii85_decode(b"<~87cURD]i,\"Ebo80~>").unwrap(), b"Hello World!");
+        assert_eq!(ascii85_decode(b"z!!~>").unwrap(), vec![0, 0, 0, 0, 0]);
+        assert!(ascii_hex_decode(b"4g>").is_err());
+    }
+
+    #[test]
+    fn run_length_data_copies_literals_and_repeats_runs() {
+        assert_eq!(run_length_decode(&[2, b'a', b'b', b'c', 254, b'x', 128, 0, b'z']), b"abcxxx");
+    }
+}
@@ -0,0 +1,493 @@
+//! Zlib (RFC 1950) wrapped deflate (RFC 1951) compression and decompression,
+//! as used by the PDF FlateDecode filter and by PNG and TIFF image data.
+//! Compression uses a single block of fixed Huffman codes with a hash chain
+//! LZ77 matcher, which does well on the long runs found in scanned pages.
+#![allow(dead_code)]
+use std::io;
+
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: usize = 15;
+const MAX_CHAIN: usize = 64;
+
+const LENGTH_BASE: [usize; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+
+const LENGTH_EXTRA: [u32; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+const DIST_BASE: [usize; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+
+const DIST_EXTRA: [u32; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+/// The order in which code length code lengths are stored in a dynamic block header.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+
+    (b << 16) | a
+}
+
+
+/// Writes bits least significant bit first, as deflate requires.
+struct BitWriter {
+    output: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new(output: Vec<u8>) -> BitWriter {
+        BitWriter {
+            output: output,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn write_bits(&mut self, value: u32, bits: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += bits;
+        while self.count >= 8 {
+            self.output.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman codes are defined most significant bit first.
+    fn write_code(&mut self, code: u32, bits: u32) {
+        let mut reversed = 0;
+        for i in 0..bits {
+            reversed |= ((code >> i) & 1) << (bits - 1 - i);
+        }
+        self.write_bits(reversed, bits);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.output.push(self.buffer as u8);
+        }
+        self.output
+    }
+}
+
+fn write_literal(writer: &mut BitWriter, symbol: usize) {
+    let symbol = symbol as u32;
+    match symbol {
+        0..=143   => writer.write_code(0x30 + symbol, 8),
+        144..=255 => writer.write_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.write_code(symbol - 256, 7),
+        _         => writer.write_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let mut code = LENGTH_BASE.len() - 1;
+    while LENGTH_BASE[code] > length {
+        code -= 1;
+    }
+    write_literal(writer, 257 + code);
+    writer.write_bits((length - LENGTH_BASE[code]) as u32, LENGTH_EXTRA[code]);
+
+    let mut code = DIST_BASE.len() - 1;
+    while DIST_BASE[code] > distance {
+        code -= 1;
+    }
+    writer.write_code(code as u32, 5);
+    writer.write_bits((distance - DIST_BASE[code]) as u32, DIST_EXTRA[code]);
+}
+
+fn hash(data: &[u8], pos: usize) -> usize {
+    let value = ((data[pos] as usize) << 16) | ((data[pos + 1] as usize) << 8) | (data[pos + 2] as usize);
+    (value.wrapping_mul(2654435761) >> 7) & ((1 << HASH_BITS) - 1)
+}
+
+/// Compress data into a zlib stream.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    // CMF: deflate with a 32K window; FLG: default compression, no dictionary.
+    let mut writer = BitWriter::new(vec![0x78, 0x9c]);
+
+    // A single final block using the fixed Huffman codes.
+    writer.write_bits(1, 1);
+    writer.write_bits(1, 2);
+
+    let no_position = usize::MAX;
+    let mut head = vec![no_position; 1 << HASH_BITS];
+    let mut prev = vec![no_position; WINDOW_SIZE];
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut best_length = 0;
+        let mut best_distance = 0;
+
+        if pos + MIN_MATCH <= data.len() {
+            let h = hash(data, pos);
+            let max_length = (data.len() - pos).min(MAX_MATCH);
+            let mut candidate = head[h];
+            let mut chain = 0;
+
+            while candidate != no_position && pos - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+                let mut length = 0;
+                while length < max_length && data[candidate + length] == data[pos + length] {
+                    length += 1;
+                }
+
+                if length > best_length {
+                    best_length = length;
+                    best_distance = pos - candidate;
+                    if length == max_length {
+                        break;
+                    }
+                }
+
+                let next = prev[candidate % WINDOW_SIZE];
+                if next == no_position || next >= candidate {
+                    break;
+                }
+                candidate = next;
+                chain += 1;
+            }
+        }
+
+        let advance = if best_length >= MIN_MATCH {
+            write_match(&mut writer, best_length, best_distance);
+            best_length
+        } else {
+            write_literal(&mut writer, data[pos] as usize);
+            1
+        };
+
+        for p in pos..(pos + advance) {
+            if p + MIN_MATCH <= data.len() {
+                let h = hash(data, p);
+                prev[p % WINDOW_SIZE] = head[h];
+                head[h] = p;
+            }
+        }
+
+        pos += advance;
+    }
+
+    write_literal(&mut writer, 256);
+
+    let mut output = writer.finish();
+    let checksum = adler32(data);
+    output.push((checksum >> 24) as u8);
+    output.push((checksum >> 16) as u8);
+    output.push((checksum >> 8) as u8);
+    output.push(checksum as u8);
+
+    output
+}
+
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid deflate data: {}", message))
+}
+
+/// Reads bits least significant bit first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data: data,
+            pos: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn read_bits(&mut self, bits: u32) -> io::Result<u32> {
+        while self.count < bits {
+            if self.pos >= self.data.len() {
+                return Err(invalid_data("unexpected end of stream"));
+            }
+            self.buffer |= (self.data[self.pos] as u64) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+
+        let value = (self.buffer & ((1u64 << bits) - 1)) as u32;
+        self.buffer >>= bits;
+        self.count -= bits;
+
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        let discard = self.count % 8;
+        self.buffer >>= discard;
+        self.count -= discard;
+    }
+}
+
+/// A canonical Huffman decoding table, built from code lengths.
+struct Huffman {
+    counts: Vec<u16>,
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Huffman> {
+        let mut counts = vec![0u16; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for i in 1..15 {
+            offsets[i + 1] = offsets[i] + counts[i];
+        }
+
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+
+        Ok(Huffman {
+            counts: counts,
+            symbols: symbols,
+        })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> io::Result<usize> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+
+        for length in 1..16 {
+            code |= reader.read_bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + (code - first)) as usize] as usize);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+
+        Err(invalid_data("bad Huffman code"))
+    }
+}
+
+fn fixed_tables() -> io::Result<(Huffman, Huffman)> {
+    let mut lengths = [0u8; 288];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0..=143   => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _         => 8,
+        };
+    }
+
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5u8; 30])?))
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+    let literal_count = reader.read_bits(5)? as usize + 257;
+    let distance_count = reader.read_bits(5)? as usize + 1;
+    let code_length_count = reader.read_bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for i in 0..code_length_count {
+        code_lengths[CODE_LENGTH_ORDER[i]] = reader.read_bits(3)? as u8;
+    }
+    let code_length_table = Huffman::new(&code_lengths)?;
+
+    let mut lengths = Vec::new();
+    while lengths.len() < literal_count + distance_count {
+        let symbol = code_length_table.decode(reader)?;
+        match symbol {
+            0..=15 => lengths.push(symbol as u8),
+            16 => {
+                let previous = match lengths.last() {
+                    Some(length) => *length,
+                    None         => return Err(invalid_data("repeat with no previous length")),
+                };
+                for _ in 0..(3 + reader.read_bits(2)?) {
+                    lengths.push(previous);
+                }
+            }
+            17 => {
+                let zeros = 3 + reader.read_bits(3)? as usize;
+                lengths.resize(lengths.len() + zeros, 0);
+            }
+            _ => {
+                let zeros = 11 + reader.read_bits(7)? as usize;
+                lengths.resize(lengths.len() + zeros, 0);
+            }
+        }
+    }
+
+    if lengths.len() > literal_count + distance_count {
+        return Err(invalid_data("too many code lengths"));
+    }
+
+    let literals = Huffman::new(&lengths[..literal_count])?;
+    let distances = Huffman::new(&lengths[literal_count..])?;
+
+    Ok((literals, distances))
+}
+
+fn inflate_block(reader: &mut BitReader, output: &mut Vec<u8>, literals: &Huffman, distances: &Huffman) -> io::Result<()> {
+    loop {
+        let symbol = literals.decode(reader)?;
+        if symbol < 256 {
+            output.push(symbol as u8);
+        } else if symbol == 256 {
+            return Ok(());
+        } else {
+            let code = symbol - 257;
+            if code >= LENGTH_BASE.len() {
+                return Err(invalid_data("bad length code"));
+            }
+            let length = LENGTH_BASE[code] + reader.read_bits(LENGTH_EXTRA[code])? as usize;
+
+            let code = distances.decode(reader)?;
+            if code >= DIST_BASE.len() {
+                return Err(invalid_data("bad distance code"));
+            }
+            let distance = DIST_BASE[code] + reader.read_bits(DIST_EXTRA[code])? as usize;
+            if distance > output.len() {
+                return Err(invalid_data("distance too far back"));
+            }
+
+            let start = output.len() - distance;
+            for i in 0..length {
+                let byte = output[start + i];
+                output.push(byte);
+            }
+        }
+    }
+}
+
+/// Decompress raw deflate data, with no zlib wrapper.
+pub fn inflate(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut reader = BitReader::new(data);
+    let mut output = Vec::new();
+
+    loop {
+        let last = reader.read_bits(1)? == 1;
+        match reader.read_bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let length = reader.read_bits(16)? as usize;
+                let complement = reader.read_bits(16)? as usize;
+                if length != !complement & 0xffff {
+                    return Err(invalid_data("stored block length mismatch"));
+                }
+                for _ in 0..length {
+                    output.push(reader.read_bits(8)? as u8);
+                }
+            }
+            1 => {
+                let (literals, distances) = fixed_tables()?;
+                inflate_block(&mut reader, &mut output, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_tables(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &literals, &distances)?;
+            }
+            _ => return Err(invalid_data("reserved block type")),
+        }
+
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+/// Decompress a zlib stream. The checksum is not verified, since PDF files
+/// in the wild frequently have truncated or incorrect trailers.
+pub fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    if data.len() < 2 {
+        return Err(invalid_data("missing zlib header"));
+    }
+
+    if data[0] & 0x0f != 8 || !((data[0] as u16) << 8 | data[1] as u16).is_multiple_of(31) {
+        return Err(invalid_data("bad zlib header"));
+    }
+
+    if data[1] & 0x20 != 0 {
+        return Err(invalid_data("preset dictionaries are not supported"));
+    }
+
+    inflate(&data[2..])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8]) {
+        assert_eq!(decompress(&compress(data)).unwrap(), data);
+    }
+
+    #[test]
+    fn empty_input_round_trips() {
+        round_trip(&[]);
+    }
+
+    #[test]
+    fn single_byte_round_trips() {
+        round_trip(&[0x2a]);
+    }
+
+    #[test]
+    fn inputs_over_64_kib_round_trip() {
+        let repetitive = vec![0xffu8; 70_000];
+        round_trip(&repetitive);
+
+        // A simple generator gives data with few repeats, so little of it is matched.
+        let mut state = 0x1234_5678u32;
+        let noisy: Vec<u8> = (0..100_000).map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) as u8
+        }).collect();
+        round_trip(&noisy);
+    }
+
+    #[test]
+    fn bad_zlib_headers_are_rejected() {
+        assert!(decompress(&[]).is_err());
+        assert!(decompress(&[0x78, 0x00]).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+#![allow(dead_code)]
+use std::fmt;
+
+
+/// The colour space of an ICC profile.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum IccColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+}
+
+impl IccColorSpace {
+    /// The number of colour components, the N of an ICCBased colour space.
+    pub fn components(&self) -> usize {
+        match *self {
+            IccColorSpace::Gray => 1,
+            IccColorSpace::Rgb  => 3,
+            IccColorSpace::Cmyk => 4,
+        }
+    }
+}
+
+impl fmt::Display for IccColorSpace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IccColorSpace::Gray => write!(f, "gray"),
+            IccColorSpace::Rgb  => write!(f, "RGB"),
+            IccColorSpace::Cmyk => write!(f, "CMYK"),
+        }
+    }
+}
+
+/// The colour space recorded in an ICC profile's header, if the data looks
+/// like a profile at all.
+pub fn profile_color_space(profile: &[u8]) -> Option<IccColorSpace> {
+    if profile.len() < 128 || &profile[36..40] != b"acsp" {
+        return None;
+    }
+
+    match &profile[16..20] {
+        b"GRAY" => Some(IccColorSpace::Gray),
+        b"RGB " => Some(IccColorSpace::Rgb),
+        b"CMYK" => Some(IccColorSpace::Cmyk),
+        _       => None,
+    }
+}
+
+fn push_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&[(value >> 8) as u8, value as u8]);
+}
+
+fn push_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&[(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]);
+}
+
+fn push_s15_fixed16(out: &mut Vec<u8>, value: f64) {
+    push_u32(out, (value * 65536.0).round() as i32 as u32);
+}
+
+fn xyz_tag(x: f64, y: f64, z: f64) -> Vec<u8> {
+    let mut tag = b"XYZ \0\0\0\0".to_vec();
+    push_s15_fixed16(&mut tag, x);
+    push_s15_fixed16(&mut tag, y);
+    push_s15_fixed16(&mut tag, z);
+
+    tag
+}
+
+fn text_tag(text: &str) -> Vec<u8> {
+    let mut tag = b"text\0\0\0\0".to_vec();
+    tag.extend_from_slice(text.as_bytes());
+    tag.push(0);
+
+    tag
+}
+
+/// A version 2 textDescriptionType, with empty Unicode and ScriptCode parts.
+fn description_tag(text: &str) -> Vec<u8> {
+    let mut tag = b"desc\0\0\0\0".to_vec();
+    push_u32(&mut tag, text.len() as u32 + 1);
+    tag.extend_from_slice(text.as_bytes());
+    tag.push(0);
+    push_u32(&mut tag, 0);
+    push_u32(&mut tag, 0);
+    push_u16(&mut tag, 0);
+    tag.push(0);
+    tag.extend_from_slice(&[0; 67]);
+
+    tag
+}
+
+/// The sRGB transfer curve of IEC 61966-2-1, sampled for a curveType tag.
+fn srgb_curve_tag() -> Vec<u8> {
+    const ENTRIES: usize = 1024;
+    let mut tag = b"curv\0\0\0\0".to_vec();
+    push_u32(&mut tag, ENTRIES as u32);
+    for i in 0..ENTRIES {
+        let encoded = i as f64 / (ENTRIES - 1) as f64;
+        let linear = if encoded <= 0.04045 {
+            encoded / 12.92
+        } else {
+            ((encoded + 0.055) / 1.055).powf(2.4)
+        };
+        push_u16(&mut tag, (linear * 65535.0).round() as u16);
+    }
+
+    tag
+}
+
+/// Assemble a display profile from its tags. Tags with the same data share it.
+fn build_profile(color_space: &[u8; 4], tags: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+    let table_size = 4 + 12 * tags.len();
+    let mut data: Vec<u8> = Vec::new();
+    let mut entries = Vec::new();
+
+    for &(signature, ref tag) in tags.iter() {
+        let shared = entries.iter().zip(tags.iter())
+            .find(|&(_, (_, other))| other == tag)
+            .map(|(&(_, offset, _), _)| offset);
+        let offset = match shared {
+            Some(offset) => offset,
+            None => {
+                while !data.len().is_multiple_of(4) {
+                    data.push(0);
+                }
+                let offset = 128 + table_size + data.len();
+                data.extend_from_slice(tag);
+                offset
+            }
+        };
+        entries.push((signature, offset, tag.len()));
+    }
+    while !data.len().is_multiple_of(4) {
+        data.push(0);
+    }
+
+    let size = 128 + table_size + data.len();
+    let mut profile = Vec::with_capacity(size);
+    push_u32(&mut profile, size as u32);
+    push_u32(&mut profile, 0);
+    push_u32(&mut profile, 0x0210_0000);
+    profile.extend_from_slice(b"mntr");
+    profile.extend_from_slice(color_space);
+    profile.extend_from_slice(b"XYZ ");
+    // A fixed creation date, so that generated files are reproducible.
+    for &value in &[2000, 1, 1, 0, 0, 0] {
+        push_u16(&mut profile, value);
+    }
+    profile.extend_from_slice(b"acsp");
+    profile.extend_from_slice(&[0; 24]);
+    push_u32(&mut profile, 0);
+    // The PCS illuminant, D50.
+    push_s15_fixed16(&mut profile, 0.9642);
+    push_s15_fixed16(&mut profile, 1.0);
+    push_s15_fixed16(&mut profile, 0.8249);
+    profile.extend_from_slice(&[0; 48]);
+
+    push_u32(&mut profile, entries.len() as u32);
+    for &(signature, offset, length) in entries.iter() {
+        profile.extend_from_slice(signature);
+        push_u32(&mut profile, offset as u32);
+        push_u32(&mut profile, length as u32);
+    }
+    profile.extend_from_slice(&data);
+
+    profile
+}
+
+/// An sRGB display profile, with the primaries adapted to D50.
+pub fn srgb_profile() -> Vec<u8> {
+    let curve = srgb_curve_tag();
+    build_profile(b"RGB ", &[
+        (b"desc", description_tag("sRGB IEC61966-2.1")),
+        (b"cprt", text_tag("No copyright, use freely")),
+        (b"wtpt", xyz_tag(0.9505, 1.0, 1.0891)),
+        (b"rXYZ", xyz_tag(0.4361, 0.2225, 0.0139)),
+        (b"gXYZ", xyz_tag(0.3851, 0.7169, 0.0971)),
+        (b"bXYZ", xyz_tag(0.1431, 0.0606, 0.7141)),
+        (b"rTRC", curve.clone()),
+        (b"gTRC", curve.clone()),
+        (b"bTRC", curve),
+    ])
+}
+
+/// A gray display profile with the sRGB transfer curve.
+pub fn gray_profile() -> Vec<u8> {
+    build_profile(b"GRAY", &[
+        (b"desc", description_tag("sGray")),
+        (b"cprt", text_tag("No copyright, use freely")),
+        (b"wtpt", xyz_tag(0.9642, 1.0, 0.8249)),
+        (b"kTRC", srgb_curve_tag()),
+    ])
+}
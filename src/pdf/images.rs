@@ -0,0 +1,662 @@
+#![allow(dead_code)]
+use std::io;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::fmt;
+use image_tools::image_ops::{ImageDimensions, ImageResolution, ResolutionUnits, ImageFileFormat};
+use image_tools::tiff::{self, TiffFile, TiffDirectory};
+use pdf::objects::{ObjectId, PdfObject, PdfDictionary, PdfStream};
+use pdf::writer::PdfWriter;
+use pdf::flate;
+use pdf::ccitt::{self, CcittCoding};
+
+
+fn unsupported(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Unsupported image: {}", message))
+}
+
+
+/// The filter an image's data is stored with in the PDF file.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum ImageFilter {
+    DCTDecode,
+    CCITTFaxDecode,
+    FlateDecode,
+}
+
+impl fmt::Display for ImageFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ImageFilter::DCTDecode      => "DCTDecode".fmt(f),
+            ImageFilter::CCITTFaxDecode => "CCITTFaxDecode".fmt(f),
+            ImageFilter::FlateDecode    => "FlateDecode".fmt(f),
+        }
+    }
+}
+
+
+/// An image ready to be embedded in a PDF file as an image XObject. Where the
+/// source file's compressed data can be used by a PDF filter as it is, it is
+/// passed through; otherwise the samples are decoded and Flate compressed.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PdfImage {
+    width: usize,
+    height: usize,
+    color_space: PdfObject,
+    bits_per_component: u32,
+    filter: ImageFilter,
+    decode_parms: Option<PdfDictionary>,
+    decode: Option<Vec<f64>>,
+    data: Vec<u8>,
+    soft_mask: Option<Box<PdfImage>>,
+    matte: Option<Vec<f64>>,
+    resolution: Option<ImageResolution>,
+    source_format: ImageFileFormat,
+    passthrough: bool,
+}
+
+impl PdfImage {
+    fn new(width: usize, height: usize, color_space: PdfObject, bits_per_component: u32,
+           filter: ImageFilter, data: Vec<u8>, source_format: ImageFileFormat) -> PdfImage {
+        PdfImage {
+            width: width,
+            height: height,
+            color_space: color_space,
+            bits_per_component: bits_per_component,
+            filter: filter,
+            decode_parms: None,
+            decode: None,
+            data: data,
+            soft_mask: None,
+            matte: None,
+            resolution: None,
+            source_format: source_format,
+            passthrough: true,
+        }
+    }
+
+    /// Load an image file, deciding its format from its contents.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<PdfImage> {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+
+        PdfImage::from_bytes(data)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> io::Result<PdfImage> {
+        if data.starts_with(&[0xff, 0xd8]) {
+            from_jpeg(data)
+        } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            from_png(&data)
+        } else if TiffFile::is_tiff(&data) {
+            let tiff = TiffFile::parse(data)?;
+            from_tiff_directory(&tiff, &tiff.directories()[0])
+        } else {
+            Err(unsupported("unrecognised file format"))
+        }
+    }
+
+    pub fn dimensions(&self) -> ImageDimensions {
+        ImageDimensions::new(self.width, self.height)
+    }
+
+    /// The resolution recorded in the image file, if it records one.
+    pub fn resolution(&self) -> Option<&ImageResolution> {
+        self.resolution.as_ref()
+    }
+
+    pub fn filter(&self) -> &ImageFilter {
+        &self.filter
+    }
+
+    pub fn source_format(&self) -> &ImageFileFormat {
+        &self.source_format
+    }
+
+    /// Whether the compressed data of the source file was embedded unchanged.
+    pub fn is_passthrough(&self) -> bool {
+        self.passthrough
+    }
+
+    pub fn has_soft_mask(&self) -> bool {
+        self.soft_mask.is_some()
+    }
+
+    pub fn color_space(&self) -> &PdfObject {
+        &self.color_space
+    }
+
+    pub fn bits_per_component(&self) -> u32 {
+        self.bits_per_component
+    }
+
+    pub fn data(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    pub fn to_stream(&self) -> PdfStream {
+        let mut dict = PdfDictionary::new();
+        dict.set("Type", PdfObject::name("XObject"));
+        dict.set("Subtype", PdfObject::name("Image"));
+        dict.set("Width", PdfObject::Integer(self.width as i64));
+        dict.set("Height", PdfObject::Integer(self.height as i64));
+        dict.set("ColorSpace", self.color_space.clone());
+        dict.set("BitsPerComponent", PdfObject::Integer(self.bits_per_component as i64));
+        dict.set("Filter", PdfObject::name(self.filter.to_string().as_ref()));
+        if let Some(ref parms) = self.decode_parms {
+            dict.set("DecodeParms", PdfObject::Dictionary(parms.clone()));
+        }
+        if let Some(ref decode) = self.decode {
+            dict.set("Decode", PdfObject::Array(decode.iter().map(|v| PdfObject::real(*v)).collect()));
+        }
+        if let Some(ref matte) = self.matte {
+            dict.set("Matte", PdfObject::Array(matte.iter().map(|v| PdfObject::real(*v)).collect()));
+        }
+
+        PdfStream::new(dict, self.data.clone())
+    }
+
+    /// Add the image, and its soft mask if it has one, to a PDF file.
+    pub fn add_to(&self, writer: &mut PdfWriter) -> ObjectId {
+        let mut stream = self.to_stream();
+        if let Some(ref mask) = self.soft_mask {
+            let mask_id = mask.add_to(writer);
+            stream.dict_mut().set("SMask", PdfObject::Reference(mask_id));
+        }
+
+        writer.add(PdfObject::Stream(stream))
+    }
+}
+
+impl fmt::Display for PdfImage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PdfImage({}, {}, {} bpc, {})", self.source_format, self.dimensions(), self.bits_per_component, self.filter)
+    }
+}
+
+
+fn device_color_space(components: usize) -> io::Result<PdfObject> {
+    match components {
+        1 => Ok(PdfObject::name("DeviceGray")),
+        3 => Ok(PdfObject::name("DeviceRGB")),
+        4 => Ok(PdfObject::name("DeviceCMYK")),
+        _ => Err(unsupported(&format!("{} colour components", components))),
+    }
+}
+
+fn read_u16_be(bytes: &[u8]) -> usize {
+    (bytes[0] as usize) << 8 | bytes[1] as usize
+}
+
+fn read_u32_be(bytes: &[u8]) -> usize {
+    (bytes[0] as usize) << 24 | (bytes[1] as usize) << 16 | (bytes[2] as usize) << 8 | bytes[3] as usize
+}
+
+
+/// JPEG files are embedded unchanged with the DCTDecode filter; only the frame
+/// header and the JFIF and Adobe application markers are read.
+fn from_jpeg(data: Vec<u8>) -> io::Result<PdfImage> {
+    let mut pos = 2;
+    let mut frame = None;
+    let mut resolution = None;
+    let mut adobe = false;
+
+    while pos + 4 <= data.len() && frame.is_none() {
+        if data[pos] != 0xff {
+            return Err(unsupported("corrupt JPEG marker"));
+        }
+        let marker = data[pos + 1];
+        if marker == 0xff {
+            pos += 1;
+            continue;
+        }
+
+        // The length counts its own two bytes.
+        let length = read_u16_be(&data[pos + 2..]);
+        if length < 2 {
+            return Err(unsupported("corrupt JPEG segment length"));
+        }
+        let segment_end = pos + 2 + length;
+        if segment_end > data.len() {
+            return Err(unsupported("truncated JPEG segment"));
+        }
+        let segment = &data[pos + 4..segment_end];
+
+        match marker {
+            0xc0..=0xcf if marker != 0xc4 && marker != 0xc8 && marker != 0xcc => {
+                if segment.len() < 6 {
+                    return Err(unsupported("truncated JPEG frame header"));
+                }
+                let precision = segment[0] as u32;
+                let height = read_u16_be(&segment[1..]);
+                let width = read_u16_be(&segment[3..]);
+                let components = segment[5] as usize;
+                frame = Some((precision, width, height, components));
+            }
+            0xe0 if segment.starts_with(b"JFIF\0") && segment.len() >= 12 => {
+                let density = read_u16_be(&segment[8..]);
+                resolution = match segment[7] {
+                    1 => Some(ImageResolution::new(density, ResolutionUnits::PixelsPerInch)),
+                    2 => Some(ImageResolution::new(density, ResolutionUnits::PixelsPerCentimeter)),
+                    _ => None,
+                };
+            }
+            0xee if segment.starts_with(b"Adobe") => {
+                adobe = true;
+            }
+            _ => {}
+        }
+
+        pos = segment_end;
+    }
+
+    let (precision, width, height, components) = match frame {
+        Some(frame) => frame,
+        None        => return Err(unsupported("JPEG without a frame header")),
+    };
+
+    let mut image = PdfImage::new(width, height, device_color_space(components)?, precision,
+                                  ImageFilter::DCTDecode, data, ImageFileFormat::JPEG);
+    // Adobe applications write CMYK JPEGs with inverted components.
+    if components == 4 && adobe {
+        image.decode = Some(vec![1.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0, 0.0]);
+    }
+    image.resolution = resolution;
+
+    Ok(image)
+}
+
+
+/// Undo the PNG row filters, giving rows of packed samples.
+pub fn png_unfilter(data: &[u8], row_bytes: usize, pixel_bytes: usize, height: usize) -> io::Result<Vec<u8>> {
+    // Each row is preceded by its filter type. The size comes from the
+    // image header, so it is checked against the data before allocating.
+    match (row_bytes + 1).checked_mul(height) {
+        Some(size) if size <= data.len() => {}
+        _ => return Err(unsupported("truncated PNG image data")),
+    }
+    let mut output = vec![0u8; row_bytes * height];
+
+    for y in 0..height {
+        let start = y * (row_bytes + 1);
+        if start + row_bytes + 1 > data.len() {
+            return Err(unsupported("truncated PNG image data"));
+        }
+        let filter = data[start];
+        let row = &data[start + 1..start + 1 + row_bytes];
+
+        for x in 0..row_bytes {
+            let a = if x >= pixel_bytes { output[y * row_bytes + x - pixel_bytes] as i32 } else { 0 };
+            let b = if y > 0 { output[(y - 1) * row_bytes + x] as i32 } else { 0 };
+            let c = if x >= pixel_bytes && y > 0 { output[(y - 1) * row_bytes + x - pixel_bytes] as i32 } else { 0 };
+
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => (a + b) / 2,
+                4 => {
+                    let p = a + b - c;
+                    let (pa, pb, pc) = ((p - a).abs(), (p - b).abs(), (p - c).abs());
+                    if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+                }
+                _ => return Err(unsupported("bad PNG filter type")),
+            };
+
+            output[y * row_bytes + x] = (row[x] as i32 + predicted) as u8;
+        }
+    }
+
+    Ok(output)
+}
+
+/// Split interleaved samples into colour samples and alpha samples.
+fn split_alpha(samples: &[u8], color_channels: usize, total_channels: usize, bytes_per_sample: usize) -> (Vec<u8>, Vec<u8>) {
+    let pixel_bytes = total_channels * bytes_per_sample;
+    let color_bytes = color_channels * bytes_per_sample;
+    let mut color = Vec::with_capacity(samples.len() / total_channels * color_channels);
+    let mut alpha = Vec::with_capacity(samples.len() / total_channels);
+
+    for pixel in samples.chunks(pixel_bytes) {
+        if pixel.len() < pixel_bytes {
+            break;
+        }
+        color.extend_from_slice(&pixel[..color_bytes]);
+        alpha.extend_from_slice(&pixel[color_bytes..color_bytes + bytes_per_sample]);
+    }
+
+    (color, alpha)
+}
+
+fn gray_mask(width: usize, height: usize, bits: u32, samples: &[u8], source_format: ImageFileFormat) -> PdfImage {
+    let mut mask = PdfImage::new(width, height, PdfObject::name("DeviceGray"), bits,
+                                 ImageFilter::FlateDecode, flate::compress(samples), source_format);
+    mask.passthrough = false;
+
+    mask
+}
+
+/// PNG image data is a zlib stream of filtered rows, which FlateDecode with the
+/// PNG predictors reads directly. Images with an alpha channel are decoded so
+/// that the alpha can become a soft mask. Interlaced images are not supported.
+fn from_png(data: &[u8]) -> io::Result<PdfImage> {
+    let mut pos = 8;
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut image_data = Vec::new();
+    let mut resolution = None;
+
+    while pos + 8 <= data.len() {
+        let length = read_u32_be(&data[pos..]);
+        let kind = &data[pos + 4..pos + 8];
+        if pos + 12 + length > data.len() {
+            return Err(unsupported("truncated PNG chunk"));
+        }
+        let chunk = &data[pos + 8..pos + 8 + length];
+
+        match kind {
+            b"IHDR" if chunk.len() >= 13 => header = Some((read_u32_be(chunk), read_u32_be(&chunk[4..]),
+                                                           chunk[8] as u32, chunk[9], chunk[12])),
+            b"PLTE" => palette = chunk.to_vec(),
+            b"IDAT" => image_data.extend_from_slice(chunk),
+            b"pHYs" if chunk.len() >= 9 && chunk[8] == 1 => {
+                // PNG records pixels per metre, which converts more precisely to pixels per inch.
+                let per_inch = (read_u32_be(chunk) as f64 * 0.0254).round() as usize;
+                resolution = Some(ImageResolution::new(per_inch, ResolutionUnits::PixelsPerInch));
+            }
+            b"IEND" => break,
+            _ => {}
+        }
+
+        pos += 12 + length;
+    }
+
+    let (width, height, bit_depth, color_type, interlace) = match header {
+        Some(header) => header,
+        None         => return Err(unsupported("PNG without a header")),
+    };
+    if interlace != 0 {
+        return Err(unsupported("interlaced PNG"));
+    }
+
+    let (color_space, channels, alpha) = match color_type {
+        0 => (PdfObject::name("DeviceGray"), 1, false),
+        2 => (PdfObject::name("DeviceRGB"), 3, false),
+        3 => {
+            let hival = (palette.len() / 3).max(1) - 1;
+            (PdfObject::Array(vec![
+                PdfObject::name("Indexed"), PdfObject::name("DeviceRGB"),
+                PdfObject::Integer(hival as i64), PdfObject::String(palette.clone()),
+            ]), 1, false)
+        }
+        4 => (PdfObject::name("DeviceGray"), 1, true),
+        6 => (PdfObject::name("DeviceRGB"), 3, true),
+        _ => return Err(unsupported("bad PNG colour type")),
+    };
+
+    let mut image = if alpha {
+        if bit_depth != 8 && bit_depth != 16 {
+            return Err(unsupported("bad PNG bit depth for an image with alpha"));
+        }
+        let total_channels = channels + 1;
+        let bytes_per_sample = (bit_depth as usize) / 8;
+        let row_bytes = width.checked_mul(total_channels * bytes_per_sample)
+                             .ok_or_else(|| unsupported("PNG image too wide"))?;
+        let samples = png_unfilter(&flate::decompress(&image_data)?, row_bytes, total_channels * bytes_per_sample, height)?;
+        let (color, alpha) = split_alpha(&samples, channels, total_channels, bytes_per_sample);
+
+        let mut image = PdfImage::new(width, height, color_space, bit_depth,
+                                      ImageFilter::FlateDecode, flate::compress(&color), ImageFileFormat::PNG);
+        image.soft_mask = Some(Box::new(gray_mask(width, height, bit_depth, &alpha, ImageFileFormat::PNG)));
+        image.passthrough = false;
+        image
+    } else {
+        let mut parms = PdfDictionary::new();
+        parms.set("Predictor", PdfObject::Integer(15));
+        parms.set("Colors", PdfObject::Integer(channels as i64));
+        parms.set("BitsPerComponent", PdfObject::Integer(bit_depth as i64));
+        parms.set("Columns", PdfObject::Integer(width as i64));
+
+        let mut image = PdfImage::new(width, height, color_space, bit_depth,
+                                      ImageFilter::FlateDecode, image_data, ImageFileFormat::PNG);
+        image.decode_parms = Some(parms);
+        image
+    };
+    image.resolution = resolution;
+
+    Ok(image)
+}
+
+
+fn tiff_color_space(directory: &TiffDirectory, bits: u32) -> io::Result<(PdfObject, usize, Option<Vec<f64>>)> {
+    match directory.photometric()? {
+        tiff::PHOTOMETRIC_WHITE_IS_ZERO => Ok((PdfObject::name("DeviceGray"), 1, Some(vec![1.0, 0.0]))),
+        tiff::PHOTOMETRIC_BLACK_IS_ZERO => Ok((PdfObject::name("DeviceGray"), 1, None)),
+        tiff::PHOTOMETRIC_RGB           => Ok((PdfObject::name("DeviceRGB"), 3, None)),
+        tiff::PHOTOMETRIC_SEPARATED     => Ok((PdfObject::name("DeviceCMYK"), 4, None)),
+        tiff::PHOTOMETRIC_PALETTE       => {
+            let map = directory.color_map().ok_or_else(|| unsupported("palette TIFF without a colour map"))?;
+            if !(1..=16).contains(&bits) {
+                return Err(unsupported("palette TIFF with a bits per sample outside 1 to 16"));
+            }
+            let entries = 1usize << bits;
+            if map.len() < 3 * entries {
+                return Err(unsupported("short TIFF colour map"));
+            }
+
+            // TIFF colour maps hold all the reds, then all the greens, then all the blues, as 16 bit values.
+            let mut lookup = Vec::with_capacity(3 * entries);
+            for i in 0..entries {
+                lookup.push((map[i] >> 8) as u8);
+                lookup.push((map[entries + i] >> 8) as u8);
+                lookup.push((map[2 * entries + i] >> 8) as u8);
+            }
+
+            Ok((PdfObject::Array(vec![
+                PdfObject::name("Indexed"), PdfObject::name("DeviceRGB"),
+                PdfObject::Integer(entries as i64 - 1), PdfObject::String(lookup),
+            ]), 1, None))
+        }
+        other => Err(unsupported(&format!("TIFF photometric interpretation {}", other))),
+    }
+}
+
+/// Bilevel CCITT images and single strip Deflate images are passed through;
+/// anything else is decoded and recompressed with Flate.
+pub fn from_tiff_directory(tiff: &TiffFile, directory: &TiffDirectory) -> io::Result<PdfImage> {
+    let width = directory.width()? as usize;
+    let height = directory.height()? as usize;
+    let bits = directory.bits_per_sample()?;
+    let samples_per_pixel = directory.samples_per_pixel() as usize;
+    let compression = directory.compression();
+    let (color_space, channels, decode) = tiff_color_space(directory, bits)?;
+    let strips = directory.strips(tiff.data())?;
+
+    if samples_per_pixel < channels {
+        return Err(unsupported("fewer samples than colour channels"));
+    }
+
+    let mut image = match compression {
+        tiff::COMPRESSION_CCITT_RLE | tiff::COMPRESSION_CCITT_G3 | tiff::COMPRESSION_CCITT_G4 => {
+            let t4_options = directory.get_u32(tiff::TAG_T4_OPTIONS).unwrap_or(0);
+            let coding = match compression {
+                tiff::COMPRESSION_CCITT_G4 => CcittCoding::TwoDimensional,
+                tiff::COMPRESSION_CCITT_G3 if t4_options & 1 != 0 => CcittCoding::Mixed,
+                _ => CcittCoding::OneDimensional,
+            };
+
+            let (data, coding, byte_align) = if strips.len() == 1 {
+                let mut data = strips[0].to_vec();
+                if directory.fill_order() == 2 {
+                    tiff::reverse_bits(&mut data);
+                }
+                let byte_align = compression == tiff::COMPRESSION_CCITT_RLE
+                                 || (compression == tiff::COMPRESSION_CCITT_G3 && t4_options & 4 != 0);
+                (data, coding, byte_align)
+            } else {
+                // Each strip is coded on its own, starting again from a white
+                // reference line, so the strips cannot simply be joined. They
+                // are decoded and the whole image coded again as Group 4.
+                let rows_per_strip = directory.rows_per_strip()?;
+                let mut lines = Vec::with_capacity(height);
+                for strip in strips.iter() {
+                    let rows = rows_per_strip.min(height - lines.len());
+                    let mut strip = strip.to_vec();
+                    if directory.fill_order() == 2 {
+                        tiff::reverse_bits(&mut strip);
+                    }
+                    lines.extend(ccitt::decode(&strip, width, rows, coding,
+                                               compression == tiff::COMPRESSION_CCITT_RLE)?);
+                }
+                (ccitt::encode_g4(&lines, width), CcittCoding::TwoDimensional, false)
+            };
+
+            let mut parms = PdfDictionary::new();
+            let k = match coding {
+                CcittCoding::TwoDimensional => -1,
+                CcittCoding::Mixed          => 1,
+                CcittCoding::OneDimensional => 0,
+            };
+            parms.set("K", PdfObject::Integer(k));
+            parms.set("Columns", PdfObject::Integer(width as i64));
+            parms.set("Rows", PdfObject::Integer(height as i64));
+            if byte_align {
+                parms.set("EncodedByteAlign", PdfObject::Boolean(true));
+            }
+
+            // The fax codes describe white and black runs; a BlackIsZero image
+            // shows the runs the other way round.
+            let decode = if directory.photometric()? == tiff::PHOTOMETRIC_BLACK_IS_ZERO {
+                Some(vec![1.0, 0.0])
+            } else {
+                None
+            };
+
+            let mut image = PdfImage::new(width, height, PdfObject::name("DeviceGray"), 1,
+                                          ImageFilter::CCITTFaxDecode, data, ImageFileFormat::TIFF);
+            image.decode_parms = Some(parms);
+            image.decode = decode;
+            image
+        }
+        tiff::COMPRESSION_DEFLATE | tiff::COMPRESSION_DEFLATE_OLD
+            if strips.len() == 1 && samples_per_pixel == channels && directory.fill_order() == 1
+               && (bits == 8 || (bits < 8 && directory.predictor() == 1)) && directory.predictor() <= 2 => {
+
+            let mut image = PdfImage::new(width, height, color_space, bits,
+                                          ImageFilter::FlateDecode, strips[0].to_vec(), ImageFileFormat::TIFF);
+            if directory.predictor() == 2 {
+                let mut parms = PdfDictionary::new();
+                parms.set("Predictor", PdfObject::Integer(2));
+                parms.set("Colors", PdfObject::Integer(channels as i64));
+                parms.set("BitsPerComponent", PdfObject::Integer(bits as i64));
+                parms.set("Columns", PdfObject::Integer(width as i64));
+                image.decode_parms = Some(parms);
+            }
+            image.decode = decode;
+            image
+        }
+        _ => {
+            let samples = directory.decode_samples(tiff.data())?;
+
+            let mut image = if samples_per_pixel > channels {
+                if bits < 8 {
+                    return Err(unsupported("extra samples with fewer than 8 bits per sample"));
+                }
+
+                let bytes_per_sample = bits as usize / 8;
+                let (color, alpha) = split_alpha(&samples, channels, samples_per_pixel, bytes_per_sample);
+                let mut image = PdfImage::new(width, height, color_space, bits,
+                                              ImageFilter::FlateDecode, flate::compress(&color), ImageFileFormat::TIFF);
+
+                // Extra sample 1 is premultiplied alpha, 2 is straight alpha, 0 is unspecified data.
+                match directory.extra_samples().first().cloned().unwrap_or(0) {
+                    1 => {
+                        image.soft_mask = Some(Box::new(gray_mask(width, height, bits, &alpha, ImageFileFormat::TIFF)));
+                        image.matte = Some(vec![0.0; channels]);
+                    }
+                    2 => {
+                        image.soft_mask = Some(Box::new(gray_mask(width, height, bits, &alpha, ImageFileFormat::TIFF)));
+                    }
+                    _ => {}
+                }
+                image
+            } else {
+                PdfImage::new(width, height, color_space, bits,
+                              ImageFilter::FlateDecode, flate::compress(&samples), ImageFileFormat::TIFF)
+            };
+            image.passthrough = false;
+            image.decode = decode;
+            image
+        }
+    };
+    image.resolution = directory.resolution();
+
+    Ok(image)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image_tools::tiff::{TiffImageData, TiffValue};
+
+    #[test]
+    fn multi_strip_group_4_tiff_is_coded_again_as_one_stream() {
+        // Three strips of two rows, each coded on its own: a black bar, then
+        // white with a black block on the right.
+        let strip_lines = vec![vec![0], vec![4]];
+        let strips = (0..3).map(|_| ccitt::encode_g4(&strip_lines, 12)).collect();
+        let mut image = TiffImageData::with_strips(12, 6, 2, strips);
+        image.set(tiff::TAG_BITS_PER_SAMPLE, TiffValue::Short(vec![1]));
+        image.set(tiff::TAG_COMPRESSION, TiffValue::Short(vec![tiff::COMPRESSION_CCITT_G4 as u16]));
+        image.set(tiff::TAG_PHOTOMETRIC, TiffValue::Short(vec![tiff::PHOTOMETRIC_WHITE_IS_ZERO as u16]));
+
+        let pdf_image = PdfImage::from_bytes(tiff::write_tiff(&[image])).unwrap();
+        assert_eq!(*pdf_image.filter(), ImageFilter::CCITTFaxDecode);
+        let parms = pdf_image.decode_parms.as_ref().unwrap();
+        assert_eq!(parms.get("K"), Some(&PdfObject::Integer(-1)));
+
+        let lines = ccitt::decode(pdf_image.data(), 12, 6, CcittCoding::TwoDimensional, false).unwrap();
+        assert_eq!(lines, vec![vec![0], vec![4], vec![0], vec![4], vec![0], vec![4]]);
+    }
+
+    fn png_chunk(png: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+        let length = data.len() as u32;
+        png.extend_from_slice(&[(length >> 24) as u8, (length >> 16) as u8, (length >> 8) as u8, length as u8]);
+        png.extend_from_slice(kind);
+        png.extend_from_slice(data);
+        // The CRC is not checked.
+        png.extend_from_slice(&[0; 4]);
+    }
+
+    #[test]
+    fn jpeg_segments_shorter_than_their_length_field_are_rejected() {
+        for length in 0..2 {
+            let data = vec![0xff, 0xd8, 0xff, 0xe0, 0x00, length, 0x00, 0x00];
+            assert_eq!(PdfImage::from_bytes(data).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn png_headers_larger_than_the_image_data_are_rejected() {
+        // An RGBA image claiming 60000 by 60000 pixels, with one row of data.
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png_chunk(&mut png, b"IHDR", &[0, 0, 0xea, 0x60, 0, 0, 0xea, 0x60, 8, 6, 0, 0, 0]);
+        png_chunk(&mut png, b"IDAT", &flate::compress(&[0; 5]));
+        png_chunk(&mut png, b"IEND", &[]);
+
+        assert_eq!(PdfImage::from_bytes(png).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn palette_tiffs_with_too_many_bits_are_rejected() {
+        let mut image = TiffImageData::new(1, 1, vec![0; 8]);
+        image.set(tiff::TAG_BITS_PER_SAMPLE, TiffValue::Short(vec![64]));
+        image.set(tiff::TAG_COMPRESSION, TiffValue::Short(vec![tiff::COMPRESSION_NONE as u16]));
+        image.set(tiff::TAG_PHOTOMETRIC, TiffValue::Short(vec![tiff::PHOTOMETRIC_PALETTE as u16]));
+        image.set(tiff::TAG_COLOR_MAP, TiffValue::Short(vec![0; 6]));
+
+        assert!(PdfImage::from_bytes(tiff::write_tiff(&[image])).is_err());
+    }
+}
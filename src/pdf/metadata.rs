@@ -0,0 +1,443 @@
+#![allow(dead_code)]
+use std::io;
+use std::time::SystemTime;
+use image_tools::document::{DocumentMetadata, OutlineEntry, UtcDateTime};
+use image_tools::document::{PageLabelRange, PageLabelStyle};
+use pdf::objects::{ObjectId, PdfObject, PdfDictionary, PdfStream};
+use pdf::writer::PdfWriter;
+
+
+/// A date in the PDF format, `D:YYYYMMDDHHmmSSZ`, always in UTC.
+pub fn pdf_date(time: SystemTime) -> PdfObject {
+    let date = UtcDateTime::from_system_time(time);
+    PdfObject::string(&format!("D:{:04}{:02}{:02}{:02}{:02}{:02}Z",
+                               date.year, date.month, date.day, date.hour, date.minute, date.second))
+}
+
+/// The Info dictionary for a document, or None when there is nothing to put in it.
+pub fn info_dictionary(metadata: &DocumentMetadata) -> Option<PdfDictionary> {
+    let mut info = PdfDictionary::new();
+    if let Some(title) = metadata.title() {
+        info.set("Title", PdfObject::text(title));
+    }
+    if let Some(author) = metadata.author() {
+        info.set("Author", PdfObject::text(author));
+    }
+    if let Some(subject) = metadata.subject() {
+        info.set("Subject", PdfObject::text(subject));
+    }
+    if !metadata.keywords().is_empty() {
+        info.set("Keywords", PdfObject::text(&metadata.keywords().join(", ")));
+    }
+    if let Some(creator) = metadata.creator() {
+        info.set("Creator", PdfObject::text(creator));
+    }
+    if let Some(producer) = metadata.producer() {
+        info.set("Producer", PdfObject::text(producer));
+    }
+    if let Some(date) = metadata.creation_date() {
+        info.set("CreationDate", pdf_date(date));
+    }
+    if let Some(date) = metadata.modification_date() {
+        info.set("ModDate", pdf_date(date));
+    }
+
+    if info.iter().next().is_none() { None } else { Some(info) }
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&'  => escaped.push_str("&amp;"),
+            '<'  => escaped.push_str("&lt;"),
+            '>'  => escaped.push_str("&gt;"),
+            '"'  => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c    => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// An XMP packet carrying the same information as the Info dictionary, as
+/// described in ISO 16684-1. Additional properties, such as a PDF/A
+/// identification, are inserted into the description as they are given.
+pub fn xmp_packet(metadata: &DocumentMetadata, extra_properties: &[String]) -> String {
+    let mut namespaces = vec![
+        ("dc", "http://purl.org/dc/elements/1.1/"),
+        ("pdf", "http://ns.adobe.com/pdf/1.3/"),
+        ("xmp", "http://ns.adobe.com/xap/1.0/"),
+    ];
+    if extra_properties.iter().any(|property| property.contains("pdfaid:")) {
+        namespaces.push(("pdfaid", "http://www.aiim.org/pdfa/ns/id/"));
+    }
+
+    let mut properties = Vec::new();
+    properties.push(String::from("<dc:format>application/pdf</dc:format>"));
+    if let Some(title) = metadata.title() {
+        properties.push(format!("<dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>",
+                                xml_escape(title)));
+    }
+    if let Some(author) = metadata.author() {
+        properties.push(format!("<dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>", xml_escape(author)));
+    }
+    if let Some(subject) = metadata.subject() {
+        properties.push(format!("<dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>",
+                                xml_escape(subject)));
+    }
+    if !metadata.keywords().is_empty() {
+        properties.push(format!("<pdf:Keywords>{}</pdf:Keywords>", xml_escape(&metadata.keywords().join(", "))));
+    }
+    if let Some(producer) = metadata.producer() {
+        properties.push(format!("<pdf:Producer>{}</pdf:Producer>", xml_escape(producer)));
+    }
+    if let Some(creator) = metadata.creator() {
+        properties.push(format!("<xmp:CreatorTool>{}</xmp:CreatorTool>", xml_escape(creator)));
+    }
+    if let Some(date) = metadata.creation_date() {
+        properties.push(format!("<xmp:CreateDate>{}</xmp:CreateDate>", UtcDateTime::from_system_time(date)));
+    }
+    if let Some(date) = metadata.modification_date() {
+        let date = UtcDateTime::from_system_time(date);
+        properties.push(format!("<xmp:ModifyDate>{}</xmp:ModifyDate>", date));
+        properties.push(format!("<xmp:MetadataDate>{}</xmp:MetadataDate>", date));
+    }
+    properties.extend(extra_properties.iter().cloned());
+
+    let mut packet = String::new();
+    packet.push_str("<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n");
+    packet.push_str("<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n");
+    packet.push_str("<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n");
+    packet.push_str("<rdf:Description rdf:about=\"\"");
+    for &(prefix, uri) in namespaces.iter() {
+        packet.push_str(&format!("\n    xmlns:{}=\"{}\"", prefix, uri));
+    }
+    packet.push_str(">\n");
+    for property in properties.iter() {
+        packet.push_str("  ");
+        packet.push_str(property);
+        packet.push('\n');
+    }
+    packet.push_str("</rdf:Description>\n</rdf:RDF>\n</x:xmpmeta>\n");
+    // Padding lets the metadata be edited in place later.
+    for _ in 0..20 {
+        packet.push_str(&" ".repeat(99));
+        packet.push('\n');
+    }
+    packet.push_str("<?xpacket end=\"w\"?>");
+
+    packet
+}
+
+/// Add an XMP metadata stream. It is left uncompressed so that tools that do
+/// not understand PDF can still find it.
+pub fn add_xmp_metadata(writer: &mut PdfWriter, metadata: &DocumentMetadata, extra_properties: &[String]) -> ObjectId {
+    let mut dict = PdfDictionary::new();
+    dict.set("Type", PdfObject::name("Metadata"));
+    dict.set("Subtype", PdfObject::name("XML"));
+
+    writer.add(PdfObject::Stream(PdfStream::new(dict, xmp_packet(metadata, extra_properties).into_bytes())))
+}
+
+/// The number of entries shown below an outline entry: its children, and
+/// the entries shown below each of them, if it is open.
+fn visible_descendants(entry: &OutlineEntry) -> usize {
+    entry.children().iter().map(|child| {
+        1 + if child.is_open() { visible_descendants(child) } else { 0 }
+    }).sum()
+}
+
+/// Add outline items for a list of sibling entries under a parent, returning
+/// the first and last of them.
+fn add_outline_items(writer: &mut PdfWriter, entries: &[OutlineEntry], parent: ObjectId,
+                     page_ids: &[ObjectId]) -> io::Result<(ObjectId, ObjectId)>
+{
+    let ids: Vec<ObjectId> = entries.iter().map(|_| writer.reserve()).collect();
+
+    for (i, entry) in entries.iter().enumerate() {
+        let page_id = match page_ids.get(entry.page_number().wrapping_sub(1)) {
+            Some(page_id) => *page_id,
+            None => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          format!("Outline entry \"{}\" points to page {} of a {} page document",
+                                                  entry.title(), entry.page_number(), page_ids.len())));
+            }
+        };
+
+        let mut item = PdfDictionary::new();
+        item.set("Title", PdfObject::text(entry.title()));
+        item.set("Parent", PdfObject::Reference(parent));
+        if i > 0 {
+            item.set("Prev", PdfObject::Reference(ids[i - 1]));
+        }
+        if i + 1 < ids.len() {
+            item.set("Next", PdfObject::Reference(ids[i + 1]));
+        }
+        item.set("Dest", PdfObject::Array(vec![PdfObject::Reference(page_id), PdfObject::name("Fit")]));
+
+        if !entry.children().is_empty() {
+            let (first, last) = add_outline_items(writer, entry.children(), ids[i], page_ids)?;
+            item.set("First", PdfObject::Reference(first));
+            item.set("Last", PdfObject::Reference(last));
+            // A negative count marks a closed entry.
+            let count = visible_descendants(entry) as i64;
+            item.set("Count", PdfObject::Integer(if entry.is_open() { count } else { -count }));
+        }
+
+        writer.set(ids[i], PdfObject::Dictionary(item))?;
+    }
+
+    Ok((ids[0], ids[ids.len() - 1]))
+}
+
+/// Add the outline tree for a document, or nothing if it has no entries.
+/// `page_ids` are the page objects in document order.
+pub fn add_outline(writer: &mut PdfWriter, outline: &[OutlineEntry], page_ids: &[ObjectId]) -> io::Result<Option<ObjectId>> {
+    if outline.is_empty() {
+        return Ok(None);
+    }
+
+    let root_id = writer.reserve();
+    let (first, last) = add_outline_items(writer, outline, root_id, page_ids)?;
+    let count: usize = outline.iter().map(|entry| 1 + if entry.is_open() { visible_descendants(entry) } else { 0 }).sum();
+
+    let mut root = PdfDictionary::new();
+    root.set("Type", PdfObject::name("Outlines"));
+    root.set("First", PdfObject::Reference(first));
+    root.set("Last", PdfObject::Reference(last));
+    root.set("Count", PdfObject::Integer(count as i64));
+    writer.set(root_id, PdfObject::Dictionary(root))?;
+
+    Ok(Some(root_id))
+}
+
+/// The page label dictionary for a range of page labels.
+pub fn page_label_dictionary(range: &PageLabelRange) -> PdfDictionary {
+    let style = match range.style() {
+        PageLabelStyle::Decimal      => Some("D"),
+        PageLabelStyle::UpperRoman   => Some("R"),
+        PageLabelStyle::LowerRoman   => Some("r"),
+        PageLabelStyle::UpperLetters => Some("A"),
+        PageLabelStyle::LowerLetters => Some("a"),
+        PageLabelStyle::PrefixOnly   => None,
+    };
+
+    let mut dict = PdfDictionary::new();
+    dict.set("Type", PdfObject::name("PageLabel"));
+    if let Some(style) = style {
+        dict.set("S", PdfObject::name(style));
+    }
+    if !range.prefix().is_empty() {
+        dict.set("P", PdfObject::text(range.prefix()));
+    }
+    if range.start() != 1 {
+        dict.set("St", PdfObject::Integer(range.start() as i64));
+    }
+
+    dict
+}
+
+/// A range of page labels read from a page label dictionary, for the range
+/// starting at the given page index, which counts from 0 as in the file.
+/// The dictionary's entries must already be resolved.
+pub fn page_label_range(page_index: usize, dict: &PdfDictionary) -> PageLabelRange {
+    let style = match dict.get("S").and_then(|style| style.as_name()) {
+        Some("D") => PageLabelStyle::Decimal,
+        Some("R") => PageLabelStyle::UpperRoman,
+        Some("r") => PageLabelStyle::LowerRoman,
+        Some("A") => PageLabelStyle::UpperLetters,
+        Some("a") => PageLabelStyle::LowerLetters,
+        _         => PageLabelStyle::PrefixOnly,
+    };
+
+    let mut range = PageLabelRange::new(page_index + 1, style);
+    if let Some(prefix) = dict.get("P").and_then(|prefix| prefix.as_text()) {
+        range.set_prefix(&prefix);
+    }
+    if let Some(start) = dict.get("St").and_then(|start| start.as_integer()) {
+        if start >= 1 {
+            range.set_start(start as usize);
+        }
+    }
+
+    range
+}
+
+/// The `/PageLabels` number tree for a document, or None if it has no page
+/// labels. A number tree has to say how the first page is labelled, so
+/// pages before the first range are numbered in decimal from 1.
+pub fn page_labels_tree(ranges: &[PageLabelRange], page_count: usize) -> io::Result<Option<PdfDictionary>> {
+    if ranges.is_empty() {
+        return Ok(None);
+    }
+
+    let mut nums = Vec::new();
+    if ranges[0].first_page() != 1 {
+        nums.push(PdfObject::Integer(0));
+        nums.push(PdfObject::Dictionary(page_label_dictionary(&PageLabelRange::new(1, PageLabelStyle::Decimal))));
+    }
+    for range in ranges.iter() {
+        if range.first_page() == 0 || range.first_page() > page_count {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Page labels for {} begin outside a {} page document",
+                                              range, page_count)));
+        }
+        nums.push(PdfObject::Integer(range.first_page() as i64 - 1));
+        nums.push(PdfObject::Dictionary(page_label_dictionary(range)));
+    }
+
+    let mut tree = PdfDictionary::new();
+    tree.set("Nums", PdfObject::Array(nums));
+
+    Ok(Some(tree))
+}
+
+/// Add a document's metadata, outline and page labels to a document being written,
+/// updating its catalog and trailer. The XMP stream is written whenever there
+/// is an Info dictionary or extra XMP properties to go in it.
+pub fn add_document_metadata(writer: &mut PdfWriter, catalog: &mut PdfDictionary, trailer: &mut PdfDictionary,
+                             metadata: &DocumentMetadata, page_ids: &[ObjectId],
+                             extra_properties: &[String]) -> io::Result<()>
+{
+    let info = info_dictionary(metadata);
+    let has_info = info.is_some();
+    if let Some(info) = info {
+        let info_id = writer.add(PdfObject::Dictionary(info));
+        trailer.set("Info", PdfObject::Reference(info_id));
+    }
+    if has_info || !extra_properties.is_empty() {
+        let metadata_id = add_xmp_metadata(writer, metadata, extra_properties);
+        catalog.set("Metadata", PdfObject::Reference(metadata_id));
+    }
+
+    if let Some(outline_id) = add_outline(writer, metadata.outline(), page_ids)? {
+        catalog.set("Outlines", PdfObject::Reference(outline_id));
+        catalog.set("PageMode", PdfObject::name("UseOutlines"));
+    }
+
+    if let Some(tree) = page_labels_tree(metadata.page_labels(), page_ids.len())? {
+        catalog.set("PageLabels", PdfObject::Dictionary(tree));
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+    use std::time::{Duration, UNIX_EPOCH};
+    use pdf::assemble::PdfAssembly;
+    use pdf::reader::PdfDocument;
+    use util::scratch::{scratch_dir, scratch_tiff};
+
+    fn two_page_assembly(dir: &Path, metadata: DocumentMetadata) -> PdfAssembly {
+        let mut assembly = PdfAssembly::new();
+        assembly.add_page(scratch_tiff(dir, "first.tif"));
+        assembly.add_page(scratch_tiff(dir, "second.tif"));
+        assembly.set_metadata(metadata);
+
+        assembly
+    }
+
+    fn resolved(document: &PdfDocument, dict: &PdfDictionary, key: &str) -> PdfDictionary {
+        document.resolve_key(dict, key).unwrap().unwrap().as_dictionary().unwrap().clone()
+    }
+
+    #[test]
+    fn dates_are_written_in_utc() {
+        let date = pdf_date(UNIX_EPOCH + Duration::from_secs(1_000_000_000));
+
+        assert_eq!(date.as_text().unwrap(), "D:20010909014640Z");
+    }
+
+    #[test]
+    fn xmp_packets_escape_text_and_declare_only_the_namespaces_used() {
+        let mut metadata = DocumentMetadata::new();
+        metadata.set_title("Fish & <Chips>");
+
+        let packet = xmp_packet(&metadata, &[]);
+        assert!(packet.contains(">Fish &amp; &lt;Chips&gt;<"));
+        assert!(!packet.contains("xmlns:pdfaid"));
+        assert!(xmp_packet(&metadata, &[String::from("<pdfaid:part>2</pdfaid:part>")]).contains("xmlns:pdfaid"));
+        assert!(info_dictionary(&DocumentMetadata::new()).is_none());
+    }
+
+    #[test]
+    fn info_and_outline_are_written_to_assembled_documents() {
+        let mut chapter = OutlineEntry::new("Chapter 1", 1);
+        let mut section = OutlineEntry::new("Section 1.1", 2);
+        section.add_child(OutlineEntry::new("Figure 1", 2));
+        chapter.add_child(section);
+        chapter.set_open(false);
+        let mut metadata = DocumentMetadata::new();
+        metadata.set_title("Field Notes");
+        metadata.add_outline_entry(chapter);
+        metadata.add_outline_entry(OutlineEntry::new("Index", 2));
+
+        let dir = scratch_dir("metadata-outline");
+        let mut out = Vec::new();
+        two_page_assembly(&dir, metadata).write(&mut out).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let document = PdfDocument::parse(out).unwrap();
+
+        let info = resolved(&document, document.trailer(), "Info");
+        assert_eq!(info.get("Title").unwrap().as_text().unwrap(), "Field Notes");
+
+        let catalog = document.catalog().unwrap();
+        let outlines = resolved(&document, &catalog, "Outlines");
+        assert_eq!(outlines.get("Count"), Some(&PdfObject::Integer(2)));
+        let first = resolved(&document, &outlines, "First");
+        assert_eq!(first.get("Title").unwrap().as_text().unwrap(), "Chapter 1");
+        // Closed, with its section and the figure under it.
+        assert_eq!(first.get("Count"), Some(&PdfObject::Integer(-2)));
+        let last = resolved(&document, &outlines, "Last");
+        assert_eq!(last.get("Title").unwrap().as_text().unwrap(), "Index");
+    }
+
+    #[test]
+    fn outline_entries_must_point_into_the_document() {
+        let mut metadata = DocumentMetadata::new();
+        metadata.add_outline_entry(OutlineEntry::new("Appendix", 3));
+
+        let dir = scratch_dir("metadata-bad-outline");
+        let mut out = Vec::new();
+        let error = two_page_assembly(&dir, metadata).write(&mut out).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn page_labels_are_read_back_as_they_were_written() {
+        let mut roman = PageLabelRange::new(1, PageLabelStyle::LowerRoman);
+        roman.set_start(5);
+        let mut numbered = PageLabelRange::new(2, PageLabelStyle::Decimal);
+        numbered.set_prefix("p. ");
+        let mut metadata = DocumentMetadata::new();
+        metadata.add_page_label_range(roman.clone());
+        metadata.add_page_label_range(numbered.clone());
+
+        let dir = scratch_dir("metadata-page-labels");
+        let mut out = Vec::new();
+        two_page_assembly(&dir, metadata).write(&mut out).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(PdfDocument::parse(out).unwrap().page_labels().unwrap(), vec![roman, numbered]);
+    }
+
+    #[test]
+    fn page_label_trees_say_how_the_first_page_is_labelled() {
+        let tree = page_labels_tree(&[PageLabelRange::new(2, PageLabelStyle::UpperRoman)], 3).unwrap().unwrap();
+        let nums = tree.get("Nums").unwrap().as_array().unwrap();
+
+        assert_eq!(nums[0], PdfObject::Integer(0));
+        assert_eq!(page_label_range(0, nums[1].as_dictionary().unwrap()).style(), PageLabelStyle::Decimal);
+        assert_eq!(nums[2], PdfObject::Integer(1));
+        assert!(page_labels_tree(&[PageLabelRange::new(4, PageLabelStyle::Decimal)], 3).is_err());
+    }
+}
@@ -0,0 +1,15 @@
+pub mod objects;
+pub mod writer;
+pub mod flate;
+pub mod ccitt;
+pub mod images;
+pub mod assemble;
+pub mod parser;
+pub mod filters;
+pub mod reader;
+pub mod extract;
+pub mod update;
+pub mod operations;
+pub mod metadata;
+pub mod icc;
+pub mod pdfa;
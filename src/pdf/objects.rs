@@ -0,0 +1,354 @@
+#![allow(dead_code)]
+use std::fmt;
+use std::io;
+use std::io::Write;
+
+
+/// An indirect object identifier: an object number and a generation number.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd, Debug)]
+pub struct ObjectId {
+    number: u32,
+    generation: u16,
+}
+
+impl ObjectId {
+    pub fn new(number: u32, generation: u16) -> ObjectId {
+        ObjectId {
+            number: number,
+            generation: generation,
+        }
+    }
+
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+
+    pub fn generation(&self) -> u16 {
+        self.generation
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} R", self.number, self.generation)
+    }
+}
+
+
+/// The PDF object types, as described in section 7.3 of ISO 32000.
+#[derive(Clone, PartialEq, Debug)]
+pub enum PdfObject {
+    Null,
+    Boolean(bool),
+    Integer(i64),
+    Real(f64),
+    String(Vec<u8>),
+    Name(String),
+    Array(Vec<PdfObject>),
+    Dictionary(PdfDictionary),
+    Stream(PdfStream),
+    Reference(ObjectId),
+}
+
+impl PdfObject {
+    pub fn name(name: &str) -> PdfObject {
+        PdfObject::Name(String::from(name))
+    }
+
+    pub fn string(text: &str) -> PdfObject {
+        PdfObject::String(text.as_bytes().to_vec())
+    }
+
+    /// A text string, such as a document title. Text that is not plain ASCII is
+    /// written as UTF-16BE with a byte order mark, which every reader understands.
+    pub fn text(text: &str) -> PdfObject {
+        if text.bytes().all(|byte| (b' '..0x7f).contains(&byte) || byte == b'\n' || byte == b'\t') {
+            return PdfObject::string(text);
+        }
+
+        let mut bytes = vec![0xfe, 0xff];
+        for unit in text.encode_utf16() {
+            bytes.push((unit >> 8) as u8);
+            bytes.push(unit as u8);
+        }
+
+        PdfObject::String(bytes)
+    }
+
+    /// A rectangle, such as a MediaBox, given by its lower left and upper right corners.
+    pub fn rectangle(llx: f64, lly: f64, urx: f64, ury: f64) -> PdfObject {
+        PdfObject::Array(vec![
+            PdfObject::real(llx), PdfObject::real(lly), PdfObject::real(urx), PdfObject::real(ury),
+        ])
+    }
+
+    /// A number, written as an integer when it has no fractional part.
+    pub fn real(value: f64) -> PdfObject {
+        if value.fract() == 0.0 && value.abs() < (i64::MAX as f64) {
+            PdfObject::Integer(value as i64)
+        } else {
+            PdfObject::Real(value)
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match *self {
+            PdfObject::Integer(value) => Some(value),
+            _                         => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match *self {
+            PdfObject::Integer(value) => Some(value as f64),
+            PdfObject::Real(value)    => Some(value),
+            _                         => None,
+        }
+    }
+
+    pub fn as_name(&self) -> Option<&str> {
+        match *self {
+            PdfObject::Name(ref name) => Some(name.as_ref()),
+            _                         => None,
+        }
+    }
+
+    pub fn as_string(&self) -> Option<&[u8]> {
+        match *self {
+            PdfObject::String(ref bytes) => Some(bytes.as_ref()),
+            _                            => None,
+        }
+    }
+
+    /// The value of a text string, decoding UTF-16BE text. Other text is
+    /// taken to be Latin-1, which agrees with PDFDocEncoding on printable
+    /// characters.
+    pub fn as_text(&self) -> Option<String> {
+        let bytes = match *self {
+            PdfObject::String(ref bytes) => bytes,
+            _                            => return None,
+        };
+
+        if bytes.starts_with(&[0xfe, 0xff]) {
+            let units: Vec<u16> = bytes[2..].chunks(2)
+                .filter(|pair| pair.len() == 2)
+                .map(|pair| (pair[0] as u16) << 8 | pair[1] as u16)
+                .collect();
+            Some(String::from_utf16_lossy(&units))
+        } else {
+            Some(bytes.iter().map(|&byte| byte as char).collect())
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[PdfObject]> {
+        match *self {
+            PdfObject::Array(ref array) => Some(array.as_ref()),
+            _                           => None,
+        }
+    }
+
+    pub fn as_dictionary(&self) -> Option<&PdfDictionary> {
+        match *self {
+            PdfObject::Dictionary(ref dict) => Some(dict),
+            PdfObject::Stream(ref stream)   => Some(&stream.dict),
+            _                               => None,
+        }
+    }
+
+    pub fn as_stream(&self) -> Option<&PdfStream> {
+        match *self {
+            PdfObject::Stream(ref stream) => Some(stream),
+            _                             => None,
+        }
+    }
+
+    pub fn as_reference(&self) -> Option<ObjectId> {
+        match *self {
+            PdfObject::Reference(id) => Some(id),
+            _                        => None,
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        match *self {
+            PdfObject::Null               => write!(out, "null"),
+            PdfObject::Boolean(value)     => write!(out, "{}", value),
+            PdfObject::Integer(value)     => write!(out, "{}", value),
+            PdfObject::Real(value)        => write_real(out, value),
+            PdfObject::String(ref bytes)  => write_string(out, bytes),
+            PdfObject::Name(ref name)     => write_name(out, name),
+            PdfObject::Array(ref array)   => {
+                write!(out, "[")?;
+                for (i, item) in array.iter().enumerate() {
+                    if i > 0 {
+                        write!(out, " ")?;
+                    }
+                    item.write_to(out)?;
+                }
+                write!(out, "]")
+            }
+            PdfObject::Dictionary(ref dict) => dict.write_to(out),
+            PdfObject::Stream(ref stream)   => stream.write_to(out),
+            PdfObject::Reference(id)        => write!(out, "{}", id),
+        }
+    }
+}
+
+impl fmt::Display for PdfObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut buffer = Vec::new();
+        match self.write_to(&mut buffer) {
+            Ok(())  => write!(f, "{}", String::from_utf8_lossy(&buffer)),
+            Err(_)  => Err(fmt::Error),
+        }
+    }
+}
+
+/// Reals are written in fixed point notation, since PDF has no exponents.
+fn write_real<W: Write>(out: &mut W, value: f64) -> io::Result<()> {
+    let text = format!("{:.4}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" {
+        write!(out, "0")
+    } else {
+        write!(out, "{}", text)
+    }
+}
+
+fn write_string<W: Write>(out: &mut W, bytes: &[u8]) -> io::Result<()> {
+    let mut escaped = Vec::with_capacity(bytes.len() + 2);
+    escaped.push(b'(');
+    for byte in bytes {
+        match *byte {
+            b'(' | b')' | b'\\' => {
+                escaped.push(b'\\');
+                escaped.push(*byte);
+            }
+            b'\r' => escaped.extend_from_slice(b"\\r"),
+            b'\n' => escaped.extend_from_slice(b"\\n"),
+            _     => escaped.push(*byte),
+        }
+    }
+    escaped.push(b')');
+
+    out.write_all(&escaped)
+}
+
+fn is_delimiter(byte: u8) -> bool {
+    matches!(byte, b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%')
+}
+
+fn write_name<W: Write>(out: &mut W, name: &str) -> io::Result<()> {
+    write!(out, "/")?;
+    for byte in name.bytes() {
+        if !(b'!'..=b'~').contains(&byte) || byte == b'#' || is_delimiter(byte) {
+            write!(out, "#{:02X}", byte)?;
+        } else {
+            out.write_all(&[byte])?;
+        }
+    }
+
+    Ok(())
+}
+
+
+/// A PDF dictionary. Entries keep the order they were inserted in, so that
+/// generated files are reproducible.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PdfDictionary {
+    entries: Vec<(String, PdfObject)>,
+}
+
+impl Default for PdfDictionary {
+    fn default() -> PdfDictionary {
+        PdfDictionary::new()
+    }
+}
+
+impl PdfDictionary {
+    pub fn new() -> PdfDictionary {
+        PdfDictionary {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Set a key, replacing any existing value.
+    pub fn set(&mut self, key: &str, value: PdfObject) {
+        for entry in self.entries.iter_mut() {
+            if entry.0 == key {
+                entry.1 = value;
+                return;
+            }
+        }
+
+        self.entries.push((String::from(key), value));
+    }
+
+    pub fn get(&self, key: &str) -> Option<&PdfObject> {
+        self.entries.iter().find(|entry| entry.0 == key).map(|entry| &entry.1)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<PdfObject> {
+        match self.entries.iter().position(|entry| entry.0 == key) {
+            Some(index) => Some(self.entries.remove(index).1),
+            None        => None,
+        }
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn iter(&self) -> ::std::slice::Iter<'_, (String, PdfObject)> {
+        self.entries.iter()
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "<<")?;
+        for (key, value) in self.entries.iter() {
+            write_name(out, key)?;
+            write!(out, " ")?;
+            value.write_to(out)?;
+        }
+        write!(out, ">>")
+    }
+}
+
+
+/// A PDF stream: a dictionary followed by a sequence of bytes. The Length entry
+/// is filled in from the data when the stream is written.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PdfStream {
+    dict: PdfDictionary,
+    data: Vec<u8>,
+}
+
+impl PdfStream {
+    pub fn new(dict: PdfDictionary, data: Vec<u8>) -> PdfStream {
+        PdfStream {
+            dict: dict,
+            data: data,
+        }
+    }
+
+    pub fn dict(&self) -> &PdfDictionary {
+        &self.dict
+    }
+
+    pub fn dict_mut(&mut self) -> &mut PdfDictionary {
+        &mut self.dict
+    }
+
+    pub fn data(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut dict = self.dict.clone();
+        dict.set("Length", PdfObject::Integer(self.data.len() as i64));
+        dict.write_to(out)?;
+        writeln!(out, "\nstream")?;
+        out.write_all(&self.data)?;
+        write!(out, "\nendstream")
+    }
+}
@@ -0,0 +1,510 @@
+#![allow(dead_code)]
+use std::io;
+use std::fmt;
+use image_tools::image_ops::{ElementaryPageOperations, RunOperation, RetargetOperation, OperationResults};
+use image_tools::image_ops::{Page, Pixels, Direction, ImageResolution, ResolutionUnits, ImageDimensions};
+use image_tools::image_ops::{ImageFileFormat, OutputFormat, ColorReduction, DeskewOptions, PageOps, FileName, FilePath};
+use image_tools::image_ops::{QuarterTurn, AngleRotation, BorderRemoval, NoiseFilter};
+use pdf::objects::{PdfObject, PdfDictionary, PdfStream};
+use pdf::reader::{PdfDocument, PdfPage};
+use pdf::update::PdfUpdate;
+use pdf::assemble::POINTS_PER_INCH;
+
+
+/// The boxes that describe a page's regions, other than the MediaBox.
+const PAGE_BOXES: [&str; 4] = ["CropBox", "BleedBox", "TrimBox", "ArtBox"];
+
+
+/// An edge of a page as it is displayed, after the page's rotation.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Edge {
+    /// The edge of the unrotated page that is shown as this edge when the
+    /// page is rotated clockwise by `rotate` degrees.
+    fn unrotated(&self, rotate: i64) -> Edge {
+        let edges = [Edge::Left, Edge::Top, Edge::Right, Edge::Bottom];
+        let position = edges.iter().position(|edge| edge == self).unwrap_or(0);
+        let turns = ((rotate / 90) % 4 + 4) % 4;
+
+        edges[(position + 4 - turns as usize) % 4]
+    }
+}
+
+impl fmt::Display for Edge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Edge::Left   => "Left".fmt(f),
+            Edge::Right  => "Right".fmt(f),
+            Edge::Top    => "Top".fmt(f),
+            Edge::Bottom => "Bottom".fmt(f),
+        }
+    }
+}
+
+
+#[derive(Clone, PartialEq, Debug)]
+enum PdfPageAction {
+    Identify,
+    Rescale(Pixels, Direction),
+    Expand(Edge, Pixels),
+    Trim(Edge, Pixels),
+    SetResolution(ImageResolution),
+    /// Turn the page clockwise by a multiple of 90 degrees.
+    Rotate(u32),
+    /// An operation on pixels, which a PDF page does not have, described
+    /// by the page operation it was compiled from.
+    Raster(String),
+    NoOperation,
+}
+
+impl fmt::Display for PdfPageAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PdfPageAction::Identify                  => write!(f, "Identify"),
+            PdfPageAction::Rescale(amount, ref dir)  => write!(f, "Rescale({} pt, {})", amount, dir),
+            PdfPageAction::Expand(edge, amount)      => write!(f, "Expand{}Edge({} pt)", edge, amount),
+            PdfPageAction::Trim(edge, amount)        => write!(f, "Trim{}Edge({} pt)", edge, amount),
+            PdfPageAction::SetResolution(ref res)    => write!(f, "SetResolution({})", res),
+            PdfPageAction::Rotate(degrees)           => write!(f, "Rotate({} degrees)", degrees),
+            PdfPageAction::Raster(ref op)            => write!(f, "{}", op),
+            PdfPageAction::NoOperation               => write!(f, "NoOperation"),
+        }
+    }
+}
+
+
+/// Page operations carried out on a PDF file without rasterizing it. Edges
+/// are trimmed and expanded by moving the page boxes, and pages are rescaled
+/// by scaling the boxes and wrapping the content streams in a transform, so
+/// embedded images are left exactly as they are. The changes are appended to
+/// the file as an incremental update.
+///
+/// A PDF page has no pixels, so amounts are taken to be in points, as if
+/// the page were an image at 72 pixels per inch. Edges are those of the page
+/// as displayed, taking its Rotate entry into account. Every page in the
+/// file is changed.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PdfPageOperation {
+    file_path: FilePath,
+    action: PdfPageAction,
+}
+
+impl PdfPageOperation {
+    fn new(action: PdfPageAction) -> PdfPageOperation {
+        PdfPageOperation {
+            file_path: String::from(""),
+            action: action,
+        }
+    }
+
+    pub fn file_path(&self) -> &FilePath {
+        &self.file_path
+    }
+
+    fn run_operation(&self) -> io::Result<String> {
+        if let PdfPageAction::Raster(ref op) = self.action {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("{}: {} works on pixels; rasterize the page first",
+                                              self.file_path, op)));
+        }
+
+        let document = PdfDocument::open(&self.file_path)?;
+        let pages = document.pages()?;
+
+        match self.action {
+            PdfPageAction::Identify => {
+                let sizes: Vec<String> = pages.iter().map(|page| {
+                    let (width, height) = displayed_size(page, &visible_box(&document, page).unwrap_or(page.media_box()));
+                    format!("{}x{}", PdfObject::real(width), PdfObject::real(height))
+                }).collect();
+                return Ok(format!("{} PDF {} pages {}", self.file_path, pages.len(), sizes.join(" ")));
+            }
+            // A PDF file has no resolution to set; amounts are always points.
+            PdfPageAction::SetResolution(_) | PdfPageAction::NoOperation => {
+                return Ok(format!("{} unchanged", self.file_path));
+            }
+            _ => {}
+        }
+
+        let mut update = PdfUpdate::new(&document);
+        for page in pages.iter() {
+            self.update_page(&mut update, page)?;
+        }
+        update.write_to_file(&self.file_path)?;
+
+        Ok(format!("{} {} on {} pages", self.file_path, self.action, pages.len()))
+    }
+
+    fn update_page(&self, update: &mut PdfUpdate, page: &PdfPage) -> io::Result<()> {
+        let document = update.document();
+        let mut dict = match document.get(page.id())? {
+            PdfObject::Dictionary(dict) => dict,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} is not a page", page.id()))),
+        };
+        let visible = visible_box(document, page)?;
+
+        let mut new_box = visible;
+        match self.action {
+            PdfPageAction::Trim(edge, amount) | PdfPageAction::Expand(edge, amount) => {
+                let amount = match self.action {
+                    PdfPageAction::Trim(..) => -(amount as f64),
+                    _                       => amount as f64,
+                };
+                match edge.unrotated(page.rotate()) {
+                    Edge::Left   => new_box[0] -= amount,
+                    Edge::Bottom => new_box[1] -= amount,
+                    Edge::Right  => new_box[2] += amount,
+                    Edge::Top    => new_box[3] += amount,
+                }
+                if new_box[2] - new_box[0] < 1.0 || new_box[3] - new_box[1] < 1.0 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                              format!("{} would leave nothing of page {}", self.action, page.id())));
+                }
+
+                // The other boxes must stay inside the new page.
+                for key in PAGE_BOXES.iter().skip(1) {
+                    if let Some(region) = page_box(document, page.dict(), key)? {
+                        match intersect(&region, &new_box) {
+                            Some(region) => dict.set(key, rectangle(&region)),
+                            None         => { dict.remove(key); }
+                        }
+                    }
+                }
+            }
+            PdfPageAction::Rescale(amount, ref direction) => {
+                let (width, height) = displayed_size(page, &visible);
+                let current = match *direction {
+                    Direction::Horizontal => width,
+                    Direction::Vertical   => height,
+                };
+                if current <= 0.0 || amount == 0 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                              format!("Cannot rescale page {} to {} pt", page.id(), amount)));
+                }
+                let scale = amount as f64 / current;
+
+                for value in new_box.iter_mut() {
+                    *value *= scale;
+                }
+                for key in PAGE_BOXES.iter().skip(1) {
+                    if let Some(region) = page_box(document, page.dict(), key)? {
+                        let scaled: Vec<f64> = region.iter().map(|value| value * scale).collect();
+                        dict.set(key, rectangle(&[scaled[0], scaled[1], scaled[2], scaled[3]]));
+                    }
+                }
+                self.scale_contents(update, &mut dict, scale)?;
+                self.scale_annotations(update, &mut dict, scale)?;
+            }
+            // Only how the page is displayed changes; its content is untouched.
+            PdfPageAction::Rotate(degrees) => {
+                dict.set("Rotate", PdfObject::Integer((page.rotate() + degrees as i64).rem_euclid(360)));
+            }
+            _ => {}
+        }
+
+        dict.set("MediaBox", rectangle(&new_box));
+        dict.set("CropBox", rectangle(&new_box));
+        update.set(page.id(), PdfObject::Dictionary(dict));
+
+        Ok(())
+    }
+
+    /// Wrap the page's content streams in a scaling transform. The original
+    /// streams are kept as they are, between a new stream that saves the
+    /// graphics state and sets the transform and one that restores it.
+    fn scale_contents(&self, update: &mut PdfUpdate, dict: &mut PdfDictionary, scale: f64) -> io::Result<()> {
+        let mut contents = match dict.get("Contents") {
+            Some(&PdfObject::Reference(id)) => {
+                match update.document().get(id)? {
+                    PdfObject::Array(items) => items,
+                    _                       => vec![PdfObject::Reference(id)],
+                }
+            }
+            Some(PdfObject::Array(items)) => items.clone(),
+            _ => Vec::new(),
+        };
+
+        let prefix = format!("q {} 0 0 {} 0 0 cm\n", PdfObject::real(scale), PdfObject::real(scale));
+        let prefix_id = update.add(PdfObject::Stream(PdfStream::new(PdfDictionary::new(), prefix.into_bytes())));
+        let suffix_id = update.add(PdfObject::Stream(PdfStream::new(PdfDictionary::new(), b"\nQ\n".to_vec())));
+
+        contents.insert(0, PdfObject::Reference(prefix_id));
+        contents.push(PdfObject::Reference(suffix_id));
+        dict.set("Contents", PdfObject::Array(contents));
+
+        Ok(())
+    }
+
+    /// Scale the rectangles of the page's annotations, so that links and
+    /// form fields stay over the content they belong to.
+    fn scale_annotations(&self, update: &mut PdfUpdate, dict: &mut PdfDictionary, scale: f64) -> io::Result<()> {
+        let annotations = match update.document().resolve_key(dict, "Annots")? {
+            Some(PdfObject::Array(items)) => items,
+            _ => return Ok(()),
+        };
+
+        let mut scaled_annotations = Vec::new();
+        for item in annotations.into_iter() {
+            let mut annotation = match update.document().resolve(&item)? {
+                PdfObject::Dictionary(annotation) => annotation,
+                _ => {
+                    scaled_annotations.push(item);
+                    continue;
+                }
+            };
+            if let Some(PdfObject::Array(rect)) = update.document().resolve_key(&annotation, "Rect")? {
+                let rect: Vec<PdfObject> = rect.iter()
+                    .map(|value| PdfObject::real(value.as_number().unwrap_or(0.0) * scale))
+                    .collect();
+                annotation.set("Rect", PdfObject::Array(rect));
+            }
+
+            match item {
+                PdfObject::Reference(id) => {
+                    update.set(id, PdfObject::Dictionary(annotation));
+                    scaled_annotations.push(item);
+                }
+                _ => scaled_annotations.push(PdfObject::Dictionary(annotation)),
+            }
+        }
+        dict.set("Annots", PdfObject::Array(scaled_annotations));
+
+        Ok(())
+    }
+}
+
+fn normalize(rect: [f64; 4]) -> [f64; 4] {
+    [rect[0].min(rect[2]), rect[1].min(rect[3]), rect[0].max(rect[2]), rect[1].max(rect[3])]
+}
+
+fn intersect(a: &[f64; 4], b: &[f64; 4]) -> Option<[f64; 4]> {
+    let rect = [a[0].max(b[0]), a[1].max(b[1]), a[2].min(b[2]), a[3].min(b[3])];
+    if rect[0] < rect[2] && rect[1] < rect[3] { Some(rect) } else { None }
+}
+
+fn rectangle(rect: &[f64; 4]) -> PdfObject {
+    PdfObject::rectangle(rect[0], rect[1], rect[2], rect[3])
+}
+
+fn page_box(document: &PdfDocument, dict: &PdfDictionary, key: &str) -> io::Result<Option<[f64; 4]>> {
+    match document.resolve_key(dict, key)? {
+        Some(PdfObject::Array(values)) => {
+            if values.len() != 4 {
+                return Ok(None);
+            }
+            let mut rect = [0.0; 4];
+            for (i, value) in values.iter().enumerate() {
+                rect[i] = document.resolve(value)?.as_number().unwrap_or(0.0);
+            }
+            Ok(Some(normalize(rect)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// The part of the page that is shown: the CropBox, clipped to the MediaBox.
+fn visible_box(document: &PdfDocument, page: &PdfPage) -> io::Result<[f64; 4]> {
+    let media_box = normalize(page.media_box());
+    match page_box(document, page.dict(), "CropBox")? {
+        Some(crop_box) => Ok(intersect(&crop_box, &media_box).unwrap_or(media_box)),
+        None           => Ok(media_box),
+    }
+}
+
+/// The width and height of a region as displayed, after the page's rotation.
+fn displayed_size(page: &PdfPage, region: &[f64; 4]) -> (f64, f64) {
+    let (width, height) = (region[2] - region[0], region[3] - region[1]);
+    if page.rotate() % 180 == 0 { (width, height) } else { (height, width) }
+}
+
+/// A page for a PDF file, sized in points from its first page, for use in
+/// plans of PdfPageOperations.
+pub fn pdf_page(file_name: FileName, file_path: FilePath) -> io::Result<Page> {
+    let document = PdfDocument::open(&file_path)?;
+    let pages = document.pages()?;
+    let first = match pages.first() {
+        Some(first) => first,
+        None        => return Err(io::Error::new(io::ErrorKind::InvalidData, "PDF file has no pages")),
+    };
+    let (width, height) = displayed_size(first, &visible_box(&document, first)?);
+
+    Ok(Page::new(file_name, ImageFileFormat::PDF, file_path,
+                 ImageDimensions::new(width.round() as usize, height.round() as usize),
+                 ImageResolution::new(POINTS_PER_INCH as usize, ResolutionUnits::PixelsPerInch)))
+}
+
+
+impl ElementaryPageOperations for PdfPageOperation {
+    fn identify(_file_name: FileName, file_path: FilePath) -> PdfPageOperation {
+        let mut op = PdfPageOperation::new(PdfPageAction::Identify);
+        op.file_path = file_path;
+
+        op
+    }
+
+    fn rescale(amount: Pixels, dir: Direction)  -> PdfPageOperation {
+        PdfPageOperation::new(PdfPageAction::Rescale(amount, dir))
+    }
+
+    fn expand_left_edge(amount: Pixels)         -> PdfPageOperation {
+        PdfPageOperation::new(PdfPageAction::Expand(Edge::Left, amount))
+    }
+
+    fn expand_right_edge(amount: Pixels)        -> PdfPageOperation {
+        PdfPageOperation::new(PdfPageAction::Expand(Edge::Right, amount))
+    }
+
+    fn expand_top_edge(amount: Pixels)          -> PdfPageOperation {
+        PdfPageOperation::new(PdfPageAction::Expand(Edge::Top, amount))
+    }
+
+    fn expand_bottom_edge(amount: Pixels)       -> PdfPageOperation {
+        PdfPageOperation::new(PdfPageAction::Expand(Edge::Bottom, amount))
+    }
+
+    fn trim_left_edge(amount: Pixels)           -> PdfPageOperation {
+        PdfPageOperation::new(PdfPageAction::Trim(Edge::Left, amount))
+    }
+
+    fn trim_right_edge(amount: Pixels)          -> PdfPageOperation {
+        PdfPageOperation::new(PdfPageAction::Trim(Edge::Right, amount))
+    }
+
+    fn trim_top_edge(amount: Pixels)            -> PdfPageOperation {
+        PdfPageOperation::new(PdfPageAction::Trim(Edge::Top, amount))
+    }
+
+    fn trim_bottom_edge(amount: Pixels)         -> PdfPageOperation {
+        PdfPageOperation::new(PdfPageAction::Trim(Edge::Bottom, amount))
+    }
+
+    fn set_resolution(res: ImageResolution)     -> PdfPageOperation {
+        PdfPageOperation::new(PdfPageAction::SetResolution(res))
+    }
+
+    fn convert_format(format: OutputFormat)     -> PdfPageOperation {
+        PdfPageOperation::new(PdfPageAction::Raster(PageOps::ConvertFormat(format).to_string()))
+    }
+
+    fn reduce_colors(reduction: ColorReduction) -> PdfPageOperation {
+        PdfPageOperation::new(PdfPageAction::Raster(PageOps::ReduceColors(reduction).to_string()))
+    }
+
+    fn deskew(options: DeskewOptions)           -> PdfPageOperation {
+        PdfPageOperation::new(PdfPageAction::Raster(PageOps::Deskew(options).to_string()))
+    }
+
+    fn rotate(turn: QuarterTurn)                -> PdfPageOperation {
+        PdfPageOperation::new(PdfPageAction::Rotate(turn.degrees()))
+    }
+
+    fn rotate_by_angle(rotation: AngleRotation) -> PdfPageOperation {
+        PdfPageOperation::new(PdfPageAction::Raster(PageOps::RotateByAngle(rotation).to_string()))
+    }
+
+    fn flip(dir: Direction)                     -> PdfPageOperation {
+        PdfPageOperation::new(PdfPageAction::Raster(PageOps::Flip(dir).to_string()))
+    }
+
+    fn remove_borders(removal: BorderRemoval)   -> PdfPageOperation {
+        PdfPageOperation::new(PdfPageAction::Raster(PageOps::RemoveBorders(removal).to_string()))
+    }
+
+    fn despeckle(filter: NoiseFilter)           -> PdfPageOperation {
+        PdfPageOperation::new(PdfPageAction::Raster(PageOps::Despeckle(filter).to_string()))
+    }
+
+    fn no_operation()                           -> PdfPageOperation {
+        PdfPageOperation::new(PdfPageAction::NoOperation)
+    }
+}
+
+impl RetargetOperation for PdfPageOperation {
+    fn retarget(op: PdfPageOperation, file_path: &FilePath) -> PdfPageOperation {
+        let mut new_op = op;
+        new_op.file_path = file_path.clone();
+
+        new_op
+    }
+}
+
+impl RunOperation for PdfPageOperation {
+    fn run_operation(op: PdfPageOperation) -> OperationResults {
+        let mut results = OperationResults::new();
+        results.push(op.run_operation());
+
+        results
+    }
+}
+
+impl fmt::Display for PdfPageOperation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} on {}", self.action, self.file_path)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use util::scratch::{scratch_dir, scratch_pdf};
+
+    fn run_on(page: &Page, op: PdfPageOperation) -> OperationResults {
+        <PdfPageOperation as RunOperation>::run_operation(PdfPageOperation::retarget(op, page.file_path()))
+    }
+
+    fn displayed_size_of(page: &Page) -> (f64, f64) {
+        let document = PdfDocument::open(page.file_path()).unwrap();
+        let pdf_page = &document.pages().unwrap()[0];
+
+        displayed_size(pdf_page, &visible_box(&document, pdf_page).unwrap())
+    }
+
+    #[test]
+    fn edges_are_those_of_the_page_as_displayed() {
+        assert_eq!(Edge::Left.unrotated(0), Edge::Left);
+        assert_eq!(Edge::Left.unrotated(90), Edge::Bottom);
+        assert_eq!(Edge::Top.unrotated(270), Edge::Right);
+        assert_eq!(Edge::Right.unrotated(-90), Edge::Bottom);
+    }
+
+    #[test]
+    fn box_changes_are_appended_without_touching_the_page_content() {
+        let dir = scratch_dir("pdf-boxes");
+        let page = scratch_pdf(&dir, "page.pdf");
+        let original = fs::read(page.file_path()).unwrap();
+
+        assert!(!run_on(&page, PdfPageOperation::expand_left_edge(12)).is_failed());
+        assert!(!run_on(&page, PdfPageOperation::trim_top_edge(3)).is_failed());
+        assert_eq!(displayed_size_of(&page), (20.0, 5.0));
+
+        assert!(!run_on(&page, PdfPageOperation::rotate(QuarterTurn::Rotate90)).is_failed());
+        assert_eq!(displayed_size_of(&page), (5.0, 20.0));
+        assert!(!run_on(&page, PdfPageOperation::rescale(10, Direction::Horizontal)).is_failed());
+        assert_eq!(displayed_size_of(&page), (10.0, 40.0));
+
+        assert!(fs::read(page.file_path()).unwrap().starts_with(&original));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn operations_that_would_remove_the_page_or_need_pixels_fail() {
+        let dir = scratch_dir("pdf-boxes-refused");
+        let page = scratch_pdf(&dir, "page.pdf");
+        let original = fs::read(page.file_path()).unwrap();
+
+        assert!(run_on(&page, PdfPageOperation::trim_right_edge(8)).is_failed());
+        assert!(run_on(&page, PdfPageOperation::flip(Direction::Horizontal)).is_failed());
+        assert_eq!(fs::read(page.file_path()).unwrap(), original);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,514 @@
+#![allow(dead_code)]
+use std::io;
+use pdf::objects::{ObjectId, PdfObject, PdfDictionary, PdfStream};
+
+
+pub fn malformed(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Malformed PDF: {}", message))
+}
+
+pub fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, b'\0' | b'\t' | b'\n' | b'\x0c' | b'\r' | b' ')
+}
+
+pub fn is_delimiter(byte: u8) -> bool {
+    matches!(byte, b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%')
+}
+
+fn is_regular(byte: u8) -> bool {
+    !is_whitespace(byte) && !is_delimiter(byte)
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _           => None,
+    }
+}
+
+
+/// Parses PDF objects out of a byte buffer, as described in section 7.3 of
+/// ISO 32000. The parser works on the whole file, starting wherever it is
+/// positioned, so that objects can be read from their cross reference offsets.
+pub struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(data: &'a [u8], pos: usize) -> Parser<'a> {
+        Parser {
+            data: data,
+            pos: pos,
+        }
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn set_position(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    pub fn at_end(&mut self) -> bool {
+        self.skip_whitespace();
+        self.pos >= self.data.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).cloned()
+    }
+
+    /// Skip whitespace and comments.
+    pub fn skip_whitespace(&mut self) {
+        while let Some(byte) = self.peek() {
+            if is_whitespace(byte) {
+                self.pos += 1;
+            } else if byte == b'%' {
+                while let Some(byte) = self.peek() {
+                    if byte == b'\r' || byte == b'\n' {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// The next run of regular characters, without consuming it.
+    fn peek_keyword(&mut self) -> &'a [u8] {
+        self.skip_whitespace();
+        let start = self.pos;
+        let mut end = start;
+        while end < self.data.len() && is_regular(self.data[end]) {
+            end += 1;
+        }
+
+        &self.data[start..end]
+    }
+
+    /// Consume a keyword such as `obj` or `trailer`, failing if the next
+    /// token is anything else.
+    pub fn expect_keyword(&mut self, keyword: &str) -> io::Result<()> {
+        if self.peek_keyword() == keyword.as_bytes() {
+            self.pos += keyword.len();
+            Ok(())
+        } else {
+            Err(malformed(&format!("expected '{}' at offset {}", keyword, self.pos)))
+        }
+    }
+
+    pub fn next_is_keyword(&mut self, keyword: &str) -> bool {
+        self.peek_keyword() == keyword.as_bytes()
+    }
+
+    /// An unsigned integer, as used in object headers and cross reference
+    /// tables.
+    pub fn parse_unsigned(&mut self) -> io::Result<u64> {
+        let token = self.peek_keyword();
+        if token.is_empty() || !token.iter().all(|b| b.is_ascii_digit()) {
+            return Err(malformed(&format!("expected an integer at offset {}", self.pos)));
+        }
+        let value = token.iter().fold(0u64, |acc, b| acc.wrapping_mul(10).wrapping_add((*b - b'0') as u64));
+        self.pos += token.len();
+
+        Ok(value)
+    }
+
+    /// Parse a direct object, or an indirect reference.
+    pub fn parse_object(&mut self) -> io::Result<PdfObject> {
+        self.skip_whitespace();
+        let byte = match self.peek() {
+            Some(byte) => byte,
+            None       => return Err(malformed("unexpected end of file")),
+        };
+
+        match byte {
+            b'/' => self.parse_name().map(PdfObject::Name),
+            b'(' => self.parse_literal_string().map(PdfObject::String),
+            b'[' => {
+                self.pos += 1;
+                let mut array = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b']') => {
+                            self.pos += 1;
+                            break;
+                        }
+                        Some(_) => array.push(self.parse_object()?),
+                        None    => return Err(malformed("unterminated array")),
+                    }
+                }
+                Ok(PdfObject::Array(array))
+            }
+            b'<' => {
+                if self.data.get(self.pos + 1) == Some(&b'<') {
+                    self.parse_dictionary().map(PdfObject::Dictionary)
+                } else {
+                    self.parse_hex_string().map(PdfObject::String)
+                }
+            }
+            b'+' | b'-' | b'.' | b'0'..=b'9' => self.parse_number_or_reference(),
+            _ => {
+                let token = self.peek_keyword();
+                let object = match token {
+                    b"true"  => PdfObject::Boolean(true),
+                    b"false" => PdfObject::Boolean(false),
+                    b"null"  => PdfObject::Null,
+                    _        => return Err(malformed(&format!("unexpected token at offset {}", self.pos))),
+                };
+                self.pos += token.len();
+                Ok(object)
+            }
+        }
+    }
+
+    pub fn parse_dictionary(&mut self) -> io::Result<PdfDictionary> {
+        self.skip_whitespace();
+        if !self.data[self.pos..].starts_with(b"<<") {
+            return Err(malformed(&format!("expected a dictionary at offset {}", self.pos)));
+        }
+        self.pos += 2;
+
+        let mut dict = PdfDictionary::new();
+        loop {
+            self.skip_whitespace();
+            if self.data[self.pos..].starts_with(b">>") {
+                self.pos += 2;
+                break;
+            }
+            if self.peek() != Some(b'/') {
+                return Err(malformed(&format!("expected a name at offset {}", self.pos)));
+            }
+            let key = self.parse_name()?;
+            let value = self.parse_object()?;
+            // A null value is the same as a missing entry.
+            if value != PdfObject::Null {
+                dict.set(&key, value);
+            }
+        }
+
+        Ok(dict)
+    }
+
+    fn parse_name(&mut self) -> io::Result<String> {
+        // Skip the solidus.
+        self.pos += 1;
+        let mut name = Vec::new();
+        while let Some(byte) = self.peek() {
+            if !is_regular(byte) {
+                break;
+            }
+            self.pos += 1;
+            if byte == b'#' {
+                let high = self.data.get(self.pos).and_then(|b| hex_value(*b));
+                let low = self.data.get(self.pos + 1).and_then(|b| hex_value(*b));
+                if let (Some(high), Some(low)) = (high, low) {
+                    name.push(high << 4 | low);
+                    self.pos += 2;
+                    continue;
+                }
+            }
+            name.push(byte);
+        }
+
+        Ok(String::from_utf8_lossy(&name).into_owned())
+    }
+
+    fn parse_literal_string(&mut self) -> io::Result<Vec<u8>> {
+        // Skip the opening parenthesis.
+        self.pos += 1;
+        let mut bytes = Vec::new();
+        let mut depth = 1;
+
+        loop {
+            let byte = match self.peek() {
+                Some(byte) => byte,
+                None       => return Err(malformed("unterminated string")),
+            };
+            self.pos += 1;
+
+            match byte {
+                b'(' => {
+                    depth += 1;
+                    bytes.push(byte);
+                }
+                b')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                    bytes.push(byte);
+                }
+                b'\r' => {
+                    // End of line markers in strings read as a single newline.
+                    if self.peek() == Some(b'\n') {
+                        self.pos += 1;
+                    }
+                    bytes.push(b'\n');
+                }
+                b'\\' => {
+                    let escaped = match self.peek() {
+                        Some(escaped) => escaped,
+                        None          => return Err(malformed("unterminated string")),
+                    };
+                    self.pos += 1;
+                    match escaped {
+                        b'n'  => bytes.push(b'\n'),
+                        b'r'  => bytes.push(b'\r'),
+                        b't'  => bytes.push(b'\t'),
+                        b'b'  => bytes.push(b'\x08'),
+                        b'f'  => bytes.push(b'\x0c'),
+                        b'0'..=b'7' => {
+                            let mut value = (escaped - b'0') as u32;
+                            for _ in 0..2 {
+                                match self.peek() {
+                                    Some(digit @ b'0'..=b'7') => {
+                                        value = value * 8 + (digit - b'0') as u32;
+                                        self.pos += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            bytes.push(value as u8);
+                        }
+                        // A backslash at the end of a line continues the string.
+                        b'\r' => {
+                            if self.peek() == Some(b'\n') {
+                                self.pos += 1;
+                            }
+                        }
+                        b'\n' => {}
+                        _ => bytes.push(escaped),
+                    }
+                }
+                _ => bytes.push(byte),
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    fn parse_hex_string(&mut self) -> io::Result<Vec<u8>> {
+        // Skip the opening angle bracket.
+        self.pos += 1;
+        let mut digits = Vec::new();
+
+        loop {
+            let byte = match self.peek() {
+                Some(byte) => byte,
+                None       => return Err(malformed("unterminated hex string")),
+            };
+            self.pos += 1;
+
+            if byte == b'>' {
+                break;
+            } else if let Some(value) = hex_value(byte) {
+                digits.push(value);
+            } else if !is_whitespace(byte) {
+                return Err(malformed(&format!("bad hex digit at offset {}", self.pos - 1)));
+            }
+        }
+
+        // A missing final digit is taken to be zero.
+        if digits.len() % 2 == 1 {
+            digits.push(0);
+        }
+
+        Ok(digits.chunks(2).map(|pair| pair[0] << 4 | pair[1]).collect())
+    }
+
+    fn parse_number(&mut self) -> io::Result<PdfObject> {
+        let token = self.peek_keyword();
+        let text = String::from_utf8_lossy(token).into_owned();
+        self.pos += token.len();
+
+        if let Ok(value) = text.parse::<i64>() {
+            return Ok(PdfObject::Integer(value));
+        }
+        match text.parse::<f64>() {
+            Ok(value) => Ok(PdfObject::Real(value)),
+            // Some writers produce numbers like "--5" or "0.0.1"; read what
+            // we can rather than giving up on the file.
+            Err(_) => {
+                let cleaned: String = text.chars().skip_while(|c| *c == '-' || *c == '+').collect();
+                let negative = text.starts_with('-');
+                let value = cleaned.split('.').take(2).collect::<Vec<_>>().join(".").parse::<f64>().unwrap_or(0.0);
+                Ok(PdfObject::Real(if negative { -value } else { value }))
+            }
+        }
+    }
+
+    /// Numbers and references both start with an integer, so look ahead
+    /// for a generation number and an `R`.
+    fn parse_number_or_reference(&mut self) -> io::Result<PdfObject> {
+        let number = self.parse_number()?;
+        let number = match number {
+            PdfObject::Integer(value) if value >= 0 => value,
+            _ => return Ok(number),
+        };
+
+        let saved = self.pos;
+        if let Ok(generation) = self.parse_unsigned() {
+            if self.next_is_keyword("R") {
+                self.pos += 1;
+                return Ok(PdfObject::Reference(ObjectId::new(number as u32, generation as u16)));
+            }
+        }
+        self.pos = saved;
+
+        Ok(PdfObject::Integer(number))
+    }
+
+    /// Parse the header of an indirect object, `n g obj`.
+    pub fn parse_object_header(&mut self) -> io::Result<ObjectId> {
+        let number = self.parse_unsigned()?;
+        let generation = self.parse_unsigned()?;
+        self.expect_keyword("obj")?;
+
+        Ok(ObjectId::new(number as u32, generation as u16))
+    }
+
+    /// Parse an indirect object, `n g obj ... endobj`. Stream lengths may
+    /// themselves be indirect, so the caller supplies a way of resolving
+    /// them; when the length cannot be resolved or is wrong, the end of the
+    /// stream is found by searching for the `endstream` keyword.
+    pub fn parse_indirect_object(&mut self, resolve_length: &dyn Fn(&PdfObject) -> Option<usize>)
+        -> io::Result<(ObjectId, PdfObject)>
+    {
+        let id = self.parse_object_header()?;
+        let object = self.parse_object()?;
+
+        let object = match object {
+            PdfObject::Dictionary(dict) => {
+                if self.next_is_keyword("stream") {
+                    self.pos += "stream".len();
+                    let data = self.parse_stream_data(&dict, resolve_length)?;
+                    PdfObject::Stream(PdfStream::new(dict, data))
+                } else {
+                    PdfObject::Dictionary(dict)
+                }
+            }
+            other => other,
+        };
+
+        // Some writers leave out endobj; it carries no information.
+        if self.next_is_keyword("endobj") {
+            self.pos += "endobj".len();
+        }
+
+        Ok((id, object))
+    }
+
+    fn parse_stream_data(&mut self, dict: &PdfDictionary,
+                         resolve_length: &dyn Fn(&PdfObject) -> Option<usize>) -> io::Result<Vec<u8>>
+    {
+        // The keyword is followed by CRLF or LF, though a lone CR turns up too.
+        if self.peek() == Some(b'\r') {
+            self.pos += 1;
+        }
+        if self.peek() == Some(b'\n') {
+            self.pos += 1;
+        }
+        let start = self.pos;
+
+        let length = dict.get("Length").and_then(resolve_length);
+        if let Some(length) = length {
+            if start + length <= self.data.len() {
+                let mut end_parser = Parser::new(self.data, start + length);
+                if end_parser.next_is_keyword("endstream") {
+                    self.pos = end_parser.pos + "endstream".len();
+                    return Ok(self.data[start..start + length].to_vec());
+                }
+            }
+        }
+
+        let end = match find(&self.data[start..], b"endstream") {
+            Some(offset) => start + offset,
+            None         => return Err(malformed(&format!("unterminated stream at offset {}", start))),
+        };
+        // The end of line marker before endstream is not part of the data.
+        let mut data_end = end;
+        if data_end > start && self.data[data_end - 1] == b'\n' {
+            data_end -= 1;
+        }
+        if data_end > start && self.data[data_end - 1] == b'\r' {
+            data_end -= 1;
+        }
+        self.pos = end + "endstream".len();
+
+        Ok(self.data[start..data_end].to_vec())
+    }
+}
+
+/// The offset of the first occurrence of a byte string.
+pub fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.len() > haystack.len() {
+        return None;
+    }
+    (0..haystack.len() - needle.len() + 1).find(|&i| &haystack[i..i + needle.len()] == needle)
+}
+
+/// The offset of the last occurrence of a byte string.
+pub fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.len() > haystack.len() {
+        return None;
+    }
+    (0..haystack.len() - needle.len() + 1).rev().find(|&i| &haystack[i..i + needle.len()] == needle)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_lengths(_: &PdfObject) -> Option<usize> {
+        None
+    }
+
+    #[test]
+    fn indirect_objects_parse() {
+        let data = b"7 0 obj\n<< /Type /Page /Parent 2 0 R /Kids [1 0 R 3.5 true null] >>\nendobj\n";
+        let (id, object) = Parser::new(data, 0).parse_indirect_object(&no_lengths).unwrap();
+        let dict = object.as_dictionary().unwrap();
+
+        assert_eq!(id, ObjectId::new(7, 0));
+        assert_eq!(dict.get("Type").and_then(|o| o.as_name()), Some("Page"));
+        assert_eq!(dict.get("Parent").and_then(|o| o.as_reference()), Some(ObjectId::new(2, 0)));
+        assert_eq!(dict.get("Kids").and_then(|o| o.as_array()).unwrap(),
+                   &[PdfObject::Reference(ObjectId::new(1, 0)), PdfObject::Real(3.5),
+                     PdfObject::Boolean(true), PdfObject::Null]);
+    }
+
+    #[test]
+    fn stream_data_is_read_by_length() {
+        let data = b"4 1 obj\n<< /Length 5 >>\nstream\r\nhello\r\nendstream\nendobj";
+        let resolve = |length: &PdfObject| length.as_integer().map(|length| length as usize);
+        let (id, object) = Parser::new(data, 0).parse_indirect_object(&resolve).unwrap();
+
+        assert_eq!(id, ObjectId::new(4, 1));
+        assert_eq!(object.as_stream().unwrap().data(), b"hello");
+    }
+
+    #[test]
+    fn stream_data_falls_back_to_endstream_when_the_length_is_wrong() {
+        let data = b"4 0 obj\n<< /Length 99 >>\nstream\nhello world\nendstream\nendobj";
+        let resolve = |length: &PdfObject| length.as_integer().map(|length| length as usize);
+        let (_, object) = Parser::new(data, 0).parse_indirect_object(&resolve).unwrap();
+
+        assert_eq!(object.as_stream().unwrap().data(), b"hello world");
+    }
+
+    #[test]
+    fn unterminated_streams_are_rejected() {
+        let data = b"4 0 obj\n<< >>\nstream\nhello";
+
+        assert!(Parser::new(data, 0).parse_indirect_object(&no_lengths).is_err());
+    }
+}
@@ -0,0 +1,571 @@
+#![allow(dead_code)]
+use std::io;
+use std::io::Read;
+use std::fs::File;
+use std::fmt;
+use std::path::Path;
+use pdf::objects::{ObjectId, PdfObject, PdfDictionary, PdfStream};
+use pdf::writer::PdfWriter;
+use pdf::reader::PdfDocument;
+use pdf::filters;
+use pdf::flate;
+use pdf::icc;
+use pdf::icc::IccColorSpace;
+
+
+/// The ICC profile embedded as the output intent of a PDF/A file. Every
+/// device dependent colour in the file is interpreted through it, so it has
+/// to match the images: gray images may use either, colour images need sRGB.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum OutputIntentProfile {
+    SRgb,
+    Gray,
+}
+
+impl OutputIntentProfile {
+    pub fn identifier(&self) -> &'static str {
+        match *self {
+            OutputIntentProfile::SRgb => "sRGB IEC61966-2.1",
+            OutputIntentProfile::Gray => "sGray",
+        }
+    }
+
+    pub fn profile(&self) -> Vec<u8> {
+        match *self {
+            OutputIntentProfile::SRgb => icc::srgb_profile(),
+            OutputIntentProfile::Gray => icc::gray_profile(),
+        }
+    }
+
+    pub fn color_space(&self) -> IccColorSpace {
+        match *self {
+            OutputIntentProfile::SRgb => IccColorSpace::Rgb,
+            OutputIntentProfile::Gray => IccColorSpace::Gray,
+        }
+    }
+}
+
+impl fmt::Display for OutputIntentProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.identifier())
+    }
+}
+
+/// The XMP properties identifying a file as PDF/A-2b.
+pub fn pdfa_identification() -> Vec<String> {
+    vec![
+        String::from("<pdfaid:part>2</pdfaid:part>"),
+        String::from("<pdfaid:conformance>B</pdfaid:conformance>"),
+    ]
+}
+
+/// Add a PDF/A output intent with its embedded profile, returning the
+/// output intent dictionary for the catalog's OutputIntents array.
+pub fn add_output_intent(writer: &mut PdfWriter, profile: OutputIntentProfile) -> ObjectId {
+    let mut stream_dict = PdfDictionary::new();
+    stream_dict.set("N", PdfObject::Integer(profile.color_space().components() as i64));
+    stream_dict.set("Filter", PdfObject::name("FlateDecode"));
+    let profile_id = writer.add(PdfObject::Stream(PdfStream::new(stream_dict, flate::compress(&profile.profile()))));
+
+    let mut intent = PdfDictionary::new();
+    intent.set("Type", PdfObject::name("OutputIntent"));
+    intent.set("S", PdfObject::name("GTS_PDFA1"));
+    intent.set("OutputConditionIdentifier", PdfObject::text(profile.identifier()));
+    intent.set("Info", PdfObject::text(profile.identifier()));
+    intent.set("DestOutputProfile", PdfObject::Reference(profile_id));
+
+    writer.add(PdfObject::Dictionary(intent))
+}
+
+
+/// The requirements of ISO 19005-2 that the conformance checker tests.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum PdfARule {
+    /// 6.1.2: the header and the binary comment after it.
+    FileHeader,
+    /// 6.1.3: the trailer's ID, and nothing after the end of file marker.
+    FileTrailer,
+    /// 6.1.3: encryption is not allowed.
+    Encryption,
+    /// 6.1.7: stream data must be in the file, and not LZW or Crypt encoded.
+    StreamFilter,
+    /// 6.2.2: an output intent with a valid embedded profile.
+    OutputIntent,
+    /// 6.2.4.3: device colour spaces need a matching output intent.
+    DeviceColorSpace,
+    /// 6.2.8: images may not request interpolation, alternates or OPI.
+    ImageAttributes,
+    /// 6.5.1: actions that launch or run things are not allowed.
+    ForbiddenAction,
+    /// 6.6.2.1 and 6.6.4: an unfiltered XMP stream identifying the file.
+    Metadata,
+    /// 6.6.2.3: the Info dictionary must agree with the XMP metadata.
+    InfoConsistency,
+}
+
+impl fmt::Display for PdfARule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PdfARule::FileHeader       => write!(f, "file header (6.1.2)"),
+            PdfARule::FileTrailer      => write!(f, "file trailer (6.1.3)"),
+            PdfARule::Encryption       => write!(f, "encryption (6.1.3)"),
+            PdfARule::StreamFilter     => write!(f, "stream filters (6.1.7)"),
+            PdfARule::OutputIntent     => write!(f, "output intent (6.2.2)"),
+            PdfARule::DeviceColorSpace => write!(f, "device colour spaces (6.2.4.3)"),
+            PdfARule::ImageAttributes  => write!(f, "images (6.2.8)"),
+            PdfARule::ForbiddenAction  => write!(f, "actions (6.5.1)"),
+            PdfARule::Metadata         => write!(f, "metadata (6.6.2.1)"),
+            PdfARule::InfoConsistency  => write!(f, "document information (6.6.2.3)"),
+        }
+    }
+}
+
+/// A way in which a file fails to conform to PDF/A-2b.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PdfAViolation {
+    rule: PdfARule,
+    object: Option<ObjectId>,
+    description: String,
+}
+
+impl PdfAViolation {
+    pub fn new(rule: PdfARule, object: Option<ObjectId>, description: &str) -> PdfAViolation {
+        PdfAViolation {
+            rule: rule,
+            object: object,
+            description: String::from(description),
+        }
+    }
+
+    pub fn rule(&self) -> PdfARule {
+        self.rule
+    }
+
+    /// The object at fault, when the violation is in a particular object.
+    pub fn object(&self) -> Option<ObjectId> {
+        self.object
+    }
+
+    pub fn description(&self) -> &str {
+        self.description.as_ref()
+    }
+}
+
+impl fmt::Display for PdfAViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.rule)?;
+        if let Some(id) = self.object {
+            write!(f, "object {}: ", id)?;
+        }
+        write!(f, "{}", self.description)
+    }
+}
+
+
+/// Checks a file against the parts of PDF/A-2b that apply to documents of
+/// page images. Fonts, annotations' appearances and transparency groups are
+/// not examined.
+struct Checker<'a> {
+    document: &'a PdfDocument,
+    violations: Vec<PdfAViolation>,
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, rule: PdfARule, object: Option<ObjectId>, description: &str) {
+        self.violations.push(PdfAViolation::new(rule, object, description));
+    }
+
+    fn check_header(&mut self) {
+        let data = self.document.data();
+        let header_ok = data.starts_with(b"%PDF-1.") && data.len() > 8 && (b'0'..=b'7').contains(&data[7])
+            && (data[8] == b'\n' || data[8] == b'\r');
+        if !header_ok {
+            self.report(PdfARule::FileHeader, None, "the file does not start with a %PDF-1.0 to %PDF-1.7 header line");
+            return;
+        }
+
+        let mut comment = 9;
+        if data[8] == b'\r' && data.get(9) == Some(&b'\n') {
+            comment = 10;
+        }
+        let binary = data.get(comment) == Some(&b'%')
+            && data[comment + 1..].iter().take(4).filter(|&&byte| byte > 127).count() == 4;
+        if !binary {
+            self.report(PdfARule::FileHeader, None, "the header is not followed by a comment of four binary characters");
+        }
+    }
+
+    fn check_trailer(&mut self) {
+        let trailer = self.document.trailer().clone();
+        if trailer.contains_key("Encrypt") {
+            self.report(PdfARule::Encryption, None, "the trailer has an Encrypt entry");
+        }
+
+        let id_ok = match trailer.get("ID") {
+            Some(PdfObject::Array(parts)) => parts.len() == 2 && parts.iter().all(|part| part.as_string().is_some()),
+            _ => false,
+        };
+        if !id_ok {
+            self.report(PdfARule::FileTrailer, None, "the trailer has no ID of two strings");
+        }
+
+        let data = self.document.data();
+        let end = data.len() - data.iter().rev().take_while(|&&byte| byte == b'\r' || byte == b'\n').count();
+        if !data[..end].ends_with(b"%%EOF") {
+            self.report(PdfARule::FileTrailer, None, "there is data after the last end of file marker");
+        }
+    }
+
+    fn check_stream(&mut self, id: ObjectId, stream: &PdfStream) -> io::Result<()> {
+        let dict = stream.dict();
+        for key in &["F", "FFilter", "FDecodeParms"] {
+            if dict.contains_key(key) {
+                self.report(PdfARule::StreamFilter, Some(id), &format!("the stream refers to an external file with {}", key));
+            }
+        }
+
+        let resolved = self.document.resolve_filter_entries(dict)?;
+        for (name, _) in filters::stream_filters(&resolved) {
+            if name == "LZWDecode" || name == "LZW" {
+                self.report(PdfARule::StreamFilter, Some(id), "the stream is LZW encoded");
+            } else if name == "Crypt" {
+                self.report(PdfARule::StreamFilter, Some(id), "the stream uses a Crypt filter");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The device colour space a colour space object depends on, if any.
+    fn device_color_space(&self, color_space: &PdfObject) -> io::Result<Option<IccColorSpace>> {
+        match self.document.resolve(color_space)? {
+            PdfObject::Name(ref name) => Ok(match name.as_ref() {
+                "DeviceGray" | "G"   => Some(IccColorSpace::Gray),
+                "DeviceRGB" | "RGB"  => Some(IccColorSpace::Rgb),
+                "DeviceCMYK" | "CMYK" => Some(IccColorSpace::Cmyk),
+                _                     => None,
+            }),
+            PdfObject::Array(ref parts) => {
+                let family = parts.first().and_then(|family| family.as_name()).unwrap_or("");
+                match family {
+                    "Indexed" | "I" if parts.len() > 1 => self.device_color_space(&parts[1]),
+                    // Separation and DeviceN alternates are only used to
+                    // approximate their colorants, which is permitted.
+                    _ => Ok(None),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn check_image(&mut self, id: ObjectId, dict: &PdfDictionary, intent: Option<IccColorSpace>) -> io::Result<()> {
+        if let Some(PdfObject::Boolean(true)) = self.document.resolve_key(dict, "Interpolate")? {
+            self.report(PdfARule::ImageAttributes, Some(id), "the image has Interpolate set");
+        }
+        for key in &["Alternates", "OPI"] {
+            if dict.contains_key(key) {
+                self.report(PdfARule::ImageAttributes, Some(id), &format!("the image has an {} entry", key));
+            }
+        }
+
+        let is_mask = matches!(self.document.resolve_key(dict, "ImageMask")?, Some(PdfObject::Boolean(true)));
+        if is_mask {
+            return Ok(());
+        }
+
+        if let Some(color_space) = dict.get("ColorSpace") {
+            if let Some(device) = self.device_color_space(color_space)? {
+                let allowed = match (device, intent) {
+                    (_, None)                                    => false,
+                    (IccColorSpace::Gray, Some(_))               => true,
+                    (device, Some(intent))                       => device == intent,
+                };
+                if !allowed {
+                    let description = match intent {
+                        Some(intent) => format!("the image uses a device {} colour space, but the output intent is {}",
+                                                device, intent),
+                        None => format!("the image uses a device {} colour space without an output intent", device),
+                    };
+                    self.report(PdfARule::DeviceColorSpace, Some(id), &description);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_action(&mut self, id: ObjectId, dict: &PdfDictionary) {
+        let action = match dict.get("S").and_then(|action| action.as_name()) {
+            Some(action) => action,
+            None         => return,
+        };
+        if dict.get("Type").and_then(|t| t.as_name()).is_some_and(|t| t != "Action") {
+            return;
+        }
+        match action {
+            "Launch" | "Sound" | "Movie" | "ResetForm" | "ImportData" | "JavaScript" | "Hide"
+            | "SetOCGState" | "Rendition" | "Trans" | "GoTo3DView" => {
+                self.report(PdfARule::ForbiddenAction, Some(id), &format!("the file contains a {} action", action));
+            }
+            _ => {}
+        }
+    }
+
+    /// The colour space of the output intent, reporting anything wrong with it.
+    fn check_output_intent(&mut self, catalog: &PdfDictionary) -> io::Result<Option<IccColorSpace>> {
+        let intents = match self.document.resolve_key(catalog, "OutputIntents")? {
+            Some(PdfObject::Array(intents)) => intents,
+            _ => {
+                self.report(PdfARule::OutputIntent, None, "the catalog has no OutputIntents");
+                return Ok(None);
+            }
+        };
+
+        let mut color_space = None;
+        for intent in intents.iter() {
+            let intent_id = intent.as_reference();
+            let intent = match self.document.resolve(intent)? {
+                PdfObject::Dictionary(intent) => intent,
+                _ => continue,
+            };
+            if intent.get("S").and_then(|s| s.as_name()) != Some("GTS_PDFA1") {
+                continue;
+            }
+
+            let profile = match self.document.resolve_key(&intent, "DestOutputProfile")? {
+                Some(PdfObject::Stream(profile)) => profile,
+                _ => {
+                    self.report(PdfARule::OutputIntent, intent_id, "the PDF/A output intent has no embedded profile");
+                    continue;
+                }
+            };
+            let data = self.document.decode_stream(&profile)?;
+            match icc::profile_color_space(&data) {
+                Some(space) => {
+                    let n = self.document.resolve_key(profile.dict(), "N")?.and_then(|n| n.as_integer());
+                    if n != Some(space.components() as i64) {
+                        self.report(PdfARule::OutputIntent, intent_id,
+                                    "the profile's N entry does not match its colour space");
+                    }
+                    color_space = Some(space);
+                }
+                None => {
+                    self.report(PdfARule::OutputIntent, intent_id, "the embedded output profile is not an ICC profile");
+                }
+            }
+        }
+
+        if color_space.is_none() && !self.violations.iter().any(|v| v.rule() == PdfARule::OutputIntent) {
+            self.report(PdfARule::OutputIntent, None, "there is no GTS_PDFA1 output intent");
+        }
+
+        Ok(color_space)
+    }
+
+    /// The XMP metadata of the document, reporting anything wrong with it.
+    fn check_metadata(&mut self, catalog: &PdfDictionary) -> io::Result<Option<String>> {
+        let metadata_id = catalog.get("Metadata").and_then(|metadata| metadata.as_reference());
+        let stream = match self.document.resolve_key(catalog, "Metadata")? {
+            Some(PdfObject::Stream(stream)) => stream,
+            _ => {
+                self.report(PdfARule::Metadata, None, "the catalog has no metadata stream");
+                return Ok(None);
+            }
+        };
+
+        if stream.dict().contains_key("Filter") {
+            self.report(PdfARule::Metadata, metadata_id, "the metadata stream is filtered");
+        }
+        let packet = String::from_utf8_lossy(&self.document.decode_stream(&stream)?).into_owned();
+        let part = xmp_property(&packet, "pdfaid:part");
+        let conformance = xmp_property(&packet, "pdfaid:conformance");
+        if part.as_deref() != Some("2") {
+            self.report(PdfARule::Metadata, metadata_id, "the metadata does not give pdfaid:part 2");
+        }
+        match conformance.as_deref() {
+            Some("A") | Some("B") | Some("U") => {}
+            _ => self.report(PdfARule::Metadata, metadata_id, "the metadata does not give a pdfaid:conformance level"),
+        }
+
+        Ok(Some(packet))
+    }
+
+    fn check_info(&mut self, packet: &str) -> io::Result<()> {
+        let info_id = self.document.trailer().get("Info").and_then(|info| info.as_reference());
+        let info = match self.document.resolve_key(self.document.trailer(), "Info")? {
+            Some(PdfObject::Dictionary(info)) => info,
+            _ => return Ok(()),
+        };
+
+        let properties = [
+            ("Title", "dc:title"), ("Author", "dc:creator"), ("Subject", "dc:description"),
+            ("Keywords", "pdf:Keywords"), ("Creator", "xmp:CreatorTool"), ("Producer", "pdf:Producer"),
+        ];
+        for &(key, property) in properties.iter() {
+            if let Some(value) = self.document.resolve_key(&info, key)?.and_then(|value| value.as_text()) {
+                if xmp_property(packet, property).as_ref() != Some(&value) {
+                    self.report(PdfARule::InfoConsistency, info_id,
+                                &format!("the Info {} does not match the metadata's {}", key, property));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check(&mut self) -> io::Result<()> {
+        self.check_header();
+        self.check_trailer();
+
+        let catalog = self.document.catalog()?;
+        let intent = self.check_output_intent(&catalog)?;
+        if let Some(packet) = self.check_metadata(&catalog)? {
+            self.check_info(&packet)?;
+        }
+
+        for number in self.document.object_ids() {
+            let id = ObjectId::new(number, 0);
+            match self.document.get(id)? {
+                PdfObject::Stream(stream) => {
+                    self.check_stream(id, &stream)?;
+                    if stream.dict().get("Subtype").and_then(|s| s.as_name()) == Some("Image") {
+                        self.check_image(id, stream.dict(), intent)?;
+                    }
+                }
+                PdfObject::Dictionary(dict) => self.check_action(id, &dict),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The text of a simple XMP property, or of the first item of an array
+/// property, with XML entities decoded.
+fn xmp_property(packet: &str, property: &str) -> Option<String> {
+    let open = format!("<{}>", property);
+    let close = format!("</{}>", property);
+    let start = packet.find(&open)? + open.len();
+    let end = start + packet[start..].find(&close)?;
+    let mut value = &packet[start..end];
+
+    if let Some(item) = value.find("<rdf:li") {
+        let item_start = item + value[item..].find('>')? + 1;
+        let item_end = item_start + value[item_start..].find("</rdf:li>")?;
+        value = &value[item_start..item_end];
+    }
+
+    Some(value.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"")
+              .replace("&apos;", "'").replace("&amp;", "&"))
+}
+
+/// Check a parsed document against PDF/A-2b, returning every violation found.
+pub fn check_document(document: &PdfDocument) -> io::Result<Vec<PdfAViolation>> {
+    let mut checker = Checker {
+        document: document,
+        violations: Vec::new(),
+    };
+    checker.check()?;
+
+    Ok(checker.violations)
+}
+
+pub fn check_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<PdfAViolation>> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+
+    // Encrypted files are reported rather than refused.
+    check_document(&PdfDocument::parse_structure(data)?)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use image_tools::document::DocumentMetadata;
+    use pdf::assemble::PdfAssembly;
+    use util::scratch::{scratch_dir, scratch_tiff};
+
+    fn built(name: &str, pdfa: Option<OutputIntentProfile>) -> (PdfWriter, PdfDictionary) {
+        let dir = scratch_dir(name);
+        let mut assembly = PdfAssembly::new();
+        assembly.add_page(scratch_tiff(&dir, "page.tif"));
+        let mut metadata = DocumentMetadata::new();
+        metadata.set_title("Ledger");
+        assembly.set_metadata(metadata);
+        assembly.set_pdfa(pdfa);
+        let built = assembly.build().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        built
+    }
+
+    fn rules(violations: &[PdfAViolation]) -> Vec<PdfARule> {
+        violations.iter().map(|violation| violation.rule()).collect()
+    }
+
+    fn written(writer: &PdfWriter, trailer: &PdfDictionary) -> PdfDocument {
+        let mut out = Vec::new();
+        writer.write(&mut out, trailer).unwrap();
+
+        PdfDocument::parse_structure(out).unwrap()
+    }
+
+    #[test]
+    fn assembled_pdfa_documents_conform() {
+        let dir = scratch_dir("pdfa-conforming");
+        let mut assembly = PdfAssembly::new();
+        assembly.add_page(scratch_tiff(&dir, "page.tif"));
+        assembly.set_pdfa(Some(OutputIntentProfile::Gray));
+
+        let violations = assembly.write_pdfa_to_file(dir.join("page.pdf")).unwrap();
+        assert!(violations.is_empty(), "{:?}", violations);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn documents_without_an_output_intent_or_identification_are_flagged() {
+        let dir = scratch_dir("pdfa-plain");
+        let mut assembly = PdfAssembly::new();
+        assembly.add_page(scratch_tiff(&dir, "page.tif"));
+        let (writer, trailer) = assembly.build().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let found = rules(&check_document(&written(&writer, &trailer)).unwrap());
+        assert!(found.contains(&PdfARule::OutputIntent));
+        assert!(found.contains(&PdfARule::Metadata));
+        assert!(found.contains(&PdfARule::DeviceColorSpace));
+    }
+
+    #[test]
+    fn lzw_streams_and_encryption_are_flagged() {
+        let (mut writer, mut trailer) = built("pdfa-lzw", Some(OutputIntentProfile::Gray));
+        let mut dict = PdfDictionary::new();
+        dict.set("Filter", PdfObject::name("LZWDecode"));
+        let lzw_data = vec![0x80, 0x0b, 0x60, 0x50, 0x22, 0x0c, 0x0c, 0x85, 0x01];
+        let lzw_id = writer.add(PdfObject::Stream(PdfStream::new(dict, lzw_data)));
+        let mut encrypt = PdfDictionary::new();
+        encrypt.set("Filter", PdfObject::name("Standard"));
+        trailer.set("Encrypt", PdfObject::Reference(writer.add(PdfObject::Dictionary(encrypt))));
+
+        let violations = check_document(&written(&writer, &trailer)).unwrap();
+        assert!(violations.iter()
+                          .any(|violation| violation.rule() == PdfARule::StreamFilter
+                                           && violation.object() == Some(lzw_id)));
+        assert!(rules(&violations).contains(&PdfARule::Encryption));
+    }
+
+    #[test]
+    fn info_entries_must_match_the_metadata() {
+        let (mut writer, trailer) = built("pdfa-info", Some(OutputIntentProfile::Gray));
+        let info_id = trailer.get("Info").and_then(|info| info.as_reference()).unwrap();
+        let mut info = PdfDictionary::new();
+        info.set("Title", PdfObject::text("Journal"));
+        writer.set(info_id, PdfObject::Dictionary(info)).unwrap();
+
+        assert_eq!(rules(&check_document(&written(&writer, &trailer)).unwrap()), vec![PdfARule::InfoConsistency]);
+    }
+}
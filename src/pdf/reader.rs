@@ -0,0 +1,753 @@
+#![allow(dead_code)]
+use std::io;
+use std::io::Read;
+use std::fs::File;
+use std::path::Path;
+use std::fmt;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use pdf::objects::{ObjectId, PdfObject, PdfDictionary, PdfStream};
+use pdf::parser::{Parser, malformed, find, rfind};
+use pdf::filters;
+use pdf::metadata;
+use image_tools::document::PageLabelRange;
+
+
+/// Where an object is stored, according to the cross reference data.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum XrefEntry {
+    Free,
+    InFile(usize),
+    InStream(u32, usize),
+}
+
+
+/// A page of a PDF document, with the attributes it inherits from the
+/// page tree filled in.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PdfPage {
+    id: ObjectId,
+    dict: PdfDictionary,
+    media_box: [f64; 4],
+    rotate: i64,
+}
+
+impl PdfPage {
+    pub fn id(&self) -> ObjectId {
+        self.id
+    }
+
+    pub fn dict(&self) -> &PdfDictionary {
+        &self.dict
+    }
+
+    /// The page's MediaBox: lower left x, lower left y, upper right x, upper right y.
+    pub fn media_box(&self) -> [f64; 4] {
+        self.media_box
+    }
+
+    /// The width and height of the MediaBox in points.
+    pub fn size(&self) -> (f64, f64) {
+        ((self.media_box[2] - self.media_box[0]).abs(), (self.media_box[3] - self.media_box[1]).abs())
+    }
+
+    /// The clockwise rotation applied when the page is displayed, in degrees.
+    pub fn rotate(&self) -> i64 {
+        self.rotate
+    }
+}
+
+impl fmt::Display for PdfPage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (width, height) = self.size();
+        write!(f, "Page {} ({}x{} pt)", self.id, width, height)
+    }
+}
+
+
+/// A PDF file opened for reading. Objects are parsed on demand from the
+/// offsets in the cross reference data; when that data is missing or
+/// damaged, the file is scanned for object headers instead.
+pub struct PdfDocument {
+    data: Vec<u8>,
+    version: String,
+    xref: HashMap<u32, XrefEntry>,
+    xref_offset: Option<usize>,
+    xref_is_stream: bool,
+    trailer: PdfDictionary,
+    object_streams: RefCell<HashMap<u32, Vec<(u32, PdfObject)>>>,
+}
+
+impl PdfDocument {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<PdfDocument> {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+
+        PdfDocument::parse(data)
+    }
+
+    pub fn parse(data: Vec<u8>) -> io::Result<PdfDocument> {
+        let document = PdfDocument::parse_structure(data)?;
+        if document.trailer.contains_key("Encrypt") {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Encrypted PDF files are not supported"));
+        }
+
+        Ok(document)
+    }
+
+    /// Parse a file without refusing encryption, for looking at its structure.
+    /// The strings and streams of an encrypted file are left as they are stored.
+    pub fn parse_structure(data: Vec<u8>) -> io::Result<PdfDocument> {
+        let header = match find(&data[0..data.len().min(1024)], b"%PDF-") {
+            Some(header) => header,
+            None         => return Err(malformed("missing %PDF header")),
+        };
+        let version: String = data[header + 5..].iter()
+            .take_while(|b| b.is_ascii_digit() || **b == b'.')
+            .map(|b| *b as char)
+            .collect();
+
+        let mut document = PdfDocument {
+            data: data,
+            version: version,
+            xref: HashMap::new(),
+            xref_offset: None,
+            xref_is_stream: false,
+            trailer: PdfDictionary::new(),
+            object_streams: RefCell::new(HashMap::new()),
+        };
+
+        let loaded = document.load_xref();
+        if loaded.is_err() || !document.trailer.contains_key("Root") {
+            document.reconstruct_xref()?;
+        }
+
+        Ok(document)
+    }
+
+    pub fn version(&self) -> &str {
+        self.version.as_ref()
+    }
+
+    pub fn trailer(&self) -> &PdfDictionary {
+        &self.trailer
+    }
+
+    pub fn data(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    /// The offset of the last cross reference section, which an incremental
+    /// update points back to. There is none when the cross reference data
+    /// had to be rebuilt by scanning the file.
+    pub fn xref_offset(&self) -> Option<usize> {
+        self.xref_offset
+    }
+
+    /// Whether the last cross reference section is a stream rather than a table.
+    pub fn xref_is_stream(&self) -> bool {
+        self.xref_is_stream
+    }
+
+    pub fn xref_entry(&self, number: u32) -> Option<XrefEntry> {
+        self.xref.get(&number).cloned()
+    }
+
+    /// The numbers of every object in the cross reference data.
+    pub fn object_ids(&self) -> Vec<u32> {
+        let mut numbers: Vec<u32> = self.xref.iter()
+            .filter(|&(_, entry)| *entry != XrefEntry::Free)
+            .map(|(number, _)| *number)
+            .collect();
+        numbers.sort();
+
+        numbers
+    }
+
+    fn load_xref(&mut self) -> io::Result<()> {
+        let startxref = match rfind(&self.data, b"startxref") {
+            Some(startxref) => startxref,
+            None            => return Err(malformed("missing startxref")),
+        };
+        let mut parser = Parser::new(&self.data, startxref + "startxref".len());
+        let mut offset = Some(parser.parse_unsigned()? as usize);
+        let mut visited = HashSet::new();
+        let mut first = true;
+
+        while let Some(current) = offset {
+            if current >= self.data.len() || !visited.insert(current) {
+                break;
+            }
+            let section_trailer = self.load_xref_section(current)?;
+
+            // Hybrid files keep the entries for compressed objects in a
+            // cross reference stream named by the table's trailer.
+            if let Some(stream_offset) = section_trailer.get("XRefStm").and_then(|o| o.as_integer()) {
+                if visited.insert(stream_offset as usize) {
+                    self.load_xref_section(stream_offset as usize)?;
+                }
+            }
+
+            if first {
+                self.trailer = section_trailer.clone();
+                self.xref_offset = Some(current);
+                self.xref_is_stream = !Parser::new(&self.data, current).next_is_keyword("xref");
+                first = false;
+            }
+            offset = section_trailer.get("Prev").and_then(|prev| prev.as_integer()).map(|prev| prev as usize);
+        }
+
+        Ok(())
+    }
+
+    /// Load one cross reference section, either a table or a stream. Entries
+    /// already known come from a later update and take precedence.
+    fn load_xref_section(&mut self, offset: usize) -> io::Result<PdfDictionary> {
+        let mut parser = Parser::new(&self.data, offset);
+        let mut entries = Vec::new();
+
+        let trailer = if parser.next_is_keyword("xref") {
+            parser.expect_keyword("xref")?;
+            while !parser.next_is_keyword("trailer") {
+                let start = parser.parse_unsigned()? as u32;
+                let count = parser.parse_unsigned()? as u32;
+                for number in start..start + count {
+                    let position = parser.parse_unsigned()? as usize;
+                    let _generation = parser.parse_unsigned()?;
+                    let entry = if parser.next_is_keyword("n") {
+                        parser.expect_keyword("n")?;
+                        XrefEntry::InFile(position)
+                    } else {
+                        parser.expect_keyword("f")?;
+                        XrefEntry::Free
+                    };
+                    entries.push((number, entry));
+                }
+            }
+            parser.expect_keyword("trailer")?;
+            parser.parse_dictionary()?
+        } else {
+            let (_, object) = parser.parse_indirect_object(&|length| length.as_integer().map(|l| l as usize))?;
+            let stream = match object {
+                PdfObject::Stream(stream) => stream,
+                _ => return Err(malformed("cross reference offset points at neither a table nor a stream")),
+            };
+            entries = xref_stream_entries(&stream)?;
+            stream.dict().clone()
+        };
+
+        for (number, entry) in entries {
+            self.xref.entry(number).or_insert(entry);
+        }
+
+        Ok(trailer)
+    }
+
+    /// Rebuild the cross reference data by scanning the file for object
+    /// headers. Later definitions of an object replace earlier ones, as
+    /// they would with incremental updates.
+    fn reconstruct_xref(&mut self) -> io::Result<()> {
+        self.xref.clear();
+        self.xref_offset = None;
+        let mut trailer = PdfDictionary::new();
+
+        let mut pos = 0;
+        while let Some(found) = find(&self.data[pos..], b"obj") {
+            let obj = pos + found;
+            pos = obj + 3;
+            if let Some(start) = object_header_start(&self.data, obj) {
+                let mut parser = Parser::new(&self.data, start);
+                if let Ok(id) = parser.parse_object_header() {
+                    self.xref.insert(id.number(), XrefEntry::InFile(start));
+                }
+            }
+        }
+
+        let mut pos = 0;
+        while let Some(found) = find(&self.data[pos..], b"trailer") {
+            let mut parser = Parser::new(&self.data, pos + found + "trailer".len());
+            if let Ok(dict) = parser.parse_dictionary() {
+                for (key, value) in dict.iter() {
+                    trailer.set(key, value.clone());
+                }
+            }
+            pos += found + "trailer".len();
+        }
+
+        // Files using cross reference streams have no trailer keyword; the
+        // stream dictionaries hold the trailer entries, and the objects in
+        // object streams must be listed too.
+        for number in self.object_ids() {
+            let object = match self.get(ObjectId::new(number, 0)) {
+                Ok(object) => object,
+                Err(_)     => continue,
+            };
+            let dict = match object.as_dictionary() {
+                Some(dict) => dict.clone(),
+                None       => continue,
+            };
+            match dict.get("Type").and_then(|t| t.as_name()) {
+                Some("XRef") => {
+                    for (key, value) in dict.iter() {
+                        if key == "Root" || key == "Info" || key == "ID" || key == "Encrypt" {
+                            trailer.set(key, value.clone());
+                        }
+                    }
+                }
+                Some("ObjStm") => {
+                    if let Ok(objects) = self.object_stream(number) {
+                        for (index, &(contained, _)) in objects.iter().enumerate() {
+                            self.xref.entry(contained).or_insert(XrefEntry::InStream(number, index));
+                        }
+                    }
+                }
+                Some("Catalog") if !trailer.contains_key("Root") => {
+                    trailer.set("Root", PdfObject::Reference(ObjectId::new(number, 0)));
+                }
+                _ => {}
+            }
+        }
+
+        if !trailer.contains_key("Root") {
+            return Err(malformed("no document catalog found"));
+        }
+        self.trailer = trailer;
+
+        Ok(())
+    }
+
+    /// Fetch an indirect object. Objects missing from the file are null.
+    pub fn get(&self, id: ObjectId) -> io::Result<PdfObject> {
+        match self.xref.get(&id.number()) {
+            Some(&XrefEntry::InFile(offset)) => {
+                let mut parser = Parser::new(&self.data, offset);
+                let (found, object) = parser.parse_indirect_object(&|length| self.resolve_length(length))?;
+                if found.number() != id.number() {
+                    return Err(malformed(&format!("expected object {} at offset {}, found {}", id, offset, found)));
+                }
+                Ok(object)
+            }
+            Some(&XrefEntry::InStream(stream_number, index)) => {
+                let objects = self.object_stream(stream_number)?;
+                match objects.get(index) {
+                    Some(&(number, ref object)) if number == id.number() => Ok(object.clone()),
+                    _ => {
+                        // Fall back to searching the stream, in case the index is wrong.
+                        Ok(objects.iter()
+                            .find(|&&(number, _)| number == id.number())
+                            .map(|(_, object)| object.clone())
+                            .unwrap_or(PdfObject::Null))
+                    }
+                }
+            }
+            _ => Ok(PdfObject::Null),
+        }
+    }
+
+    fn resolve_length(&self, length: &PdfObject) -> Option<usize> {
+        match *length {
+            PdfObject::Integer(length) if length >= 0 => Some(length as usize),
+            PdfObject::Reference(id) => {
+                // A length stored in an object stream is fine; one stored in
+                // another stream in the file would be circular.
+                match self.xref.get(&id.number()) {
+                    Some(&XrefEntry::InFile(offset)) => {
+                        let mut parser = Parser::new(&self.data, offset);
+                        parser.parse_object_header().ok()?;
+                        parser.parse_object().ok()?.as_integer().map(|length| length as usize)
+                    }
+                    _ => self.get(id).ok()?.as_integer().map(|length| length as usize),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The objects in an object stream, decoded once and cached.
+    fn object_stream(&self, number: u32) -> io::Result<Vec<(u32, PdfObject)>> {
+        if let Some(objects) = self.object_streams.borrow().get(&number) {
+            return Ok(objects.clone());
+        }
+
+        let stream = match self.get(ObjectId::new(number, 0))? {
+            PdfObject::Stream(stream) => stream,
+            _ => return Err(malformed(&format!("object {} is not an object stream", number))),
+        };
+        let data = self.decode_stream(&stream)?;
+        let count = stream.dict().get("N").and_then(|n| n.as_integer()).unwrap_or(0) as usize;
+        let first = stream.dict().get("First").and_then(|f| f.as_integer()).unwrap_or(0) as usize;
+
+        let mut header = Parser::new(&data, 0);
+        let mut offsets = Vec::new();
+        for _ in 0..count {
+            let contained = header.parse_unsigned()? as u32;
+            let offset = header.parse_unsigned()? as usize;
+            offsets.push((contained, offset));
+        }
+
+        let mut objects = Vec::new();
+        for (contained, offset) in offsets {
+            let mut parser = Parser::new(&data, first + offset);
+            objects.push((contained, parser.parse_object()?));
+        }
+
+        self.object_streams.borrow_mut().insert(number, objects.clone());
+
+        Ok(objects)
+    }
+
+    /// Follow references until reaching a direct object.
+    pub fn resolve(&self, object: &PdfObject) -> io::Result<PdfObject> {
+        let mut object = object.clone();
+        let mut depth = 0;
+        while let PdfObject::Reference(id) = object {
+            depth += 1;
+            if depth > 32 {
+                return Err(malformed(&format!("reference loop at {}", id)));
+            }
+            object = self.get(id)?;
+        }
+
+        Ok(object)
+    }
+
+    /// Look up a key in a dictionary and resolve its value.
+    pub fn resolve_key(&self, dict: &PdfDictionary, key: &str) -> io::Result<Option<PdfObject>> {
+        match dict.get(key) {
+            Some(value) => {
+                let value = self.resolve(value)?;
+                if value == PdfObject::Null { Ok(None) } else { Ok(Some(value)) }
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Decode the data of a stream, undoing all its filters.
+    pub fn decode_stream(&self, stream: &PdfStream) -> io::Result<Vec<u8>> {
+        let dict = self.resolve_filter_entries(stream.dict())?;
+        filters::decode_stream_data(&dict, stream.data())
+    }
+
+    /// A copy of a stream dictionary with Filter and DecodeParms resolved
+    /// to direct objects.
+    pub fn resolve_filter_entries(&self, dict: &PdfDictionary) -> io::Result<PdfDictionary> {
+        let mut dict = dict.clone();
+        for key in &["Filter", "DecodeParms"] {
+            if let Some(value) = self.resolve_key(&dict, key)? {
+                let value = match value {
+                    PdfObject::Array(items) => {
+                        let mut resolved = Vec::new();
+                        for item in items.iter() {
+                            resolved.push(self.resolve(item)?);
+                        }
+                        PdfObject::Array(resolved)
+                    }
+                    value => value,
+                };
+                dict.set(key, value);
+            }
+        }
+
+        Ok(dict)
+    }
+
+    pub fn catalog(&self) -> io::Result<PdfDictionary> {
+        match self.resolve_key(&self.trailer, "Root")? {
+            Some(PdfObject::Dictionary(dict)) => Ok(dict),
+            _ => Err(malformed("missing document catalog")),
+        }
+    }
+
+    /// The pages of the document in order, found by walking the page tree.
+    pub fn pages(&self) -> io::Result<Vec<PdfPage>> {
+        let catalog = self.catalog()?;
+        let root = match catalog.get("Pages") {
+            Some(&PdfObject::Reference(id)) => id,
+            _ => return Err(malformed("missing page tree")),
+        };
+
+        let mut pages = Vec::new();
+        let mut visited = HashSet::new();
+        self.collect_pages(root, &PdfDictionary::new(), &mut visited, &mut pages)?;
+
+        Ok(pages)
+    }
+
+    fn collect_pages(&self, id: ObjectId, inherited: &PdfDictionary,
+                     visited: &mut HashSet<ObjectId>, pages: &mut Vec<PdfPage>) -> io::Result<()>
+    {
+        if !visited.insert(id) {
+            return Err(malformed(&format!("page tree loop at {}", id)));
+        }
+
+        let node = match self.get(id)? {
+            PdfObject::Dictionary(dict) => dict,
+            _ => return Err(malformed(&format!("page tree node {} is not a dictionary", id))),
+        };
+
+        // Attributes a page may inherit from its ancestors.
+        let mut attributes = inherited.clone();
+        for key in &["Resources", "MediaBox", "CropBox", "Rotate"] {
+            if let Some(value) = node.get(key) {
+                attributes.set(key, value.clone());
+            }
+        }
+
+        let kids = self.resolve_key(&node, "Kids")?;
+        let is_leaf = match node.get("Type").and_then(|t| t.as_name()) {
+            Some("Pages") => false,
+            Some("Page")  => true,
+            _             => kids.is_none(),
+        };
+
+        if is_leaf {
+            let mut dict = node.clone();
+            for (key, value) in attributes.iter() {
+                if !dict.contains_key(key) {
+                    dict.set(key, value.clone());
+                }
+            }
+            pages.push(self.make_page(id, dict)?);
+        } else if let Some(PdfObject::Array(kids)) = kids {
+            for kid in kids.iter() {
+                if let PdfObject::Reference(kid) = *kid {
+                    self.collect_pages(kid, &attributes, visited, pages)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The page label ranges of the document, read from the catalog's
+    /// `/PageLabels` number tree, in page order. A document without page
+    /// labels has none.
+    pub fn page_labels(&self) -> io::Result<Vec<PageLabelRange>> {
+        let catalog = self.catalog()?;
+        let mut ranges = Vec::new();
+        if let Some(PdfObject::Dictionary(root)) = self.resolve_key(&catalog, "PageLabels")? {
+            let mut visited = HashSet::new();
+            self.collect_page_labels(&root, &mut visited, &mut ranges)?;
+        }
+        ranges.sort_by_key(|range| range.first_page());
+
+        Ok(ranges)
+    }
+
+    fn collect_page_labels(&self, node: &PdfDictionary, visited: &mut HashSet<ObjectId>,
+                           ranges: &mut Vec<PageLabelRange>) -> io::Result<()>
+    {
+        if let Some(PdfObject::Array(nums)) = self.resolve_key(node, "Nums")? {
+            for pair in nums.chunks(2).filter(|pair| pair.len() == 2) {
+                let index = self.resolve(&pair[0])?.as_integer();
+                let label = self.resolve(&pair[1])?;
+                if let (Some(index), PdfObject::Dictionary(label)) = (index, label) {
+                    if index < 0 {
+                        continue;
+                    }
+                    let mut dict = PdfDictionary::new();
+                    for (key, value) in label.iter() {
+                        dict.set(key, self.resolve(value)?);
+                    }
+                    ranges.push(metadata::page_label_range(index as usize, &dict));
+                }
+            }
+        }
+
+        if let Some(PdfObject::Array(kids)) = self.resolve_key(node, "Kids")? {
+            for kid in kids.iter() {
+                if let PdfObject::Reference(id) = *kid {
+                    if !visited.insert(id) {
+                        return Err(malformed(&format!("page label tree loop at {}", id)));
+                    }
+                    if let PdfObject::Dictionary(kid) = self.get(id)? {
+                        self.collect_page_labels(&kid, visited, ranges)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn make_page(&self, id: ObjectId, dict: PdfDictionary) -> io::Result<PdfPage> {
+        // A page without a MediaBox is taken to be US Letter.
+        let mut media_box = [0.0, 0.0, 612.0, 792.0];
+        if let Some(PdfObject::Array(values)) = self.resolve_key(&dict, "MediaBox")? {
+            if values.len() == 4 {
+                for (i, value) in values.iter().enumerate() {
+                    media_box[i] = self.resolve(value)?.as_number().unwrap_or(media_box[i]);
+                }
+            }
+        }
+        let rotate = self.resolve_key(&dict, "Rotate")?.and_then(|r| r.as_integer()).unwrap_or(0);
+
+        Ok(PdfPage {
+            id: id,
+            dict: dict,
+            media_box: media_box,
+            rotate: ((rotate % 360) + 360) % 360,
+        })
+    }
+}
+
+impl fmt::Display for PdfDocument {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PDF-{} ({} objects)", self.version, self.object_ids().len())
+    }
+}
+
+/// Find where the `n g` of an object header starts, given the offset of its
+/// `obj` keyword, checking that it is preceded by two integers.
+fn object_header_start(data: &[u8], obj: usize) -> Option<usize> {
+    if data.get(obj + 3).is_some_and(|b| b.is_ascii_alphanumeric()) {
+        return None;
+    }
+
+    let mut pos = obj;
+    for _ in 0..2 {
+        let end = pos;
+        while pos > 0 && (data[pos - 1] == b' ' || data[pos - 1] == b'\t' || data[pos - 1] == b'\r' || data[pos - 1] == b'\n') {
+            pos -= 1;
+        }
+        if pos == end {
+            return None;
+        }
+        let digits_end = pos;
+        while pos > 0 && data[pos - 1].is_ascii_digit() {
+            pos -= 1;
+        }
+        if pos == digits_end {
+            return None;
+        }
+    }
+
+    Some(pos)
+}
+
+/// The entries of a cross reference stream, as described in section 7.5.8
+/// of ISO 32000.
+fn xref_stream_entries(stream: &PdfStream) -> io::Result<Vec<(u32, XrefEntry)>> {
+    let data = filters::decode_stream_data(stream.dict(), stream.data())?;
+    let widths: Vec<usize> = match stream.dict().get("W").and_then(|w| w.as_array()) {
+        Some(widths) if widths.len() == 3 => {
+            widths.iter().map(|w| w.as_integer().unwrap_or(0) as usize).collect()
+        }
+        _ => return Err(malformed("cross reference stream without field widths")),
+    };
+    let size = stream.dict().get("Size").and_then(|s| s.as_integer()).unwrap_or(0);
+    let index: Vec<i64> = match stream.dict().get("Index").and_then(|i| i.as_array()) {
+        Some(index) => index.iter().filter_map(|i| i.as_integer()).collect(),
+        None        => vec![0, size],
+    };
+
+    let entry_size: usize = widths.iter().sum();
+    if entry_size == 0 {
+        return Err(malformed("cross reference stream with empty entries"));
+    }
+    let mut records = data.chunks(entry_size);
+    let mut entries = Vec::new();
+
+    for subsection in index.chunks(2) {
+        if subsection.len() < 2 {
+            break;
+        }
+        for number in subsection[0]..subsection[0] + subsection[1] {
+            let record = match records.next() {
+                Some(record) if record.len() == entry_size => record,
+                _ => return Ok(entries),
+            };
+            let mut fields = [0u64; 3];
+            let mut pos = 0;
+            for (field, width) in widths.iter().enumerate() {
+                fields[field] = record[pos..pos + width].iter().fold(0u64, |acc, b| acc << 8 | *b as u64);
+                pos += width;
+            }
+            // The type defaults to 1 when its field is left out.
+            let entry_type = if widths[0] == 0 { 1 } else { fields[0] };
+            let entry = match entry_type {
+                0 => XrefEntry::Free,
+                1 => XrefEntry::InFile(fields[1] as usize),
+                2 => XrefEntry::InStream(fields[1] as u32, fields[2] as usize),
+                _ => continue,
+            };
+            entries.push((number as u32, entry));
+        }
+    }
+
+    Ok(entries)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A document of one empty page, with a cross reference table pointing
+    /// at its objects, or at the given offset instead of the table.
+    fn one_page_document(startxref: Option<usize>) -> (Vec<u8>, Vec<usize>) {
+        let objects = [
+            "<< /Type /Catalog /Pages 2 0 R >>",
+            "<< /Type /Pages /Count 1 /Kids [3 0 R] >>",
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Rotate 90 >>",
+        ];
+        let mut data = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(data.len());
+            data.extend(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).into_bytes());
+        }
+
+        let xref = data.len();
+        data.extend(format!("xref\n0 {}\n0000000000 65535 f\r\n", objects.len() + 1).into_bytes());
+        for offset in offsets.iter() {
+            data.extend(format!("{:010} 00000 n\r\n", offset).into_bytes());
+        }
+        data.extend(format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+                            objects.len() + 1, startxref.unwrap_or(xref)).into_bytes());
+
+        (data, offsets)
+    }
+
+    #[test]
+    fn cross_reference_tables_are_read() {
+        let (data, offsets) = one_page_document(None);
+        let document = PdfDocument::parse(data).unwrap();
+
+        assert_eq!(document.version(), "1.4");
+        assert!(document.xref_offset().is_some());
+        assert!(!document.xref_is_stream());
+        assert_eq!(document.xref_entry(0), Some(XrefEntry::Free));
+        for (i, &offset) in offsets.iter().enumerate() {
+            assert_eq!(document.xref_entry(i as u32 + 1), Some(XrefEntry::InFile(offset)));
+        }
+        assert_eq!(document.object_ids(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn indirect_objects_resolve_through_the_cross_reference_table() {
+        let (data, _) = one_page_document(None);
+        let document = PdfDocument::parse(data).unwrap();
+        let pages = document.pages().unwrap();
+
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].id(), ObjectId::new(3, 0));
+        assert_eq!(pages[0].size(), (612.0, 792.0));
+        assert_eq!(pages[0].rotate(), 90);
+    }
+
+    #[test]
+    fn broken_cross_reference_offsets_are_rebuilt_by_scanning() {
+        let (data, offsets) = one_page_document(Some(3));
+        let document = PdfDocument::parse(data).unwrap();
+
+        assert_eq!(document.xref_offset(), None);
+        assert_eq!(document.xref_entry(3), Some(XrefEntry::InFile(offsets[2])));
+        assert_eq!(document.pages().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn files_without_a_header_are_rejected() {
+        assert!(PdfDocument::parse(b"1 0 obj\nnull\nendobj\n".to_vec()).is_err());
+    }
+}
@@ -0,0 +1,202 @@
+#![allow(dead_code)]
+use std::io;
+use std::io::Write;
+use std::fs;
+use std::fs::File;
+use std::path::Path;
+use pdf::objects::{ObjectId, PdfObject, PdfDictionary, PdfStream};
+use pdf::reader::{PdfDocument, XrefEntry};
+use image_tools::transaction;
+
+
+/// Changes to an existing PDF file, written as an incremental update: the
+/// new and replaced objects are appended after the original file, followed
+/// by a cross reference section that points back to the original one. The
+/// original bytes are never rewritten, so nothing the update does not touch
+/// can be lost.
+pub struct PdfUpdate<'a> {
+    document: &'a PdfDocument,
+    objects: Vec<(ObjectId, PdfObject)>,
+    next_number: u32,
+}
+
+impl<'a> PdfUpdate<'a> {
+    pub fn new(document: &'a PdfDocument) -> PdfUpdate<'a> {
+        let size = document.trailer().get("Size").and_then(|size| size.as_integer()).unwrap_or(0) as u32;
+        let highest = document.object_ids().last().cloned().unwrap_or(0);
+
+        PdfUpdate {
+            document: document,
+            objects: Vec::new(),
+            next_number: size.max(highest + 1),
+        }
+    }
+
+    pub fn document(&self) -> &PdfDocument {
+        self.document
+    }
+
+    /// Replace an object, or set an object added earlier in the update.
+    pub fn set(&mut self, id: ObjectId, object: PdfObject) {
+        self.objects.retain(|&(existing, _)| existing.number() != id.number());
+        self.objects.push((id, object));
+    }
+
+    pub fn add(&mut self, object: PdfObject) -> ObjectId {
+        let id = ObjectId::new(self.next_number, 0);
+        self.next_number += 1;
+        self.set(id, object);
+
+        id
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Write the original file followed by the update.
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut output = self.document.data().to_vec();
+        if !output.ends_with(b"\n") && !output.ends_with(b"\r") {
+            output.push(b'\n');
+        }
+
+        let mut offsets = Vec::new();
+        let mut objects: Vec<&(ObjectId, PdfObject)> = self.objects.iter().collect();
+        objects.sort_by_key(|&&(id, _)| id.number());
+        for &&(id, ref object) in objects.iter() {
+            offsets.push((id, output.len()));
+            writeln!(output, "{} {} obj", id.number(), id.generation())?;
+            object.write_to(&mut output)?;
+            writeln!(output, "\nendobj")?;
+        }
+
+        let mut trailer = PdfDictionary::new();
+        for key in &["Root", "Info", "ID"] {
+            if let Some(value) = self.document.trailer().get(key) {
+                trailer.set(key, value.clone());
+            }
+        }
+
+        match self.document.xref_offset() {
+            Some(previous) => {
+                trailer.set("Prev", PdfObject::Integer(previous as i64));
+                if self.document.xref_is_stream() {
+                    self.write_xref_stream(&mut output, &offsets, trailer, false)?;
+                } else {
+                    self.write_xref_table(&mut output, &offsets, trailer)?;
+                }
+            }
+            // Without a section to point back to, the new section has to
+            // list every object, including those in object streams.
+            None => self.write_xref_stream(&mut output, &offsets, trailer, true)?,
+        }
+
+        out.write_all(&output)?;
+        out.flush()
+    }
+
+    fn write_xref_table(&self, output: &mut Vec<u8>, offsets: &[(ObjectId, usize)],
+                        trailer: PdfDictionary) -> io::Result<()>
+    {
+        let xref_offset = output.len();
+        writeln!(output, "xref")?;
+        // One subsection per run of consecutive object numbers.
+        let mut i = 0;
+        while i < offsets.len() {
+            let mut j = i + 1;
+            while j < offsets.len() && offsets[j].0.number() == offsets[j - 1].0.number() + 1 {
+                j += 1;
+            }
+            writeln!(output, "{} {}", offsets[i].0.number(), j - i)?;
+            for &(id, offset) in offsets[i..j].iter() {
+                writeln!(output, "{:010} {:05} n\r", offset, id.generation())?;
+            }
+            i = j;
+        }
+
+        let mut trailer = trailer;
+        trailer.set("Size", PdfObject::Integer(self.next_number as i64));
+        writeln!(output, "trailer")?;
+        trailer.write_to(output)?;
+        writeln!(output, "\nstartxref\n{}\n%%EOF", xref_offset)?;
+
+        Ok(())
+    }
+
+    fn write_xref_stream(&self, output: &mut Vec<u8>, offsets: &[(ObjectId, usize)],
+                         trailer: PdfDictionary, complete: bool) -> io::Result<()>
+    {
+        // The stream lists itself, as the last object.
+        let stream_id = ObjectId::new(self.next_number, 0);
+        let xref_offset = output.len();
+
+        let mut entries: Vec<(u32, XrefEntry)> = Vec::new();
+        if complete {
+            for number in self.document.object_ids() {
+                if let Some(entry) = self.document.xref_entry(number) {
+                    entries.push((number, entry));
+                }
+            }
+        }
+        for &(id, offset) in offsets.iter() {
+            entries.retain(|&(number, _)| number != id.number());
+            entries.push((id.number(), XrefEntry::InFile(offset)));
+        }
+        entries.push((stream_id.number(), XrefEntry::InFile(xref_offset)));
+        entries.sort_by_key(|&(number, _)| number);
+
+        let mut index = Vec::new();
+        let mut data = Vec::new();
+        let mut i = 0;
+        while i < entries.len() {
+            let mut j = i + 1;
+            while j < entries.len() && entries[j].0 == entries[j - 1].0 + 1 {
+                j += 1;
+            }
+            index.push(PdfObject::Integer(entries[i].0 as i64));
+            index.push(PdfObject::Integer((j - i) as i64));
+            for &(_, entry) in entries[i..j].iter() {
+                let (kind, field, extra) = match entry {
+                    XrefEntry::Free                   => (0, 0, 0),
+                    XrefEntry::InFile(offset)         => (1, offset as u64, 0),
+                    XrefEntry::InStream(stream, item) => (2, stream as u64, item as u64),
+                };
+                data.push(kind);
+                data.extend_from_slice(&[(field >> 32) as u8, (field >> 24) as u8, (field >> 16) as u8,
+                                         (field >> 8) as u8, field as u8]);
+                data.extend_from_slice(&[(extra >> 8) as u8, extra as u8]);
+            }
+            i = j;
+        }
+
+        let mut dict = trailer;
+        dict.set("Type", PdfObject::name("XRef"));
+        dict.set("Size", PdfObject::Integer(self.next_number as i64 + 1));
+        dict.set("Index", PdfObject::Array(index));
+        dict.set("W", PdfObject::Array(vec![PdfObject::Integer(1), PdfObject::Integer(5), PdfObject::Integer(2)]));
+
+        writeln!(output, "{} 0 obj", stream_id.number())?;
+        PdfStream::new(dict, data).write_to(output)?;
+        writeln!(output, "\nendobj\nstartxref\n{}\n%%EOF", xref_offset)?;
+
+        Ok(())
+    }
+
+    /// Write the updated document over a file, through a temporary file beside
+    /// it so that the original is replaced all at once.
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let temporary = transaction::working_copy_path(path);
+
+        {
+            let mut out = File::create(&temporary)?;
+            self.write(&mut out)?;
+            out.sync_all()?;
+        }
+
+        fs::rename(&temporary, path).inspect_err(|_| {
+            let _ = fs::remove_file(&temporary);
+        })
+    }
+}
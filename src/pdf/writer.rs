@@ -0,0 +1,176 @@
+#![allow(dead_code)]
+use std::io;
+use std::io::Write;
+use pdf::objects::{ObjectId, PdfObject, PdfDictionary};
+use util::checksum;
+
+
+/// Counts the bytes written through it, so that object offsets can be
+/// recorded for the cross reference table.
+struct CountingWriter<'a, W: Write + 'a> {
+    inner: &'a mut W,
+    count: usize,
+}
+
+impl<'a, W: Write> Write for CountingWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+
+/// Collects the indirect objects of a PDF file and writes them out with a
+/// cross reference table and trailer. Object numbers are handed out in order,
+/// starting from 1; an object may be reserved first and set later, so that
+/// objects can refer to each other.
+#[derive(Clone, Debug)]
+pub struct PdfWriter {
+    version: String,
+    objects: Vec<Option<PdfObject>>,
+}
+
+impl Default for PdfWriter {
+    fn default() -> PdfWriter {
+        PdfWriter::new()
+    }
+}
+
+impl PdfWriter {
+    pub fn new() -> PdfWriter {
+        PdfWriter {
+            version: String::from("1.5"),
+            objects: Vec::new(),
+        }
+    }
+
+    pub fn set_version(&mut self, version: &str) {
+        self.version = String::from(version);
+    }
+
+    pub fn version(&self) -> &str {
+        self.version.as_ref()
+    }
+
+    /// Reserve an object number for an object that will be set later.
+    pub fn reserve(&mut self) -> ObjectId {
+        self.objects.push(None);
+        ObjectId::new(self.objects.len() as u32, 0)
+    }
+
+    /// Set an object reserved earlier.
+    pub fn set(&mut self, id: ObjectId, object: PdfObject) -> io::Result<()> {
+        match (id.number() as usize).checked_sub(1).and_then(|index| self.objects.get_mut(index)) {
+            Some(slot) => {
+                *slot = Some(object);
+                Ok(())
+            }
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("object {} was never reserved", id))),
+        }
+    }
+
+    pub fn add(&mut self, object: PdfObject) -> ObjectId {
+        self.objects.push(Some(object));
+        ObjectId::new(self.objects.len() as u32, 0)
+    }
+
+    pub fn get(&self, id: ObjectId) -> Option<&PdfObject> {
+        match (id.number() as usize).checked_sub(1).and_then(|index| self.objects.get(index)) {
+            Some(Some(object)) => Some(object),
+            _                  => None,
+        }
+    }
+
+    /// A document ID derived from the file's objects, so that the same
+    /// document always gets the same ID.
+    fn document_id(&self) -> io::Result<PdfObject> {
+        let mut hash = checksum::FNV_OFFSET_BASIS;
+        for object in self.objects.iter() {
+            if let Some(ref object) = *object {
+                let mut buffer = Vec::new();
+                object.write_to(&mut buffer)?;
+                hash = checksum::fnv1a_update(hash, &buffer);
+            }
+        }
+
+        let mut id = Vec::new();
+        for i in 0..2 {
+            let part = checksum::fnv1a_update(hash, &[i as u8]);
+            for shift in 0..8 {
+                id.push((part >> (56 - 8 * shift)) as u8);
+            }
+        }
+
+        Ok(PdfObject::String(id))
+    }
+
+    /// Write the file. The trailer should contain at least the Root entry;
+    /// Size is always filled in, and an ID is generated if one is not given.
+    pub fn write<W: Write>(&self, out: &mut W, trailer: &PdfDictionary) -> io::Result<()> {
+        for (i, object) in self.objects.iter().enumerate() {
+            if object.is_none() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          format!("Object {} was reserved but never set", i + 1)));
+            }
+        }
+
+        let mut out = CountingWriter { inner: out, count: 0 };
+        let mut offsets = Vec::new();
+
+        writeln!(out, "%PDF-{}", self.version)?;
+        // A comment with high bit characters marks the file as binary.
+        out.write_all(b"%\xe2\xe3\xcf\xd3\n")?;
+
+        for (i, object) in self.objects.iter().enumerate() {
+            offsets.push(out.count);
+            writeln!(out, "{} 0 obj", i + 1)?;
+            if let Some(ref object) = *object {
+                object.write_to(&mut out)?;
+            }
+            writeln!(out, "\nendobj")?;
+        }
+
+        let xref_offset = out.count;
+        writeln!(out, "xref\n0 {}", self.objects.len() + 1)?;
+        writeln!(out, "0000000000 65535 f\r")?;
+        for offset in offsets {
+            writeln!(out, "{:010} 00000 n\r", offset)?;
+        }
+
+        let mut trailer = trailer.clone();
+        trailer.set("Size", PdfObject::Integer(self.objects.len() as i64 + 1));
+        if !trailer.contains_key("ID") {
+            let id = self.document_id()?;
+            trailer.set("ID", PdfObject::Array(vec![id.clone(), id]));
+        }
+
+        writeln!(out, "trailer")?;
+        trailer.write_to(&mut out)?;
+        writeln!(out, "\nstartxref\n{}\n%%EOF", xref_offset)?;
+
+        out.flush()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_reserved_objects_can_be_set() {
+        let mut writer = PdfWriter::new();
+        let id = writer.reserve();
+        assert!(writer.set(id, PdfObject::Integer(1)).is_ok());
+        assert_eq!(writer.get(id), Some(&PdfObject::Integer(1)));
+
+        assert!(writer.set(ObjectId::new(0, 0), PdfObject::Null).is_err());
+        assert!(writer.set(ObjectId::new(2, 0), PdfObject::Null).is_err());
+        assert_eq!(writer.get(ObjectId::new(0, 0)), None);
+    }
+}
@@ -0,0 +1,4 @@
+pub mod op_types;
+pub mod operations;
+
+mod rasterize_commands;
@@ -0,0 +1,76 @@
+use std::fmt;
+use util::shell;
+
+
+/// The external programs that can rasterize PDF pages.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum RasterizerType {
+    Ghostscript,
+    Pdftoppm,
+}
+
+impl shell::AsShellCommand for RasterizerType {
+    fn as_shell_command(&self) -> String {
+        match *self {
+            RasterizerType::Ghostscript => String::from("gs"),
+            RasterizerType::Pdftoppm    => String::from("pdftoppm"),
+        }
+    }
+}
+
+impl fmt::Display for RasterizerType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RasterizerType::Ghostscript => write!(f, "Ghostscript"),
+            RasterizerType::Pdftoppm    => write!(f, "pdftoppm"),
+        }
+    }
+}
+
+
+/// The kind of image a page is rendered to.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum RasterDevice {
+    /// Black and white, in a Group 4 compressed TIFF file where the tool
+    /// supports it.
+    Bilevel,
+    /// 8 bit grayscale PNG.
+    Gray,
+    /// 24 bit colour PNG.
+    Color,
+}
+
+impl RasterDevice {
+    pub fn extension(&self, tool: RasterizerType) -> &'static str {
+        match (*self, tool) {
+            (RasterDevice::Bilevel, _) => "tif",
+            _                          => "png",
+        }
+    }
+
+    /// The arguments choosing the device for a tool.
+    pub fn device_args(&self, tool: RasterizerType) -> Vec<String> {
+        let args: &[&str] = match (tool, *self) {
+            // A single strip, so that the page can be embedded as it is.
+            (RasterizerType::Ghostscript, RasterDevice::Bilevel) => &["-sDEVICE=tiffg4", "-dMaxStripSize=0"],
+            (RasterizerType::Ghostscript, RasterDevice::Gray)    => &["-sDEVICE=pnggray"],
+            (RasterizerType::Ghostscript, RasterDevice::Color)   => &["-sDEVICE=png16m"],
+            // pdftoppm cannot write fax compressed TIFF files.
+            (RasterizerType::Pdftoppm, RasterDevice::Bilevel)    => &["-mono", "-tiff", "-tiffcompression", "deflate"],
+            (RasterizerType::Pdftoppm, RasterDevice::Gray)       => &["-gray", "-png"],
+            (RasterizerType::Pdftoppm, RasterDevice::Color)      => &["-png"],
+        };
+
+        args.iter().map(|arg| String::from(*arg)).collect()
+    }
+}
+
+impl fmt::Display for RasterDevice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RasterDevice::Bilevel => write!(f, "Bilevel"),
+            RasterDevice::Gray    => write!(f, "Gray"),
+            RasterDevice::Color   => write!(f, "Color"),
+        }
+    }
+}
@@ -0,0 +1,264 @@
+#![allow(dead_code)]
+use std::io;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+use image_tools::image_ops::{Page, ImageFileFormat, ImageResolution, ResolutionUnits, FilePath};
+use image_tools::cancellation::CancellationToken;
+use pdf::reader::PdfDocument;
+use pdf::images::PdfImage;
+use image_tools::tiff::TiffFile;
+use super::op_types::{RasterizerType, RasterDevice};
+use super::rasterize_commands;
+
+
+/// A rendered PDF page, with the tool that rendered it.
+#[derive(Clone, Debug)]
+pub struct RasterizedPage {
+    page_number: usize,
+    tool: RasterizerType,
+    page: Page,
+}
+
+impl RasterizedPage {
+    /// The number of the PDF page, counting from 1.
+    pub fn page_number(&self) -> usize {
+        self.page_number
+    }
+
+    pub fn tool(&self) -> RasterizerType {
+        self.tool
+    }
+
+    pub fn page(&self) -> &Page {
+        &self.page
+    }
+}
+
+impl fmt::Display for RasterizedPage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "page {} rendered by {} -> {}", self.page_number, self.tool, self.page.file_path())
+    }
+}
+
+
+/// Renders the pages of a PDF file to images with a locally installed
+/// Ghostscript or pdftoppm, one invocation per page. Without a preferred
+/// tool, Ghostscript is tried first and pdftoppm is used for any page
+/// Ghostscript is missing for or fails on.
+#[derive(Clone, Debug)]
+pub struct Rasterizer {
+    tool: Option<RasterizerType>,
+    resolution: usize,
+    device: RasterDevice,
+    timeout: Option<Duration>,
+}
+
+impl Rasterizer {
+    pub fn new(resolution: usize, device: RasterDevice) -> Rasterizer {
+        Rasterizer {
+            tool: None,
+            resolution: resolution,
+            device: device,
+            timeout: None,
+        }
+    }
+
+    /// Use only the given tool, rather than falling back to the other.
+    pub fn set_tool(&mut self, tool: RasterizerType) {
+        self.tool = Some(tool);
+    }
+
+    /// The longest a single page may take to render.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    pub fn tool(&self) -> Option<RasterizerType> {
+        self.tool
+    }
+
+    pub fn resolution(&self) -> usize {
+        self.resolution
+    }
+
+    pub fn device(&self) -> RasterDevice {
+        self.device
+    }
+
+    fn tools(&self) -> Vec<RasterizerType> {
+        match self.tool {
+            Some(tool) => vec![tool],
+            None       => vec![RasterizerType::Ghostscript, RasterizerType::Pdftoppm],
+        }
+    }
+
+    /// Render every page of a PDF file into a directory, returning the pages
+    /// in document order.
+    pub fn rasterize<P: AsRef<Path>, Q: AsRef<Path>>(&self, pdf_path: P, output_dir: Q, token: &CancellationToken)
+        -> io::Result<Vec<RasterizedPage>>
+    {
+        let pdf_path = pdf_path.as_ref();
+        let page_count = self.page_count(pdf_path, token)?;
+        let stem = pdf_path.file_stem()
+                           .map(|stem| stem.to_string_lossy().into_owned())
+                           .unwrap_or(String::from("page"));
+
+        let mut pages = Vec::new();
+        for page_number in 1..page_count + 1 {
+            let file_stem = format!("{}-p{:04}", stem, page_number);
+            pages.push(self.rasterize_page(pdf_path, page_number, output_dir.as_ref(), &file_stem, token)?);
+        }
+
+        Ok(pages)
+    }
+
+    /// The number of pages in a PDF file. Files the native parser cannot read
+    /// are counted by the tools, in the order they are tried for rendering.
+    pub fn page_count(&self, pdf_path: &Path, token: &CancellationToken) -> io::Result<usize> {
+        let native_error = match PdfDocument::open(pdf_path).and_then(|document| document.pages()) {
+            Ok(pages) => return Ok(pages.len()),
+            Err(e)    => e,
+        };
+
+        let file_path: FilePath = pdf_path.to_string_lossy().into_owned();
+        let mut errors = vec![format!("native parser: {}", native_error)];
+        for tool in self.tools() {
+            let counted = match tool {
+                RasterizerType::Ghostscript => rasterize_commands::ghostscript_page_count(&file_path, token, self.timeout),
+                RasterizerType::Pdftoppm    => rasterize_commands::pdfinfo_page_count(&file_path, token, self.timeout),
+            };
+
+            match counted {
+                Ok(count) => return Ok(count),
+                Err(e) => match e.kind() {
+                    io::ErrorKind::Interrupted | io::ErrorKind::TimedOut => return Err(e),
+                    _ => errors.push(format!("{}: {}", tool, e)),
+                },
+            }
+        }
+
+        Err(io::Error::new(io::ErrorKind::InvalidData,
+                           format!("Could not count the pages of {}: {}", pdf_path.display(), errors.join("; "))))
+    }
+
+    /// Render a single page, trying each tool in turn.
+    pub fn rasterize_page(&self, pdf_path: &Path, page_number: usize, output_dir: &Path,
+                          file_stem: &str, token: &CancellationToken) -> io::Result<RasterizedPage>
+    {
+        let pdf_path: FilePath = pdf_path.to_string_lossy().into_owned();
+        let mut errors = Vec::new();
+
+        for tool in self.tools() {
+            let file_name = format!("{}.{}", file_stem, self.device.extension(tool));
+            let file_path: FilePath = output_dir.join(&file_name).to_string_lossy().into_owned();
+
+            let rendered = match tool {
+                RasterizerType::Ghostscript => {
+                    rasterize_commands::ghostscript_render(&pdf_path, page_number, self.resolution, self.device,
+                                                           &file_path, token, self.timeout)
+                }
+                RasterizerType::Pdftoppm => {
+                    let output_root = output_dir.join(file_stem).to_string_lossy().into_owned();
+                    rasterize_commands::pdftoppm_render(&pdf_path, page_number, self.resolution, self.device,
+                                                        &output_root, token, self.timeout)
+                }
+            };
+
+            match rendered {
+                Ok(_) => {
+                    let page = self.rendered_page(file_name, file_path, tool)?;
+                    return Ok(RasterizedPage {
+                        page_number: page_number,
+                        tool: tool,
+                        page: page,
+                    });
+                }
+                // Cancellation and timeouts apply to the page, not to one tool.
+                Err(e) => match e.kind() {
+                    io::ErrorKind::Interrupted | io::ErrorKind::TimedOut => return Err(e),
+                    _ => errors.push(format!("{}: {}", tool, e)),
+                },
+            }
+        }
+
+        Err(io::Error::other(format!("Could not rasterize page {}: {}", page_number, errors.join("; "))))
+    }
+
+    /// The page for a rendered image. The dimensions are read from the file,
+    /// since they depend on the page's rotation and the tool's rounding. For
+    /// TIFF files only the first directory is read, whatever the strips hold.
+    fn rendered_page(&self, file_name: String, file_path: FilePath, tool: RasterizerType) -> io::Result<Page> {
+        let requested = ImageResolution::new(self.resolution, ResolutionUnits::PixelsPerInch);
+        let (format, dimensions, resolution) = match self.device.extension(tool) {
+            "tif" => {
+                let tiff = TiffFile::open(&file_path)?;
+                let directory = &tiff.directories()[0];
+                (ImageFileFormat::TIFF, directory.dimensions()?, directory.resolution().unwrap_or(requested))
+            }
+            _ => (ImageFileFormat::PNG, PdfImage::open(&file_path)?.dimensions(), requested),
+        };
+
+        Ok(Page::new(file_name, format, file_path, dimensions, resolution))
+    }
+}
+
+impl fmt::Display for Rasterizer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.tool {
+            Some(tool) => write!(f, "Rasterizer({}, {} ppi, {})", tool, self.resolution, self.device),
+            None       => write!(f, "Rasterizer({} ppi, {})", self.resolution, self.device),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use image_tools::tiff::{self, TiffImageData, TiffValue};
+    use pdf::ccitt;
+    use util::scratch::{scratch_dir, scratch_pdf};
+
+    #[test]
+    fn pdf_files_the_native_parser_reads_are_counted_without_the_tools() {
+        let dir = scratch_dir("rasterize-count");
+        let page = scratch_pdf(&dir, "book.pdf");
+        let mut rasterizer = Rasterizer::new(300, RasterDevice::Gray);
+        rasterizer.set_tool(RasterizerType::Pdftoppm);
+
+        assert_eq!(rasterizer.page_count(Path::new(page.file_path()), &CancellationToken::new()).unwrap(), 1);
+
+        ::std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rendered_multi_strip_fax_pages_are_read_from_the_tiff_header() {
+        // Ghostscript's tiffg4 device writes a strip per 8 KiB unless told
+        // otherwise; this page has two strips of 20 rows.
+        let (width, height) = (64, 40);
+        let lines: Vec<Vec<usize>> = (0..20).map(|y| if y % 4 == 0 { vec![8, 56] } else { vec![] }).collect();
+        let strips = vec![ccitt::encode_g4(&lines, width), ccitt::encode_g4(&lines, width)];
+        let mut image = TiffImageData::with_strips(width as u32, height, 20, strips);
+        image.set(tiff::TAG_BITS_PER_SAMPLE, TiffValue::Short(vec![1]));
+        image.set(tiff::TAG_COMPRESSION, TiffValue::Short(vec![tiff::COMPRESSION_CCITT_G4 as u16]));
+        image.set(tiff::TAG_PHOTOMETRIC, TiffValue::Short(vec![tiff::PHOTOMETRIC_WHITE_IS_ZERO as u16]));
+        image.set_resolution(&ImageResolution::new(200, ResolutionUnits::PixelsPerInch));
+
+        let dir = scratch_dir("rendered-fax-page");
+        let file_path: FilePath = dir.join("page-1.tif").to_string_lossy().into_owned();
+        File::create(&file_path).unwrap().write_all(&tiff::write_tiff(&[image])).unwrap();
+
+        let rasterizer = Rasterizer::new(300, RasterDevice::Bilevel);
+        let page = rasterizer.rendered_page(String::from("page-1.tif"), file_path.clone(),
+                                            RasterizerType::Ghostscript).unwrap();
+        assert_eq!(page.dimensions().x_pixels(), 64);
+        assert_eq!(page.dimensions().y_pixels(), 40);
+        assert_eq!(page.resolution().amount(), 200);
+
+        // The page can be embedded as it is in an assembled PDF.
+        assert!(PdfImage::open(&file_path).is_ok());
+    }
+}
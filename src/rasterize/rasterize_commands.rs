@@ -0,0 +1,172 @@
+#![allow(dead_code)]
+use std::process::{Command, Child, Stdio};
+use std::io;
+use std::time::Duration;
+use image_tools::image_ops::FilePath;
+use image_tools::cancellation::{self, CancellationToken};
+use super::op_types::{RasterizerType, RasterDevice};
+use util::shell::AsShellCommand;
+use util::process;
+
+
+fn run_command(command_name: &str,
+               args: &[String],
+               token: &CancellationToken,
+               timeout: Option<Duration>)
+    -> io::Result<String> {
+
+    if token.is_cancelled() {
+        return Err(cancellation::cancelled_error());
+    }
+
+    let child: Child = Command::new(command_name)
+                                .args(args)
+                                .stdin(Stdio::null())
+                                .stdout(Stdio::piped())
+                                .stderr(Stdio::piped())
+                                .spawn()?;
+
+    let stdout = process::wait_for_output(command_name, child, token, timeout)?;
+
+    Ok(String::from_utf8_lossy(&stdout).into_owned())
+}
+
+fn rasterizer_command(tool: RasterizerType,
+                      args: &[String],
+                      token: &CancellationToken,
+                      timeout: Option<Duration>)
+    -> io::Result<String> {
+
+    run_command(&tool.as_shell_command(), args, token, timeout)
+}
+
+/// A path written as a PostScript string, with the characters that end or
+/// escape a string escaped.
+fn postscript_string(text: &str) -> String {
+    let mut escaped = String::from("(");
+    for c in text.chars() {
+        if c == '(' || c == ')' || c == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped.push(')');
+
+    escaped
+}
+
+fn parse_page_count(tool: &str, output: &str) -> io::Result<usize> {
+    output.trim().parse().map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{} reported no page count: {}", tool, output.trim()))
+    })
+}
+
+/// Count the pages of a PDF file with Ghostscript's PDF interpreter, for
+/// files the native parser cannot read. Like rendering, this runs with
+/// -dSAFER and may read only the PDF file itself.
+pub fn ghostscript_page_count(pdf_path: &FilePath,
+                              token: &CancellationToken,
+                              timeout: Option<Duration>)
+    -> io::Result<usize> {
+
+    let args = vec![
+        String::from("-q"),
+        String::from("-dNODISPLAY"),
+        String::from("-dSAFER"),
+        format!("--permit-file-read={}", pdf_path),
+        String::from("-dBATCH"),
+        String::from("-c"),
+        format!("{} (r) file runpdfbegin pdfpagecount = quit", postscript_string(pdf_path)),
+    ];
+    let output = rasterizer_command(RasterizerType::Ghostscript, &args, token, timeout)?;
+
+    parse_page_count("Ghostscript", &output)
+}
+
+
+/// Count the pages of a PDF file with pdfinfo, which comes with pdftoppm.
+pub fn pdfinfo_page_count(pdf_path: &FilePath,
+                          token: &CancellationToken,
+                          timeout: Option<Duration>)
+    -> io::Result<usize> {
+
+    let output = run_command("pdfinfo", ::std::slice::from_ref(pdf_path), token, timeout)?;
+    let pages = output.lines()
+                      .find(|line| line.starts_with("Pages:"))
+                      .map(|line| line["Pages:".len()..].to_string())
+                      .unwrap_or_default();
+
+    parse_page_count("pdfinfo", &pages)
+}
+
+
+/// Render one page with Ghostscript, writing the image to `output_path`.
+pub fn ghostscript_render(pdf_path: &FilePath,
+                          page_number: usize,
+                          resolution: usize,
+                          device: RasterDevice,
+                          output_path: &FilePath,
+                          token: &CancellationToken,
+                          timeout: Option<Duration>)
+    -> io::Result<String> {
+
+    let mut args = vec![
+        String::from("-dNOPAUSE"),
+        String::from("-dBATCH"),
+        String::from("-dSAFER"),
+        format!("--permit-file-read={}", pdf_path),
+        String::from("-dQUIET"),
+    ];
+    args.extend(device.device_args(RasterizerType::Ghostscript));
+    args.push(format!("-r{}", resolution));
+    args.push(format!("-dFirstPage={}", page_number));
+    args.push(format!("-dLastPage={}", page_number));
+    args.push(format!("-sOutputFile={}", output_path));
+    args.push(pdf_path.clone());
+
+    rasterizer_command(RasterizerType::Ghostscript, &args, token, timeout)
+}
+
+
+/// Render one page with pdftoppm. pdftoppm names its output itself, adding
+/// the extension to `output_root`.
+pub fn pdftoppm_render(pdf_path: &FilePath,
+                       page_number: usize,
+                       resolution: usize,
+                       device: RasterDevice,
+                       output_root: &FilePath,
+                       token: &CancellationToken,
+                       timeout: Option<Duration>)
+    -> io::Result<String> {
+
+    let mut args = device.device_args(RasterizerType::Pdftoppm);
+    args.push(String::from("-r"));
+    args.push(resolution.to_string());
+    args.push(String::from("-f"));
+    args.push(page_number.to_string());
+    args.push(String::from("-l"));
+    args.push(page_number.to_string());
+    args.push(String::from("-singlefile"));
+    args.push(pdf_path.clone());
+    args.push(output_root.clone());
+
+    rasterizer_command(RasterizerType::Pdftoppm, &args, token, timeout)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_escaped_as_postscript_strings() {
+        assert_eq!(postscript_string("scans/book (1).pdf"), "(scans/book \\(1\\).pdf)");
+        assert_eq!(postscript_string("a\\b"), "(a\\\\b)");
+    }
+
+    #[test]
+    fn page_counts_must_be_numbers() {
+        assert_eq!(parse_page_count("gs", " 12\n").unwrap(), 12);
+        assert_eq!(parse_page_count("gs", "Error: /undefined").unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
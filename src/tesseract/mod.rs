@@ -0,0 +1,84 @@
+#![allow(dead_code)]
+use std::process::{Command, Child, Stdio};
+use std::io;
+use std::time::Duration;
+use image_tools::image_ops::{FilePath, QuarterTurn};
+use image_tools::cancellation::{self, CancellationToken};
+use image_tools::orientation::{Orientation, OrientationMethod};
+use util::process;
+
+
+const TESSERACT: &str = "tesseract";
+
+
+/// Ask Tesseract's orientation and script detection which way up a page is.
+/// Tesseract must be installed with the `osd` language data.
+pub fn detect_orientation(file_path: &FilePath,
+                          token: &CancellationToken,
+                          timeout: Option<Duration>)
+    -> io::Result<Orientation> {
+
+    if token.is_cancelled() {
+        return Err(cancellation::cancelled_error());
+    }
+
+    let child: Child = Command::new(TESSERACT)
+                                .arg(file_path)
+                                .args(["stdout", "--psm", "0"])
+                                .stdin(Stdio::null())
+                                .stdout(Stdio::piped())
+                                .stderr(Stdio::piped())
+                                .spawn()?;
+
+    let stdout = process::wait_for_output(TESSERACT, child, token, timeout)?;
+
+    parse_osd(&String::from_utf8_lossy(&stdout))
+}
+
+/// Read an orientation from Tesseract's `--psm 0` report. Its `Rotate` line
+/// is the clockwise turn that puts the page upright.
+pub fn parse_osd(report: &str) -> io::Result<Orientation> {
+    let field = |name: &str| {
+        report.lines()
+              .filter_map(|line| {
+                  let mut parts = line.splitn(2, ':');
+                  match (parts.next(), parts.next()) {
+                      (Some(key), Some(value)) if key.trim() == name => Some(value.trim()),
+                      _ => None,
+                  }
+              })
+              .next()
+    };
+
+    let rotate = field("Rotate").and_then(|value| value.parse::<i64>().ok()).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "Tesseract reported no orientation")
+    })?;
+    let confidence = field("Orientation confidence").and_then(|value| value.parse::<f64>().ok()).unwrap_or(0.0);
+
+    Ok(Orientation::new(QuarterTurn::from_degrees(rotate), confidence, OrientationMethod::Tesseract))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn osd_reports_give_the_turn_and_confidence() {
+        let report = "Page number: 0\nOrientation in degrees: 90\nRotate: 270\n\
+                      Orientation confidence: 7.62\nScript: Latin\nScript confidence: 2.41\n";
+        let orientation = parse_osd(report).unwrap();
+
+        assert_eq!(orientation.correction(), Some(QuarterTurn::Rotate270));
+        assert_eq!(orientation.confidence(), 7.62);
+        assert_eq!(orientation.method(), OrientationMethod::Tesseract);
+        assert_eq!(parse_osd("Rotate: 0\n").unwrap().correction(), None);
+    }
+
+    #[test]
+    fn reports_without_a_rotation_are_invalid() {
+        let error = parse_osd("Too few characters. Skipping this page\n").unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1 +1,5 @@
-pub mod shell;
\ No newline at end of file
+pub mod shell;
+pub mod checksum;
+pub mod process;
+#[cfg(test)]
+pub mod scratch;
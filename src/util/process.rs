@@ -0,0 +1,118 @@
+#![allow(dead_code)]
+use std::process::Child;
+use std::io;
+use std::io::Read;
+use std::thread;
+use std::time::{Duration, Instant};
+use image_tools::cancellation::{self, CancellationToken};
+
+
+/// How often a running child process is polled for completion or cancellation.
+const POLL_INTERVAL_MILLIS: u64 = 10;
+
+
+pub fn timed_out_error(timeout: Duration) -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, format!("Command timed out after {:?}", timeout))
+}
+
+type PipeReader = thread::JoinHandle<io::Result<Vec<u8>>>;
+
+fn spawn_reader<R>(pipe: Option<R>) -> Option<PipeReader> where R: Read + Send + 'static {
+    pipe.map(|mut pipe| {
+        thread::spawn(move || {
+            let mut buffer = Vec::new();
+            pipe.read_to_end(&mut buffer).map(|_| buffer)
+        })
+    })
+}
+
+fn join_reader(reader: Option<PipeReader>) -> io::Result<Vec<u8>> {
+    match reader {
+        Some(handle) => {
+            match handle.join() {
+                Ok(result) => result,
+                Err(_)     => Err(io::Error::other("Failed to read process output")),
+            }
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Wait for a child process to finish, killing it if the token is cancelled
+/// or the timeout elapses in the meantime. Standard output and error are drained
+/// on separate threads so that a chatty process cannot block on a full pipe while
+/// we poll it. A process exiting with a failure status is reported as an error.
+pub fn wait_for_output(command_name: &str,
+                       mut child: Child,
+                       token: &CancellationToken,
+                       timeout: Option<Duration>)
+    -> io::Result<Vec<u8>> {
+
+    let started = Instant::now();
+
+    let stdout_reader = spawn_reader(child.stdout.take());
+    let stderr_reader = spawn_reader(child.stderr.take());
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+
+        if token.is_cancelled() {
+            // The process may exit on its own between the poll and the kill.
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(cancellation::cancelled_error());
+        }
+
+        if let Some(timeout) = timeout {
+            if started.elapsed() >= timeout {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(timed_out_error(timeout));
+            }
+        }
+
+        thread::sleep(Duration::from_millis(POLL_INTERVAL_MILLIS));
+    };
+
+    let stdout = join_reader(stdout_reader)?;
+    let stderr = join_reader(stderr_reader)?;
+
+    if !status.success() {
+        let message = format!("{} exited with {}: {}", 
+                              command_name, status, String::from_utf8_lossy(&stderr).trim());
+        return Err(io::Error::other(message));
+    }
+
+    Ok(stdout)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Command, Stdio};
+
+    fn sh(script: &str) -> Child {
+        Command::new("sh").args(["-c", script]).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap()
+    }
+
+    #[test]
+    fn commands_running_past_their_timeout_are_killed() {
+        let started = Instant::now();
+        let result = wait_for_output("sh", sh("sleep 10"), &CancellationToken::new(), Some(Duration::from_millis(50)));
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn commands_within_their_timeout_report_their_output() {
+        let timeout = Some(Duration::from_secs(10));
+
+        assert_eq!(wait_for_output("sh", sh("echo done"), &CancellationToken::new(), timeout).unwrap(), b"done\n");
+        let failed = wait_for_output("sh", sh("echo broken >&2; exit 3"), &CancellationToken::new(), timeout);
+        assert!(failed.unwrap_err().to_string().contains("broken"));
+    }
+}
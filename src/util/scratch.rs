@@ -0,0 +1,46 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use image_tools::image_ops::{Page, ImageFileFormat, ImageDimensions, ImageResolution, ResolutionUnits};
+use image_tools::tiff::{self, TiffImageData, TiffValue};
+use pdf::assemble::PdfAssembly;
+
+
+/// An empty directory for a test to write files in, named after the test
+/// and the process so that tests running at the same time do not collide.
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("pdftools-{}-{}", name, ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
+
+/// A small gray TIFF page, for operations and assemblies that need an image.
+pub fn scratch_tiff(dir: &Path, name: &str) -> Page {
+    let res = ImageResolution::new(72, ResolutionUnits::PixelsPerInch);
+    let mut image = TiffImageData::new(8, 8, vec![0x80; 64]);
+    image.set(tiff::TAG_BITS_PER_SAMPLE, TiffValue::Short(vec![8]));
+    image.set(tiff::TAG_COMPRESSION, TiffValue::Short(vec![tiff::COMPRESSION_NONE as u16]));
+    image.set(tiff::TAG_PHOTOMETRIC, TiffValue::Short(vec![tiff::PHOTOMETRIC_BLACK_IS_ZERO as u16]));
+    image.set_resolution(&res);
+    let path = dir.join(name);
+    fs::write(&path, tiff::write_tiff(&[image])).unwrap();
+
+    Page::new(String::from(name), ImageFileFormat::TIFF, path.to_string_lossy().into_owned(),
+              ImageDimensions::new(8, 8), res)
+}
+
+/// A one page PDF file holding the page of `scratch_tiff`, for operations
+/// that run natively on PDF files.
+pub fn scratch_pdf(dir: &Path, name: &str) -> Page {
+    let tiff_page = scratch_tiff(dir, &format!("{}.tif", name));
+    let mut assembly = PdfAssembly::new();
+    assembly.add_page(tiff_page.clone());
+    let pdf_path = dir.join(name);
+    assembly.write_to_file(&pdf_path).unwrap();
+    fs::remove_file(tiff_page.file_path()).unwrap();
+
+    Page::new(String::from(name), ImageFileFormat::PDF, pdf_path.to_string_lossy().into_owned(),
+              ImageDimensions::new(8, 8), tiff_page.resolution().clone())
+}